@@ -0,0 +1,131 @@
+//! 无界面地加载插件并执行 `*_spec.lua` 测试用例
+//!
+//! ```text
+//! ptla-plugin-test <插件目录> [用例文件或目录...] [--format text|json|junit] [--output 文件]
+//!                  [--now RFC3339 时间] [--filter 文字]
+//! ```
+//!
+//! 全部用例通过时退出码为 0，有用例失败或用例文件无法执行时为 1，参数错误或插件无法读取时为 2。
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+
+use todo_lib::plugin::harness::{self, Harness, ReportFormat};
+
+const USAGE: &str = "\
+Usage: ptla-plugin-test <plugin-dir> [spec...] [options]
+
+Loads the plugin headlessly with in-memory task, storage and notification APIs
+and runs every *_spec.lua under the plugin directory (or the given files and directories).
+
+Options:
+  --format <text|json|junit>  Report format (default: text)
+  --output <file>             Write the report to a file instead of stdout
+  --now <rfc3339>             Start time of the fake clock (default: 2024-01-01T00:00:00Z)
+  --filter <text>             Only run cases whose name contains the text
+  -h, --help                  Show this help";
+
+struct Options {
+    plugin_dir: PathBuf,
+    specs: Vec<PathBuf>,
+    format: ReportFormat,
+    output: Option<PathBuf>,
+    start: DateTime<Utc>,
+    filter: Option<String>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Option<Options>> {
+    let mut paths = Vec::new();
+    let mut format = ReportFormat::Text;
+    let mut output = None;
+    let mut start = harness::DEFAULT_START.to_string();
+    let mut filter = None;
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().with_context(|| format!("{} requires a value", name));
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--format" => format = value("--format")?.parse()?,
+            "--output" => output = Some(PathBuf::from(value("--output")?)),
+            "--now" => start = value("--now")?,
+            "--filter" => filter = Some(value("--filter")?),
+            flag if flag.starts_with("--") => anyhow::bail!("Unknown option {}", flag),
+            path => paths.push(PathBuf::from(path)),
+        }
+    }
+
+    let mut paths = paths.into_iter();
+    let plugin_dir = paths.next().context("Missing the plugin directory")?;
+    let start = DateTime::parse_from_rfc3339(&start)
+        .with_context(|| format!("Invalid --now time '{}'", start))?
+        .with_timezone(&Utc);
+    Ok(Some(Options {
+        plugin_dir,
+        specs: paths.collect(),
+        format,
+        output,
+        start,
+        filter,
+    }))
+}
+
+fn run(options: Options) -> Result<bool> {
+    let runtime = tokio::runtime::Runtime::new().context("Failed to start the async runtime")?;
+    let harness = Harness::new(&options.plugin_dir, options.start, runtime.handle().clone())?;
+
+    let roots = if options.specs.is_empty() {
+        vec![options.plugin_dir.clone()]
+    } else {
+        options.specs.clone()
+    };
+    let mut specs = Vec::new();
+    for root in roots {
+        if root.is_dir() {
+            specs.extend(harness::find_specs(&root)?);
+        } else if root.is_file() {
+            specs.push(root);
+        } else {
+            anyhow::bail!("Spec {} not found", root.display());
+        }
+    }
+
+    let report = harness.run(&specs, &options.plugin_dir, options.filter.as_deref());
+    let rendered = options.format.render(&report);
+    match &options.output {
+        Some(path) => {
+            std::fs::write(path, rendered).with_context(|| format!("Failed to write {}", path.display()))?;
+            // 报告写入文件时仍在终端输出摘要
+            let summary = &report.summary;
+            eprintln!(
+                "{} passed, {} failed, {} skipped, {} errors",
+                summary.passed, summary.failed, summary.skipped, summary.errors
+            );
+        }
+        None => print!("{}", rendered),
+    }
+    Ok(report.success())
+}
+
+fn main() -> ExitCode {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("error: {:#}\n\n{}", e, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    match run(options) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(1),
+        Err(e) => {
+            eprintln!("error: {:#}", e);
+            ExitCode::from(2)
+        }
+    }
+}
@@ -0,0 +1,233 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+use tauri::ipc::Channel;
+use tauri::State;
+
+use crate::plugin::command::{CommandInfo, CommandProgress};
+use crate::plugin::config::ConfigSchema;
+use crate::plugin::contribution::{ContributionPoint, ContributionSet};
+use crate::plugin::diagnostics::PluginErrorRecord;
+use crate::plugin::http::HttpAuditRecord;
+use crate::plugin::timer::TimerSpec;
+use crate::plugin::{PluginError, PluginInfo, PluginManager, PluginMetadata, ResourceUsage};
+
+/// 插件事件（[`crate::plugin::PluginEvent`]）推送到前端时使用的事件名
+pub const PLUGIN_EVENT: &str = "plugin://event";
+
+/// 插件详情页展示的信息
+#[derive(Debug, Serialize)]
+pub struct PluginDetails {
+    #[serde(flatten)]
+    pub info: PluginInfo,
+    pub recent_errors: Vec<PluginErrorRecord>,
+    /// 依赖该插件的插件，停用或卸载时会一并停用
+    pub dependents: Vec<String>,
+}
+
+/// 返回给前端的错误，插件系统的错误保留 `code` 及各字段，其他错误的 `code` 为 `internal`
+#[derive(Debug, Serialize)]
+#[serde(transparent)]
+pub struct CommandError(Value);
+
+impl From<anyhow::Error> for CommandError {
+    fn from(err: anyhow::Error) -> Self {
+        match err.downcast_ref::<PluginError>() {
+            Some(plugin_error) => CommandError(plugin_error.to_value()),
+            None => CommandError(serde_json::json!({
+                "code": "internal",
+                "message": format!("{:#}", err),
+            })),
+        }
+    }
+}
+
+type CommandResult<T> = Result<T, CommandError>;
+
+/// 插件目录中的全部插件，包括尚未加载的插件
+#[tauri::command]
+pub fn plugin_list(manager: State<'_, PluginManager>) -> CommandResult<Vec<PluginInfo>> {
+    Ok(manager.plugin_infos()?)
+}
+
+#[tauri::command]
+pub fn plugin_inspect(manager: State<'_, PluginManager>, plugin_id: String) -> CommandResult<PluginDetails> {
+    Ok(PluginDetails {
+        info: manager.plugin_info(&plugin_id)?,
+        recent_errors: manager.recent_errors(&plugin_id, 20),
+        dependents: manager.dependents(&plugin_id),
+    })
+}
+
+#[tauri::command]
+pub fn plugin_resource_usage(manager: State<'_, PluginManager>, plugin_id: String) -> CommandResult<ResourceUsage> {
+    Ok(manager.resource_usage(&plugin_id)?)
+}
+
+/// 启用插件，插件尚未加载时先加载
+#[tauri::command]
+pub async fn plugin_enable(manager: State<'_, PluginManager>, plugin_id: String) -> CommandResult<()> {
+    if manager.plugin_status(&plugin_id).is_none() {
+        manager.load_plugin(&plugin_id).await?;
+    } else {
+        manager.enable_plugin(&plugin_id).await?;
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn plugin_disable(manager: State<'_, PluginManager>, plugin_id: String) -> CommandResult<()> {
+    Ok(manager.disable_plugin(&plugin_id).await?)
+}
+
+#[tauri::command]
+pub async fn plugin_reload(manager: State<'_, PluginManager>, plugin_id: String) -> CommandResult<()> {
+    Ok(manager.reload_plugin(&plugin_id).await?)
+}
+
+/// 安装插件包并加载，返回插件的元数据
+#[tauri::command]
+pub async fn plugin_install(manager: State<'_, PluginManager>, archive: String) -> CommandResult<PluginMetadata> {
+    let metadata = manager.install_package(&archive).await?;
+    manager.load_plugin(&metadata.name).await?;
+    Ok(metadata)
+}
+
+/// 卸载插件，`purge` 为 `true` 时同时删除插件数据
+#[tauri::command]
+pub async fn plugin_uninstall(
+    manager: State<'_, PluginManager>,
+    plugin_id: String,
+    purge: bool,
+) -> CommandResult<()> {
+    Ok(manager.uninstall_plugin(&plugin_id, purge).await?)
+}
+
+/// 插件声明的配置项，插件没有配置项时返回 `null`
+#[tauri::command]
+pub fn plugin_get_config_schema(
+    manager: State<'_, PluginManager>,
+    plugin_id: String,
+) -> CommandResult<Option<ConfigSchema>> {
+    Ok(manager.config_schema(&plugin_id)?)
+}
+
+/// 当前用户的插件配置，未设置的项使用默认值
+#[tauri::command]
+pub fn plugin_get_config(
+    manager: State<'_, PluginManager>,
+    plugin_id: String,
+) -> CommandResult<Map<String, Value>> {
+    Ok(manager.plugin_config(&plugin_id)?)
+}
+
+/// 保存设置表单提交的完整配置，返回保存后的配置
+#[tauri::command]
+pub async fn plugin_set_config(
+    manager: State<'_, PluginManager>,
+    plugin_id: String,
+    values: Map<String, Value>,
+) -> CommandResult<Map<String, Value>> {
+    Ok(manager.set_plugin_config(&plugin_id, values).await?)
+}
+
+/// 插件最近的错误记录，最新的在前，`limit` 缺省为 50
+#[tauri::command]
+pub fn plugin_recent_errors(
+    manager: State<'_, PluginManager>,
+    plugin_id: String,
+    limit: Option<usize>,
+) -> Vec<PluginErrorRecord> {
+    manager.recent_errors(&plugin_id, limit.unwrap_or(50))
+}
+
+/// 命令面板中的全部插件命令
+#[tauri::command]
+pub fn plugin_list_commands(manager: State<'_, PluginManager>) -> Vec<CommandInfo> {
+    manager.list_commands()
+}
+
+/// 执行插件命令，执行过程中的进度通过 `on_progress` 推送，返回命令的结果
+#[tauri::command]
+pub async fn plugin_invoke_command(
+    manager: State<'_, PluginManager>,
+    plugin_id: String,
+    command_id: String,
+    args: Option<Value>,
+    on_progress: Channel<CommandProgress>,
+) -> CommandResult<Value> {
+    let on_progress = Arc::new(move |progress: &CommandProgress| {
+        let _ = on_progress.send(progress.clone());
+    });
+    let args = args.unwrap_or(Value::Null);
+    Ok(manager
+        .invoke_command_with_progress(&plugin_id, &command_id, args, Some(on_progress))
+        .await?)
+}
+
+/// 所有运行中插件的界面扩展点，插件状态变化后需要重新获取
+#[tauri::command]
+pub fn plugin_contributions(manager: State<'_, PluginManager>) -> ContributionSet {
+    manager.contributions()
+}
+
+/// 用户触发界面扩展点（点击任务操作、渲染徽标或小组件、修改自定义字段）时调用插件的处理函数
+#[tauri::command]
+pub async fn plugin_invoke_contribution(
+    manager: State<'_, PluginManager>,
+    plugin_id: String,
+    point: ContributionPoint,
+    id: String,
+    payload: Option<Value>,
+) -> CommandResult<Value> {
+    let payload = payload.unwrap_or(Value::Null);
+    Ok(manager.invoke_contribution(&plugin_id, point, &id, payload).await?)
+}
+
+/// 用户授权插件只读访问的目录，名称 -> 路径
+#[tauri::command]
+pub fn plugin_read_roots(
+    manager: State<'_, PluginManager>,
+    plugin_id: String,
+) -> CommandResult<BTreeMap<String, PathBuf>> {
+    Ok(manager.read_roots(&plugin_id)?)
+}
+
+/// 授权插件以 `root` 为名只读访问用户选择的目录 `path`
+#[tauri::command]
+pub fn plugin_grant_read_root(
+    manager: State<'_, PluginManager>,
+    plugin_id: String,
+    root: String,
+    path: PathBuf,
+) -> CommandResult<()> {
+    Ok(manager.grant_read_root(&plugin_id, &root, path)?)
+}
+
+#[tauri::command]
+pub fn plugin_revoke_read_root(
+    manager: State<'_, PluginManager>,
+    plugin_id: String,
+    root: String,
+) -> CommandResult<bool> {
+    Ok(manager.revoke_read_root(&plugin_id, &root)?)
+}
+
+/// 插件最近的 HTTP 请求记录，最新的在前，`limit` 缺省为 50
+#[tauri::command]
+pub fn plugin_http_audit(
+    manager: State<'_, PluginManager>,
+    plugin_id: String,
+    limit: Option<usize>,
+) -> Vec<HttpAuditRecord> {
+    manager.http_audit(&plugin_id, limit.unwrap_or(50))
+}
+
+/// 插件当前的定时器
+#[tauri::command]
+pub fn plugin_timers(manager: State<'_, PluginManager>, plugin_id: String) -> Vec<TimerSpec> {
+    manager.timers(&plugin_id)
+}
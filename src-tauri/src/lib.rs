@@ -1,14 +1,94 @@
+mod commands;
+pub mod plugin;
+
+use std::sync::Arc;
+
+use tauri::{Emitter, Manager};
+use tokio::sync::broadcast::error::RecvError;
+
+use plugin::diagnostics::JsonLinesSink;
+use plugin::package::TRUST_POLICY_FILE;
+use plugin::{PluginManager, TrustPolicy};
+
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
 #[tauri::command]
 fn greet(name: &str) -> String {
     format!("Hello, {}! You've been greeted from Rust!", name)
 }
 
+/// 创建插件管理器并交给 Tauri 管理：监控应用数据目录下的 `plugins`，在后台加载全部插件，
+/// 插件事件以 [`commands::PLUGIN_EVENT`] 推送到前端。插件包的信任策略读取自应用配置目录下的
+/// [`TRUST_POLICY_FILE`]
+fn setup_plugins(app: &mut tauri::App) -> Result<(), Box<dyn std::error::Error>> {
+    let plugin_dir = app.path().app_data_dir()?.join("plugins");
+    std::fs::create_dir_all(&plugin_dir)?;
+
+    let mut manager = PluginManager::with_plugin_dir(&plugin_dir);
+    let log_file = app.path().app_log_dir()?.join("plugins.jsonl");
+    manager.set_log_sink(Some(Arc::new(JsonLinesSink::new(log_file))));
+    let trust_file = app.path().app_config_dir()?.join(TRUST_POLICY_FILE);
+    match TrustPolicy::load(&trust_file) {
+        Ok(policy) => manager.set_trust_policy(policy),
+        // 策略文件无效时保持默认的严格策略，只接受签名可验证的插件包
+        Err(e) => eprintln!("[PluginManager] 信任策略读取失败: {:#}", e),
+    }
+
+    let mut events = manager.subscribe();
+    let handle = app.handle().clone();
+    tauri::async_runtime::spawn(async move {
+        loop {
+            match events.recv().await {
+                Ok(event) => {
+                    if let Err(e) = handle.emit(commands::PLUGIN_EVENT, &event) {
+                        eprintln!("[PluginManager] 插件事件推送失败: {}", e);
+                    }
+                }
+                // 前端只需要最新状态，落后时跳过积压的事件
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+
+    tauri::async_runtime::block_on(manager.init_with_watch(&plugin_dir))?;
+    app.manage(manager.clone());
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = manager.load_all().await {
+            eprintln!("[PluginManager] 插件加载失败: {:#}", e);
+        }
+    });
+    Ok(())
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
-        .invoke_handler(tauri::generate_handler![greet])
+        .setup(setup_plugins)
+        .invoke_handler(tauri::generate_handler![
+            greet,
+            commands::plugin_list,
+            commands::plugin_inspect,
+            commands::plugin_resource_usage,
+            commands::plugin_enable,
+            commands::plugin_disable,
+            commands::plugin_reload,
+            commands::plugin_install,
+            commands::plugin_uninstall,
+            commands::plugin_get_config_schema,
+            commands::plugin_get_config,
+            commands::plugin_set_config,
+            commands::plugin_recent_errors,
+            commands::plugin_list_commands,
+            commands::plugin_invoke_command,
+            commands::plugin_contributions,
+            commands::plugin_invoke_contribution,
+            commands::plugin_read_roots,
+            commands::plugin_grant_read_root,
+            commands::plugin_revoke_read_root,
+            commands::plugin_http_audit,
+            commands::plugin_timers,
+        ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
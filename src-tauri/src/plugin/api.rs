@@ -1,7 +1,6 @@
-use std::sync::Arc;
-use anyhow::{Result, Context};
+use anyhow::Result;
 use serde_json::Value;
-use crate::plugin::PluginApi;
+use crate::plugin::{MethodAccess, PluginApi};
 
 // 任务管理 API
 pub struct TaskApi {
@@ -25,6 +24,14 @@ impl PluginApi for TaskApi {
             "task.write".to_string(),
         ]
     }
+
+    fn method_access(&self, method: &str) -> Option<MethodAccess> {
+        match method {
+            "list" => Some(MethodAccess::Requires("task.read".to_string())),
+            "create" | "update" | "delete" => Some(MethodAccess::Requires("task.write".to_string())),
+            _ => None,
+        }
+    }
 }
 
 impl TaskApi {
@@ -70,6 +77,14 @@ impl PluginApi for StorageApi {
             "storage.write".to_string(),
         ]
     }
+
+    fn method_access(&self, method: &str) -> Option<MethodAccess> {
+        match method {
+            "get" => Some(MethodAccess::Requires("storage.read".to_string())),
+            "set" | "delete" => Some(MethodAccess::Requires("storage.write".to_string())),
+            _ => None,
+        }
+    }
 }
 
 impl StorageApi {
@@ -109,6 +124,14 @@ impl PluginApi for NotificationApi {
             "notification.read".to_string(),
         ]
     }
+
+    fn method_access(&self, method: &str) -> Option<MethodAccess> {
+        match method {
+            "send" => Some(MethodAccess::Requires("notification.send".to_string())),
+            "list" => Some(MethodAccess::Requires("notification.read".to_string())),
+            _ => None,
+        }
+    }
 }
 
 impl NotificationApi {
@@ -129,7 +152,7 @@ pub struct SystemApi {
 }
 
 impl PluginApi for SystemApi {
-    fn call(&self, method: &str, params: Value) -> Result<Value> {
+    fn call(&self, method: &str, _params: Value) -> Result<Value> {
         match method {
             "get_info" => self.get_system_info(),
             "get_memory_usage" => self.get_memory_usage(),
@@ -144,6 +167,14 @@ impl PluginApi for SystemApi {
             "system.metrics".to_string(),
         ]
     }
+
+    fn method_access(&self, method: &str) -> Option<MethodAccess> {
+        match method {
+            "get_info" => Some(MethodAccess::Requires("system.info".to_string())),
+            "get_memory_usage" | "get_cpu_usage" => Some(MethodAccess::Requires("system.metrics".to_string())),
+            _ => None,
+        }
+    }
 }
 
 impl SystemApi {
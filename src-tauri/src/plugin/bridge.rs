@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use anyhow::Result;
+use serde_json::Value;
+
+use crate::plugin::error::PluginError;
+use crate::plugin::limits::{BudgetTracker, RateLimiter};
+use crate::plugin::permission::PermissionSet;
+use crate::plugin::{MethodAccess, PluginApi, PluginMetadata, ResourceLimits};
+
+/// 插件调用宿主 API 的唯一入口
+///
+/// 每个插件运行时持有一个 `ApiBridge`，所有 `api.<name>(method, params)` 调用都经过
+/// 这里完成 API 查找、权限校验和限流，各语言运行时只负责参数与返回值的转换。
+pub struct ApiBridge {
+    plugin_id: String,
+    permissions: PermissionSet,
+    apis: Mutex<HashMap<String, Arc<dyn PluginApi>>>,
+    rate_limiter: RateLimiter,
+    // 当前这次进入脚本的执行预算
+    budget: Mutex<Option<Arc<BudgetTracker>>>,
+}
+
+impl ApiBridge {
+    pub fn new(metadata: &PluginMetadata) -> Self {
+        Self {
+            plugin_id: metadata.name.clone(),
+            permissions: PermissionSet::new(metadata.permissions.iter().cloned()),
+            apis: Mutex::new(HashMap::new()),
+            rate_limiter: RateLimiter::new(&ResourceLimits::default()),
+            budget: Mutex::new(None),
+        }
+    }
+
+    pub fn plugin_id(&self) -> &str {
+        &self.plugin_id
+    }
+
+    pub fn permissions(&self) -> &PermissionSet {
+        &self.permissions
+    }
+
+    pub fn register(&self, name: &str, api: Arc<dyn PluginApi>) {
+        self.apis.lock().unwrap().insert(name.to_string(), api);
+    }
+
+    /// 按照 `max_api_calls` 和 `api_quotas` 更新调用配额
+    pub fn set_limits(&self, limits: &ResourceLimits) {
+        self.rate_limiter.set_limits(limits);
+    }
+
+    pub fn rate_limiter(&self) -> &RateLimiter {
+        &self.rate_limiter
+    }
+
+    /// 运行时进入和离开脚本时设置当前的执行预算，API 调用耗费的时间不计入预算
+    pub fn set_budget(&self, budget: Option<Arc<BudgetTracker>>) {
+        *self.budget.lock().unwrap() = budget;
+    }
+
+    /// 已注册的 API 名称
+    pub fn api_names(&self) -> Vec<String> {
+        let mut names: Vec<_> = self.apis.lock().unwrap().keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// 校验权限和配额后调用 API
+    pub fn call(&self, api: &str, method: &str, params: Value) -> Result<Value> {
+        let handler = self
+            .apis
+            .lock()
+            .unwrap()
+            .get(api)
+            .cloned()
+            .ok_or_else(|| PluginError::UnknownApi {
+                plugin_id: self.plugin_id.clone(),
+                api: api.to_string(),
+            })?;
+
+        let required = match handler.method_access(method) {
+            Some(MethodAccess::Public) => None,
+            Some(MethodAccess::Requires(permission)) => Some(permission),
+            None => {
+                return Err(PluginError::UnknownMethod {
+                    plugin_id: self.plugin_id.clone(),
+                    api: api.to_string(),
+                    method: method.to_string(),
+                }
+                .into())
+            }
+        };
+        self.permissions
+            .authorize(&self.plugin_id, api, method, required.as_deref())?;
+        self.rate_limiter
+            .acquire(api)
+            .map_err(|retry_after| PluginError::RateLimited {
+                plugin_id: self.plugin_id.clone(),
+                api: api.to_string(),
+                retry_after_ms: retry_after.as_millis().max(1) as u64,
+            })?;
+
+        let budget = self.budget.lock().unwrap().clone();
+        match budget {
+            Some(budget) => budget.in_host(|| handler.call(method, params)),
+            None => handler.call(method, params),
+        }
+    }
+}
+
+/// 把 API 调用错误转换为交给插件脚本的结构化错误
+///
+/// 已知的 [`PluginError`] 保留其 `code` 与字段，其他错误统一为 `api_error`。
+pub fn error_value(err: &anyhow::Error) -> Value {
+    match err.downcast_ref::<PluginError>() {
+        Some(plugin_error) => plugin_error.to_value(),
+        None => serde_json::json!({
+            "code": "api_error",
+            "message": format!("{:#}", err),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin::api::TaskApi;
+    use crate::plugin::PluginLanguage;
+
+    fn metadata(permissions: &[&str]) -> PluginMetadata {
+        PluginMetadata {
+            name: "bridge_test".to_string(),
+            version: "1.0.0".to_string(),
+            description: None,
+            author: None,
+            homepage_url: None,
+            repository_url: None,
+            license: None,
+            language: PluginLanguage::Lua,
+            main_file: "main.lua".to_string(),
+            permissions: permissions.iter().map(|p| p.to_string()).collect(),
+            dependencies: None,
+            configuration: None,
+            commands: vec![],
+            contributes: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_bridge_enforces_permissions() {
+        let bridge = ApiBridge::new(&metadata(&["task.read"]));
+        bridge.register("task", Arc::new(TaskApi {}));
+
+        assert!(bridge.call("task", "list", Value::Null).is_ok());
+
+        let err = bridge.call("task", "create", serde_json::json!({"title": "x"})).unwrap_err();
+        let value = error_value(&err);
+        assert_eq!(value["code"], "permission_denied");
+        assert_eq!(value["permission"], "task.write");
+        assert_eq!(value["api"], "task");
+        assert_eq!(value["method"], "create");
+
+        let err = bridge.call("fs", "read", Value::Null).unwrap_err();
+        assert_eq!(error_value(&err)["code"], "unknown_api");
+    }
+
+    #[test]
+    fn test_bridge_rejects_unmapped_methods() {
+        // 即使插件拥有全部权限，API 没有声明的方法也不能调用
+        let bridge = ApiBridge::new(&metadata(&["task.*"]));
+        bridge.register("task", Arc::new(TaskApi {}));
+
+        let err = bridge.call("task", "drop_all", Value::Null).unwrap_err();
+        let value = error_value(&err);
+        assert_eq!(value["code"], "unknown_method");
+        assert_eq!(value["api"], "task");
+        assert_eq!(value["method"], "drop_all");
+    }
+
+    #[test]
+    fn test_bridge_rate_limits_calls() {
+        let bridge = ApiBridge::new(&metadata(&["task.*"]));
+        bridge.register("task", Arc::new(TaskApi {}));
+        bridge.set_limits(&ResourceLimits {
+            api_quotas: HashMap::from([("task".to_string(), 5)]),
+            ..ResourceLimits::default()
+        });
+
+        for _ in 0..5 {
+            assert!(bridge.call("task", "update", Value::Null).is_ok());
+        }
+        let err = bridge.call("task", "update", Value::Null).unwrap_err();
+        let value = error_value(&err);
+        assert_eq!(value["code"], "rate_limited");
+        assert_eq!(value["api"], "task");
+        assert!(value["retry_after_ms"].as_u64().unwrap() > 0);
+        assert_eq!(bridge.rate_limiter().calls(), 5);
+        assert_eq!(bridge.rate_limiter().rejected(), 1);
+    }
+}
@@ -0,0 +1,332 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use crate::plugin::error::PluginError;
+use crate::plugin::{MethodAccess, PluginApi, PluginMessage};
+
+/// 每个插件消息队列的容量，队列满时新消息进入死信记录
+pub const BUS_QUEUE_CAPACITY: usize = 32;
+
+/// 死信记录保留的条数
+const DEAD_LETTER_CAPACITY: usize = 100;
+
+/// 消息无法投递的原因
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum DeadLetterReason {
+    /// 目标插件未加载或没有在运行
+    UnknownTarget,
+    /// 目标插件的消息队列已满
+    QueueFull,
+    /// 广播消息没有任何接收者
+    NoSubscribers,
+    /// 插件的 `on_message` 处理失败
+    DeliveryFailed { error: String },
+}
+
+/// 无法投递的消息
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLetter {
+    pub message: PluginMessage,
+    /// 原本要投递给的插件，广播没有接收者时为空
+    pub recipient: Option<String>,
+    #[serde(flatten)]
+    pub reason: DeadLetterReason,
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+}
+
+/// 插件之间的消息总线
+///
+/// - `target = Some(id)`：点对点投递给指定插件
+/// - `target = None`：广播给除发送者以外的所有插件；订阅过 `message_type` 模式的插件
+///   只接收匹配的广播，没有订阅的插件接收全部广播
+///
+/// 每个运行中的插件有一个有界队列，由管理器按顺序取出并调用插件的 `on_message`。
+#[derive(Default)]
+pub struct MessageBus {
+    queues: Mutex<HashMap<String, mpsc::Sender<PluginMessage>>>,
+    subscriptions: Mutex<HashMap<String, Vec<String>>>,
+    dead_letters: Mutex<VecDeque<DeadLetter>>,
+}
+
+impl MessageBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 为插件创建消息队列，返回的接收端由调用方负责消费
+    pub fn attach(&self, plugin_id: &str) -> mpsc::Receiver<PluginMessage> {
+        let (tx, rx) = mpsc::channel(BUS_QUEUE_CAPACITY);
+        self.queues.lock().unwrap().insert(plugin_id.to_string(), tx);
+        rx
+    }
+
+    /// 移除插件的消息队列和订阅，队列中尚未投递的消息会被丢弃
+    pub fn detach(&self, plugin_id: &str) {
+        self.queues.lock().unwrap().remove(plugin_id);
+        self.subscriptions.lock().unwrap().remove(plugin_id);
+    }
+
+    /// 订阅匹配 `pattern` 的广播消息，`*` 匹配任意字符，例如 `task.*`
+    pub fn subscribe(&self, plugin_id: &str, pattern: &str) {
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        let patterns = subscriptions.entry(plugin_id.to_string()).or_default();
+        if !patterns.iter().any(|p| p == pattern) {
+            patterns.push(pattern.to_string());
+        }
+    }
+
+    pub fn unsubscribe(&self, plugin_id: &str, pattern: &str) {
+        if let Some(patterns) = self.subscriptions.lock().unwrap().get_mut(plugin_id) {
+            patterns.retain(|p| p != pattern);
+        }
+    }
+
+    pub fn subscriptions(&self, plugin_id: &str) -> Vec<String> {
+        self.subscriptions
+            .lock()
+            .unwrap()
+            .get(plugin_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// 整体替换插件的订阅，重新加载插件时用于清空和恢复订阅
+    pub fn set_subscriptions(&self, plugin_id: &str, patterns: Vec<String>) {
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        if patterns.is_empty() {
+            subscriptions.remove(plugin_id);
+        } else {
+            subscriptions.insert(plugin_id.to_string(), patterns);
+        }
+    }
+
+    /// 发布消息，返回进入队列的接收者数量
+    ///
+    /// 点对点消息无法投递时返回错误；所有无法投递的消息都会记入死信。
+    pub fn publish(&self, message: PluginMessage) -> Result<usize> {
+        match message.target.clone() {
+            Some(target) => {
+                let sender = self.queues.lock().unwrap().get(&target).cloned();
+                let Some(sender) = sender else {
+                    self.dead_letter(message, Some(target.clone()), DeadLetterReason::UnknownTarget);
+                    return Err(PluginError::NotLoaded { plugin_id: target }.into());
+                };
+                self.enqueue(&sender, &target, message)?;
+                Ok(1)
+            }
+            None => {
+                let recipients: Vec<_> = {
+                    let queues = self.queues.lock().unwrap();
+                    let subscriptions = self.subscriptions.lock().unwrap();
+                    let mut recipients: Vec<_> = queues
+                        .iter()
+                        .filter(|(id, _)| **id != message.source)
+                        .filter(|(id, _)| match subscriptions.get(*id) {
+                            Some(patterns) if !patterns.is_empty() => patterns
+                                .iter()
+                                .any(|p| matches_pattern(p, &message.message_type)),
+                            _ => true,
+                        })
+                        .map(|(id, tx)| (id.clone(), tx.clone()))
+                        .collect();
+                    recipients.sort_by(|a, b| a.0.cmp(&b.0));
+                    recipients
+                };
+                if recipients.is_empty() {
+                    self.dead_letter(message, None, DeadLetterReason::NoSubscribers);
+                    return Ok(0);
+                }
+
+                let mut delivered = 0;
+                for (id, sender) in recipients {
+                    if self.enqueue(&sender, &id, message.clone()).is_ok() {
+                        delivered += 1;
+                    }
+                }
+                Ok(delivered)
+            }
+        }
+    }
+
+    fn enqueue(&self, sender: &mpsc::Sender<PluginMessage>, recipient: &str, message: PluginMessage) -> Result<()> {
+        match sender.try_send(message) {
+            Ok(()) => Ok(()),
+            Err(mpsc::error::TrySendError::Full(message)) => {
+                self.dead_letter(message, Some(recipient.to_string()), DeadLetterReason::QueueFull);
+                Err(PluginError::QueueFull { plugin_id: recipient.to_string() }.into())
+            }
+            Err(mpsc::error::TrySendError::Closed(message)) => {
+                self.dead_letter(message, Some(recipient.to_string()), DeadLetterReason::UnknownTarget);
+                Err(PluginError::NotLoaded { plugin_id: recipient.to_string() }.into())
+            }
+        }
+    }
+
+    /// 记录一条无法投递的消息
+    pub fn dead_letter(&self, message: PluginMessage, recipient: Option<String>, reason: DeadLetterReason) {
+        eprintln!(
+            "[MessageBus] 消息 {} 来自 {} 无法投递: {:?}",
+            message.message_type, message.source, reason
+        );
+        let mut dead_letters = self.dead_letters.lock().unwrap();
+        if dead_letters.len() == DEAD_LETTER_CAPACITY {
+            dead_letters.pop_front();
+        }
+        dead_letters.push_back(DeadLetter {
+            message,
+            recipient,
+            reason,
+            timestamp: chrono::Utc::now(),
+        });
+    }
+
+    /// 最近的死信记录，按时间顺序排列
+    pub fn dead_letters(&self) -> Vec<DeadLetter> {
+        self.dead_letters.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+/// 简单的通配匹配，`*` 匹配任意长度的字符
+pub fn matches_pattern(pattern: &str, value: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = value.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<_> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // 模式中没有 *
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+/// 插件使用的消息总线 API，发送者固定为所属插件
+pub struct BusApi {
+    plugin_id: String,
+    bus: Arc<MessageBus>,
+}
+
+impl BusApi {
+    pub fn new(plugin_id: &str, bus: Arc<MessageBus>) -> Self {
+        Self {
+            plugin_id: plugin_id.to_string(),
+            bus,
+        }
+    }
+}
+
+impl PluginApi for BusApi {
+    fn call(&self, method: &str, params: serde_json::Value) -> Result<serde_json::Value> {
+        match method {
+            "publish" => {
+                let message_type = params["message_type"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("bus.publish requires a message_type"))?;
+                let message = PluginMessage {
+                    source: self.plugin_id.clone(),
+                    target: params["target"].as_str().map(str::to_string),
+                    message_type: message_type.to_string(),
+                    payload: params["payload"].clone(),
+                    timestamp: chrono::Utc::now(),
+                };
+                let delivered = self.bus.publish(message)?;
+                Ok(serde_json::json!({ "delivered": delivered }))
+            }
+            "subscribe" | "unsubscribe" => {
+                let pattern = params["pattern"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("bus.{} requires a pattern", method))?;
+                if method == "subscribe" {
+                    self.bus.subscribe(&self.plugin_id, pattern);
+                } else {
+                    self.bus.unsubscribe(&self.plugin_id, pattern);
+                }
+                Ok(serde_json::Value::Null)
+            }
+            _ => Err(anyhow::anyhow!("Unknown bus method: {}", method)),
+        }
+    }
+
+    fn get_permissions(&self) -> Vec<String> {
+        vec![]
+    }
+
+    fn method_access(&self, method: &str) -> Option<MethodAccess> {
+        match method {
+            "publish" | "subscribe" | "unsubscribe" => Some(MethodAccess::Public),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(source: &str, target: Option<&str>, message_type: &str) -> PluginMessage {
+        PluginMessage {
+            source: source.to_string(),
+            target: target.map(str::to_string),
+            message_type: message_type.to_string(),
+            payload: serde_json::Value::Null,
+            timestamp: chrono::Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_matches_pattern() {
+        assert!(matches_pattern("task.created", "task.created"));
+        assert!(matches_pattern("task.*", "task.created"));
+        assert!(matches_pattern("*", "anything"));
+        assert!(matches_pattern("*.created", "task.created"));
+        assert!(matches_pattern("a*c*e", "abcde"));
+        assert!(!matches_pattern("task.*", "note.created"));
+        assert!(!matches_pattern("task", "task.created"));
+    }
+
+    #[test]
+    fn test_routing_and_dead_letters() {
+        let bus = MessageBus::new();
+        let mut a = bus.attach("a");
+        let mut b = bus.attach("b");
+        let mut c = bus.attach("c");
+        bus.subscribe("b", "task.*");
+
+        // 点对点
+        assert_eq!(bus.publish(message("a", Some("c"), "ping")).unwrap(), 1);
+        assert_eq!(c.try_recv().unwrap().message_type, "ping");
+
+        // 广播：b 只接收匹配的消息，发送者自己不接收
+        assert_eq!(bus.publish(message("a", None, "task.created")).unwrap(), 2);
+        assert_eq!(bus.publish(message("a", None, "note.created")).unwrap(), 1);
+        assert_eq!(b.try_recv().unwrap().message_type, "task.created");
+        assert!(b.try_recv().is_err());
+        assert!(a.try_recv().is_err());
+        assert_eq!(c.try_recv().unwrap().message_type, "task.created");
+        assert_eq!(c.try_recv().unwrap().message_type, "note.created");
+
+        // 未知目标进入死信
+        assert!(bus.publish(message("a", Some("missing"), "ping")).is_err());
+
+        // 队列满
+        for _ in 0..BUS_QUEUE_CAPACITY {
+            bus.publish(message("a", Some("c"), "flood")).unwrap();
+        }
+        let err = bus.publish(message("a", Some("c"), "flood")).unwrap_err();
+        assert!(matches!(err.downcast_ref::<PluginError>(), Some(PluginError::QueueFull { .. })));
+
+        let reasons: Vec<_> = bus.dead_letters().into_iter().map(|d| d.reason).collect();
+        assert_eq!(reasons, vec![DeadLetterReason::UnknownTarget, DeadLetterReason::QueueFull]);
+    }
+}
@@ -0,0 +1,340 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::broadcast;
+
+use crate::plugin::config::ConfigSchema;
+use crate::plugin::error::PluginError;
+use crate::plugin::lifecycle::PluginEvent;
+use crate::plugin::{MethodAccess, PluginApi};
+
+/// 插件提供给前端命令面板的命令
+///
+/// 在清单的 `commands` 中声明，或者由插件运行时通过 `api.commands("register", ...)` 注册：
+///
+/// ```json
+/// {
+///   "id": "sync-now",
+///   "title": "立即同步",
+///   "function": "sync_now",
+///   "arguments": { "properties": { "full": { "type": "boolean", "default": false } } },
+///   "keybinding": "Ctrl+Shift+S"
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CommandSpec {
+    pub id: String,
+    pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// 参数格式，与插件配置项使用相同的描述方式
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arguments: Option<ConfigSchema>,
+    /// 快捷键提示，仅用于展示，由前端决定是否绑定
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keybinding: Option<String>,
+    /// 执行命令时调用的插件函数，缺省时与 `id` 相同
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub function: Option<String>,
+}
+
+impl CommandSpec {
+    pub fn handler(&self) -> &str {
+        self.function.as_deref().unwrap_or(&self.id)
+    }
+
+    /// 校验参数并补充默认值，没有声明参数格式时原样返回
+    pub fn resolve_arguments(&self, plugin_id: &str, args: Value) -> Result<Value, PluginError> {
+        let Some(schema) = &self.arguments else {
+            return Ok(args);
+        };
+        let invalid = |field: String, reason: String| PluginError::InvalidArguments {
+            plugin_id: plugin_id.to_string(),
+            command_id: self.id.clone(),
+            field,
+            reason,
+        };
+        let values = match args {
+            Value::Null => serde_json::Map::new(),
+            Value::Object(values) => values,
+            other => return Err(invalid(String::new(), format!("expected an object, found {}", other))),
+        };
+        schema.validate(plugin_id, &values).map_err(|e| match e {
+            PluginError::InvalidConfig { field, reason, .. } => invalid(field, reason),
+            other => other,
+        })?;
+        Ok(Value::Object(schema.resolve(&values)))
+    }
+}
+
+/// 命令是在清单中声明的还是运行时注册的
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CommandSource {
+    Manifest,
+    Runtime,
+}
+
+/// 命令面板中的一项
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CommandInfo {
+    pub plugin_id: String,
+    #[serde(flatten)]
+    pub spec: CommandSpec,
+    pub source: CommandSource,
+    /// 插件正在运行，命令可以执行
+    pub enabled: bool,
+}
+
+/// 命令执行过程中插件上报的进度
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CommandProgress {
+    pub invocation_id: u64,
+    pub plugin_id: String,
+    pub command_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// 完成百分比，0 到 100
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub percent: Option<f64>,
+}
+
+pub type ProgressCallback = Arc<dyn Fn(&CommandProgress) + Send + Sync>;
+
+struct Invocation {
+    plugin_id: String,
+    command_id: String,
+    on_progress: Option<ProgressCallback>,
+}
+
+/// 运行时注册的命令以及正在执行的命令
+pub struct CommandRegistry {
+    registered: Mutex<HashMap<String, Vec<CommandSpec>>>,
+    invocations: Mutex<HashMap<u64, Invocation>>,
+    next_invocation: AtomicU64,
+    events: broadcast::Sender<PluginEvent>,
+}
+
+impl CommandRegistry {
+    pub fn new(events: broadcast::Sender<PluginEvent>) -> Self {
+        Self {
+            registered: Mutex::new(HashMap::new()),
+            invocations: Mutex::new(HashMap::new()),
+            next_invocation: AtomicU64::new(1),
+            events,
+        }
+    }
+
+    /// 插件运行时注册的命令，按注册顺序排列
+    pub fn registered(&self, plugin_id: &str) -> Vec<CommandSpec> {
+        self.registered.lock().unwrap().get(plugin_id).cloned().unwrap_or_default()
+    }
+
+    pub fn set_registered(&self, plugin_id: &str, commands: Vec<CommandSpec>) {
+        let mut registered = self.registered.lock().unwrap();
+        if commands.is_empty() {
+            registered.remove(plugin_id);
+        } else {
+            registered.insert(plugin_id.to_string(), commands);
+        }
+    }
+
+    /// 注册或替换运行时命令，不能与清单中声明的命令重名
+    pub fn register(&self, plugin_id: &str, spec: CommandSpec, declared: &[String]) -> Result<(), PluginError> {
+        if declared.contains(&spec.id) {
+            return Err(PluginError::DuplicateCommand {
+                plugin_id: plugin_id.to_string(),
+                command_id: spec.id,
+            });
+        }
+        let mut registered = self.registered.lock().unwrap();
+        let commands = registered.entry(plugin_id.to_string()).or_default();
+        match commands.iter_mut().find(|command| command.id == spec.id) {
+            Some(existing) => *existing = spec,
+            None => commands.push(spec),
+        }
+        Ok(())
+    }
+
+    pub fn unregister(&self, plugin_id: &str, command_id: &str) -> bool {
+        let mut registered = self.registered.lock().unwrap();
+        let Some(commands) = registered.get_mut(plugin_id) else {
+            return false;
+        };
+        let before = commands.len();
+        commands.retain(|command| command.id != command_id);
+        before != commands.len()
+    }
+
+    pub fn clear(&self, plugin_id: &str) {
+        self.registered.lock().unwrap().remove(plugin_id);
+    }
+
+    /// 开始一次命令执行，返回传给插件的执行 ID
+    pub fn begin(&self, plugin_id: &str, command_id: &str, on_progress: Option<ProgressCallback>) -> u64 {
+        let id = self.next_invocation.fetch_add(1, Ordering::Relaxed);
+        self.invocations.lock().unwrap().insert(
+            id,
+            Invocation {
+                plugin_id: plugin_id.to_string(),
+                command_id: command_id.to_string(),
+                on_progress,
+            },
+        );
+        id
+    }
+
+    pub fn finish(&self, invocation_id: u64) {
+        self.invocations.lock().unwrap().remove(&invocation_id);
+    }
+
+    /// 转发插件上报的进度，插件只能上报自己正在执行的命令
+    pub fn progress(
+        &self,
+        plugin_id: &str,
+        invocation_id: u64,
+        message: Option<String>,
+        percent: Option<f64>,
+    ) -> Result<()> {
+        let (progress, on_progress) = {
+            let invocations = self.invocations.lock().unwrap();
+            let invocation = invocations
+                .get(&invocation_id)
+                .filter(|invocation| invocation.plugin_id == plugin_id)
+                .ok_or_else(|| anyhow::anyhow!("No running command invocation {}", invocation_id))?;
+            let progress = CommandProgress {
+                invocation_id,
+                plugin_id: plugin_id.to_string(),
+                command_id: invocation.command_id.clone(),
+                message,
+                percent: percent.map(|p| p.clamp(0.0, 100.0)),
+            };
+            (progress, invocation.on_progress.clone())
+        };
+        if let Some(on_progress) = on_progress {
+            on_progress(&progress);
+        }
+        let _ = self.events.send(PluginEvent::CommandProgress(progress));
+        Ok(())
+    }
+}
+
+/// 插件注册命令和上报进度的 API
+pub struct CommandsApi {
+    plugin_id: String,
+    declared: Vec<String>,
+    registry: Arc<CommandRegistry>,
+}
+
+impl CommandsApi {
+    /// `declared` 是清单中声明的命令 ID
+    pub fn new(plugin_id: &str, declared: Vec<String>, registry: Arc<CommandRegistry>) -> Self {
+        Self {
+            plugin_id: plugin_id.to_string(),
+            declared,
+            registry,
+        }
+    }
+}
+
+impl PluginApi for CommandsApi {
+    fn call(&self, method: &str, params: Value) -> Result<Value> {
+        match method {
+            "register" => {
+                let spec: CommandSpec = serde_json::from_value(params)
+                    .map_err(|e| anyhow::anyhow!("commands.register: invalid command: {}", e))?;
+                if spec.id.is_empty() {
+                    return Err(anyhow::anyhow!("commands.register requires an id"));
+                }
+                self.registry.register(&self.plugin_id, spec, &self.declared)?;
+                Ok(Value::Null)
+            }
+            "unregister" => {
+                let id = params["id"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("commands.unregister requires an id"))?;
+                Ok(Value::Bool(self.registry.unregister(&self.plugin_id, id)))
+            }
+            "progress" => {
+                let invocation_id = params["invocation_id"]
+                    .as_u64()
+                    .ok_or_else(|| anyhow::anyhow!("commands.progress requires an invocation_id"))?;
+                let message = params["message"].as_str().map(str::to_string);
+                self.registry
+                    .progress(&self.plugin_id, invocation_id, message, params["percent"].as_f64())?;
+                Ok(Value::Null)
+            }
+            _ => Err(anyhow::anyhow!("Unknown commands method: {}", method)),
+        }
+    }
+
+    fn get_permissions(&self) -> Vec<String> {
+        vec![]
+    }
+
+    fn method_access(&self, method: &str) -> Option<MethodAccess> {
+        match method {
+            "register" | "unregister" | "progress" => Some(MethodAccess::Public),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_register_and_progress() {
+        let (events, mut rx) = broadcast::channel(8);
+        let registry = Arc::new(CommandRegistry::new(events));
+        let api = CommandsApi::new("p", vec!["sync".to_string()], Arc::clone(&registry));
+
+        api.call("register", json!({ "id": "export", "title": "Export" })).unwrap();
+        api.call("register", json!({ "id": "export", "title": "Export all" })).unwrap();
+        assert_eq!(registry.registered("p").len(), 1);
+        assert_eq!(registry.registered("p")[0].title, "Export all");
+        let err = api.call("register", json!({ "id": "sync", "title": "Sync" })).unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(PluginError::DuplicateCommand { .. })));
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&seen);
+        let id = registry.begin("p", "export", Some(Arc::new(move |p: &CommandProgress| {
+            sink.lock().unwrap().push(p.percent);
+        })));
+        api.call("progress", json!({ "invocation_id": id, "percent": 150 })).unwrap();
+        assert_eq!(*seen.lock().unwrap(), vec![Some(100.0)]);
+        assert!(matches!(rx.try_recv(), Ok(PluginEvent::CommandProgress(p)) if p.command_id == "export"));
+
+        // 其他插件不能为该次执行上报进度，执行结束后也不能再上报
+        let other = CommandsApi::new("q", vec![], Arc::clone(&registry));
+        assert!(other.call("progress", json!({ "invocation_id": id })).is_err());
+        registry.finish(id);
+        assert!(api.call("progress", json!({ "invocation_id": id })).is_err());
+
+        assert_eq!(api.call("unregister", json!({ "id": "export" })).unwrap(), json!(true));
+        assert!(registry.registered("p").is_empty());
+    }
+
+    #[test]
+    fn test_resolve_arguments() {
+        let spec: CommandSpec = serde_json::from_value(json!({
+            "id": "sync",
+            "title": "Sync",
+            "arguments": { "properties": { "full": { "type": "boolean", "default": false } } }
+        }))
+        .unwrap();
+        assert_eq!(spec.handler(), "sync");
+        assert_eq!(spec.resolve_arguments("p", Value::Null).unwrap(), json!({ "full": false }));
+        match spec.resolve_arguments("p", json!({ "full": 1 })) {
+            Err(PluginError::InvalidArguments { field, .. }) => assert_eq!(field, "full"),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(spec.resolve_arguments("p", json!([1])).is_err());
+    }
+}
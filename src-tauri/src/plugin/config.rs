@@ -0,0 +1,313 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::plugin::error::PluginError;
+use crate::plugin::package::is_valid_name;
+use crate::plugin::{MethodAccess, PluginApi};
+
+/// 没有切换用户时使用的配置档
+pub const DEFAULT_USER: &str = "default";
+
+/// 插件数据目录下保存配置的子目录，每个用户一个 `<user>.json`
+const CONFIG_DIR: &str = "config";
+
+/// 插件在清单 `configuration` 中声明的配置项，格式参照 JSON Schema 的子集
+///
+/// ```json
+/// {
+///   "properties": {
+///     "api_key": { "type": "string", "title": "API Key", "secret": true },
+///     "list_id": { "type": "integer", "minimum": 1, "default": 1 }
+///   },
+///   "required": ["api_key"]
+/// }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ConfigSchema {
+    #[serde(default)]
+    pub properties: BTreeMap<String, ConfigProperty>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub required: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigProperty {
+    #[serde(rename = "type")]
+    pub kind: ConfigType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<Value>,
+    /// 允许的取值
+    #[serde(rename = "enum", default, skip_serializing_if = "Option::is_none")]
+    pub allowed: Option<Vec<Value>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub minimum: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub maximum: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_length: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_length: Option<usize>,
+    /// 数组元素的类型
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub items: Option<Box<ConfigProperty>>,
+    /// 敏感信息（例如 API Key），设置界面应按密码输入框展示
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub secret: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigType {
+    String,
+    Number,
+    Integer,
+    Boolean,
+    Array,
+}
+
+impl ConfigSchema {
+    /// 校验用户提交的完整配置，值为 `null` 表示未设置
+    pub fn validate(&self, plugin_id: &str, values: &Map<String, Value>) -> Result<(), PluginError> {
+        let error = |field: &str, reason: String| PluginError::InvalidConfig {
+            plugin_id: plugin_id.to_string(),
+            field: field.to_string(),
+            reason,
+        };
+
+        for (key, value) in values {
+            let property = self
+                .properties
+                .get(key)
+                .ok_or_else(|| error(key, "not declared in the configuration schema".to_string()))?;
+            if !value.is_null() {
+                property.check(value).map_err(|reason| error(key, reason))?;
+            }
+        }
+        for key in &self.required {
+            let missing = values.get(key).is_none_or(Value::is_null);
+            let has_default = self.properties.get(key).is_some_and(|p| p.default.is_some());
+            if missing && !has_default {
+                return Err(error(key, "is required".to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    /// 在已保存的配置上补充默认值
+    pub fn resolve(&self, values: &Map<String, Value>) -> Map<String, Value> {
+        let mut resolved = Map::new();
+        for (key, property) in &self.properties {
+            if let Some(value) = values.get(key).filter(|v| !v.is_null()).or(property.default.as_ref()) {
+                resolved.insert(key.clone(), value.clone());
+            }
+        }
+        resolved
+    }
+}
+
+impl ConfigProperty {
+    fn check(&self, value: &Value) -> Result<(), String> {
+        match (self.kind, value) {
+            (ConfigType::String, Value::String(s)) => {
+                let len = s.chars().count();
+                if self.min_length.is_some_and(|min| len < min) || self.max_length.is_some_and(|max| len > max) {
+                    return Err(format!("length {} is out of range", len));
+                }
+            }
+            (ConfigType::Integer, Value::Number(n)) if n.is_i64() || n.is_u64() => self.check_range(n.as_f64())?,
+            (ConfigType::Number, Value::Number(n)) => self.check_range(n.as_f64())?,
+            (ConfigType::Boolean, Value::Bool(_)) => {}
+            (ConfigType::Array, Value::Array(items)) => {
+                if self.min_length.is_some_and(|min| items.len() < min)
+                    || self.max_length.is_some_and(|max| items.len() > max)
+                {
+                    return Err(format!("{} items is out of range", items.len()));
+                }
+                if let Some(item) = &self.items {
+                    for (i, value) in items.iter().enumerate() {
+                        item.check(value).map_err(|reason| format!("item {}: {}", i, reason))?;
+                    }
+                }
+            }
+            _ => return Err(format!("expected {:?}, found {}", self.kind, value)),
+        }
+        if let Some(allowed) = &self.allowed {
+            if !allowed.contains(value) {
+                return Err(format!("{} is not one of the allowed values", value));
+            }
+        }
+        Ok(())
+    }
+
+    fn check_range(&self, value: Option<f64>) -> Result<(), String> {
+        let value = value.unwrap_or(f64::NAN);
+        if self.minimum.is_some_and(|min| value < min) || self.maximum.is_some_and(|max| value > max) {
+            return Err(format!("{} is out of range", value));
+        }
+        Ok(())
+    }
+}
+
+/// 按用户保存插件配置，文件位于插件数据目录下，卸载插件并清除数据时一并删除
+#[derive(Debug, Clone)]
+pub struct ConfigStore {
+    dir: PathBuf,
+}
+
+impl ConfigStore {
+    pub fn new(plugin_data_dir: &Path) -> Self {
+        Self { dir: plugin_data_dir.join(CONFIG_DIR) }
+    }
+
+    /// 读取用户保存的配置，尚未保存过时返回空表
+    pub fn load(&self, user: &str) -> Result<Map<String, Value>> {
+        let path = self.path(user)?;
+        if !path.is_file() {
+            return Ok(Map::new());
+        }
+        let content = fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+        serde_json::from_str(&content).with_context(|| format!("Invalid plugin configuration {}", path.display()))
+    }
+
+    /// 保存用户的配置，先写入临时文件再替换，`null` 值不会保存
+    pub fn save(&self, user: &str, values: &Map<String, Value>) -> Result<()> {
+        let path = self.path(user)?;
+        fs::create_dir_all(&self.dir).with_context(|| format!("Failed to create {}", self.dir.display()))?;
+        let values: Map<String, Value> = values
+            .iter()
+            .filter(|(_, value)| !value.is_null())
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        let temp = path.with_extension("json.tmp");
+        fs::write(&temp, serde_json::to_string_pretty(&values)?)?;
+        fs::rename(&temp, &path).with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(())
+    }
+
+    fn path(&self, user: &str) -> Result<PathBuf> {
+        if !is_valid_name(user) {
+            return Err(anyhow::anyhow!("Invalid user name '{}'", user));
+        }
+        Ok(self.dir.join(format!("{}.json", user)))
+    }
+}
+
+/// 插件读取自身配置的 API，读取的是当前用户的配置，缺省值按清单补齐
+pub struct ConfigApi {
+    schema: ConfigSchema,
+    store: ConfigStore,
+    user: Arc<Mutex<String>>,
+}
+
+impl ConfigApi {
+    pub fn new(schema: ConfigSchema, store: ConfigStore, user: Arc<Mutex<String>>) -> Self {
+        Self { schema, store, user }
+    }
+}
+
+impl PluginApi for ConfigApi {
+    fn call(&self, method: &str, params: Value) -> Result<Value> {
+        match method {
+            "get" => {
+                let user = self.user.lock().unwrap().clone();
+                let config = self.schema.resolve(&self.store.load(&user)?);
+                match params.get("key").and_then(Value::as_str) {
+                    Some(key) if !self.schema.properties.contains_key(key) => {
+                        Err(anyhow::anyhow!("Configuration key '{}' is not declared", key))
+                    }
+                    Some(key) => Ok(config.get(key).cloned().unwrap_or(Value::Null)),
+                    None => Ok(Value::Object(config)),
+                }
+            }
+            "schema" => Ok(serde_json::to_value(&self.schema)?),
+            _ => Err(anyhow::anyhow!("Unknown config method: {}", method)),
+        }
+    }
+
+    fn get_permissions(&self) -> Vec<String> {
+        vec![]
+    }
+
+    fn method_access(&self, method: &str) -> Option<MethodAccess> {
+        match method {
+            "get" | "schema" => Some(MethodAccess::Public),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn schema() -> ConfigSchema {
+        serde_json::from_value(json!({
+            "properties": {
+                "api_key": { "type": "string", "minLength": 4, "secret": true },
+                "list_id": { "type": "integer", "minimum": 1, "default": 1 },
+                "mode": { "type": "string", "enum": ["fast", "safe"] },
+                "tags": { "type": "array", "items": { "type": "string" } }
+            },
+            "required": ["api_key", "list_id"]
+        }))
+        .unwrap()
+    }
+
+    fn values(value: Value) -> Map<String, Value> {
+        value.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn test_validate_and_resolve() {
+        let schema = schema();
+        assert!(schema.validate("p", &values(json!({ "api_key": "abcd", "tags": ["a"] }))).is_ok());
+
+        for (input, field) in [
+            (json!({}), "api_key"),
+            (json!({ "api_key": "abc" }), "api_key"),
+            (json!({ "api_key": "abcd", "list_id": 1.5 }), "list_id"),
+            (json!({ "api_key": "abcd", "list_id": 0 }), "list_id"),
+            (json!({ "api_key": "abcd", "mode": "slow" }), "mode"),
+            (json!({ "api_key": "abcd", "tags": [1] }), "tags"),
+            (json!({ "api_key": "abcd", "other": true }), "other"),
+        ] {
+            match schema.validate("p", &values(input.clone())) {
+                Err(PluginError::InvalidConfig { field: f, .. }) => assert_eq!(f, field, "{}", input),
+                other => panic!("{} should fail, got {:?}", input, other),
+            }
+        }
+
+        let resolved = schema.resolve(&values(json!({ "api_key": "abcd", "mode": null })));
+        assert_eq!(Value::Object(resolved), json!({ "api_key": "abcd", "list_id": 1 }));
+    }
+
+    #[test]
+    fn test_store_and_api() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ConfigStore::new(dir.path());
+        store.save("alice", &values(json!({ "api_key": "abcd", "mode": null }))).unwrap();
+        assert!(store.load("bob").unwrap().is_empty());
+        assert!(store.load("../alice").is_err());
+
+        let user = Arc::new(Mutex::new("alice".to_string()));
+        let api = ConfigApi::new(schema(), store, Arc::clone(&user));
+        assert_eq!(api.call("get", Value::Null).unwrap(), json!({ "api_key": "abcd", "list_id": 1 }));
+        assert_eq!(api.call("get", json!({ "key": "list_id" })).unwrap(), json!(1));
+        assert!(api.call("get", json!({ "key": "missing" })).is_err());
+
+        *user.lock().unwrap() = "bob".to_string();
+        assert_eq!(api.call("get", json!({ "key": "api_key" })).unwrap(), Value::Null);
+    }
+}
@@ -0,0 +1,231 @@
+//! 所有语言运行时共用的一致性测试
+//!
+//! 每种语言提供一份行为相同的插件脚本，通过 `PluginManager` 驱动，
+//! 断言生命周期、函数调用、API 绑定、权限、沙箱和执行预算的表现一致。
+
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use std::fs;
+use tempfile::tempdir;
+
+use crate::plugin::api::TaskApi;
+use crate::plugin::{PluginError, PluginManager, PluginMessage, PluginStatus, ResourceLimits, MANIFEST_FILE};
+
+const LUA_PLUGIN: &str = r#"
+local events = {}
+
+function init() events[#events + 1] = "init" end
+function start() events[#events + 1] = "start" end
+function on_message(message) events[#events + 1] = "message:" .. message.message_type end
+
+function events_log() return events end
+function add(a, b) return a + b end
+function list_tasks() return api.task("list", {}) end
+
+function create_task()
+    local ok, err = pcall(api.task, "create", { title = "x" })
+    return { ok = ok, code = err.code, permission = err.permission }
+end
+
+function host_globals()
+    local names = { "io", "dofile", "loadfile", "load", "debug", "process", "fs" }
+    local found = ""
+    for i = 1, #names do
+        if _ENV[names[i]] ~= nil then found = found .. names[i] .. "," end
+    end
+    return found
+end
+
+function spin() while true do end end
+"#;
+
+const TS_PLUGIN: &str = r#"
+declare const api: any;
+
+const events: string[] = [];
+
+export function init(): void { events.push("init"); }
+export function start(): void { events.push("start"); }
+export function on_message(message: { message_type: string }): void { events.push(`message:${message.message_type}`); }
+
+export function events_log(): string[] { return events; }
+export function add(a: number, b: number): number { return a + b; }
+export function list_tasks(): unknown { return api.task("list", {}); }
+
+export function create_task() {
+    try {
+        api.task("create", { title: "x" });
+        return { ok: true };
+    } catch (err: any) {
+        return { ok: false, code: err.code, permission: err.permission };
+    }
+}
+
+export function host_globals(): string {
+    let found = "";
+    for (const name of ["io", "dofile", "loadfile", "load", "debug", "process", "fs", "eval", "Function"]) {
+        if (typeof (globalThis as any)[name] !== "undefined") found += name + ",";
+    }
+    // 函数的 constructor 与 Function 一样可以把字符串编译成代码
+    for (const fn of [function () {}, async function () {}, function* () {}, async function* () {}]) {
+        try {
+            (fn as any).constructor("return 1");
+            found += "constructor,";
+        } catch (err) {}
+    }
+    return found;
+}
+
+export function spin(): void { while (true) {} }
+"#;
+
+/// (语言, 入口文件, 脚本)
+const PLUGINS: &[(&str, &str, &str)] = &[("lua", "main.lua", LUA_PLUGIN), ("typescript", "main.ts", TS_PLUGIN)];
+
+fn write_plugin(root: &Path, language: &str, main_file: &str, script: &str) {
+    let dir = root.join("conformance");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join(MANIFEST_FILE),
+        serde_json::json!({
+            "name": "conformance",
+            "version": "1.0.0",
+            "description": null,
+            "author": null,
+            "homepage_url": null,
+            "repository_url": null,
+            "license": null,
+            "language": language,
+            "main_file": main_file,
+            "permissions": ["task.read"],
+            "dependencies": null,
+        })
+        .to_string(),
+    )
+    .unwrap();
+    fs::write(dir.join(main_file), script).unwrap();
+}
+
+async fn load(language: &str, main_file: &str, script: &str) -> (PluginManager, tempfile::TempDir) {
+    let dir = tempdir().unwrap();
+    write_plugin(dir.path(), language, main_file, script);
+    let manager = PluginManager::with_plugin_dir(dir.path());
+    manager.register_api("task", Arc::new(TaskApi {}));
+    manager.load_plugin("conformance").await.unwrap();
+    (manager, dir)
+}
+
+#[tokio::test]
+async fn test_lifecycle_and_messages() {
+    for (language, main_file, script) in PLUGINS {
+        let (manager, _dir) = load(language, main_file, script).await;
+        manager
+            .send_message(PluginMessage {
+                source: "host".to_string(),
+                target: Some("conformance".to_string()),
+                message_type: "ping".to_string(),
+                payload: serde_json::json!({ "n": 1 }),
+                timestamp: chrono::Utc::now(),
+            })
+            .unwrap();
+
+        let expected = serde_json::json!(["init", "start", "message:ping"]);
+        let mut events = serde_json::Value::Null;
+        for _ in 0..100 {
+            events = manager.call_function("conformance", "events_log", vec![]).await.unwrap();
+            if events == expected {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(events, expected, "{}", language);
+    }
+}
+
+#[tokio::test]
+async fn test_call_function() {
+    for (language, main_file, script) in PLUGINS {
+        let (manager, _dir) = load(language, main_file, script).await;
+        let result = manager
+            .call_function("conformance", "add", vec![serde_json::json!(2), serde_json::json!(3)])
+            .await
+            .unwrap();
+        assert_eq!(result, serde_json::json!(5), "{}", language);
+        assert!(manager.call_function("conformance", "missing", vec![]).await.is_err(), "{}", language);
+    }
+}
+
+#[tokio::test]
+async fn test_api_bindings_and_permissions() {
+    for (language, main_file, script) in PLUGINS {
+        let (manager, _dir) = load(language, main_file, script).await;
+        assert!(
+            manager.call_function("conformance", "list_tasks", vec![]).await.is_ok(),
+            "{}",
+            language
+        );
+        let result = manager.call_function("conformance", "create_task", vec![]).await.unwrap();
+        assert_eq!(
+            result,
+            serde_json::json!({ "ok": false, "code": "permission_denied", "permission": "task.write" }),
+            "{}",
+            language
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_sandbox_hides_host_capabilities() {
+    for (language, main_file, script) in PLUGINS {
+        let (manager, _dir) = load(language, main_file, script).await;
+        let found = manager.call_function("conformance", "host_globals", vec![]).await.unwrap();
+        assert_eq!(found, serde_json::json!(""), "{}", language);
+    }
+}
+
+#[tokio::test]
+async fn test_runaway_call_is_interrupted() {
+    for (language, main_file, script) in PLUGINS {
+        let (manager, _dir) = load(language, main_file, script).await;
+        manager
+            .set_resource_limits(
+                "conformance",
+                ResourceLimits {
+                    max_cpu_time: Some(50),
+                    ..ResourceLimits::default()
+                },
+            )
+            .unwrap();
+
+        let err = manager.call_function("conformance", "spin", vec![]).await.unwrap_err();
+        assert!(
+            matches!(err.downcast_ref::<PluginError>(), Some(PluginError::CpuTimeExceeded { .. })),
+            "{}: {:#}",
+            language,
+            err
+        );
+        // 中断之后插件仍然可以继续调用
+        let result = manager
+            .call_function("conformance", "add", vec![serde_json::json!(1), serde_json::json!(1)])
+            .await
+            .unwrap();
+        assert_eq!(result, serde_json::json!(2), "{}", language);
+        assert_eq!(manager.resource_usage("conformance").unwrap().cpu_violations, 1);
+    }
+}
+
+#[tokio::test]
+async fn test_failing_init_marks_error() {
+    for (language, main_file, script) in [
+        ("lua", "main.lua", "function init() error('broken') end"),
+        ("typescript", "main.ts", "function init(): void { throw new Error('broken'); }"),
+    ] {
+        let dir = tempdir().unwrap();
+        write_plugin(dir.path(), language, main_file, script);
+        let manager = PluginManager::with_plugin_dir(dir.path());
+        let err = manager.load_plugin("conformance").await.unwrap_err();
+        assert!(format!("{:#}", err).contains("broken"), "{}", language);
+        assert_eq!(manager.plugin_status("conformance"), Some(PluginStatus::Error), "{}", language);
+    }
+}
@@ -0,0 +1,210 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::plugin::config::ConfigProperty;
+
+/// 插件在清单 `contributes` 中声明的界面扩展点
+///
+/// ```json
+/// "contributes": {
+///   "taskActions": [{ "id": "share", "title": "分享任务", "function": "share_task" }],
+///   "taskBadges": [{ "id": "sync-state", "function": "sync_badge" }],
+///   "taskFields": [{ "id": "estimate", "title": "预估工时", "type": "number", "minimum": 0 }],
+///   "dashboardWidgets": [{ "id": "burndown", "title": "燃尽图", "function": "burndown", "refreshInterval": 300 }]
+/// }
+/// ```
+///
+/// 每个扩展点的处理函数缺省时与 `id` 同名。
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Contributions {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub task_actions: Vec<TaskAction>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub task_badges: Vec<TaskBadge>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub task_fields: Vec<TaskField>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dashboard_widgets: Vec<DashboardWidget>,
+}
+
+/// 任务右键菜单中的操作，处理函数的参数为 `(task)`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaskAction {
+    pub id: String,
+    pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub function: Option<String>,
+}
+
+/// 任务行上的徽标，处理函数的参数为 `(task)`，返回 `{ text, color }`，返回 `nil` 时不显示
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaskBadge {
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub function: Option<String>,
+}
+
+/// 任务编辑器中的自定义字段，字段类型和校验规则与插件配置项相同
+///
+/// 声明了 `function` 时，字段值变化后调用该函数，参数为 `(task, value)`。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaskField {
+    pub id: String,
+    #[serde(flatten)]
+    pub property: ConfigProperty,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub function: Option<String>,
+}
+
+/// 仪表盘小组件，处理函数无参数，返回组件展示的数据
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DashboardWidget {
+    pub id: String,
+    pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub function: Option<String>,
+    /// 自动刷新间隔（秒），缺省时只在打开仪表盘时加载
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_interval: Option<u64>,
+}
+
+/// 扩展点种类
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContributionPoint {
+    TaskAction,
+    TaskBadge,
+    TaskField,
+    DashboardWidget,
+}
+
+impl ContributionPoint {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContributionPoint::TaskAction => "task_action",
+            ContributionPoint::TaskBadge => "task_badge",
+            ContributionPoint::TaskField => "task_field",
+            ContributionPoint::DashboardWidget => "dashboard_widget",
+        }
+    }
+}
+
+impl Contributions {
+    pub fn is_empty(&self) -> bool {
+        self.task_actions.is_empty()
+            && self.task_badges.is_empty()
+            && self.task_fields.is_empty()
+            && self.dashboard_widgets.is_empty()
+    }
+
+    /// 扩展点对应的处理函数，扩展点不存在或没有处理函数（只声明而不响应变化的字段）时返回 `None`
+    pub fn handler(&self, point: ContributionPoint, id: &str) -> Option<Option<String>> {
+        let function_or_id = |function: &Option<String>, id: &str| {
+            function.clone().unwrap_or_else(|| id.to_string())
+        };
+        match point {
+            ContributionPoint::TaskAction => self
+                .task_actions
+                .iter()
+                .find(|c| c.id == id)
+                .map(|c| Some(function_or_id(&c.function, &c.id))),
+            ContributionPoint::TaskBadge => self
+                .task_badges
+                .iter()
+                .find(|c| c.id == id)
+                .map(|c| Some(function_or_id(&c.function, &c.id))),
+            ContributionPoint::TaskField => self.task_fields.iter().find(|c| c.id == id).map(|c| c.function.clone()),
+            ContributionPoint::DashboardWidget => self
+                .dashboard_widgets
+                .iter()
+                .find(|c| c.id == id)
+                .map(|c| Some(function_or_id(&c.function, &c.id))),
+        }
+    }
+
+    /// 调用处理函数时传入的参数
+    pub fn arguments(point: ContributionPoint, payload: Value) -> Vec<Value> {
+        match point {
+            ContributionPoint::DashboardWidget => vec![],
+            ContributionPoint::TaskField => vec![payload["task"].clone(), payload["value"].clone()],
+            ContributionPoint::TaskAction | ContributionPoint::TaskBadge => vec![payload],
+        }
+    }
+}
+
+/// 带有来源插件的扩展点
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Contributed<T> {
+    pub plugin_id: String,
+    #[serde(flatten)]
+    pub item: T,
+}
+
+/// 所有运行中插件的扩展点，按插件名称排序
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContributionSet {
+    pub task_actions: Vec<Contributed<TaskAction>>,
+    pub task_badges: Vec<Contributed<TaskBadge>>,
+    pub task_fields: Vec<Contributed<TaskField>>,
+    pub dashboard_widgets: Vec<Contributed<DashboardWidget>>,
+}
+
+impl ContributionSet {
+    pub fn add(&mut self, plugin_id: &str, contributions: &Contributions) {
+        fn tag<T: Clone>(plugin_id: &str, items: &[T]) -> Vec<Contributed<T>> {
+            items
+                .iter()
+                .map(|item| Contributed {
+                    plugin_id: plugin_id.to_string(),
+                    item: item.clone(),
+                })
+                .collect()
+        }
+        self.task_actions.extend(tag(plugin_id, &contributions.task_actions));
+        self.task_badges.extend(tag(plugin_id, &contributions.task_badges));
+        self.task_fields.extend(tag(plugin_id, &contributions.task_fields));
+        self.dashboard_widgets.extend(tag(plugin_id, &contributions.dashboard_widgets));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_and_aggregate() {
+        let contributions: Contributions = serde_json::from_value(json!({
+            "taskActions": [{ "id": "share", "title": "Share", "function": "share_task" }],
+            "taskFields": [{ "id": "estimate", "title": "Estimate", "type": "number", "minimum": 0 }],
+            "dashboardWidgets": [{ "id": "burndown", "title": "Burndown", "refreshInterval": 60 }]
+        }))
+        .unwrap();
+        assert_eq!(contributions.task_fields[0].property.minimum, Some(0.0));
+        assert_eq!(
+            contributions.handler(ContributionPoint::TaskAction, "share"),
+            Some(Some("share_task".to_string()))
+        );
+        assert_eq!(
+            contributions.handler(ContributionPoint::DashboardWidget, "burndown"),
+            Some(Some("burndown".to_string()))
+        );
+        assert_eq!(contributions.handler(ContributionPoint::TaskField, "estimate"), Some(None));
+        assert_eq!(contributions.handler(ContributionPoint::TaskBadge, "share"), None);
+
+        let mut set = ContributionSet::default();
+        set.add("p", &contributions);
+        let value = serde_json::to_value(&set).unwrap();
+        assert_eq!(
+            value["taskActions"][0],
+            json!({ "plugin_id": "p", "id": "share", "title": "Share", "function": "share_task" })
+        );
+        assert_eq!(value["taskFields"][0]["type"], "number");
+        assert_eq!(value["dashboardWidgets"][0]["refreshInterval"], 60);
+    }
+}
@@ -0,0 +1,262 @@
+use mlua::{Lua, LuaSerdeExt, Result as LuaResult, Table, Value as LuaValue};
+use serde_json::{Map, Number, Value};
+
+/// 转换时允许的最大嵌套层数，同时防止自引用的表导致无限递归
+const MAX_DEPTH: usize = 128;
+
+/// 整数键的表在缺少元素时按数组转换的最大稀疏程度，超过后报错
+const MAX_SPARSE_RATIO: usize = 2;
+
+/// 稀疏程度不受限制的数组长度
+const SAFE_SPARSE_LENGTH: usize = 10;
+
+/// 在插件环境中提供的 `json` 表
+///
+/// - `json.null`：在表中表示 JSON `null`，JSON 数组和对象中的 `null` 也会转换为它
+/// - `json.array(t)`：把表标记为数组，空表也会转换为 `[]`
+pub fn create_json_table(lua: &Lua) -> LuaResult<Table<'_>> {
+    let json = lua.create_table()?;
+    json.set("null", LuaValue::NULL)?;
+    json.set(
+        "array",
+        lua.create_function(|lua, table: Option<Table>| {
+            let table = match table {
+                Some(table) => table,
+                None => lua.create_table()?,
+            };
+            table.set_metatable(Some(lua.array_metatable()));
+            Ok(table)
+        })?,
+    )?;
+    Ok(json)
+}
+
+/// 把 JSON 值转换为 Lua 值
+///
+/// - 整数保持为 Lua 整数，小数保持为 Lua 浮点数；超出 `i64` 范围的整数转换为浮点数
+/// - 数组转换为带有数组标记的序列表，对象转换为普通表
+/// - 顶层的 `null` 转换为 `nil`，数组和对象中的 `null` 转换为 `json.null`，保证长度和键不丢失
+pub fn json_to_lua<'lua>(lua: &'lua Lua, value: &Value) -> LuaResult<LuaValue<'lua>> {
+    match value {
+        Value::Null => Ok(LuaValue::Nil),
+        value => to_lua(lua, value, 0),
+    }
+}
+
+fn to_lua<'lua>(lua: &'lua Lua, value: &Value, depth: usize) -> LuaResult<LuaValue<'lua>> {
+    if depth > MAX_DEPTH {
+        return Err(mlua::Error::runtime("JSON value is nested too deeply"));
+    }
+    Ok(match value {
+        Value::Null => LuaValue::NULL,
+        Value::Bool(b) => LuaValue::Boolean(*b),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => LuaValue::Integer(i),
+            None => LuaValue::Number(n.as_f64().unwrap_or(f64::NAN)),
+        },
+        Value::String(s) => LuaValue::String(lua.create_string(s)?),
+        Value::Array(items) => {
+            let table = lua.create_table_with_capacity(items.len(), 0)?;
+            for (i, item) in items.iter().enumerate() {
+                table.raw_seti(i + 1, to_lua(lua, item, depth + 1)?)?;
+            }
+            table.set_metatable(Some(lua.array_metatable()));
+            LuaValue::Table(table)
+        }
+        Value::Object(map) => {
+            let table = lua.create_table_with_capacity(0, map.len())?;
+            for (key, item) in map {
+                table.raw_set(key.as_str(), to_lua(lua, item, depth + 1)?)?;
+            }
+            LuaValue::Table(table)
+        }
+    })
+}
+
+/// 把 Lua 值转换为 JSON 值
+///
+/// 表按以下规则转换：
+/// - 带有数组标记（`json.array` 或来自 JSON 数组）的表转换为数组，空表为 `[]`
+/// - 只有正整数键的表转换为数组，缺少的元素为 `null`；过于稀疏的表会报错
+/// - 只有字符串键的表转换为对象，空表为 `{}`
+/// - 同时包含整数键和字符串键、或包含其他类型键的表会报错
+///
+/// `nil` 和 `json.null` 转换为 `null`。函数、线程、userdata 以及 NaN、无穷大无法表示为 JSON，会报错。
+pub fn lua_to_json(lua: &Lua, value: &LuaValue) -> LuaResult<Value> {
+    to_json(lua, value, 0)
+}
+
+fn to_json(lua: &Lua, value: &LuaValue, depth: usize) -> LuaResult<Value> {
+    if depth > MAX_DEPTH {
+        return Err(mlua::Error::runtime(
+            "table is nested too deeply or contains a reference to itself",
+        ));
+    }
+    match value {
+        LuaValue::Nil => Ok(Value::Null),
+        value if *value == LuaValue::NULL => Ok(Value::Null),
+        LuaValue::Boolean(b) => Ok(Value::Bool(*b)),
+        LuaValue::Integer(i) => Ok(Value::Number((*i).into())),
+        LuaValue::Number(n) => Number::from_f64(*n)
+            .map(Value::Number)
+            .ok_or_else(|| mlua::Error::runtime(format!("cannot convert {} to JSON", n))),
+        LuaValue::String(s) => Ok(Value::String(
+            s.to_str()
+                .map_err(|_| mlua::Error::runtime("cannot convert a string that is not valid UTF-8 to JSON"))?
+                .to_string(),
+        )),
+        LuaValue::Table(table) => table_to_json(lua, table, depth),
+        other => Err(mlua::Error::runtime(format!(
+            "cannot convert a Lua {} to JSON",
+            other.type_name()
+        ))),
+    }
+}
+
+fn table_to_json(lua: &Lua, table: &Table, depth: usize) -> LuaResult<Value> {
+    let mut indices: Vec<(usize, Value)> = Vec::new();
+    let mut fields = Map::new();
+
+    for pair in table.clone().pairs::<LuaValue, LuaValue>() {
+        let (key, value) = pair?;
+        let value = to_json(lua, &value, depth + 1)?;
+        match key {
+            LuaValue::Integer(i) if i >= 1 => indices.push((i as usize, value)),
+            LuaValue::String(s) => {
+                let key = s
+                    .to_str()
+                    .map_err(|_| mlua::Error::runtime("table key is not valid UTF-8"))?
+                    .to_string();
+                fields.insert(key, value);
+            }
+            key => {
+                return Err(mlua::Error::runtime(format!(
+                    "cannot convert a table with {} key {:?} to JSON",
+                    key.type_name(),
+                    key
+                )))
+            }
+        }
+    }
+
+    let is_array = table.get_metatable().is_some_and(|mt| mt == lua.array_metatable());
+    if !indices.is_empty() && !fields.is_empty() {
+        return Err(mlua::Error::runtime(
+            "cannot convert a table with both integer and string keys to JSON",
+        ));
+    }
+    if is_array && !fields.is_empty() {
+        return Err(mlua::Error::runtime("array table has non-integer keys"));
+    }
+    if indices.is_empty() && !is_array {
+        return Ok(Value::Object(fields));
+    }
+
+    let len = indices.iter().map(|(i, _)| *i).max().unwrap_or(0);
+    if len > SAFE_SPARSE_LENGTH && len > indices.len() * MAX_SPARSE_RATIO {
+        return Err(mlua::Error::runtime(format!(
+            "array is too sparse ({} elements, max index {}), use json.null for missing elements",
+            indices.len(),
+            len
+        )));
+    }
+    let mut items = vec![Value::Null; len];
+    for (i, value) in indices {
+        items[i - 1] = value;
+    }
+    Ok(Value::Array(items))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn round_trip(lua: &Lua, value: &Value) -> Value {
+        let lua_value = json_to_lua(lua, value).unwrap();
+        lua_to_json(lua, &lua_value).unwrap()
+    }
+
+    fn eval(lua: &Lua, code: &str) -> LuaResult<Value> {
+        lua.globals().set("json", create_json_table(lua)?)?;
+        let value: LuaValue = lua.load(code).eval()?;
+        lua_to_json(lua, &value)
+    }
+
+    #[test]
+    fn test_table_rules() {
+        let lua = Lua::new();
+        assert_eq!(eval(&lua, "return {}").unwrap(), serde_json::json!({}));
+        assert_eq!(eval(&lua, "return json.array()").unwrap(), serde_json::json!([]));
+        assert_eq!(eval(&lua, "return json.array({1, 2})").unwrap(), serde_json::json!([1, 2]));
+        assert_eq!(eval(&lua, "return {1, 2.5, 'a'}").unwrap(), serde_json::json!([1, 2.5, "a"]));
+        assert_eq!(eval(&lua, "return {1, nil, 3}").unwrap(), serde_json::json!([1, null, 3]));
+        assert_eq!(eval(&lua, "return {1, json.null}").unwrap(), serde_json::json!([1, null]));
+        assert_eq!(
+            eval(&lua, "return { a = json.null, b = { c = true } }").unwrap(),
+            serde_json::json!({ "a": null, "b": { "c": true } })
+        );
+        assert_eq!(eval(&lua, "return 3").unwrap(), serde_json::json!(3));
+        assert_eq!(eval(&lua, "return 3.0").unwrap(), serde_json::json!(3.0));
+
+        for code in [
+            "return { 1, a = 2 }",
+            "return { [100] = 1 }",
+            "return { [true] = 1 }",
+            "return { f = function() end }",
+            "return 0/0",
+            "local t = {}; t.self = t; return t",
+            "return setmetatable({ a = 1 }, getmetatable(json.array()))",
+        ] {
+            assert!(eval(&lua, code).is_err(), "{} should fail", code);
+        }
+    }
+
+    #[test]
+    fn test_json_to_lua() {
+        let lua = Lua::new();
+        lua.globals().set("json", create_json_table(&lua).unwrap()).unwrap();
+        let value = serde_json::json!({ "list": [1, null], "n": 1.5, "i": 7, "empty": [], "obj": {} });
+        lua.globals().set("value", json_to_lua(&lua, &value).unwrap()).unwrap();
+
+        let check: bool = lua
+            .load(
+                r#"
+                return #value.list == 2 and value.list[2] == json.null
+                    and math.type(value.i) == "integer" and math.type(value.n) == "float"
+                    and next(value.empty) == nil and next(value.obj) == nil
+                "#,
+            )
+            .eval()
+            .unwrap();
+        assert!(check);
+        assert_eq!(json_to_lua(&lua, &Value::Null).unwrap(), LuaValue::Nil);
+    }
+
+    fn arb_json() -> impl Strategy<Value = Value> {
+        let leaf = prop_oneof![
+            Just(Value::Null),
+            any::<bool>().prop_map(Value::Bool),
+            any::<i64>().prop_map(|i| Value::Number(i.into())),
+            any::<f64>()
+                .prop_filter_map("finite", Number::from_f64)
+                .prop_map(Value::Number),
+            ".*".prop_map(Value::String),
+        ];
+        leaf.prop_recursive(4, 64, 8, |inner| {
+            prop_oneof![
+                prop::collection::vec(inner.clone(), 0..8).prop_map(Value::Array),
+                prop::collection::hash_map(".*", inner, 0..8)
+                    .prop_map(|map| Value::Object(map.into_iter().collect())),
+            ]
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn prop_json_round_trip(value in arb_json()) {
+            let lua = Lua::new();
+            prop_assert_eq!(round_trip(&lua, &value), value);
+        }
+    }
+}
@@ -0,0 +1,253 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use semver::{Version, VersionReq};
+
+use crate::plugin::error::PluginError;
+use crate::plugin::PluginMetadata;
+
+/// 已加载依赖的信息，供依赖检查使用
+#[derive(Debug, Clone)]
+pub struct InstalledDependency {
+    pub version: String,
+    pub active: bool,
+}
+
+/// 按依赖关系排序的加载顺序
+#[derive(Debug, Default)]
+pub struct LoadOrder {
+    /// 依赖总是排在依赖它的插件之前
+    pub order: Vec<String>,
+    /// 处于依赖环中、无法排序的插件
+    pub errors: Vec<(String, PluginError)>,
+}
+
+pub fn parse_version(plugin_id: &str, version: &str) -> Result<Version, PluginError> {
+    Version::parse(version).map_err(|_| PluginError::InvalidVersion {
+        plugin_id: plugin_id.to_string(),
+        version: version.to_string(),
+    })
+}
+
+pub fn parse_requirement(plugin_id: &str, requirement: &str) -> Result<VersionReq, PluginError> {
+    VersionReq::parse(requirement).map_err(|_| PluginError::InvalidVersion {
+        plugin_id: plugin_id.to_string(),
+        version: requirement.to_string(),
+    })
+}
+
+/// 插件清单中声明的依赖，按名称排序
+fn dependencies(metadata: &PluginMetadata) -> BTreeMap<&str, &str> {
+    metadata
+        .dependencies
+        .iter()
+        .flatten()
+        .map(|(name, requirement)| (name.as_str(), requirement.as_str()))
+        .collect()
+}
+
+/// 检查插件的依赖是否都已加载、版本兼容并处于激活状态
+///
+/// `lookup` 根据插件名称返回已加载插件的信息。
+pub fn check_dependencies(
+    metadata: &PluginMetadata,
+    lookup: impl Fn(&str) -> Option<InstalledDependency>,
+) -> Result<(), PluginError> {
+    let plugin_id = &metadata.name;
+    for (dependency, requirement) in dependencies(metadata) {
+        let req = parse_requirement(plugin_id, requirement)?;
+        let installed = lookup(dependency).ok_or_else(|| PluginError::MissingDependency {
+            plugin_id: plugin_id.clone(),
+            dependency: dependency.to_string(),
+            requirement: requirement.to_string(),
+        })?;
+        let version = parse_version(dependency, &installed.version)?;
+        if !req.matches(&version) {
+            return Err(PluginError::IncompatibleDependency {
+                plugin_id: plugin_id.clone(),
+                dependency: dependency.to_string(),
+                requirement: requirement.to_string(),
+                found: installed.version,
+            });
+        }
+        if !installed.active {
+            return Err(PluginError::InactiveDependency {
+                plugin_id: plugin_id.clone(),
+                dependency: dependency.to_string(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// 计算插件的加载顺序
+///
+/// 只考虑 `plugins` 之间的依赖关系，缺失的依赖留到激活时由 [`check_dependencies`] 报告。
+/// 同一层级的插件按名称排序，保证顺序稳定。
+pub fn load_order(plugins: &[PluginMetadata]) -> LoadOrder {
+    let graph: BTreeMap<&str, Vec<&str>> = plugins
+        .iter()
+        .map(|p| {
+            let deps = dependencies(p)
+                .into_keys()
+                .filter(|dep| plugins.iter().any(|other| other.name == *dep))
+                .collect();
+            (p.name.as_str(), deps)
+        })
+        .collect();
+
+    #[derive(Clone, Copy, PartialEq)]
+    enum Mark {
+        Visiting,
+        Done,
+    }
+
+    fn visit<'a>(
+        node: &'a str,
+        graph: &BTreeMap<&'a str, Vec<&'a str>>,
+        marks: &mut HashMap<&'a str, Mark>,
+        stack: &mut Vec<&'a str>,
+        result: &mut LoadOrder,
+        in_cycle: &mut HashSet<&'a str>,
+    ) {
+        match marks.get(node) {
+            Some(Mark::Done) => return,
+            Some(Mark::Visiting) => {
+                // 从栈中取出环上的节点，例如 a -> b -> a
+                let start = stack.iter().position(|n| *n == node).unwrap_or(0);
+                let mut cycle: Vec<String> = stack[start..].iter().map(|n| n.to_string()).collect();
+                cycle.push(node.to_string());
+                for member in &stack[start..] {
+                    if in_cycle.insert(member) {
+                        result.errors.push((
+                            member.to_string(),
+                            PluginError::DependencyCycle { cycle: cycle.clone() },
+                        ));
+                    }
+                }
+                return;
+            }
+            None => {}
+        }
+
+        marks.insert(node, Mark::Visiting);
+        stack.push(node);
+        for dep in &graph[node] {
+            visit(dep, graph, marks, stack, result, in_cycle);
+        }
+        stack.pop();
+        marks.insert(node, Mark::Done);
+        if !in_cycle.contains(node) {
+            result.order.push(node.to_string());
+        }
+    }
+
+    let mut result = LoadOrder::default();
+    let mut marks = HashMap::new();
+    let mut in_cycle = HashSet::new();
+    for node in graph.keys() {
+        visit(node, &graph, &mut marks, &mut Vec::new(), &mut result, &mut in_cycle);
+    }
+    result
+}
+
+/// 直接或间接依赖 `name` 的插件，依赖链最深的排在最前面，可以按顺序停用
+pub fn dependents(plugins: &[PluginMetadata], name: &str) -> Vec<String> {
+    let mut found: Vec<String> = Vec::new();
+    let mut frontier = vec![name.to_string()];
+    while let Some(current) = frontier.pop() {
+        for plugin in plugins {
+            if dependencies(plugin).contains_key(current.as_str())
+                && plugin.name != name
+                && !found.contains(&plugin.name)
+            {
+                found.push(plugin.name.clone());
+                frontier.push(plugin.name.clone());
+            }
+        }
+    }
+
+    // 按加载顺序的逆序停用，保证依赖者先于其依赖停止
+    let order = load_order(plugins).order;
+    found.sort_by_key(|p| std::cmp::Reverse(order.iter().position(|o| o == p)));
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin::PluginLanguage;
+
+    fn plugin(name: &str, version: &str, deps: &[(&str, &str)]) -> PluginMetadata {
+        PluginMetadata {
+            name: name.to_string(),
+            version: version.to_string(),
+            description: None,
+            author: None,
+            homepage_url: None,
+            repository_url: None,
+            license: None,
+            language: PluginLanguage::Lua,
+            main_file: "main.lua".to_string(),
+            permissions: vec![],
+            dependencies: Some(deps.iter().map(|(n, r)| (n.to_string(), r.to_string())).collect()),
+            configuration: None,
+            commands: vec![],
+            contributes: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_load_order_and_cycles() {
+        let plugins = vec![
+            plugin("app", "1.0.0", &[("core", "^1"), ("ui", "^1")]),
+            plugin("core", "1.2.0", &[]),
+            plugin("ui", "1.0.0", &[("core", "^1")]),
+            plugin("x", "1.0.0", &[("y", "*")]),
+            plugin("y", "1.0.0", &[("x", "*")]),
+        ];
+        let result = load_order(&plugins);
+        assert_eq!(result.order, vec!["core", "ui", "app"]);
+
+        let cycle_members: Vec<_> = result.errors.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(cycle_members, vec!["x", "y"]);
+        assert_eq!(
+            result.errors[0].1,
+            PluginError::DependencyCycle {
+                cycle: vec!["x".to_string(), "y".to_string(), "x".to_string()],
+            }
+        );
+
+        assert_eq!(dependents(&plugins, "core"), vec!["app", "ui"]);
+        assert!(dependents(&plugins, "app").is_empty());
+    }
+
+    #[test]
+    fn test_check_dependencies() {
+        let app = plugin("app", "1.0.0", &[("core", "^1.1")]);
+        let lookup = |version: &'static str, active: bool| {
+            move |name: &str| {
+                (name == "core").then(|| InstalledDependency {
+                    version: version.to_string(),
+                    active,
+                })
+            }
+        };
+
+        assert!(check_dependencies(&app, lookup("1.2.0", true)).is_ok());
+        assert!(matches!(
+            check_dependencies(&app, lookup("1.0.5", true)),
+            Err(PluginError::IncompatibleDependency { found, .. }) if found == "1.0.5"
+        ));
+        assert!(matches!(
+            check_dependencies(&app, lookup("1.2.0", false)),
+            Err(PluginError::InactiveDependency { .. })
+        ));
+        assert!(matches!(
+            check_dependencies(&app, |_: &str| None),
+            Err(PluginError::MissingDependency { .. })
+        ));
+        assert!(matches!(
+            check_dependencies(&plugin("bad", "1.0.0", &[("core", "not a range")]), lookup("1.2.0", true)),
+            Err(PluginError::InvalidVersion { .. })
+        ));
+    }
+}
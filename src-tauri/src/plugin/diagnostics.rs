@@ -0,0 +1,345 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::broadcast;
+
+use crate::plugin::contribution::ContributionPoint;
+use crate::plugin::error::{source_location, PluginError};
+use crate::plugin::lifecycle::PluginEvent;
+use crate::plugin::{MethodAccess, PluginApi};
+
+/// 每个插件保留的最近错误条数
+pub const DEFAULT_ERROR_CAPACITY: usize = 100;
+
+/// 插件错误在 `logs` 表中的事件类型
+pub const PLUGIN_ERROR_EVENT: &str = "PLUGIN_ERROR";
+
+/// 错误发生的位置
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ErrorSource {
+    /// 创建运行时或检查依赖时失败
+    Load,
+    /// 生命周期钩子或宿主回调，例如 `init`、`on_config_changed`
+    Hook { name: String },
+    /// 宿主调用插件函数
+    Function { name: String },
+    /// 投递消息给 `on_message`
+    Message,
+    /// 执行插件命令
+    Command { id: String },
+    /// 定时器的处理函数
+    Timer { id: String },
+    /// 界面扩展点的处理函数
+    Contribution { point: ContributionPoint, id: String },
+    /// 插件调用的宿主 API 返回错误
+    Api { api: String, method: String },
+    /// 重新加载
+    Reload,
+}
+
+/// 一条插件错误记录
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PluginErrorRecord {
+    pub plugin_id: String,
+    pub source: ErrorSource,
+    /// 能识别为 [`PluginError`] 时为其 `code`，例如 `permission_denied`
+    pub code: Option<String>,
+    pub message: String,
+    /// Lua 的 `stack traceback` 或 JavaScript 的调用栈
+    pub traceback: Option<String>,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// 按 `logs` 表的列组织的日志条目
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LogEntry {
+    pub log_level: String,
+    pub event_type: String,
+    pub message: String,
+    pub details: Value,
+    pub source: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// 持久化插件错误的目标，例如 `logs` 表
+pub trait LogSink: Send + Sync {
+    fn write(&self, entry: &LogEntry) -> Result<()>;
+}
+
+/// 可以保存在 [`PluginLog`] 中的记录
+pub trait ToLogEntry: Clone {
+    /// 记录所属的插件
+    fn plugin_id(&self) -> &str;
+
+    /// 转换为一行 `logs` 记录
+    fn to_log_entry(&self) -> LogEntry;
+}
+
+impl PluginErrorRecord {
+    pub fn new(plugin_id: &str, source: ErrorSource, err: &anyhow::Error) -> Self {
+        let (message, traceback) = split_traceback(&format!("{:#}", err));
+        let location = source_location(&message).or_else(|| traceback.as_deref().and_then(source_location));
+        let code = err
+            .downcast_ref::<PluginError>()
+            .and_then(|e| e.to_value()["code"].as_str().map(str::to_string));
+        Self {
+            plugin_id: plugin_id.to_string(),
+            source,
+            code,
+            message,
+            traceback,
+            file: location.as_ref().map(|(file, _)| file.clone()),
+            line: location.map(|(_, line)| line),
+            timestamp: Utc::now(),
+        }
+    }
+
+}
+
+impl ToLogEntry for PluginErrorRecord {
+    fn plugin_id(&self) -> &str {
+        &self.plugin_id
+    }
+
+    /// 错误的结构化字段放在 `details` 中
+    fn to_log_entry(&self) -> LogEntry {
+        LogEntry {
+            log_level: "ERROR".to_string(),
+            event_type: PLUGIN_ERROR_EVENT.to_string(),
+            message: self.message.lines().next().unwrap_or_default().to_string(),
+            details: serde_json::to_value(self).unwrap_or(Value::Null),
+            source: format!("plugin:{}", self.plugin_id),
+            created_at: self.timestamp,
+        }
+    }
+}
+
+/// 把错误信息拆成消息和调用栈
+fn split_traceback(message: &str) -> (String, Option<String>) {
+    let split = message
+        .find("\nstack traceback:")
+        .or_else(|| message.find("\n    at "));
+    match split {
+        Some(pos) => (message[..pos].to_string(), Some(message[pos + 1..].trim_end().to_string())),
+        None => (message.to_string(), None),
+    }
+}
+
+/// 按插件保存最近记录的环形缓冲区，配置了 [`LogSink`] 时同时持久化
+pub struct PluginLog<T> {
+    capacity: usize,
+    records: Mutex<HashMap<String, VecDeque<T>>>,
+    sink: Mutex<Option<Arc<dyn LogSink>>>,
+    listener: Option<Listener<T>>,
+}
+
+type Listener<T> = Box<dyn Fn(&T) + Send + Sync>;
+
+/// 插件最近的错误
+pub type ErrorLog = PluginLog<PluginErrorRecord>;
+
+impl<T: ToLogEntry> PluginLog<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            records: Mutex::new(HashMap::new()),
+            sink: Mutex::new(None),
+            listener: None,
+        }
+    }
+
+    /// 每条新记录在写入缓冲区之前交给 `listener`
+    pub fn with_listener(capacity: usize, listener: impl Fn(&T) + Send + Sync + 'static) -> Self {
+        Self {
+            listener: Some(Box::new(listener)),
+            ..Self::new(capacity)
+        }
+    }
+
+    pub fn set_sink(&self, sink: Option<Arc<dyn LogSink>>) {
+        *self.sink.lock().unwrap() = sink;
+    }
+
+    pub fn record(&self, record: T) {
+        let sink = self.sink.lock().unwrap().clone();
+        if let Some(sink) = sink {
+            let entry = record.to_log_entry();
+            if let Err(e) = sink.write(&entry) {
+                eprintln!("[PluginManager] 插件日志 {} 写入失败: {:#}", entry.event_type, e);
+            }
+        }
+
+        if let Some(listener) = &self.listener {
+            listener(&record);
+        }
+
+        let mut records = self.records.lock().unwrap();
+        let buffer = records.entry(record.plugin_id().to_string()).or_default();
+        if buffer.len() == self.capacity {
+            buffer.pop_front();
+        }
+        buffer.push_back(record);
+    }
+
+    /// 插件最近的记录，最新的在前
+    pub fn recent(&self, plugin_id: &str, limit: usize) -> Vec<T> {
+        self.records
+            .lock()
+            .unwrap()
+            .get(plugin_id)
+            .map(|buffer| buffer.iter().rev().take(limit).cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn clear(&self, plugin_id: &str) {
+        self.records.lock().unwrap().remove(plugin_id);
+    }
+}
+
+impl ErrorLog {
+    /// 每条新记录同时作为 [`PluginEvent::ErrorRecorded`] 广播
+    pub fn with_events(capacity: usize, events: broadcast::Sender<PluginEvent>) -> Self {
+        Self::with_listener(capacity, move |error: &PluginErrorRecord| {
+            let _ = events.send(PluginEvent::ErrorRecorded { error: error.clone() });
+        })
+    }
+}
+
+impl Default for ErrorLog {
+    fn default() -> Self {
+        Self::new(DEFAULT_ERROR_CAPACITY)
+    }
+}
+
+/// 以 JSON Lines 追加写入的日志文件，每行是一条 [`LogEntry`]，可以直接导入 `logs` 表
+pub struct JsonLinesSink {
+    path: PathBuf,
+    file: Mutex<Option<std::fs::File>>,
+}
+
+impl JsonLinesSink {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            file: Mutex::new(None),
+        }
+    }
+}
+
+impl LogSink for JsonLinesSink {
+    fn write(&self, entry: &LogEntry) -> Result<()> {
+        let mut file = self.file.lock().unwrap();
+        if file.is_none() {
+            if let Some(parent) = self.path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            let opened = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)
+                .with_context(|| format!("Failed to open {}", self.path.display()))?;
+            *file = Some(opened);
+        }
+        if let Some(file) = file.as_mut() {
+            writeln!(file, "{}", serde_json::to_string(entry)?)?;
+        }
+        Ok(())
+    }
+}
+
+/// 记录宿主 API 返回的错误，错误本身仍原样返回给插件
+pub struct RecordingApi {
+    plugin_id: String,
+    api: String,
+    inner: Arc<dyn PluginApi>,
+    log: Arc<ErrorLog>,
+}
+
+impl RecordingApi {
+    pub fn new(plugin_id: &str, api: &str, inner: Arc<dyn PluginApi>, log: Arc<ErrorLog>) -> Self {
+        Self {
+            plugin_id: plugin_id.to_string(),
+            api: api.to_string(),
+            inner,
+            log,
+        }
+    }
+}
+
+impl PluginApi for RecordingApi {
+    fn call(&self, method: &str, params: Value) -> Result<Value> {
+        let result = self.inner.call(method, params);
+        if let Err(e) = &result {
+            let source = ErrorSource::Api {
+                api: self.api.clone(),
+                method: method.to_string(),
+            };
+            self.log.record(PluginErrorRecord::new(&self.plugin_id, source, e));
+        }
+        result
+    }
+
+    fn get_permissions(&self) -> Vec<String> {
+        self.inner.get_permissions()
+    }
+
+    fn method_access(&self, method: &str) -> Option<MethodAccess> {
+        self.inner.method_access(method)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct MemorySink(Mutex<Vec<LogEntry>>);
+
+    impl LogSink for MemorySink {
+        fn write(&self, entry: &LogEntry) -> Result<()> {
+            self.0.lock().unwrap().push(entry.clone());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_record_and_ring_buffer() {
+        let err = anyhow::anyhow!(
+            "runtime error: [string \"main.lua\"]:2: boom\nstack traceback:\n\t[C]: in function 'error'\n\t[string \"main.lua\"]:2: in function 'init'"
+        )
+        .context("Failed to call Lua function 'init'");
+        let record = PluginErrorRecord::new("p", ErrorSource::Hook { name: "init".to_string() }, &err);
+        assert_eq!(
+            record.message,
+            "Failed to call Lua function 'init': runtime error: [string \"main.lua\"]:2: boom"
+        );
+        assert!(record.traceback.as_deref().unwrap().starts_with("stack traceback:"));
+        assert_eq!((record.file.as_deref(), record.line), (Some("main.lua"), Some(2)));
+
+        let sink = Arc::new(MemorySink(Mutex::new(Vec::new())));
+        let log = ErrorLog::new(2);
+        log.set_sink(Some(sink.clone()));
+        for name in ["a", "b", "c"] {
+            let err = anyhow::Error::new(PluginError::NotFound { plugin_id: name.to_string() });
+            log.record(PluginErrorRecord::new("p", ErrorSource::Load, &err));
+        }
+        let recent = log.recent("p", 10);
+        assert_eq!(recent.len(), 2);
+        assert_eq!(recent[0].message, "Plugin 'c' not found");
+        assert_eq!(recent[0].code.as_deref(), Some("not_found"));
+
+        let entries = sink.0.lock().unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].event_type, PLUGIN_ERROR_EVENT);
+        assert_eq!(entries[0].source, "plugin:p");
+        assert_eq!(entries[0].details["source"]["kind"], "load");
+    }
+}
@@ -0,0 +1,397 @@
+use std::fmt;
+use serde::{Deserialize, Serialize};
+
+use crate::plugin::contribution::ContributionPoint;
+use crate::plugin::lifecycle::{LifecycleAction, RuntimeState};
+
+/// 插件系统中可被调用方识别的错误类型
+///
+/// 管理器和运行时对外仍返回 `anyhow::Result`，需要区分错误种类时可以通过
+/// `err.downcast_ref::<PluginError>()` 取回具体的错误。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "code", rename_all = "snake_case")]
+pub enum PluginError {
+    /// 插件目录中找不到对应的插件
+    NotFound { plugin_id: String },
+    /// 插件已经加载
+    AlreadyLoaded { plugin_id: String },
+    /// 插件尚未加载到管理器中
+    NotLoaded { plugin_id: String },
+    /// 当前生命周期状态下不允许执行该操作
+    InvalidTransition {
+        plugin_id: String,
+        from: RuntimeState,
+        action: LifecycleAction,
+    },
+    /// 插件清单没有授予调用该 API 方法所需的权限
+    PermissionDenied {
+        plugin_id: String,
+        api: String,
+        method: String,
+        permission: String,
+    },
+    /// 插件调用了未注册的 API
+    UnknownApi { plugin_id: String, api: String },
+    /// API 没有公开插件调用的方法
+    UnknownMethod {
+        plugin_id: String,
+        api: String,
+        method: String,
+    },
+    /// 插件脚本的内存分配超出了限制
+    MemoryLimitExceeded { plugin_id: String, limit: usize },
+    /// 单次调用超出了 CPU 时间预算
+    CpuTimeExceeded { plugin_id: String, limit_ms: u64 },
+    /// 单次调用超出了指令数预算
+    InstructionLimitExceeded { plugin_id: String, limit: u64 },
+    /// 插件因反复超出资源限制被限流
+    Throttled { plugin_id: String, retry_after_ms: u64 },
+    /// 目标插件的消息队列已满
+    QueueFull { plugin_id: String },
+    /// 版本号或版本要求不是合法的 semver
+    InvalidVersion { plugin_id: String, version: String },
+    /// 依赖的插件没有安装
+    MissingDependency {
+        plugin_id: String,
+        dependency: String,
+        requirement: String,
+    },
+    /// 依赖插件的版本不满足要求
+    IncompatibleDependency {
+        plugin_id: String,
+        dependency: String,
+        requirement: String,
+        found: String,
+    },
+    /// 依赖的插件已安装但没有激活
+    InactiveDependency { plugin_id: String, dependency: String },
+    /// 插件之间存在循环依赖，`cycle` 首尾相同，例如 `[a, b, a]`
+    DependencyCycle { cycle: Vec<String> },
+    /// `require` 的模块在插件目录和 vendor 目录中都不存在
+    ModuleNotFound { plugin_id: String, module: String },
+    /// `require` 的模块名或解析结果位于允许的目录之外
+    ModuleAccessDenied { plugin_id: String, module: String },
+    /// 插件包格式错误或内容不合法
+    InvalidPackage { reason: String },
+    /// 插件包中的文件与校验清单不一致
+    PackageTampered { file: String },
+    /// 插件包没有可信的签名
+    UntrustedPackage { reason: String },
+    /// 同名插件已经安装
+    AlreadyInstalled { plugin_id: String },
+    /// 升级包的版本不高于已安装的版本
+    NotAnUpgrade {
+        plugin_id: String,
+        installed: String,
+        version: String,
+    },
+    /// 插件配置不符合清单中声明的配置项
+    InvalidConfig {
+        plugin_id: String,
+        field: String,
+        reason: String,
+    },
+    /// 重新加载插件失败，`file` 和 `line` 是能从错误信息中找到的出错位置
+    ReloadFailed {
+        plugin_id: String,
+        file: Option<String>,
+        line: Option<u32>,
+        message: String,
+    },
+    /// API 调用超出了配额
+    RateLimited {
+        plugin_id: String,
+        api: String,
+        retry_after_ms: u64,
+    },
+    /// 插件没有声明或注册该命令
+    CommandNotFound { plugin_id: String, command_id: String },
+    /// 插件已经声明了同名的命令
+    DuplicateCommand { plugin_id: String, command_id: String },
+    /// 命令参数不符合命令声明的参数格式
+    InvalidArguments {
+        plugin_id: String,
+        command_id: String,
+        field: String,
+        reason: String,
+    },
+    /// 插件没有声明该扩展点
+    ContributionNotFound {
+        plugin_id: String,
+        point: ContributionPoint,
+        id: String,
+    },
+    /// 文件 API 拒绝访问该路径
+    FileAccessDenied {
+        plugin_id: String,
+        path: String,
+        reason: String,
+    },
+    /// 插件的私有文件超出了磁盘配额
+    DiskQuotaExceeded { plugin_id: String, quota: u64 },
+    /// 插件没有声明访问该主机的 `http:<主机>` 权限
+    HostNotAllowed { plugin_id: String, host: String },
+    /// HTTP 请求（包括重定向）超时
+    HttpTimeout {
+        plugin_id: String,
+        url: String,
+        timeout_ms: u64,
+    },
+    /// HTTP 响应体超出了大小上限
+    ResponseTooLarge {
+        plugin_id: String,
+        url: String,
+        limit: u64,
+    },
+    /// 插件的定时器数量达到了 `max_tasks`
+    TaskLimitExceeded { plugin_id: String, limit: usize },
+}
+
+impl fmt::Display for PluginError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PluginError::NotFound { plugin_id } => write!(f, "Plugin '{}' not found", plugin_id),
+            PluginError::AlreadyLoaded { plugin_id } => {
+                write!(f, "Plugin '{}' is already loaded", plugin_id)
+            }
+            PluginError::NotLoaded { plugin_id } => write!(f, "Plugin '{}' is not loaded", plugin_id),
+            PluginError::InvalidTransition { plugin_id, from, action } => write!(
+                f,
+                "Plugin '{}' cannot {} while {}",
+                plugin_id, action, from
+            ),
+            PluginError::PermissionDenied { plugin_id, api, method, permission } => write!(
+                f,
+                "Permission denied: plugin '{}' needs '{}' to call {}.{}",
+                plugin_id, permission, api, method
+            ),
+            PluginError::UnknownApi { plugin_id, api } => {
+                write!(f, "Plugin '{}' called unknown API '{}'", plugin_id, api)
+            }
+            PluginError::UnknownMethod { plugin_id, api, method } => {
+                write!(f, "Plugin '{}' called unknown method {}.{}", plugin_id, api, method)
+            }
+            PluginError::MemoryLimitExceeded { plugin_id, limit } => write!(
+                f,
+                "Plugin '{}' exceeded its memory limit of {} bytes",
+                plugin_id, limit
+            ),
+            PluginError::CpuTimeExceeded { plugin_id, limit_ms } => write!(
+                f,
+                "Plugin '{}' timed out after {} ms of execution",
+                plugin_id, limit_ms
+            ),
+            PluginError::InstructionLimitExceeded { plugin_id, limit } => write!(
+                f,
+                "Plugin '{}' timed out after executing {} instructions",
+                plugin_id, limit
+            ),
+            PluginError::Throttled { plugin_id, retry_after_ms } => write!(
+                f,
+                "Plugin '{}' is throttled, retry after {} ms",
+                plugin_id, retry_after_ms
+            ),
+            PluginError::QueueFull { plugin_id } => {
+                write!(f, "Message queue of plugin '{}' is full", plugin_id)
+            }
+            PluginError::InvalidVersion { plugin_id, version } => write!(
+                f,
+                "Plugin '{}' has an invalid version or version requirement '{}'",
+                plugin_id, version
+            ),
+            PluginError::MissingDependency { plugin_id, dependency, requirement } => write!(
+                f,
+                "Plugin '{}' requires '{}' {}, which is not installed",
+                plugin_id, dependency, requirement
+            ),
+            PluginError::IncompatibleDependency {
+                plugin_id,
+                dependency,
+                requirement,
+                found,
+            } => write!(
+                f,
+                "Plugin '{}' requires '{}' {}, but version {} is installed",
+                plugin_id, dependency, requirement, found
+            ),
+            PluginError::InactiveDependency { plugin_id, dependency } => write!(
+                f,
+                "Plugin '{}' requires '{}', which is not active",
+                plugin_id, dependency
+            ),
+            PluginError::DependencyCycle { cycle } => {
+                write!(f, "Circular plugin dependency: {}", cycle.join(" -> "))
+            }
+            PluginError::ModuleNotFound { plugin_id, module } => {
+                write!(f, "Plugin '{}' cannot find module '{}'", plugin_id, module)
+            }
+            PluginError::ModuleAccessDenied { plugin_id, module } => write!(
+                f,
+                "Plugin '{}' cannot load module '{}' from outside its directory",
+                plugin_id, module
+            ),
+            PluginError::InvalidPackage { reason } => write!(f, "Invalid plugin package: {}", reason),
+            PluginError::PackageTampered { file } => write!(
+                f,
+                "Plugin package is corrupted or tampered: '{}' does not match its checksum",
+                file
+            ),
+            PluginError::UntrustedPackage { reason } => {
+                write!(f, "Plugin package is not trusted: {}", reason)
+            }
+            PluginError::AlreadyInstalled { plugin_id } => {
+                write!(f, "Plugin '{}' is already installed", plugin_id)
+            }
+            PluginError::NotAnUpgrade { plugin_id, installed, version } => write!(
+                f,
+                "Cannot upgrade plugin '{}' from version {} to {}",
+                plugin_id, installed, version
+            ),
+            PluginError::InvalidConfig { plugin_id, field, reason } => write!(
+                f,
+                "Invalid configuration for plugin '{}': '{}' {}",
+                plugin_id, field, reason
+            ),
+            PluginError::ReloadFailed { plugin_id, file: Some(file), line: Some(line), message } => write!(
+                f,
+                "Plugin '{}' failed to reload ({}:{}): {}",
+                plugin_id, file, line, message
+            ),
+            PluginError::ReloadFailed { plugin_id, message, .. } => {
+                write!(f, "Plugin '{}' failed to reload: {}", plugin_id, message)
+            }
+            PluginError::RateLimited { plugin_id, api, retry_after_ms } => write!(
+                f,
+                "Plugin '{}' exceeded its quota for API '{}', retry after {} ms",
+                plugin_id, api, retry_after_ms
+            ),
+            PluginError::CommandNotFound { plugin_id, command_id } => {
+                write!(f, "Plugin '{}' has no command '{}'", plugin_id, command_id)
+            }
+            PluginError::DuplicateCommand { plugin_id, command_id } => {
+                write!(f, "Plugin '{}' already has a command '{}'", plugin_id, command_id)
+            }
+            PluginError::InvalidArguments { plugin_id, command_id, field, reason } => write!(
+                f,
+                "Invalid arguments for command '{}' of plugin '{}': '{}' {}",
+                command_id, plugin_id, field, reason
+            ),
+            PluginError::ContributionNotFound { plugin_id, point, id } => write!(
+                f,
+                "Plugin '{}' has no {} '{}'",
+                plugin_id,
+                point.as_str(),
+                id
+            ),
+            PluginError::FileAccessDenied { plugin_id, path, reason } => write!(
+                f,
+                "Plugin '{}' cannot access '{}': {}",
+                plugin_id, path, reason
+            ),
+            PluginError::DiskQuotaExceeded { plugin_id, quota } => write!(
+                f,
+                "Plugin '{}' exceeded its disk quota of {} bytes",
+                plugin_id, quota
+            ),
+            PluginError::HostNotAllowed { plugin_id, host } => write!(
+                f,
+                "Plugin '{}' is not allowed to access host '{}', declare 'http:{}' in its permissions",
+                plugin_id, host, host
+            ),
+            PluginError::HttpTimeout { plugin_id, url, timeout_ms } => write!(
+                f,
+                "HTTP request of plugin '{}' to {} timed out after {} ms",
+                plugin_id, url, timeout_ms
+            ),
+            PluginError::ResponseTooLarge { plugin_id, url, limit } => write!(
+                f,
+                "HTTP response to plugin '{}' from {} exceeds {} bytes",
+                plugin_id, url, limit
+            ),
+            PluginError::TaskLimitExceeded { plugin_id, limit } => write!(
+                f,
+                "Plugin '{}' cannot have more than {} active timers",
+                plugin_id, limit
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PluginError {}
+
+impl PluginError {
+    /// 是否属于资源限制类错误，这类错误会计入违规次数
+    pub fn is_resource_violation(&self) -> bool {
+        matches!(
+            self,
+            PluginError::MemoryLimitExceeded { .. }
+                | PluginError::CpuTimeExceeded { .. }
+                | PluginError::InstructionLimitExceeded { .. }
+        )
+    }
+
+    /// 根据重新加载时的错误生成 `ReloadFailed`，从错误信息中提取出错的源文件和行号
+    pub fn reload_failed(plugin_id: &str, err: &anyhow::Error) -> Self {
+        let message = format!("{:#}", err);
+        let location = source_location(&message);
+        PluginError::ReloadFailed {
+            plugin_id: plugin_id.to_string(),
+            file: location.as_ref().map(|(file, _)| file.clone()),
+            line: location.map(|(_, line)| line),
+            message,
+        }
+    }
+
+    /// 转换为可以交给插件脚本的结构化错误，包含 `code`、`message` 及各字段
+    pub fn to_value(&self) -> serde_json::Value {
+        let mut value = serde_json::to_value(self).unwrap_or_else(|_| serde_json::json!({}));
+        if let Some(object) = value.as_object_mut() {
+            object.insert("message".to_string(), serde_json::Value::String(self.to_string()));
+        }
+        value
+    }
+}
+
+/// 插件源文件的扩展名，用于在错误信息中识别 `文件:行号`
+const SOURCE_EXTENSIONS: &[&str] = &[".lua", ".js", ".mjs", ".ts"];
+
+/// 找出错误信息中第一个 `文件:行号` 形式的位置
+///
+/// 支持 Lua 的 `[string "main.lua"]:3:` 和 `main.lua:3:`，以及 JavaScript 堆栈中的 `(main.js:3)`。
+pub fn source_location(message: &str) -> Option<(String, u32)> {
+    let message = message.replace("[string \"", " ").replace("\"]:", ":");
+    message.match_indices(':').find_map(|(i, _)| {
+        let before = &message[..i];
+        let start = before
+            .rfind(|c: char| c.is_ascii_whitespace() || matches!(c, '(' | '\'' | '"'))
+            .map_or(0, |pos| pos + 1);
+        let file = &before[start..];
+        let line: String = message[i + 1..].chars().take_while(char::is_ascii_digit).collect();
+        if line.is_empty() || !SOURCE_EXTENSIONS.iter().any(|ext| file.ends_with(ext)) {
+            return None;
+        }
+        Some((file.to_string(), line.parse().ok()?))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_source_location() {
+        assert_eq!(
+            source_location(r#"syntax error: [string "main.lua"]:3: unexpected symbol near '='"#),
+            Some(("main.lua".to_string(), 3))
+        );
+        assert_eq!(
+            source_location("runtime error: lib/util.lua:12: attempt to index a nil value"),
+            Some(("lib/util.lua".to_string(), 12))
+        );
+        assert_eq!(
+            source_location("boom\n    at init (main.js:7:3)"),
+            Some(("main.js".to_string(), 7))
+        );
+        assert_eq!(source_location("Plugin 'a' not found"), None);
+    }
+}
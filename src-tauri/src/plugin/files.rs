@@ -0,0 +1,395 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use base64::Engine;
+use serde_json::{json, Value};
+
+use crate::plugin::error::PluginError;
+use crate::plugin::package::is_valid_name;
+use crate::plugin::{MethodAccess, PluginApi};
+
+/// 插件数据目录下保存插件私有文件的子目录
+pub const FILES_DIR: &str = "files";
+
+/// 没有设置 `max_disk_usage` 时每个插件私有文件的总大小上限
+pub const DEFAULT_DISK_QUOTA: u64 = 50 * 1024 * 1024;
+
+/// 单次读取的文件大小上限
+const MAX_READ_SIZE: u64 = 16 * 1024 * 1024;
+
+/// 插件数据目录下记录用户授权的只读目录，位于 `files` 之外，插件无法修改
+const GRANTS_FILE: &str = "fs-grants.json";
+
+/// 用户授权给插件读取的目录，名称 -> 绝对路径
+#[derive(Debug, Clone)]
+pub struct FileGrants {
+    path: PathBuf,
+}
+
+impl FileGrants {
+    pub fn new(plugin_data_dir: &Path) -> Self {
+        Self { path: plugin_data_dir.join(GRANTS_FILE) }
+    }
+
+    pub fn load(&self) -> Result<BTreeMap<String, PathBuf>> {
+        if !self.path.is_file() {
+            return Ok(BTreeMap::new());
+        }
+        let content =
+            fs::read_to_string(&self.path).with_context(|| format!("Failed to read {}", self.path.display()))?;
+        serde_json::from_str(&content).with_context(|| format!("Invalid file grants {}", self.path.display()))
+    }
+
+    /// 授权插件以 `name` 读取目录 `dir`，同名授权会被替换
+    pub fn grant(&self, name: &str, dir: &Path) -> Result<()> {
+        if !is_valid_name(name) {
+            return Err(anyhow::anyhow!("Invalid root name '{}'", name));
+        }
+        let dir = dir
+            .canonicalize()
+            .with_context(|| format!("Failed to resolve {}", dir.display()))?;
+        if !dir.is_dir() {
+            return Err(anyhow::anyhow!("{} is not a directory", dir.display()));
+        }
+        let mut grants = self.load()?;
+        grants.insert(name.to_string(), dir);
+        self.save(&grants)
+    }
+
+    pub fn revoke(&self, name: &str) -> Result<bool> {
+        let mut grants = self.load()?;
+        let removed = grants.remove(name).is_some();
+        if removed {
+            self.save(&grants)?;
+        }
+        Ok(removed)
+    }
+
+    fn save(&self, grants: &BTreeMap<String, PathBuf>) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&self.path, serde_json::to_string_pretty(grants)?)
+            .with_context(|| format!("Failed to write {}", self.path.display()))
+    }
+}
+
+/// 插件的文件 API
+///
+/// 默认操作插件的私有目录（`<插件数据目录>/files`），传入 `root` 时读取用户授权的只读目录。
+/// 路径必须是相对路径，不能包含 `..`，解析后（包括经过符号链接）必须仍位于所在目录之内。
+///
+/// | 方法 | 参数 | 权限 |
+/// |------|------|------|
+/// | `read` | `path`, `root?`, `encoding?`（`utf8` / `base64`） | `fs.read` |
+/// | `list` | `path?`, `root?` | `fs.read` |
+/// | `stat` | `path`, `root?`，不存在时返回 `nil` | `fs.read` |
+/// | `write` | `path`, `content`, `encoding?`, `append?` | `fs.write` |
+/// | `delete` | `path`, `recursive?` | `fs.write` |
+/// | `usage` | - | - |
+pub struct FileApi {
+    plugin_id: String,
+    dir: PathBuf,
+    grants: FileGrants,
+    quota: Arc<AtomicU64>,
+}
+
+impl FileApi {
+    /// `quota` 由管理器持有，修改资源限制后立即生效
+    pub fn new(plugin_id: &str, plugin_data_dir: &Path, quota: Arc<AtomicU64>) -> Self {
+        Self {
+            plugin_id: plugin_id.to_string(),
+            dir: plugin_data_dir.join(FILES_DIR),
+            grants: FileGrants::new(plugin_data_dir),
+            quota,
+        }
+    }
+
+    fn denied(&self, path: &str, reason: &str) -> anyhow::Error {
+        PluginError::FileAccessDenied {
+            plugin_id: self.plugin_id.clone(),
+            path: path.to_string(),
+            reason: reason.to_string(),
+        }
+        .into()
+    }
+
+    /// 把插件给出的路径解析为所在目录中的绝对路径，返回 (目录, 路径)
+    fn resolve(&self, params: &Value, writable: bool) -> Result<(PathBuf, PathBuf)> {
+        let path = params["path"].as_str().unwrap_or("");
+        let base = match params["root"].as_str() {
+            None => {
+                fs::create_dir_all(&self.dir)
+                    .with_context(|| format!("Failed to create {}", self.dir.display()))?;
+                self.dir.canonicalize()?
+            }
+            Some(_) if writable => return Err(self.denied(path, "granted roots are read-only")),
+            Some(root) => self
+                .grants
+                .load()?
+                .remove(root)
+                .ok_or_else(|| self.denied(path, &format!("root '{}' is not granted", root)))?
+                .canonicalize()
+                .map_err(|_| self.denied(path, &format!("root '{}' no longer exists", root)))?,
+        };
+
+        let mut resolved = base.clone();
+        for component in Path::new(path).components() {
+            match component {
+                Component::Normal(part) => resolved.push(part),
+                Component::CurDir => {}
+                _ => return Err(self.denied(path, "path must be relative and stay inside its root")),
+            }
+        }
+
+        // 经过符号链接后仍须位于目录之内：检查已存在的最深一级
+        let mut existing = resolved.as_path();
+        while existing != base && fs::symlink_metadata(existing).is_err() {
+            existing = existing.parent().unwrap_or(&base);
+        }
+        let real = existing.canonicalize().map_err(|_| self.denied(path, "cannot resolve path"))?;
+        if !real.starts_with(&base) {
+            return Err(self.denied(path, "path escapes its root"));
+        }
+        Ok((base, resolved))
+    }
+
+    fn read(&self, params: Value) -> Result<Value> {
+        let (_, path) = self.resolve(&params, false)?;
+        let size = fs::metadata(&path).with_context(|| format!("Failed to read {}", display(&params)))?.len();
+        if size > MAX_READ_SIZE {
+            return Err(anyhow::anyhow!("{} is larger than {} bytes", display(&params), MAX_READ_SIZE));
+        }
+        let bytes = fs::read(&path).with_context(|| format!("Failed to read {}", display(&params)))?;
+        match params["encoding"].as_str().unwrap_or("utf8") {
+            "base64" => Ok(Value::String(base64::engine::general_purpose::STANDARD.encode(bytes))),
+            _ => String::from_utf8(bytes).map(Value::String).map_err(|_| {
+                anyhow::anyhow!("{} is not valid UTF-8, read it with encoding = \"base64\"", display(&params))
+            }),
+        }
+    }
+
+    fn write(&self, params: Value) -> Result<Value> {
+        let (base, path) = self.resolve(&params, true)?;
+        if path == base {
+            return Err(self.denied(display(&params), "cannot write to the root directory"));
+        }
+        let content = params["content"].as_str().unwrap_or("");
+        let bytes = match params["encoding"].as_str().unwrap_or("utf8") {
+            "base64" => base64::engine::general_purpose::STANDARD
+                .decode(content)
+                .context("Invalid base64 content")?,
+            _ => content.as_bytes().to_vec(),
+        };
+        let append = params["append"].as_bool().unwrap_or(false);
+
+        let existing = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        let size = if append { existing + bytes.len() as u64 } else { bytes.len() as u64 };
+        let quota = self.quota.load(Ordering::Relaxed);
+        if disk_usage(&base).saturating_sub(existing) + size > quota {
+            return Err(PluginError::DiskQuotaExceeded {
+                plugin_id: self.plugin_id.clone(),
+                quota,
+            }
+            .into());
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if append {
+            let mut file = fs::OpenOptions::new().create(true).append(true).open(&path)?;
+            file.write_all(&bytes)?;
+        } else {
+            // 先写临时文件再替换，写入失败不会留下不完整的文件
+            let temp = path.with_file_name(format!(
+                ".{}.tmp",
+                path.file_name().unwrap_or_default().to_string_lossy()
+            ));
+            fs::write(&temp, &bytes)?;
+            fs::rename(&temp, &path).with_context(|| format!("Failed to write {}", display(&params)))?;
+        }
+        Ok(json!({ "size": size }))
+    }
+
+    fn list(&self, params: Value) -> Result<Value> {
+        let (_, dir) = self.resolve(&params, false)?;
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(&dir).with_context(|| format!("Failed to list {}", display(&params)))? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            // 隐藏写入过程中的临时文件
+            if name.starts_with('.') && name.ends_with(".tmp") {
+                continue;
+            }
+            let mut info = stat_value(&entry.path())?;
+            info["name"] = Value::String(name);
+            entries.push(info);
+        }
+        entries.sort_by(|a, b| a["name"].as_str().cmp(&b["name"].as_str()));
+        Ok(Value::Array(entries))
+    }
+
+    fn stat(&self, params: Value) -> Result<Value> {
+        let (_, path) = self.resolve(&params, false)?;
+        if fs::symlink_metadata(&path).is_err() {
+            return Ok(Value::Null);
+        }
+        stat_value(&path)
+    }
+
+    fn delete(&self, params: Value) -> Result<Value> {
+        let (base, path) = self.resolve(&params, true)?;
+        if path == base {
+            return Err(self.denied(display(&params), "cannot delete the root directory"));
+        }
+        let Ok(metadata) = fs::symlink_metadata(&path) else {
+            return Ok(Value::Bool(false));
+        };
+        if metadata.is_dir() {
+            if params["recursive"].as_bool().unwrap_or(false) {
+                fs::remove_dir_all(&path)?;
+            } else {
+                fs::remove_dir(&path).with_context(|| format!("{} is not empty", display(&params)))?;
+            }
+        } else {
+            fs::remove_file(&path)?;
+        }
+        Ok(Value::Bool(true))
+    }
+}
+
+impl PluginApi for FileApi {
+    fn call(&self, method: &str, params: Value) -> Result<Value> {
+        match method {
+            "read" => self.read(params),
+            "write" => self.write(params),
+            "list" => self.list(params),
+            "stat" => self.stat(params),
+            "delete" => self.delete(params),
+            "usage" => Ok(json!({
+                "used": disk_usage(&self.dir),
+                "quota": self.quota.load(Ordering::Relaxed),
+            })),
+            _ => Err(anyhow::anyhow!("Unknown fs method: {}", method)),
+        }
+    }
+
+    fn get_permissions(&self) -> Vec<String> {
+        vec!["fs.read".to_string(), "fs.write".to_string()]
+    }
+
+    fn method_access(&self, method: &str) -> Option<MethodAccess> {
+        match method {
+            "read" | "list" | "stat" => Some(MethodAccess::Requires("fs.read".to_string())),
+            "write" | "delete" => Some(MethodAccess::Requires("fs.write".to_string())),
+            _ => None,
+        }
+    }
+}
+
+fn display(params: &Value) -> &str {
+    params["path"].as_str().unwrap_or("")
+}
+
+fn stat_value(path: &Path) -> Result<Value> {
+    let metadata = fs::metadata(path)?;
+    let modified = metadata
+        .modified()
+        .ok()
+        .map(|time| chrono::DateTime::<chrono::Utc>::from(time).to_rfc3339());
+    Ok(json!({
+        "type": if metadata.is_dir() { "dir" } else { "file" },
+        "size": metadata.len(),
+        "modified": modified,
+        "readonly": metadata.permissions().readonly(),
+    }))
+}
+
+/// 目录中全部文件的总大小，不跟随符号链接
+pub fn disk_usage(dir: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|entry| match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => disk_usage(&entry.path()),
+            Ok(file_type) if file_type.is_file() => entry.metadata().map(|m| m.len()).unwrap_or(0),
+            _ => 0,
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn api(dir: &Path, quota: u64) -> FileApi {
+        FileApi::new("p", dir, Arc::new(AtomicU64::new(quota)))
+    }
+
+    #[test]
+    fn test_private_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let api = api(dir.path(), 10);
+
+        api.call("write", json!({ "path": "notes/a.txt", "content": "hello" })).unwrap();
+        api.call("write", json!({ "path": "notes/a.txt", "content": "!", "append": true })).unwrap();
+        assert_eq!(api.call("read", json!({ "path": "notes/a.txt" })).unwrap(), "hello!");
+        let encoded = api.call("read", json!({ "path": "./notes/a.txt", "encoding": "base64" })).unwrap();
+        assert_eq!(encoded, "aGVsbG8h");
+        assert!(dir.path().join(FILES_DIR).join("notes/a.txt").is_file());
+
+        let listing = api.call("list", json!({ "path": "notes" })).unwrap();
+        assert_eq!(listing[0]["name"], "a.txt");
+        assert_eq!(listing[0]["size"], 6);
+        assert_eq!(api.call("stat", json!({ "path": "notes" })).unwrap()["type"], "dir");
+        assert!(api.call("stat", json!({ "path": "missing" })).unwrap().is_null());
+
+        // 覆盖写入按新大小计算配额
+        api.call("write", json!({ "path": "notes/a.txt", "content": "0123456789" })).unwrap();
+        let err = api.call("write", json!({ "path": "b.txt", "content": "x" })).unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(PluginError::DiskQuotaExceeded { quota: 10, .. })));
+        assert_eq!(api.call("usage", Value::Null).unwrap(), json!({ "used": 10, "quota": 10 }));
+
+        assert!(api.call("delete", json!({ "path": "notes" })).is_err());
+        assert_eq!(api.call("delete", json!({ "path": "notes", "recursive": true })).unwrap(), true);
+        assert_eq!(api.call("delete", json!({ "path": "notes" })).unwrap(), false);
+    }
+
+    #[test]
+    fn test_path_protection() {
+        let dir = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        fs::write(outside.path().join("secret.txt"), "secret").unwrap();
+        let api = api(dir.path(), DEFAULT_DISK_QUOTA);
+
+        for path in ["../fs-grants.json", "/etc/passwd", "notes/../../x"] {
+            let err = api.call("read", json!({ "path": path })).unwrap_err();
+            assert!(matches!(err.downcast_ref(), Some(PluginError::FileAccessDenied { .. })), "{}", path);
+        }
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(outside.path(), dir.path().join(FILES_DIR).join("link")).unwrap();
+            assert!(api.call("read", json!({ "path": "link/secret.txt" })).is_err());
+            assert!(api.call("write", json!({ "path": "link/new.txt", "content": "x" })).is_err());
+            assert!(!outside.path().join("new.txt").exists());
+        }
+
+        // 授权的目录只能读取
+        assert!(api.call("read", json!({ "root": "docs", "path": "secret.txt" })).is_err());
+        FileGrants::new(dir.path()).grant("docs", outside.path()).unwrap();
+        assert_eq!(api.call("read", json!({ "root": "docs", "path": "secret.txt" })).unwrap(), "secret");
+        let err = api.call("write", json!({ "root": "docs", "path": "x.txt", "content": "" })).unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(PluginError::FileAccessDenied { .. })));
+        assert!(FileGrants::new(dir.path()).revoke("docs").unwrap());
+        assert!(api.call("read", json!({ "root": "docs", "path": "secret.txt" })).is_err());
+    }
+}
@@ -0,0 +1,1084 @@
+//! 无界面的插件测试工具，`ptla-plugin-test` 的实现
+//!
+//! 在临时数据目录中加载插件，用内存中的模拟实现替代任务、存储和通知 API，定时器使用手动时钟。
+//! `*_spec.lua` 在独立的 Lua 环境中执行，通过 `plugin`、`mock`、`clock` 驱动插件，
+//! 每个用例都在重新加载的插件、清空的模拟数据和拨回起点的时钟上运行。
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use mlua::{Function, Lua, Table, Value as LuaValue, Variadic};
+use serde::Serialize;
+use serde_json::{json, Value};
+use tokio::runtime::Handle;
+
+use crate::plugin::timer::Clock;
+use crate::plugin::{bridge, convert, read_manifest, MethodAccess, PluginApi, PluginManager};
+
+/// 测试用例文件名的后缀
+pub const SPEC_SUFFIX: &str = "_spec.lua";
+
+/// 没有指定起始时间时手动时钟的起点，固定的起点让定时器相关的断言可以复现
+pub const DEFAULT_START: &str = "2024-01-01T00:00:00Z";
+
+/// 查找测试用例时跳过的目录
+const SKIPPED_DIRS: &[&str] = &["node_modules", "target"];
+
+/// 测试用例文件中可用的断言库和 `describe` / `it`
+///
+/// 返回的表包含 `names()`（全部用例的名称和是否跳过）和 `run(index)`。
+/// 用例按声明顺序执行，外层的 `before_each` 先于内层执行，`after_each` 相反，并且总会执行。
+const SPEC_PRELUDE: &str = r#"
+local raw_plugin, plugin_id = ...
+local error_mt = { __tostring = function(e) return e.message end }
+
+local function host(op)
+    return function(...)
+        local ok, result = raw_plugin(op, ...)
+        if not ok then
+            error(setmetatable(result, error_mt), 2)
+        end
+        return result
+    end
+end
+
+plugin = {
+    id = plugin_id,
+    call = host("call"),
+    start = host("start"),
+    stop = host("stop"),
+    reload = host("reload"),
+    timers = host("timers"),
+    errors = host("errors"),
+}
+
+local function format(value, depth)
+    depth = depth or 0
+    if type(value) == "string" then return string.format("%q", value) end
+    if value == json.null then return "json.null" end
+    if type(value) ~= "table" then return tostring(value) end
+    if depth > 4 then return "{...}" end
+    local parts, n = {}, #value
+    for i = 1, n do parts[#parts + 1] = format(value[i], depth + 1) end
+    local keys = {}
+    for k in pairs(value) do
+        if not (math.type(k) == "integer" and k >= 1 and k <= n) then keys[#keys + 1] = k end
+    end
+    table.sort(keys, function(a, b) return tostring(a) < tostring(b) end)
+    for _, k in ipairs(keys) do
+        local key = type(k) == "string" and k:match("^[%a_][%w_]*$") and k or "[" .. format(k) .. "]"
+        parts[#parts + 1] = key .. " = " .. format(value[k], depth + 1)
+    end
+    if #parts == 0 then return "{}" end
+    return "{ " .. table.concat(parts, ", ") .. " }"
+end
+
+local function deep_equal(a, b)
+    if a == b then return true end
+    if type(a) ~= "table" or type(b) ~= "table" then return false end
+    for k, v in pairs(a) do
+        if not deep_equal(v, b[k]) then return false end
+    end
+    for k in pairs(b) do
+        if a[k] == nil then return false end
+    end
+    return true
+end
+
+-- 层级 4 指向调用断言的用例代码：fail <- check <- 断言函数 <- 用例
+local function fail(message) error(message, 4) end
+
+local function check(ok, message, default)
+    if not ok then
+        fail(message and message .. "\n" .. default or default)
+    end
+end
+
+assert = setmetatable({}, {
+    __call = function(_, value, message, ...)
+        check(value, message, "assertion failed!")
+        return value, message, ...
+    end,
+})
+
+function assert.equals(expected, actual, message)
+    check(deep_equal(expected, actual), message, "expected: " .. format(expected) .. "\n  actual: " .. format(actual))
+end
+assert.same = assert.equals
+
+function assert.not_equals(unexpected, actual, message)
+    check(not deep_equal(unexpected, actual), message, "expected a value other than " .. format(unexpected))
+end
+
+function assert.is_true(value, message) check(value == true, message, "expected true, got " .. format(value)) end
+function assert.is_false(value, message) check(value == false, message, "expected false, got " .. format(value)) end
+function assert.is_nil(value, message) check(value == nil, message, "expected nil, got " .. format(value)) end
+function assert.not_nil(value, message) check(value ~= nil, message, "expected a value, got nil") end
+function assert.truthy(value, message) check(value, message, "expected a truthy value, got " .. format(value)) end
+function assert.falsy(value, message) check(not value, message, "expected a falsy value, got " .. format(value)) end
+
+function assert.matches(pattern, actual, message)
+    check(
+        type(actual) == "string" and actual:find(pattern) ~= nil,
+        message,
+        "expected " .. format(actual) .. " to match " .. format(pattern)
+    )
+end
+
+function assert.contains(list, value, message)
+    local found = false
+    for _, item in ipairs(list) do
+        if deep_equal(item, value) then found = true break end
+    end
+    check(found, message, "expected " .. format(list) .. " to contain " .. format(value))
+end
+
+-- expected 为结构化错误的 code，或错误信息中的一段文字
+function assert.has_error(fn, expected, message)
+    local ok, err = pcall(fn)
+    check(not ok, message, "expected an error")
+    if expected ~= nil then
+        local matched = (type(err) == "table" and err.code == expected)
+            or tostring(err):find(expected, 1, true) ~= nil
+        check(matched, message, "expected error " .. format(expected) .. ", got: " .. tostring(err))
+    end
+    return err
+end
+
+local cases, scopes = {}, { { before = {}, after = {} } }
+
+function describe(name, body)
+    scopes[#scopes + 1] = { name = name, before = {}, after = {} }
+    body()
+    scopes[#scopes] = nil
+end
+
+function before_each(fn) table.insert(scopes[#scopes].before, fn) end
+function after_each(fn) table.insert(scopes[#scopes].after, fn) end
+
+function it(name, body)
+    local chain, names = {}, {}
+    for i, scope in ipairs(scopes) do
+        chain[i] = scope
+        if scope.name then names[#names + 1] = scope.name end
+    end
+    names[#names + 1] = name
+    cases[#cases + 1] = { name = table.concat(names, " "), body = body, chain = chain }
+end
+
+function pending(name) it(name) end
+
+local function run(index)
+    local case = cases[index]
+    local ok, err = pcall(function()
+        for _, scope in ipairs(case.chain) do
+            for _, fn in ipairs(scope.before) do fn() end
+        end
+        case.body()
+    end)
+    for i = #case.chain, 1, -1 do
+        for _, fn in ipairs(case.chain[i].after) do
+            local after_ok, after_err = pcall(fn)
+            if ok and not after_ok then ok, err = false, after_err end
+        end
+    end
+    if ok then return true end
+    return false, tostring(err)
+end
+
+local function names()
+    local result = {}
+    for i, case in ipairs(cases) do
+        result[i] = { name = case.name, skipped = case.body == nil }
+    end
+    return result
+end
+
+return { names = names, run = run }
+"#;
+
+#[derive(Default)]
+struct MockState {
+    tasks: Vec<Value>,
+    next_task_id: u64,
+    storage: BTreeMap<String, Value>,
+    notifications: Vec<Value>,
+}
+
+/// 内存中的宿主数据，供模拟的任务、存储和通知 API 共享
+#[derive(Clone)]
+pub struct MockHost {
+    state: Arc<Mutex<MockState>>,
+    clock: Clock,
+}
+
+impl MockHost {
+    /// 记录中的时间戳取自 `clock`
+    pub fn new(clock: Clock) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(MockState::default())),
+            clock,
+        }
+    }
+
+    /// 以 `task`、`storage`、`notification` 的名称注册模拟 API，插件仍然需要在清单中声明对应权限
+    pub fn register(&self, manager: &PluginManager) {
+        manager.register_api("task", Arc::new(MockTaskApi { host: self.clone() }));
+        manager.register_api("storage", Arc::new(MockStorageApi { host: self.clone() }));
+        manager.register_api("notification", Arc::new(MockNotificationApi { host: self.clone() }));
+    }
+
+    pub fn reset(&self) {
+        *self.state.lock().unwrap() = MockState::default();
+    }
+
+    pub fn tasks(&self) -> Vec<Value> {
+        self.state.lock().unwrap().tasks.clone()
+    }
+
+    pub fn storage(&self) -> BTreeMap<String, Value> {
+        self.state.lock().unwrap().storage.clone()
+    }
+
+    pub fn notifications(&self) -> Vec<Value> {
+        self.state.lock().unwrap().notifications.clone()
+    }
+
+    /// 添加任务，分配 `id` 并补全 `completed` 和时间戳
+    pub fn add_task(&self, fields: Value) -> Result<Value> {
+        let Value::Object(mut task) = fields else {
+            anyhow::bail!("task.create expects a table of task fields");
+        };
+        if task.get("title").and_then(Value::as_str).is_none_or(str::is_empty) {
+            anyhow::bail!("task.create requires a title");
+        }
+        let now = self.clock.now().to_rfc3339();
+        let mut state = self.state.lock().unwrap();
+        state.next_task_id += 1;
+        task.insert("id".to_string(), json!(state.next_task_id));
+        task.entry("completed").or_insert(Value::Bool(false));
+        task.insert("created_at".to_string(), json!(now));
+        task.insert("updated_at".to_string(), json!(now));
+        state.tasks.push(Value::Object(task.clone()));
+        Ok(Value::Object(task))
+    }
+
+    pub fn set_storage(&self, key: &str, value: Value) {
+        self.state.lock().unwrap().storage.insert(key.to_string(), value);
+    }
+
+    fn update_task(&self, params: Value) -> Result<Value> {
+        let id = params["id"].as_u64().context("task.update requires an id")?;
+        let now = self.clock.now().to_rfc3339();
+        let mut state = self.state.lock().unwrap();
+        let task = state
+            .tasks
+            .iter_mut()
+            .find(|task| task["id"] == id)
+            .with_context(|| format!("Task {} not found", id))?;
+        if let (Some(task), Some(fields)) = (task.as_object_mut(), params.as_object()) {
+            for (key, value) in fields.iter().filter(|(key, _)| !matches!(key.as_str(), "id" | "created_at")) {
+                task.insert(key.clone(), value.clone());
+            }
+            task.insert("updated_at".to_string(), json!(now));
+        }
+        Ok(task.clone())
+    }
+
+    fn delete_task(&self, params: Value) -> Result<Value> {
+        let id = params["id"].as_u64().context("task.delete requires an id")?;
+        let mut state = self.state.lock().unwrap();
+        let pos = state
+            .tasks
+            .iter()
+            .position(|task| task["id"] == id)
+            .with_context(|| format!("Task {} not found", id))?;
+        Ok(state.tasks.remove(pos))
+    }
+}
+
+/// 模拟的任务 API，`list` 可以按 `completed` 过滤
+pub struct MockTaskApi {
+    host: MockHost,
+}
+
+impl PluginApi for MockTaskApi {
+    fn call(&self, method: &str, params: Value) -> Result<Value> {
+        match method {
+            "list" => {
+                let completed = params["completed"].as_bool();
+                let tasks = self.host.tasks().into_iter();
+                Ok(Value::Array(
+                    tasks
+                        .filter(|task| completed.is_none_or(|completed| task["completed"] == completed))
+                        .collect(),
+                ))
+            }
+            "create" => self.host.add_task(params),
+            "update" => self.host.update_task(params),
+            "delete" => self.host.delete_task(params),
+            _ => Err(anyhow::anyhow!("Unknown method: {}", method)),
+        }
+    }
+
+    fn get_permissions(&self) -> Vec<String> {
+        vec!["task.read".to_string(), "task.write".to_string()]
+    }
+
+    fn method_access(&self, method: &str) -> Option<MethodAccess> {
+        match method {
+            "list" => Some(MethodAccess::Requires("task.read".to_string())),
+            "create" | "update" | "delete" => Some(MethodAccess::Requires("task.write".to_string())),
+            _ => None,
+        }
+    }
+}
+
+/// 模拟的键值存储 API
+pub struct MockStorageApi {
+    host: MockHost,
+}
+
+impl MockStorageApi {
+    fn key(method: &str, params: &Value) -> Result<String> {
+        params["key"]
+            .as_str()
+            .map(str::to_string)
+            .with_context(|| format!("storage.{} requires a key", method))
+    }
+}
+
+impl PluginApi for MockStorageApi {
+    fn call(&self, method: &str, params: Value) -> Result<Value> {
+        let key = Self::key(method, &params)?;
+        let mut state = self.host.state.lock().unwrap();
+        match method {
+            "get" => Ok(state.storage.get(&key).cloned().unwrap_or(Value::Null)),
+            "set" => {
+                state.storage.insert(key, params["value"].clone());
+                Ok(Value::Null)
+            }
+            "delete" => Ok(Value::Bool(state.storage.remove(&key).is_some())),
+            _ => Err(anyhow::anyhow!("Unknown method: {}", method)),
+        }
+    }
+
+    fn get_permissions(&self) -> Vec<String> {
+        vec!["storage.read".to_string(), "storage.write".to_string()]
+    }
+
+    fn method_access(&self, method: &str) -> Option<MethodAccess> {
+        match method {
+            "get" => Some(MethodAccess::Requires("storage.read".to_string())),
+            "set" | "delete" => Some(MethodAccess::Requires("storage.write".to_string())),
+            _ => None,
+        }
+    }
+}
+
+/// 模拟的通知 API，发送的通知按顺序记录
+pub struct MockNotificationApi {
+    host: MockHost,
+}
+
+impl PluginApi for MockNotificationApi {
+    fn call(&self, method: &str, params: Value) -> Result<Value> {
+        match method {
+            "send" => {
+                let Value::Object(mut notification) = params else {
+                    anyhow::bail!("notification.send expects a table");
+                };
+                let mut state = self.host.state.lock().unwrap();
+                notification.insert("id".to_string(), json!(state.notifications.len() + 1));
+                notification.insert("sent_at".to_string(), json!(self.host.clock.now().to_rfc3339()));
+                state.notifications.push(Value::Object(notification.clone()));
+                Ok(Value::Object(notification))
+            }
+            "list" => Ok(Value::Array(self.host.notifications())),
+            _ => Err(anyhow::anyhow!("Unknown method: {}", method)),
+        }
+    }
+
+    fn get_permissions(&self) -> Vec<String> {
+        vec!["notification.send".to_string(), "notification.read".to_string()]
+    }
+
+    fn method_access(&self, method: &str) -> Option<MethodAccess> {
+        match method {
+            "send" => Some(MethodAccess::Requires("notification.send".to_string())),
+            "list" => Some(MethodAccess::Requires("notification.read".to_string())),
+            _ => None,
+        }
+    }
+}
+
+/// 单个用例的结果
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Outcome {
+    Passed,
+    Failed { message: String },
+    Skipped,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CaseReport {
+    pub name: String,
+    #[serde(flatten)]
+    pub outcome: Outcome,
+    pub duration_ms: u64,
+}
+
+/// 一个测试用例文件的结果，文件本身无法执行时 `error` 为错误信息
+#[derive(Debug, Clone, Serialize)]
+pub struct SuiteReport {
+    pub file: String,
+    pub cases: Vec<CaseReport>,
+    pub error: Option<String>,
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Summary {
+    pub total: usize,
+    pub passed: usize,
+    pub failed: usize,
+    pub skipped: usize,
+    /// 无法执行的测试用例文件数
+    pub errors: usize,
+}
+
+impl Summary {
+    fn add(&mut self, suite: &SuiteReport) {
+        self.errors += usize::from(suite.error.is_some());
+        for case in &suite.cases {
+            self.total += 1;
+            match case.outcome {
+                Outcome::Passed => self.passed += 1,
+                Outcome::Failed { .. } => self.failed += 1,
+                Outcome::Skipped => self.skipped += 1,
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TestReport {
+    pub plugin_id: String,
+    pub summary: Summary,
+    pub suites: Vec<SuiteReport>,
+}
+
+impl TestReport {
+    pub fn new(plugin_id: &str, suites: Vec<SuiteReport>) -> Self {
+        let mut summary = Summary::default();
+        for suite in &suites {
+            summary.add(suite);
+        }
+        Self {
+            plugin_id: plugin_id.to_string(),
+            summary,
+            suites,
+        }
+    }
+
+    pub fn success(&self) -> bool {
+        self.summary.failed == 0 && self.summary.errors == 0
+    }
+}
+
+/// 测试结果的输出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Text,
+    Json,
+    Junit,
+}
+
+impl std::str::FromStr for ReportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            "junit" => Ok(Self::Junit),
+            _ => Err(anyhow::anyhow!("Unknown report format '{}', expected text, json or junit", s)),
+        }
+    }
+}
+
+impl ReportFormat {
+    pub fn render(&self, report: &TestReport) -> String {
+        match self {
+            Self::Text => render_text(report),
+            Self::Json => serde_json::to_string_pretty(report).unwrap_or_default(),
+            Self::Junit => render_junit(report),
+        }
+    }
+}
+
+fn render_text(report: &TestReport) -> String {
+    let mut out = String::new();
+    for suite in &report.suites {
+        let _ = writeln!(out, "{}", suite.file);
+        if let Some(error) = &suite.error {
+            let _ = writeln!(out, "  ! {}", error.replace('\n', "\n    "));
+        }
+        for case in &suite.cases {
+            match &case.outcome {
+                Outcome::Passed => {
+                    let _ = writeln!(out, "  ok   {} ({} ms)", case.name, case.duration_ms);
+                }
+                Outcome::Failed { message } => {
+                    let _ = writeln!(out, "  FAIL {} ({} ms)", case.name, case.duration_ms);
+                    let _ = writeln!(out, "       {}", message.replace('\n', "\n       "));
+                }
+                Outcome::Skipped => {
+                    let _ = writeln!(out, "  skip {}", case.name);
+                }
+            }
+        }
+    }
+    let summary = &report.summary;
+    let _ = writeln!(
+        out,
+        "{} passed, {} failed, {} skipped, {} errors",
+        summary.passed, summary.failed, summary.skipped, summary.errors
+    );
+    out
+}
+
+fn render_junit(report: &TestReport) -> String {
+    let seconds = |ms: u64| format!("{:.3}", ms as f64 / 1000.0);
+    let summary = &report.summary;
+    let total_ms: u64 = report.suites.iter().map(|suite| suite.duration_ms).sum();
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(
+        out,
+        "<testsuites name=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"{}\" skipped=\"{}\" time=\"{}\">",
+        xml_escape(&report.plugin_id),
+        summary.total + summary.errors,
+        summary.failed,
+        summary.errors,
+        summary.skipped,
+        seconds(total_ms)
+    );
+    for suite in &report.suites {
+        let mut counts = Summary::default();
+        counts.add(suite);
+        let _ = writeln!(
+            out,
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"{}\" skipped=\"{}\" time=\"{}\">",
+            xml_escape(&suite.file),
+            counts.total + counts.errors,
+            counts.failed,
+            counts.errors,
+            counts.skipped,
+            seconds(suite.duration_ms)
+        );
+        let classname = xml_escape(&format!("{}.{}", report.plugin_id, suite.file));
+        if let Some(error) = &suite.error {
+            let _ = writeln!(
+                out,
+                "    <testcase classname=\"{}\" name=\"{}\" time=\"0.000\">\n      \
+                 <error message=\"{}\">{}</error>\n    </testcase>",
+                classname,
+                xml_escape(&suite.file),
+                xml_escape(error.lines().next().unwrap_or_default()),
+                xml_escape(error)
+            );
+        }
+        for case in &suite.cases {
+            let _ = write!(
+                out,
+                "    <testcase classname=\"{}\" name=\"{}\" time=\"{}\"",
+                classname,
+                xml_escape(&case.name),
+                seconds(case.duration_ms)
+            );
+            match &case.outcome {
+                Outcome::Passed => out.push_str("/>\n"),
+                Outcome::Failed { message } => {
+                    let _ = writeln!(
+                        out,
+                        ">\n      <failure message=\"{}\">{}</failure>\n    </testcase>",
+                        xml_escape(message.lines().next().unwrap_or_default()),
+                        xml_escape(message)
+                    );
+                }
+                Outcome::Skipped => out.push_str(">\n      <skipped/>\n    </testcase>\n"),
+            }
+        }
+        out.push_str("  </testsuite>\n");
+    }
+    out.push_str("</testsuites>\n");
+    out
+}
+
+fn xml_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // XML 1.0 不允许的控制字符
+            c if c.is_control() && !matches!(c, '\n' | '\r' | '\t') => escaped.push('\u{FFFD}'),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// 查找目录下全部 `*_spec.lua`，跳过隐藏目录和依赖目录，按路径排序
+pub fn find_specs(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut specs = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in fs::read_dir(&dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+            let path = entry?.path();
+            let name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+            if path.is_dir() {
+                if !name.starts_with('.') && !SKIPPED_DIRS.contains(&name) {
+                    pending.push(path);
+                }
+            } else if name.ends_with(SPEC_SUFFIX) {
+                specs.push(path);
+            }
+        }
+    }
+    specs.sort();
+    Ok(specs)
+}
+
+/// 一次测试运行中被测插件的加载状态，在用例的 Lua 函数之间共享
+#[derive(Clone)]
+struct Session {
+    manager: PluginManager,
+    host: MockHost,
+    handle: Handle,
+    plugin_id: String,
+    // 规范化后的被测插件目录，按路径加载，不会选中插件目录旁同名的其他插件
+    plugin_dir: PathBuf,
+    start: DateTime<Utc>,
+}
+
+impl Session {
+    fn load(&self) -> Result<()> {
+        let result = self.handle.block_on(self.manager.load_plugin_from(&self.plugin_dir));
+        if result.is_err() && self.manager.plugin_state(&self.plugin_id).is_some() {
+            let _ = self.handle.block_on(self.manager.unload_plugin(&self.plugin_id));
+        }
+        result
+    }
+
+    fn unload(&self) -> Result<()> {
+        if self.manager.plugin_state(&self.plugin_id).is_none() {
+            return Ok(());
+        }
+        self.handle.block_on(self.manager.unload_plugin(&self.plugin_id))
+    }
+
+    /// 清空插件数据、模拟数据、错误记录并拨回时钟，然后重新加载插件
+    fn reset(&self) -> Result<()> {
+        self.unload()?;
+        let data_dir = self.manager.plugin_data_dir(&self.plugin_id);
+        if data_dir.exists() {
+            fs::remove_dir_all(&data_dir).with_context(|| format!("Failed to clear {}", data_dir.display()))?;
+        }
+        self.host.reset();
+        self.manager.clear_errors(&self.plugin_id);
+        self.manager.clock().set(Some(self.start));
+        self.load().context("Plugin failed to load")
+    }
+
+    /// `plugin` 表中各函数的实现
+    fn plugin_op(&self, op: &str, args: Vec<Value>) -> Result<Value> {
+        let id = self.plugin_id.as_str();
+        match op {
+            "call" => {
+                let mut args = args.into_iter();
+                let function = args
+                    .next()
+                    .and_then(|name| name.as_str().map(str::to_string))
+                    .context("plugin.call requires a function name")?;
+                self.handle.block_on(self.manager.call_function(id, &function, args.collect()))
+            }
+            "start" => self.handle.block_on(self.manager.start_plugin(id)).map(|_| Value::Null),
+            "stop" => self.handle.block_on(self.manager.stop_plugin(id)).map(|_| Value::Null),
+            // 保留插件数据和模拟数据，相当于重启应用
+            "reload" => {
+                self.unload()?;
+                self.load().map(|_| Value::Null)
+            }
+            "timers" => Ok(json!(self.manager.timers(id))),
+            "errors" => Ok(json!(self.manager.recent_errors(id, usize::MAX))),
+            _ => Err(anyhow::anyhow!("Unknown plugin operation: {}", op)),
+        }
+    }
+}
+
+/// 加载一个插件目录并执行测试用例
+///
+/// 插件所在目录的其他插件不会被加载，依赖其他插件的插件无法在这里测试。
+/// 用例中的函数通过 `handle` 同步等待插件，不能在异步运行时的工作线程中调用。
+pub struct Harness {
+    session: Session,
+    // 插件数据目录的根，测试结束后删除
+    _data_dir: tempfile::TempDir,
+}
+
+impl Harness {
+    pub fn new(plugin_dir: &Path, start: DateTime<Utc>, handle: Handle) -> Result<Self> {
+        let plugin_dir = plugin_dir
+            .canonicalize()
+            .with_context(|| format!("Plugin directory {} not found", plugin_dir.display()))?;
+        let metadata = read_manifest(&plugin_dir)?;
+        let root = plugin_dir.parent().context("The plugin directory has no parent directory")?;
+
+        let manager = PluginManager::with_plugin_dir(root);
+        let data_dir = tempfile::tempdir().context("Failed to create a temporary data directory")?;
+        manager.set_data_dir(Some(data_dir.path().to_path_buf()));
+        let clock = manager.clock();
+        clock.set(Some(start));
+        let host = MockHost::new(clock);
+        host.register(&manager);
+
+        Ok(Self {
+            session: Session {
+                manager,
+                host,
+                handle,
+                plugin_id: metadata.name,
+                plugin_dir,
+                start,
+            },
+            _data_dir: data_dir,
+        })
+    }
+
+    pub fn plugin_id(&self) -> &str {
+        &self.session.plugin_id
+    }
+
+    pub fn host(&self) -> &MockHost {
+        &self.session.host
+    }
+
+    /// 依次执行测试用例文件，`filter` 只保留名称中包含该文字的用例
+    pub fn run(&self, specs: &[PathBuf], base: &Path, filter: Option<&str>) -> TestReport {
+        let suites = specs
+            .iter()
+            .map(|spec| {
+                let file = spec.strip_prefix(base).unwrap_or(spec).display().to_string();
+                self.run_spec(spec, &file, filter)
+            })
+            .collect();
+        TestReport::new(self.plugin_id(), suites)
+    }
+
+    fn run_spec(&self, path: &Path, file: &str, filter: Option<&str>) -> SuiteReport {
+        let started = Instant::now();
+        let mut suite = SuiteReport {
+            file: file.to_string(),
+            cases: Vec::new(),
+            error: None,
+            duration_ms: 0,
+        };
+        let result = self.run_cases(path, file, filter, &mut suite.cases);
+        // 每个文件结束后卸载插件，最后一个用例遗留的定时器不会继续执行
+        let unloaded = self.session.unload();
+        if let Err(e) = result.and(unloaded) {
+            suite.error = Some(format!("{:#}", e));
+        }
+        suite.duration_ms = started.elapsed().as_millis() as u64;
+        suite
+    }
+
+    fn run_cases(&self, path: &Path, file: &str, filter: Option<&str>, cases: &mut Vec<CaseReport>) -> Result<()> {
+        let source = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let lua = Lua::new();
+        let control = self.prepare(&lua)?;
+        lua.load(source.as_str()).set_name(format!("@{}", file)).exec()?;
+
+        let names: Vec<Table> = control.get::<_, Function>("names")?.call(())?;
+        let run: Function = control.get("run")?;
+        for (index, case) in names.into_iter().enumerate() {
+            let name: String = case.get("name")?;
+            let skipped: bool = case.get("skipped")?;
+            if filter.is_some_and(|filter| !name.contains(filter)) {
+                continue;
+            }
+            let started = Instant::now();
+            let outcome = if skipped {
+                Outcome::Skipped
+            } else {
+                self.run_case(&run, index + 1)
+            };
+            cases.push(CaseReport {
+                name,
+                outcome,
+                duration_ms: started.elapsed().as_millis() as u64,
+            });
+        }
+        Ok(())
+    }
+
+    fn run_case(&self, run: &Function, index: usize) -> Outcome {
+        if let Err(e) = self.session.reset() {
+            return Outcome::Failed { message: format!("{:#}", e) };
+        }
+        let result: mlua::Result<(bool, Option<String>)> = run.call(index);
+        let unloaded = self.session.unload();
+        match (result, unloaded) {
+            (Ok((true, _)), Ok(())) => Outcome::Passed,
+            (Ok((false, message)), _) => Outcome::Failed {
+                message: message.unwrap_or_default(),
+            },
+            (Err(e), _) => Outcome::Failed { message: e.to_string() },
+            (_, Err(e)) => Outcome::Failed {
+                message: format!("Plugin failed to unload: {:#}", e),
+            },
+        }
+    }
+
+    /// 注册 `plugin`、`mock`、`clock`、`json` 和断言库，返回控制用例执行的表
+    fn prepare<'lua>(&self, lua: &'lua Lua) -> mlua::Result<Table<'lua>> {
+        let globals = lua.globals();
+        globals.set("json", convert::create_json_table(lua)?)?;
+
+        let session = self.session.clone();
+        let raw_plugin = lua.create_function(move |lua, (op, args): (String, Variadic<LuaValue>)| {
+            let args = args
+                .iter()
+                .map(|arg| convert::lua_to_json(lua, arg))
+                .collect::<mlua::Result<Vec<_>>>()?;
+            match session.plugin_op(&op, args) {
+                Ok(value) => Ok((true, convert::json_to_lua(lua, &value)?)),
+                Err(e) => Ok((false, convert::json_to_lua(lua, &bridge::error_value(&e))?)),
+            }
+        })?;
+
+        let mock = lua.create_table()?;
+        let host = self.session.host.clone();
+        mock.set(
+            "tasks",
+            lua.create_function(move |lua, ()| convert::json_to_lua(lua, &Value::Array(host.tasks())))?,
+        )?;
+        let host = self.session.host.clone();
+        mock.set(
+            "storage",
+            lua.create_function(move |lua, ()| convert::json_to_lua(lua, &json!(host.storage())))?,
+        )?;
+        let host = self.session.host.clone();
+        mock.set(
+            "notifications",
+            lua.create_function(move |lua, ()| convert::json_to_lua(lua, &Value::Array(host.notifications())))?,
+        )?;
+        let host = self.session.host.clone();
+        mock.set(
+            "add_task",
+            lua.create_function(move |lua, fields: LuaValue| {
+                let task = host
+                    .add_task(convert::lua_to_json(lua, &fields)?)
+                    .map_err(mlua::Error::external)?;
+                convert::json_to_lua(lua, &task)
+            })?,
+        )?;
+        let host = self.session.host.clone();
+        mock.set(
+            "set_storage",
+            lua.create_function(move |lua, (key, value): (String, LuaValue)| {
+                host.set_storage(&key, convert::lua_to_json(lua, &value)?);
+                Ok(())
+            })?,
+        )?;
+        globals.set("mock", mock)?;
+
+        let clock = lua.create_table()?;
+        let manager = self.session.manager.clone();
+        clock.set("now", lua.create_function(move |_, ()| Ok(manager.clock().now().to_rfc3339()))?)?;
+        let session = self.session.clone();
+        clock.set(
+            "advance",
+            lua.create_function(move |_, ms: i64| {
+                if ms < 0 {
+                    return Err(mlua::Error::runtime("clock.advance cannot move the clock backwards"));
+                }
+                let advance = session.manager.advance_clock(chrono::Duration::milliseconds(ms));
+                session.handle.block_on(advance).map_err(mlua::Error::external)
+            })?,
+        )?;
+        globals.set("clock", clock)?;
+
+        lua.load(SPEC_PRELUDE)
+            .set_name("=spec")
+            .call((raw_plugin, self.session.plugin_id.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin::MANIFEST_FILE;
+
+    const PLUGIN: &str = r#"
+function start()
+    api.timer("every", { id = "sweep", interval = 60 * 1000 })
+end
+
+function sweep()
+    for _, task in ipairs(api.task("list", { completed = true })) do
+        api.task("delete", { id = task.id })
+    end
+    api.storage("set", { key = "last_sweep", value = api.storage("get", { key = "sweeps" }) })
+end
+
+function add(title)
+    local task = api.task("create", { title = title })
+    api.notification("send", { title = "Created", body = title })
+    return task
+end
+"#;
+
+    const SPEC: &str = r#"
+describe("tasks", function()
+    local created
+    before_each(function() created = plugin.call("add", "write docs") end)
+
+    it("creates tasks and notifies", function()
+        assert.equals({ "write docs" }, { mock.tasks()[1].title })
+        assert.equals(1, created.id)
+        assert.equals("Created", mock.notifications()[1].title)
+    end)
+
+    it("sweeps completed tasks on a timer", function()
+        mock.add_task({ title = "done", completed = true })
+        mock.set_storage("sweeps", 3)
+        assert.equals(0, clock.advance(59 * 1000))
+        assert.equals(1, clock.advance(1000))
+        assert.equals(1, #mock.tasks())
+        assert.equals(3, mock.storage().last_sweep)
+        assert.equals("2024-01-01T00:01:00+00:00", clock.now())
+    end)
+
+    it("starts every case from a clean host", function()
+        assert.equals(1, #mock.tasks())
+        assert.has_error(function() plugin.call("missing") end, "is not defined")
+    end)
+
+    it("reports failures", function()
+        assert.equals("other", mock.tasks()[1].title)
+    end)
+
+    pending("not written yet")
+end)
+"#;
+
+    fn write_plugin(dir: &Path, main: &str) {
+        fs::create_dir_all(dir.join("spec")).unwrap();
+        fs::write(
+            dir.join(MANIFEST_FILE),
+            json!({
+                "name": "sweeper",
+                "version": "1.0.0",
+                "description": null,
+                "author": null,
+                "homepage_url": null,
+                "repository_url": null,
+                "license": null,
+                "language": "lua",
+                "main_file": "main.lua",
+                "permissions": ["task.read", "task.write", "storage.read", "storage.write", "notification.send"],
+                "dependencies": null,
+            })
+            .to_string(),
+        )
+        .unwrap();
+        fs::write(dir.join("main.lua"), main).unwrap();
+    }
+
+    fn start() -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(DEFAULT_START).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_harness_runs_specs() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("sweeper");
+        write_plugin(&dir, PLUGIN);
+        fs::write(dir.join("spec").join("sweeper_spec.lua"), SPEC).unwrap();
+        fs::write(dir.join("spec").join("broken_spec.lua"), "describe(").unwrap();
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let harness = Harness::new(&dir, start(), runtime.handle().clone()).unwrap();
+        let specs = find_specs(&dir).unwrap();
+        assert_eq!(specs.len(), 2);
+        let report = harness.run(&specs, &dir, None);
+
+        let broken = &report.suites[0];
+        assert!(broken.error.as_deref().unwrap().contains("broken_spec.lua"), "{:?}", broken.error);
+        let cases = &report.suites[1].cases;
+        let outcomes: Vec<_> = cases.iter().map(|case| (case.name.as_str(), &case.outcome)).collect();
+        assert_eq!(outcomes[0], ("tasks creates tasks and notifies", &Outcome::Passed));
+        assert_eq!(outcomes[1].1, &Outcome::Passed, "{:?}", outcomes[1]);
+        assert_eq!(outcomes[2].1, &Outcome::Passed, "{:?}", outcomes[2]);
+        let Outcome::Failed { message } = outcomes[3].1 else {
+            panic!("{:?}", outcomes[3]);
+        };
+        assert!(message.starts_with("spec/sweeper_spec.lua:"), "{}", message);
+        assert!(message.contains("expected: \"other\"\n  actual: \"write docs\""), "{}", message);
+        assert_eq!(outcomes[4].1, &Outcome::Skipped);
+        assert_eq!(
+            report.summary,
+            Summary { total: 5, passed: 3, failed: 1, skipped: 1, errors: 1 }
+        );
+        assert!(!report.success());
+
+        let junit = ReportFormat::Junit.render(&report);
+        assert!(junit.contains(r#"<testsuites name="sweeper" tests="6" failures="1" errors="1" skipped="1""#));
+        assert!(junit.contains("<failure message=\"spec/sweeper_spec.lua:"));
+        let filtered = harness.run(&specs[1..], &dir, Some("sweeps"));
+        assert_eq!(filtered.summary.passed, 1);
+        assert!(filtered.success());
+    }
+
+    #[test]
+    fn test_harness_ignores_same_named_siblings() {
+        let root = tempfile::tempdir().unwrap();
+        for copy in ["a-copy", "b-copy", "z-copy"] {
+            write_plugin(&root.path().join(copy), r#"function which() return "sibling" end"#);
+        }
+        let dir = root.path().join("sweeper");
+        write_plugin(&dir, r#"function which() return "tested" end"#);
+        fs::write(
+            dir.join("spec").join("which_spec.lua"),
+            r#"
+describe("plugin", function()
+    it("is the one under test", function() assert.equals("tested", plugin.call("which")) end)
+    it("stays the one under test after reload", function()
+        plugin.reload()
+        assert.equals("tested", plugin.call("which"))
+    end)
+end)
+"#,
+        )
+        .unwrap();
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        // 通过指向上级目录的相对路径传入，绑定的仍是规范化后的目录
+        let harness = Harness::new(&dir.join("spec").join(".."), start(), runtime.handle().clone()).unwrap();
+        let report = harness.run(&find_specs(&dir).unwrap(), &dir, None);
+        assert_eq!(report.summary.passed, 2, "{:?}", report.suites);
+        assert!(report.success());
+    }
+}
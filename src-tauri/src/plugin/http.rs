@@ -0,0 +1,788 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use ureq::http::Uri;
+
+use crate::plugin::diagnostics::{LogEntry, PluginLog, ToLogEntry};
+use crate::plugin::error::PluginError;
+use crate::plugin::{MethodAccess, PluginApi};
+
+/// 授予访问主机的权限前缀，例如 `http:api.example.com`
+pub const HOST_PERMISSION_PREFIX: &str = "http:";
+
+/// 每个插件保留的最近请求记录条数
+pub const DEFAULT_AUDIT_CAPACITY: usize = 200;
+
+/// 插件请求审计在 `logs` 表中的事件类型
+pub const PLUGIN_HTTP_EVENT: &str = "PLUGIN_HTTP";
+
+/// 请求超时的上限，`HttpPolicy::timeout_ms` 设置得更长时按此计算
+///
+/// 请求在插件的调用中同步执行，期间插件无法处理其他调用。
+pub const MAX_TIMEOUT_MS: u64 = 60_000;
+
+/// 插件可以使用的请求方法
+const METHODS: &[&str] = &["GET", "HEAD", "POST", "PUT", "PATCH", "DELETE", "OPTIONS"];
+
+/// 不允许携带请求体的方法
+const METHODS_WITHOUT_BODY: &[&str] = &["GET", "HEAD", "DELETE", "OPTIONS"];
+
+/// 跨主机重定向时移除的请求头，避免把凭据带到其他主机
+const CREDENTIAL_HEADERS: &[&str] = &["authorization", "cookie", "proxy-authorization"];
+
+/// 宿主对插件 HTTP 请求的统一限制
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HttpPolicy {
+    /// 单次请求（包括跟随的重定向）的超时，插件可以通过 `timeout` 参数缩短，最长为 [`MAX_TIMEOUT_MS`]
+    pub timeout_ms: u64,
+    /// 响应体大小上限，插件可以通过 `max_size` 参数缩小
+    pub max_response_size: u64,
+    /// 最多跟随的重定向次数，0 表示不跟随
+    pub max_redirects: u32,
+    /// 是否允许明文 `http://`，默认只允许 `https://`
+    pub allow_plain_http: bool,
+}
+
+impl Default for HttpPolicy {
+    fn default() -> Self {
+        Self {
+            timeout_ms: 30_000,
+            max_response_size: 10 * 1024 * 1024,
+            max_redirects: 5,
+            allow_plain_http: false,
+        }
+    }
+}
+
+/// 交给传输层的单个请求，重定向由 [`HttpApi`] 处理，传输层不应自动跟随
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpRequest {
+    pub method: String,
+    pub url: String,
+    /// 请求头，名称为小写
+    pub headers: BTreeMap<String, String>,
+    pub body: Option<Vec<u8>>,
+    pub timeout: Duration,
+    /// 响应体超过该大小时应返回 [`TransportError::TooLarge`]
+    pub max_response_size: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct HttpResponse {
+    pub status: u16,
+    /// 响应头，名称为小写，重复的响应头以 `, ` 连接
+    pub headers: BTreeMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransportError {
+    Timeout,
+    TooLarge,
+    Failed(String),
+}
+
+impl fmt::Display for TransportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransportError::Timeout => write!(f, "request timed out"),
+            TransportError::TooLarge => write!(f, "response body is too large"),
+            TransportError::Failed(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+/// 发送 HTTP 请求的传输层，测试中可以替换为本地的模拟实现
+pub trait HttpTransport: Send + Sync {
+    fn send(&self, request: &HttpRequest) -> Result<HttpResponse, TransportError>;
+}
+
+/// 基于 `ureq` 的默认传输层
+pub struct UreqTransport {
+    agent: ureq::Agent,
+}
+
+impl UreqTransport {
+    pub fn new() -> Self {
+        let config = ureq::Agent::config_builder()
+            .http_status_as_error(false)
+            .max_redirects(0)
+            .user_agent(concat!("todo-plugin/", env!("CARGO_PKG_VERSION")))
+            .build();
+        Self { agent: config.into() }
+    }
+
+    fn run<S: ureq::AsSendBody>(
+        &self,
+        request: ureq::http::Request<S>,
+        timeout: Duration,
+    ) -> Result<ureq::http::Response<ureq::Body>, ureq::Error> {
+        let request = self.agent.configure_request(request).timeout_global(Some(timeout)).build();
+        self.agent.run(request)
+    }
+}
+
+impl Default for UreqTransport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HttpTransport for UreqTransport {
+    fn send(&self, request: &HttpRequest) -> Result<HttpResponse, TransportError> {
+        let mut builder = ureq::http::Request::builder()
+            .method(request.method.as_str())
+            .uri(request.url.as_str());
+        for (name, value) in &request.headers {
+            builder = builder.header(name.as_str(), value.as_str());
+        }
+        let failed = |e: ureq::http::Error| TransportError::Failed(e.to_string());
+        let response = match &request.body {
+            Some(body) => self.run(builder.body(body.clone()).map_err(failed)?, request.timeout),
+            None => self.run(builder.body(()).map_err(failed)?, request.timeout),
+        };
+        let mut response = response.map_err(transport_error)?;
+
+        let mut headers = BTreeMap::<String, String>::new();
+        for (name, value) in response.headers() {
+            let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+            headers
+                .entry(name.as_str().to_string())
+                .and_modify(|existing| {
+                    existing.push_str(", ");
+                    existing.push_str(&value);
+                })
+                .or_insert(value);
+        }
+        let body = response
+            .body_mut()
+            .with_config()
+            .limit(request.max_response_size)
+            .read_to_vec()
+            .map_err(transport_error)?;
+        Ok(HttpResponse {
+            status: response.status().as_u16(),
+            headers,
+            body,
+        })
+    }
+}
+
+fn transport_error(err: ureq::Error) -> TransportError {
+    match err {
+        ureq::Error::Timeout(_) => TransportError::Timeout,
+        ureq::Error::BodyExceedsLimit(_) => TransportError::TooLarge,
+        ureq::Error::Io(e) if e.kind() == std::io::ErrorKind::TimedOut => TransportError::Timeout,
+        e => TransportError::Failed(e.to_string()),
+    }
+}
+
+/// 插件在权限中声明的可访问主机
+///
+/// - `http:api.example.com` 允许该主机的任意端口
+/// - `http:*.example.com` 允许 `example.com` 的所有子域名，不包括 `example.com` 本身
+/// - `http:localhost:8080` 只允许该端口
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HostAllowlist {
+    patterns: Vec<(String, Option<u16>)>,
+}
+
+impl HostAllowlist {
+    pub fn from_permissions<I, S>(permissions: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let patterns = permissions
+            .into_iter()
+            .filter_map(|permission| {
+                let pattern = permission.as_ref().strip_prefix(HOST_PERMISSION_PREFIX)?.to_ascii_lowercase();
+                // IPv6 地址本身包含冒号，只有 `]:` 之后或不含方括号时的最后一个冒号是端口
+                let has_port = |host: &str| !host.is_empty() && (!pattern.starts_with('[') || host.ends_with(']'));
+                match pattern.rsplit_once(':') {
+                    Some((host, port)) if has_port(host) => Some((host.to_string(), Some(port.parse().ok()?))),
+                    _ => Some((pattern, None)),
+                }
+            })
+            .collect();
+        Self { patterns }
+    }
+
+    pub fn allows(&self, host: &str, port: u16) -> bool {
+        let host = host.to_ascii_lowercase();
+        self.patterns.iter().any(|(pattern, allowed_port)| {
+            let host_matches = match pattern.strip_prefix("*.") {
+                Some(domain) => host
+                    .strip_suffix(domain)
+                    .is_some_and(|sub| sub.ends_with('.') && sub.len() > 1),
+                None => *pattern == host,
+            };
+            host_matches && allowed_port.is_none_or(|p| p == port)
+        })
+    }
+}
+
+/// 一次插件 HTTP 请求的审计记录
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HttpAuditRecord {
+    pub plugin_id: String,
+    pub method: String,
+    /// 请求地址，去掉了查询参数和片段，避免把令牌写入日志
+    pub url: String,
+    /// 最终响应的状态码，请求没有发出或失败时为空
+    pub status: Option<u16>,
+    /// 依次跟随的重定向地址
+    pub redirects: Vec<String>,
+    pub request_size: u64,
+    pub response_size: u64,
+    pub duration_ms: u64,
+    pub error: Option<String>,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl ToLogEntry for HttpAuditRecord {
+    fn plugin_id(&self) -> &str {
+        &self.plugin_id
+    }
+
+    fn to_log_entry(&self) -> LogEntry {
+        let outcome = match (&self.status, &self.error) {
+            (_, Some(error)) => error.lines().next().unwrap_or_default().to_string(),
+            (Some(status), None) => status.to_string(),
+            (None, None) => String::new(),
+        };
+        LogEntry {
+            log_level: if self.error.is_some() { "WARN" } else { "INFO" }.to_string(),
+            event_type: PLUGIN_HTTP_EVENT.to_string(),
+            message: format!("{} {} -> {}", self.method, self.url, outcome),
+            details: serde_json::to_value(self).unwrap_or(Value::Null),
+            source: format!("plugin:{}", self.plugin_id),
+            created_at: self.timestamp,
+        }
+    }
+}
+
+/// 插件最近的请求记录
+pub type HttpAuditLog = PluginLog<HttpAuditRecord>;
+
+/// 所有插件共享的传输层、限制和审计记录，修改后对已加载的插件立即生效
+pub struct HttpClient {
+    transport: Mutex<Arc<dyn HttpTransport>>,
+    policy: Mutex<HttpPolicy>,
+    audit: HttpAuditLog,
+}
+
+impl HttpClient {
+    pub fn new(transport: Arc<dyn HttpTransport>) -> Self {
+        Self {
+            transport: Mutex::new(transport),
+            policy: Mutex::new(HttpPolicy::default()),
+            audit: HttpAuditLog::new(DEFAULT_AUDIT_CAPACITY),
+        }
+    }
+
+    pub fn set_transport(&self, transport: Arc<dyn HttpTransport>) {
+        *self.transport.lock().unwrap() = transport;
+    }
+
+    pub fn policy(&self) -> HttpPolicy {
+        self.policy.lock().unwrap().clone()
+    }
+
+    pub fn set_policy(&self, policy: HttpPolicy) {
+        *self.policy.lock().unwrap() = policy;
+    }
+
+    pub fn audit(&self) -> &HttpAuditLog {
+        &self.audit
+    }
+}
+
+impl Default for HttpClient {
+    fn default() -> Self {
+        Self::new(Arc::new(UreqTransport::new()))
+    }
+}
+
+/// 插件的 HTTP API
+///
+/// 只能访问权限中以 `http:<主机>` 声明的主机（见 [`HostAllowlist`]），重定向的每一跳都会重新检查。
+/// 非 2xx 的状态码作为正常响应返回，由插件自行判断。
+///
+/// | 方法 | 参数 |
+/// |------|------|
+/// | `request` | `method`, `url`, `headers?`, `body?` / `json?`, `timeout?`（毫秒）, `max_size?`, `follow_redirects?`, `encoding?` |
+/// | `get` / `post` | 同 `request`，不需要 `method` |
+///
+/// 返回 `{ status, headers, body, url, redirected }`，响应为 JSON 时额外包含解析后的 `json`。
+/// `encoding = "base64"` 时 `body` 以 base64 返回。
+pub struct HttpApi {
+    plugin_id: String,
+    allowlist: HostAllowlist,
+    client: Arc<HttpClient>,
+}
+
+impl HttpApi {
+    pub fn new(plugin_id: &str, permissions: &[String], client: Arc<HttpClient>) -> Self {
+        Self {
+            plugin_id: plugin_id.to_string(),
+            allowlist: HostAllowlist::from_permissions(permissions),
+            client,
+        }
+    }
+
+    /// 发出请求并记录审计日志，无论成功与否
+    fn request(&self, method: &str, params: Value) -> Result<Value> {
+        let started = Instant::now();
+        let mut record = HttpAuditRecord {
+            plugin_id: self.plugin_id.clone(),
+            method: method.to_ascii_uppercase(),
+            url: redact(params["url"].as_str().unwrap_or("")),
+            status: None,
+            redirects: Vec::new(),
+            request_size: 0,
+            response_size: 0,
+            duration_ms: 0,
+            error: None,
+            timestamp: Utc::now(),
+        };
+        let result = self.execute(&params, &mut record);
+        record.duration_ms = started.elapsed().as_millis() as u64;
+        record.error = result.as_ref().err().map(|e| format!("{:#}", e));
+        self.client.audit().record(record);
+        result
+    }
+
+    fn execute(&self, params: &Value, record: &mut HttpAuditRecord) -> Result<Value> {
+        let policy = self.client.policy();
+        let transport = self.client.transport.lock().unwrap().clone();
+        let mut method = record.method.clone();
+        if !METHODS.contains(&method.as_str()) {
+            return Err(anyhow::anyhow!("Unsupported HTTP method: {}", method));
+        }
+        let mut url = params["url"]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("Missing 'url'"))?
+            .to_string();
+
+        let mut headers = BTreeMap::new();
+        if let Some(object) = params["headers"].as_object() {
+            for (name, value) in object {
+                let value = value.as_str().map_or_else(|| value.to_string(), str::to_string);
+                headers.insert(name.to_ascii_lowercase(), value);
+            }
+        }
+        let mut body = match (&params["json"], params["body"].as_str()) {
+            (Value::Null, body) => body.map(|body| body.as_bytes().to_vec()),
+            (json, _) => {
+                headers
+                    .entry("content-type".to_string())
+                    .or_insert_with(|| "application/json".to_string());
+                Some(serde_json::to_vec(json)?)
+            }
+        };
+        if body.is_some() && METHODS_WITHOUT_BODY.contains(&method.as_str()) {
+            return Err(anyhow::anyhow!("{} requests cannot have a body", method));
+        }
+        record.request_size = body.as_ref().map_or(0, |body| body.len() as u64);
+
+        let max_timeout_ms = policy.timeout_ms.min(MAX_TIMEOUT_MS);
+        let timeout_ms = params["timeout"].as_u64().map_or(max_timeout_ms, |t| t.min(max_timeout_ms));
+        let max_size = params["max_size"]
+            .as_u64()
+            .map_or(policy.max_response_size, |s| s.min(policy.max_response_size));
+        let follow_redirects = params["follow_redirects"].as_bool().unwrap_or(true);
+        let deadline = Instant::now() + Duration::from_millis(timeout_ms);
+        let timed_out = |url: &str| PluginError::HttpTimeout {
+            plugin_id: self.plugin_id.clone(),
+            url: redact(url),
+            timeout_ms,
+        };
+
+        loop {
+            let uri = self.check_url(&url, &policy)?;
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(timed_out(&url).into());
+            }
+            let request = HttpRequest {
+                method: method.clone(),
+                url: url.clone(),
+                headers: headers.clone(),
+                body: body.clone(),
+                timeout: remaining,
+                max_response_size: max_size,
+            };
+            let too_large = || PluginError::ResponseTooLarge {
+                plugin_id: self.plugin_id.clone(),
+                url: redact(&url),
+                limit: max_size,
+            };
+            let response = transport.send(&request).map_err(|e| match e {
+                TransportError::Timeout => timed_out(&url).into(),
+                TransportError::TooLarge => too_large().into(),
+                TransportError::Failed(message) => {
+                    anyhow::anyhow!("HTTP request to {} failed: {}", redact(&url), message)
+                }
+            })?;
+            // 传输层可能没有限制大小，这里再检查一次
+            if response.body.len() as u64 > max_size {
+                return Err(too_large().into());
+            }
+            record.status = Some(response.status);
+            record.response_size = response.body.len() as u64;
+
+            let location = response.headers.get("location").filter(|_| is_redirect(response.status));
+            let Some(location) = location.filter(|_| follow_redirects) else {
+                return Ok(response_value(&url, !record.redirects.is_empty(), response, params));
+            };
+            if record.redirects.len() >= policy.max_redirects as usize {
+                return Err(anyhow::anyhow!(
+                    "Too many redirects (more than {}) from {}",
+                    policy.max_redirects,
+                    record.url
+                ));
+            }
+            let next = resolve_location(&uri, location);
+            let next_uri: Uri = next
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Invalid redirect location: {}", redact(&next)))?;
+            if uri.scheme_str() == Some("https") && next_uri.scheme_str() != Some("https") {
+                return Err(anyhow::anyhow!("Refusing to follow redirect from https to {}", redact(&next)));
+            }
+            if next_uri.host() != uri.host() {
+                for name in CREDENTIAL_HEADERS {
+                    headers.remove(*name);
+                }
+            }
+            // 303 以及 POST 的 301/302 改为不带请求体的 GET，与浏览器的行为一致
+            if response.status == 303 || (matches!(response.status, 301 | 302) && method == "POST") {
+                if method != "HEAD" {
+                    method = "GET".to_string();
+                }
+                body = None;
+                headers.remove("content-type");
+            }
+            record.redirects.push(redact(&next));
+            url = next;
+        }
+    }
+
+    /// 校验地址的协议和主机是否允许访问
+    fn check_url(&self, url: &str, policy: &HttpPolicy) -> Result<Uri> {
+        let uri: Uri = url.parse().map_err(|_| anyhow::anyhow!("Invalid URL: {}", redact(url)))?;
+        let default_port = match uri.scheme_str() {
+            Some("https") => 443,
+            Some("http") if policy.allow_plain_http => 80,
+            Some("http") => return Err(anyhow::anyhow!("Plain http is not allowed, use https: {}", redact(url))),
+            _ => return Err(anyhow::anyhow!("Unsupported URL scheme: {}", redact(url))),
+        };
+        let authority = uri.authority().ok_or_else(|| anyhow::anyhow!("Invalid URL: {}", redact(url)))?;
+        if authority.as_str().contains('@') {
+            return Err(anyhow::anyhow!("URLs with credentials are not allowed: {}", redact(url)));
+        }
+        let host = authority.host();
+        if !self.allowlist.allows(host, authority.port_u16().unwrap_or(default_port)) {
+            return Err(PluginError::HostNotAllowed {
+                plugin_id: self.plugin_id.clone(),
+                host: host.to_string(),
+            }
+            .into());
+        }
+        Ok(uri)
+    }
+}
+
+impl PluginApi for HttpApi {
+    fn call(&self, method: &str, params: Value) -> Result<Value> {
+        match method {
+            "request" => {
+                let method = params["method"].as_str().unwrap_or("GET").to_string();
+                self.request(&method, params)
+            }
+            "get" => self.request("GET", params),
+            "post" => self.request("POST", params),
+            _ => Err(anyhow::anyhow!("Unknown http method: {}", method)),
+        }
+    }
+
+    fn get_permissions(&self) -> Vec<String> {
+        vec![]
+    }
+
+    // 主机权限在调用时按请求地址检查
+    fn method_access(&self, method: &str) -> Option<MethodAccess> {
+        match method {
+            "request" | "get" | "post" => Some(MethodAccess::Public),
+            _ => None,
+        }
+    }
+}
+
+fn is_redirect(status: u16) -> bool {
+    matches!(status, 301 | 302 | 303 | 307 | 308)
+}
+
+/// 按当前地址解析 `Location`，支持绝对地址、`//host/path`、绝对路径和相对路径
+fn resolve_location(base: &Uri, location: &str) -> String {
+    if location.contains("://") {
+        return location.to_string();
+    }
+    let scheme = base.scheme_str().unwrap_or("https");
+    if let Some(rest) = location.strip_prefix("//") {
+        return format!("{}://{}", scheme, rest);
+    }
+    let authority = base.authority().map_or("", |a| a.as_str());
+    if location.starts_with('/') {
+        return format!("{}://{}{}", scheme, authority, location);
+    }
+    let path = base.path();
+    let dir = &path[..path.rfind('/').map_or(0, |i| i + 1)];
+    format!("{}://{}{}{}", scheme, authority, if dir.is_empty() { "/" } else { dir }, location)
+}
+
+/// 去掉地址中的查询参数和片段
+fn redact(url: &str) -> String {
+    url.split(['?', '#']).next().unwrap_or_default().to_string()
+}
+
+fn response_value(url: &str, redirected: bool, response: HttpResponse, params: &Value) -> Value {
+    let is_json = response
+        .headers
+        .get("content-type")
+        .is_some_and(|content_type| content_type.contains("json"));
+    let parsed = if is_json {
+        serde_json::from_slice::<Value>(&response.body).ok()
+    } else {
+        None
+    };
+    let body = match params["encoding"].as_str().unwrap_or("utf8") {
+        "base64" => base64::engine::general_purpose::STANDARD.encode(&response.body),
+        _ => String::from_utf8_lossy(&response.body).into_owned(),
+    };
+    let mut value = json!({
+        "status": response.status,
+        "headers": response.headers,
+        "body": body,
+        "url": url,
+        "redirected": redirected,
+    });
+    if let Some(parsed) = parsed {
+        value["json"] = parsed;
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+
+    /// 本地的 HTTP 服务，按路径返回固定的响应
+    fn serve() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let mut reader = BufReader::new(stream);
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if let Some(length) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                        content_length = length.trim().parse().unwrap();
+                    }
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+
+                let path = request_line.split_whitespace().nth(1).unwrap_or("/").to_string();
+                let (status, headers, body) = match path.split('?').next().unwrap() {
+                    "/ok" => ("200 OK", "Content-Type: text/plain\r\n".to_string(), b"hello".to_vec()),
+                    "/echo" => ("201 Created", "Content-Type: application/json\r\n".to_string(), body),
+                    "/redirect" => ("302 Found", "Location: /ok\r\n".to_string(), vec![]),
+                    "/away" => ("302 Found", "Location: http://evil.test/x\r\n".to_string(), vec![]),
+                    "/loop" => ("307 Temporary Redirect", "Location: loop\r\n".to_string(), vec![]),
+                    "/big" => ("200 OK", String::new(), vec![b'x'; 2000]),
+                    "/slow" => {
+                        std::thread::sleep(Duration::from_millis(500));
+                        ("200 OK", String::new(), vec![])
+                    }
+                    _ => ("404 Not Found", String::new(), vec![]),
+                };
+                let mut stream = reader.into_inner();
+                let head = format!(
+                    "HTTP/1.1 {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n",
+                    status,
+                    headers,
+                    body.len()
+                );
+                let _ = stream.write_all(head.as_bytes());
+                let _ = stream.write_all(&body);
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    fn api(permissions: &[&str]) -> (HttpApi, Arc<HttpClient>) {
+        let client = Arc::new(HttpClient::default());
+        client.set_policy(HttpPolicy {
+            max_response_size: 1000,
+            allow_plain_http: true,
+            ..HttpPolicy::default()
+        });
+        let permissions: Vec<String> = permissions.iter().map(|p| p.to_string()).collect();
+        (HttpApi::new("p", &permissions, Arc::clone(&client)), client)
+    }
+
+    fn code(err: &anyhow::Error) -> Option<String> {
+        err.downcast_ref::<PluginError>()
+            .and_then(|e| e.to_value()["code"].as_str().map(str::to_string))
+    }
+
+    #[test]
+    fn test_host_allowlist() {
+        let allowlist = HostAllowlist::from_permissions([
+            "http:API.example.com",
+            "http:*.corp.test",
+            "http:localhost:8080",
+            "fs.read",
+        ]);
+        assert!(allowlist.allows("api.example.com", 443));
+        assert!(!allowlist.allows("example.com", 443));
+        assert!(allowlist.allows("a.b.corp.test", 443));
+        assert!(!allowlist.allows("corp.test", 443));
+        assert!(!allowlist.allows("evilcorp.test", 443));
+        assert!(allowlist.allows("localhost", 8080));
+        assert!(!allowlist.allows("localhost", 8081));
+    }
+
+    #[test]
+    fn test_requests_against_local_server() {
+        let base = serve();
+        let (api, client) = api(&["http:127.0.0.1"]);
+
+        let response = api.call("get", json!({ "url": format!("{}/ok?token=secret", base) })).unwrap();
+        assert_eq!(response["status"], 200);
+        assert_eq!(response["body"], "hello");
+        assert_eq!(response["headers"]["content-type"], "text/plain");
+
+        let response = api
+            .call("post", json!({ "url": format!("{}/echo", base), "json": { "title": "x" } }))
+            .unwrap();
+        assert_eq!(response["status"], 201);
+        assert_eq!(response["json"], json!({ "title": "x" }));
+
+        let response = api.call("get", json!({ "url": format!("{}/redirect", base) })).unwrap();
+        assert_eq!(response["body"], "hello");
+        assert_eq!(response["redirected"], true);
+        assert_eq!(response["url"], format!("{}/ok", base));
+        let response = api
+            .call("get", json!({ "url": format!("{}/redirect", base), "follow_redirects": false }))
+            .unwrap();
+        assert_eq!(response["status"], 302);
+
+        // 重定向到未授权的主机、重定向次数过多、响应过大、超时
+        let err = api.call("get", json!({ "url": format!("{}/away", base) })).unwrap_err();
+        assert_eq!(code(&err).as_deref(), Some("host_not_allowed"));
+        assert!(format!("{:#}", err).contains("evil.test"));
+        let err = api.call("get", json!({ "url": format!("{}/loop", base) })).unwrap_err();
+        assert!(format!("{:#}", err).contains("Too many redirects"));
+        let err = api.call("get", json!({ "url": format!("{}/big", base) })).unwrap_err();
+        assert_eq!(code(&err).as_deref(), Some("response_too_large"));
+        let err = api.call("get", json!({ "url": format!("{}/slow", base), "timeout": 100 })).unwrap_err();
+        assert_eq!(code(&err).as_deref(), Some("http_timeout"));
+
+        let err = api.call("get", json!({ "url": "https://example.com/" })).unwrap_err();
+        assert_eq!(code(&err).as_deref(), Some("host_not_allowed"));
+        assert!(api.call("get", json!({ "url": format!("{}/ok", base), "body": "x" })).is_err());
+
+        // 每个请求都有记录，查询参数不会写入
+        let audit = client.audit().recent("p", 100);
+        assert_eq!(audit.len(), 10);
+        assert!(audit.iter().all(|r| !r.url.contains("token")));
+        let find = |method: &str, path: &str, status: Option<u16>| {
+            let url = format!("{}{}", base, path);
+            audit
+                .iter()
+                .find(|r| r.method == method && r.url == url && r.status == status)
+                .unwrap_or_else(|| panic!("no {} {} record with status {:?}", method, path, status))
+        };
+        assert_eq!(find("GET", "/ok", Some(200)).response_size, 5);
+        assert!(find("GET", "/ok", None).error.is_some());
+        assert_eq!(find("POST", "/echo", Some(201)).request_size, 13);
+        assert_eq!(find("GET", "/redirect", Some(200)).redirects, vec![format!("{}/ok", base)]);
+        assert!(find("GET", "/redirect", Some(302)).redirects.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_slow_request_not_charged_to_budget() {
+        use crate::plugin::lua::LuaRuntime;
+        use crate::plugin::{PluginMetadata, PluginRuntime, ResourceLimits};
+
+        let base = serve();
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("main.lua"),
+            format!(r#"
+                function fetch()
+                    local status = api.http("get", {{ url = "{}/slow" }}).status
+                    local sum = 0
+                    for i = 1, 100000 do sum = sum + i end
+                    return status
+                end
+            "#, base),
+        )
+        .unwrap();
+        let metadata: PluginMetadata = serde_json::from_value(json!({
+            "name": "p",
+            "version": "1.0.0",
+            "description": null,
+            "author": null,
+            "homepage_url": null,
+            "repository_url": null,
+            "license": null,
+            "language": "lua",
+            "main_file": "main.lua",
+            "permissions": ["http:127.0.0.1"],
+            "dependencies": null,
+        }))
+        .unwrap();
+        let runtime = LuaRuntime::new(metadata, dir.path().join("main.lua").display().to_string()).unwrap();
+        let (api, _client) = api(&["http:127.0.0.1"]);
+        runtime.register_api("http", Arc::new(api)).unwrap();
+        runtime
+            .set_resource_limits(ResourceLimits {
+                max_cpu_time: Some(100),
+                ..ResourceLimits::default()
+            })
+            .unwrap();
+        runtime.init().await.unwrap();
+
+        // 服务端 500 毫秒后才响应，等待时间不计入 100 毫秒的执行预算
+        assert_eq!(runtime.call_function("fetch", vec![]).await.unwrap(), json!(200));
+        assert_eq!(runtime.get_resource_usage().unwrap().cpu_violations, 0);
+    }
+
+    #[test]
+    fn test_plain_http_requires_policy() {
+        let base = serve();
+        let (api, client) = api(&["http:127.0.0.1"]);
+        client.set_policy(HttpPolicy::default());
+        let err = api.call("get", json!({ "url": format!("{}/ok", base) })).unwrap_err();
+        assert!(format!("{:#}", err).contains("Plain http"));
+    }
+}
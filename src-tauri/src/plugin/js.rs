@@ -0,0 +1,518 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use async_trait::async_trait;
+use anyhow::{anyhow, Context as _, Result};
+use rquickjs::function::Rest;
+use rquickjs::{Context, Ctx, Exception, Function, Object, Promise, Runtime, Value as JsValue};
+
+use crate::plugin::bridge::{self, ApiBridge};
+use crate::plugin::error::PluginError;
+use crate::plugin::limits::{BudgetTracker, ResourceMonitor};
+use crate::plugin::typescript;
+use crate::plugin::{PluginApi, PluginMessage, PluginMetadata, PluginRuntime, ResourceLimits, ResourceUsage};
+
+/// 在全局对象上构造插件可见的 `api` 对象
+///
+/// 底层调用返回 JSON 字符串 `{ ok, result }` 或 `{ ok, error }`，失败时抛出带有
+/// `code`、`message` 等字段的 `Error`，插件可以用 `try/catch` 读取。
+const API_WRAPPER: &str = r#"
+(function (rawCall, names) {
+    const api = {};
+    for (const name of names) {
+        api[name] = function (method, params) {
+            const response = JSON.parse(rawCall(name, method, JSON.stringify(params === undefined ? null : params)));
+            if (!response.ok) {
+                const error = new Error(response.error.message);
+                Object.assign(error, response.error);
+                throw error;
+            }
+            return response.result;
+        };
+    }
+    return Object.freeze(api);
+})
+"#;
+
+/// 基于 `api.bus` 构造插件可见的 `bus` 对象
+const BUS_WRAPPER: &str = r#"
+(function (call) {
+    return Object.freeze({
+        publish: (message_type, payload, target) => call("publish", { message_type, payload, target }),
+        subscribe: (pattern) => call("subscribe", { pattern }),
+        unsubscribe: (pattern) => call("unsubscribe", { pattern }),
+    });
+})
+"#;
+
+/// 移除插件动态执行代码的入口：全局的 `eval`、`Function`，以及普通、异步、生成器函数原型上的 `constructor`
+///
+/// 宿主通过 `Ctx::eval` 加载脚本不受影响。
+const DYNAMIC_CODE_GUARD: &str = r#"
+(function () {
+    const blocked = function () {
+        throw new EvalError("dynamic code evaluation is not allowed");
+    };
+    for (const fn of [function () {}, async function () {}, function* () {}, async function* () {}]) {
+        Object.defineProperty(Object.getPrototypeOf(fn), "constructor", {
+            value: blocked,
+            writable: false,
+            configurable: false,
+        });
+    }
+    delete globalThis.eval;
+    delete globalThis.Function;
+})();
+"#;
+
+/// QuickJS 大约每执行这么多条字节码调用一次中断检查
+const INTERRUPT_INTERVAL: u64 = 10_000;
+
+/// 基于 QuickJS 的 JavaScript / TypeScript 插件运行时
+///
+/// `.ts` 入口文件在加载时擦除类型后执行，见 [`typescript::strip_types`]。
+/// 插件在全局作用域中定义 `init`、`start`、`stop`、`unload`、`on_message` 等函数，
+/// 与 Lua 插件的约定一致。
+pub struct JsRuntime {
+    runtime: Runtime,
+    context: Context,
+    // 串行化对脚本的调用，保证每次调用使用自己的执行预算
+    entry: Mutex<()>,
+    metadata: PluginMetadata,
+    script_path: String,
+    bridge: Arc<ApiBridge>,
+    resource_limits: Arc<Mutex<ResourceLimits>>,
+    monitor: ResourceMonitor,
+    budget: Arc<Mutex<Option<Arc<BudgetTracker>>>>,
+}
+
+impl JsRuntime {
+    pub fn new(metadata: PluginMetadata, script_path: String) -> Result<Self> {
+        let runtime = Runtime::new()?;
+        let context = Context::full(&runtime)?;
+
+        let limits = ResourceLimits {
+            max_memory: Some(50 * 1024 * 1024), // 默认 50MB
+            max_cpu_time: Some(1000),           // 默认 1 秒
+            max_instructions: None,             // 默认不限制指令数
+            max_tasks: Some(10),                // 默认最多 10 个并发任务
+            max_api_calls: Some(1000),          // 默认每分钟 1000 次 API 调用
+            api_quotas: HashMap::new(),
+            max_disk_usage: None,
+        };
+        runtime.set_memory_limit(limits.max_memory.unwrap_or(0));
+
+        // 中断检查由当前调用的执行预算决定，没有调用在执行时不会中断
+        let budget: Arc<Mutex<Option<Arc<BudgetTracker>>>> = Arc::new(Mutex::new(None));
+        let handler_budget = Arc::clone(&budget);
+        runtime.set_interrupt_handler(Some(Box::new(move || {
+            handler_budget
+                .lock()
+                .unwrap()
+                .as_ref()
+                .is_some_and(|tracker| tracker.tick(INTERRUPT_INTERVAL).is_some())
+        })));
+
+        context.with(Self::setup_sandbox)?;
+
+        let monitor = ResourceMonitor::new();
+        monitor.record_memory(runtime.memory_usage().malloc_size as usize);
+
+        let bridge = ApiBridge::new(&metadata);
+        bridge.set_limits(&limits);
+
+        Ok(Self {
+            runtime,
+            context,
+            entry: Mutex::new(()),
+            bridge: Arc::new(bridge),
+            metadata,
+            script_path,
+            resource_limits: Arc::new(Mutex::new(limits)),
+            monitor,
+            budget,
+        })
+    }
+
+    /// 进入 JavaScript 执行代码的唯一入口
+    ///
+    /// 与 `LuaRuntime::enter` 相同：每次进入都有独立的执行预算，超时和内存超限被转换为
+    /// 对应的 [`PluginError`]，脚本抛出的异常转换为包含堆栈的错误。
+    fn enter<R>(
+        &self,
+        context: impl FnOnce() -> String,
+        f: impl for<'js> FnOnce(&Ctx<'js>) -> rquickjs::Result<R>,
+    ) -> Result<R> {
+        let _entry = self.entry.lock().unwrap();
+        let tracker = Arc::new(BudgetTracker::new(&self.resource_limits.lock().unwrap()));
+        *self.budget.lock().unwrap() = Some(Arc::clone(&tracker));
+        self.bridge.set_budget(Some(Arc::clone(&tracker)));
+
+        let result = self.context.with(|ctx| {
+            f(&ctx).map_err(|err| match err {
+                rquickjs::Error::Exception => {
+                    let exception = ctx.catch();
+                    if is_out_of_memory(&exception) {
+                        anyhow::Error::new(rquickjs::Error::Allocation)
+                    } else {
+                        anyhow!(describe_exception(exception))
+                    }
+                }
+                err => anyhow::Error::new(err),
+            })
+        });
+
+        *self.budget.lock().unwrap() = None;
+        self.bridge.set_budget(None);
+        self.monitor.record_cpu_time(tracker.elapsed());
+
+        if let Some(err) = tracker.error(&self.metadata.name) {
+            self.monitor.record_cpu_violation();
+            self.monitor.record_memory(self.memory_used());
+            return Err(anyhow::Error::new(err).context(context()));
+        }
+
+        if let Err(err) = &result {
+            if is_memory_error(err) {
+                let limit = self.resource_limits.lock().unwrap().max_memory.unwrap_or(0);
+                self.monitor.record_memory_violation(self.memory_used());
+                // 尽量回收本次调用留下的垃圾，让插件后续调用可以继续执行
+                self.runtime.run_gc();
+                self.monitor.record_memory(self.memory_used());
+                return Err(anyhow::Error::new(PluginError::MemoryLimitExceeded {
+                    plugin_id: self.metadata.name.clone(),
+                    limit,
+                })
+                .context(context()));
+            }
+        }
+
+        self.monitor.record_memory(self.memory_used());
+        result.with_context(context)
+    }
+
+    fn memory_used(&self) -> usize {
+        self.runtime.memory_usage().malloc_size.max(0) as usize
+    }
+
+    fn setup_sandbox(ctx: Ctx<'_>) -> rquickjs::Result<()> {
+        // QuickJS 本身不包含文件、网络、进程等宿主能力，只需要去掉动态执行代码的入口并补充日志输出
+        ctx.eval::<(), _>(DYNAMIC_CODE_GUARD)?;
+        let globals = ctx.globals();
+        // 内存不足时抛出的是 InternalError，去掉构造函数以免插件伪造内存超限
+        globals.remove("InternalError")?;
+        let print = Function::new(ctx.clone(), |msg: rquickjs::Coerced<String>| {
+            println!("[JS Plugin]: {}", msg.0);
+        })?;
+        let console = Object::new(ctx.clone())?;
+        console.set("log", print.clone())?;
+        globals.set("console", console)?;
+        globals.set("print", print)?;
+        Ok(())
+    }
+
+    fn setup_api_bindings(&self) -> Result<()> {
+        let api_bridge = Arc::clone(&self.bridge);
+        let names = self.bridge.api_names();
+        self.enter(
+            || "Failed to set up API bindings".to_string(),
+            move |ctx| {
+                // 所有 API 调用都经过 ApiBridge 完成权限校验和限流
+                let raw_call = Function::new(ctx.clone(), move |api: String, method: String, params: String| {
+                    let response = serde_json::from_str(&params)
+                        .map_err(anyhow::Error::from)
+                        .and_then(|params| api_bridge.call(&api, &method, params));
+                    let response = match response {
+                        Ok(result) => serde_json::json!({ "ok": true, "result": result }),
+                        Err(e) => serde_json::json!({ "ok": false, "error": bridge::error_value(&e) }),
+                    };
+                    response.to_string()
+                })?;
+
+                let make_api: Function = ctx.eval(API_WRAPPER)?;
+                let api: Object = make_api.call((raw_call, names))?;
+                let globals = ctx.globals();
+                if let Some(bus_call) = api.get::<_, Option<Function>>("bus")? {
+                    let make_bus: Function = ctx.eval(BUS_WRAPPER)?;
+                    globals.set("bus", make_bus.call::<_, Object>((bus_call,))?)?;
+                }
+                globals.set("api", api)?;
+                Ok(())
+            },
+        )
+    }
+
+    fn load_script(&self) -> Result<()> {
+        let source = std::fs::read_to_string(&self.script_path)
+            .context("Failed to read JavaScript plugin script")?;
+        let source = if self.metadata.main_file.ends_with(".ts") {
+            typescript::strip_types(&source, &self.metadata.main_file)?
+        } else {
+            source
+        };
+
+        self.enter(
+            || format!("Failed to execute script {}", self.metadata.main_file),
+            |ctx| ctx.eval::<(), _>(source),
+        )
+    }
+
+    /// 调用可选的生命周期钩子，插件未定义该函数时直接跳过
+    fn call_hook(&self, name: &str) -> Result<()> {
+        self.enter(
+            || format!("Failed to call JavaScript function '{}'", name),
+            |ctx| match ctx.globals().get::<_, Option<Function>>(name)? {
+                Some(func) => settle(func.call::<_, JsValue>(())?).map(|_| ()),
+                None => Ok(()),
+            },
+        )
+    }
+}
+
+/// 等待 `async` 函数返回的 Promise 完成；普通返回值原样返回
+fn settle(value: JsValue<'_>) -> rquickjs::Result<JsValue<'_>> {
+    match value.as_promise() {
+        Some(promise) => Promise::finish(promise),
+        None => Ok(value),
+    }
+}
+
+fn to_json<'js>(ctx: &Ctx<'js>, value: JsValue<'js>) -> rquickjs::Result<serde_json::Value> {
+    match ctx.json_stringify(value)? {
+        Some(json) => serde_json::from_str(&json.to_string()?)
+            .map_err(|e| rquickjs::Error::new_from_js_message("value", "JSON", e.to_string())),
+        None => Ok(serde_json::Value::Null), // undefined、函数等无法序列化的值
+    }
+}
+
+/// 把脚本抛出的值格式化为错误信息，`Error` 对象附带堆栈
+fn describe_exception(value: JsValue<'_>) -> String {
+    if let Some(exception) = value.as_object().cloned().and_then(Exception::from_object) {
+        let message = exception.message().unwrap_or_default();
+        return match exception.stack() {
+            Some(stack) if !stack.is_empty() => format!("{}\n{}", message, stack.trim_end()),
+            _ => message,
+        };
+    }
+    match value.as_string() {
+        Some(s) => s.to_string().unwrap_or_default(),
+        None => format!("{:?}", value),
+    }
+}
+
+/// QuickJS 分配失败时抛出消息为 `out of memory` 的 `InternalError`
+///
+/// 沙箱中没有 `InternalError` 构造函数，插件抛出的同名字符串或 `Error` 不会被当作内存超限。
+fn is_out_of_memory(value: &JsValue<'_>) -> bool {
+    let Some(object) = value.as_object() else {
+        return false;
+    };
+    let name = object.get::<_, Option<String>>("name").ok().flatten();
+    let message = object.get::<_, Option<String>>("message").ok().flatten();
+    name.as_deref() == Some("InternalError") && message.as_deref() == Some("out of memory")
+}
+
+/// 无法创建异常对象时 rquickjs 直接返回分配错误，其余内存不足由 [`is_out_of_memory`] 转换而来
+fn is_memory_error(err: &anyhow::Error) -> bool {
+    matches!(err.downcast_ref::<rquickjs::Error>(), Some(rquickjs::Error::Allocation))
+}
+
+#[async_trait]
+impl PluginRuntime for JsRuntime {
+    fn metadata(&self) -> &PluginMetadata {
+        &self.metadata
+    }
+
+    async fn init(&self) -> Result<()> {
+        self.setup_api_bindings()?;
+        self.load_script()?;
+        self.call_hook("init")
+    }
+
+    async fn start(&self) -> Result<()> {
+        self.call_hook("start")
+    }
+
+    async fn stop(&self) -> Result<()> {
+        self.call_hook("stop")
+    }
+
+    async fn unload(&self) -> Result<()> {
+        self.call_hook("unload")
+    }
+
+    async fn call_function(&self, name: &str, args: Vec<serde_json::Value>) -> Result<serde_json::Value> {
+        self.enter(
+            || format!("Failed to call JavaScript function '{}'", name),
+            |ctx| {
+                let func = ctx
+                    .globals()
+                    .get::<_, Option<Function>>(name)?
+                    .ok_or_else(|| {
+                        rquickjs::Error::new_from_js_message(
+                            "undefined",
+                            "function",
+                            format!("JavaScript function '{}' is not defined", name),
+                        )
+                    })?;
+                let args = args
+                    .iter()
+                    .map(|arg| ctx.json_parse(arg.to_string()))
+                    .collect::<rquickjs::Result<Vec<_>>>()?;
+                let result = settle(func.call((Rest(args),))?)?;
+                to_json(ctx, result)
+            },
+        )
+    }
+
+    fn has_function(&self, name: &str) -> bool {
+        self.context
+            .with(|ctx| ctx.globals().get::<_, Option<Function>>(name).is_ok_and(|func| func.is_some()))
+    }
+
+    async fn send_message(&self, message: PluginMessage) -> Result<()> {
+        let message = serde_json::to_string(&message)?;
+        self.enter(
+            || "Failed to call message handler".to_string(),
+            |ctx| {
+                if let Some(handler) = ctx.globals().get::<_, Option<Function>>("on_message")? {
+                    settle(handler.call((ctx.json_parse(message)?,))?)?;
+                }
+                Ok(())
+            },
+        )
+    }
+
+    fn get_resource_usage(&self) -> Result<ResourceUsage> {
+        Ok(ResourceUsage {
+            api_calls: self.bridge.rate_limiter().calls(),
+            api_calls_rejected: self.bridge.rate_limiter().rejected(),
+            ..self.monitor.usage()
+        })
+    }
+
+    fn set_resource_limits(&self, limits: ResourceLimits) -> Result<()> {
+        self.runtime.set_memory_limit(limits.max_memory.unwrap_or(0));
+        self.bridge.set_limits(&limits);
+        // 执行预算在下一次进入脚本时生效
+        *self.resource_limits.lock().unwrap() = limits;
+        Ok(())
+    }
+
+    fn register_api(&self, name: &str, api: Arc<dyn PluginApi>) -> Result<()> {
+        self.bridge.register(name, api);
+        self.setup_api_bindings()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn create_test_plugin(main_file: &str, script: &str) -> (JsRuntime, tempfile::TempDir) {
+        let dir = tempdir().unwrap();
+        let script_path = dir.path().join(main_file);
+        fs::write(&script_path, script).unwrap();
+
+        let metadata = PluginMetadata {
+            name: "js_plugin".to_string(),
+            version: "1.0.0".to_string(),
+            author: None,
+            description: None,
+            homepage_url: None,
+            repository_url: None,
+            license: None,
+            language: crate::plugin::PluginLanguage::TypeScript,
+            main_file: main_file.to_string(),
+            permissions: vec![],
+            dependencies: None,
+            configuration: None,
+            commands: vec![],
+            contributes: Default::default(),
+        };
+        let runtime = JsRuntime::new(metadata, script_path.to_str().unwrap().to_string()).unwrap();
+        (runtime, dir)
+    }
+
+    #[tokio::test]
+    async fn test_typescript_plugin() {
+        let (runtime, _dir) = create_test_plugin(
+            "main.ts",
+            r#"
+                interface Point { x: number; y: number }
+
+                export function add(a: number, b: number): number {
+                    return a + b;
+                }
+
+                export async function midpoint(p: Point, q: Point): Promise<Point> {
+                    return { x: (p.x + q.x) / 2, y: (p.y + q.y) / 2 };
+                }
+            "#,
+        );
+        runtime.init().await.unwrap();
+
+        let result = runtime
+            .call_function("add", vec![serde_json::json!(5), serde_json::json!(3)])
+            .await
+            .unwrap();
+        assert_eq!(result, serde_json::json!(8));
+
+        let result = runtime
+            .call_function(
+                "midpoint",
+                vec![serde_json::json!({"x": 0, "y": 0}), serde_json::json!({"x": 2, "y": 4})],
+            )
+            .await
+            .unwrap();
+        assert_eq!(result, serde_json::json!({"x": 1, "y": 2}));
+    }
+
+    #[tokio::test]
+    async fn test_memory_limit() {
+        let (runtime, _dir) = create_test_plugin(
+            "main.js",
+            r#"
+                function grow() { const parts = []; while (true) parts.push("x".repeat(1024)); }
+                function fake() { throw "out of memory"; }
+                function fake_error() { throw new Error("out of memory"); }
+                function forge() { return typeof InternalError; }
+            "#,
+        );
+        runtime.init().await.unwrap();
+        runtime
+            .set_resource_limits(ResourceLimits {
+                max_memory: Some(4 * 1024 * 1024),
+                ..ResourceLimits::default()
+            })
+            .unwrap();
+
+        let err = runtime.call_function("grow", vec![]).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<PluginError>(), Some(PluginError::MemoryLimitExceeded { .. })));
+        assert_eq!(runtime.get_resource_usage().unwrap().memory_violations, 1);
+
+        // 插件自己抛出的同名错误只是普通异常
+        for function in ["fake", "fake_error"] {
+            let err = runtime.call_function(function, vec![]).await.unwrap_err();
+            assert!(err.downcast_ref::<PluginError>().is_none(), "{}", function);
+        }
+        assert_eq!(runtime.get_resource_usage().unwrap().memory_violations, 1);
+        assert_eq!(runtime.call_function("forge", vec![]).await.unwrap(), serde_json::json!("undefined"));
+    }
+
+    #[tokio::test]
+    async fn test_exception_includes_stack() {
+        let (runtime, _dir) = create_test_plugin(
+            "main.js",
+            "function fail() { throw new Error('boom'); }",
+        );
+        runtime.init().await.unwrap();
+
+        let err = runtime.call_function("fail", vec![]).await.unwrap_err();
+        let message = format!("{:#}", err);
+        assert!(message.contains("boom"));
+        assert!(message.contains("at fail"));
+    }
+}
@@ -0,0 +1,167 @@
+use std::fmt;
+use serde::{Deserialize, Serialize};
+
+use crate::plugin::command::CommandProgress;
+use crate::plugin::diagnostics::PluginErrorRecord;
+use crate::plugin::error::PluginError;
+use crate::plugin::PluginStatus;
+
+/// 插件运行时的生命周期状态
+///
+/// ```text
+/// Loaded --init--> Initialized --start--> Running --stop--> Stopped
+///                       |                    ^                 |
+///                       |                    +------start------+
+///                       +------------------unload--------------+--> Unloaded
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuntimeState {
+    Loaded,
+    Initialized,
+    Running,
+    Stopped,
+    Unloaded,
+}
+
+/// 作用于运行时的生命周期操作
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LifecycleAction {
+    Init,
+    Start,
+    Stop,
+    Unload,
+    /// 调用插件函数，不改变状态
+    Call,
+    /// 投递插件消息，不改变状态
+    Message,
+}
+
+impl RuntimeState {
+    /// 校验状态转换，返回执行 `action` 之后的状态
+    pub fn next(self, plugin_id: &str, action: LifecycleAction) -> Result<RuntimeState, PluginError> {
+        use LifecycleAction::*;
+        use RuntimeState::*;
+
+        let next = match (self, action) {
+            (Loaded, Init) => Some(Initialized),
+            (Initialized | Stopped, Start) => Some(Running),
+            (Running, Stop) => Some(Stopped),
+            (Loaded | Initialized | Stopped, Unload) => Some(Unloaded),
+            (Initialized | Running, Call) => Some(self),
+            (Running, Message) => Some(self),
+            _ => None,
+        };
+
+        next.ok_or_else(|| PluginError::InvalidTransition {
+            plugin_id: plugin_id.to_string(),
+            from: self,
+            action,
+        })
+    }
+}
+
+impl fmt::Display for RuntimeState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            RuntimeState::Loaded => "loaded",
+            RuntimeState::Initialized => "initialized",
+            RuntimeState::Running => "running",
+            RuntimeState::Stopped => "stopped",
+            RuntimeState::Unloaded => "unloaded",
+        };
+        f.write_str(name)
+    }
+}
+
+impl fmt::Display for LifecycleAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            LifecycleAction::Init => "init",
+            LifecycleAction::Start => "start",
+            LifecycleAction::Stop => "stop",
+            LifecycleAction::Unload => "unload",
+            LifecycleAction::Call => "call a function",
+            LifecycleAction::Message => "receive a message",
+        };
+        f.write_str(name)
+    }
+}
+
+/// 插件管理器向宿主广播的事件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PluginEvent {
+    /// 运行时生命周期状态发生变化
+    StateChanged {
+        plugin_id: String,
+        from: RuntimeState,
+        to: RuntimeState,
+    },
+    /// 插件状态（面向用户）发生变化
+    StatusChanged {
+        plugin_id: String,
+        status: PluginStatus,
+    },
+    /// 插件包已安装或升级到插件目录
+    Installed { plugin_id: String, version: String },
+    /// 插件已从插件目录删除
+    Uninstalled { plugin_id: String },
+    /// 插件已按磁盘上的文件重新加载
+    Reloaded { plugin_id: String, version: String },
+    /// 重新加载失败，正在运行的旧实例（如果有）保持不变
+    ReloadFailed { error: PluginError },
+    /// 插件产生了一条新的错误记录
+    ErrorRecorded { error: PluginErrorRecord },
+    /// 执行中的命令上报了进度
+    CommandProgress(CommandProgress),
+    /// 命令执行成功
+    CommandCompleted {
+        invocation_id: u64,
+        plugin_id: String,
+        command_id: String,
+        result: serde_json::Value,
+    },
+    /// 命令执行失败
+    CommandFailed {
+        invocation_id: u64,
+        plugin_id: String,
+        command_id: String,
+        error: PluginErrorRecord,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_valid_transitions() {
+        let state = RuntimeState::Loaded;
+        let state = state.next("p", LifecycleAction::Init).unwrap();
+        assert_eq!(state, RuntimeState::Initialized);
+        let state = state.next("p", LifecycleAction::Start).unwrap();
+        assert_eq!(state, RuntimeState::Running);
+        assert_eq!(state.next("p", LifecycleAction::Call).unwrap(), RuntimeState::Running);
+        let state = state.next("p", LifecycleAction::Stop).unwrap();
+        assert_eq!(state.next("p", LifecycleAction::Start).unwrap(), RuntimeState::Running);
+        assert_eq!(state.next("p", LifecycleAction::Unload).unwrap(), RuntimeState::Unloaded);
+    }
+
+    #[test]
+    fn test_invalid_transitions() {
+        let err = RuntimeState::Loaded.next("p", LifecycleAction::Start).unwrap_err();
+        assert_eq!(
+            err,
+            PluginError::InvalidTransition {
+                plugin_id: "p".to_string(),
+                from: RuntimeState::Loaded,
+                action: LifecycleAction::Start,
+            }
+        );
+        assert!(RuntimeState::Unloaded.next("p", LifecycleAction::Call).is_err());
+        assert!(RuntimeState::Running.next("p", LifecycleAction::Unload).is_err());
+        assert!(RuntimeState::Initialized.next("p", LifecycleAction::Message).is_err());
+    }
+}
@@ -0,0 +1,405 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+
+use crate::plugin::error::PluginError;
+use crate::plugin::{ResourceLimits, ResourceUsage};
+
+/// 插件资源使用统计，各语言运行时共用
+///
+/// 运行时在每次进入脚本之后上报内存占用，超出限制时记录一次违规，
+/// 管理器根据违规次数决定是否让插件进入错误状态。
+#[derive(Debug, Default)]
+pub struct ResourceMonitor {
+    memory_used: AtomicUsize,
+    peak_memory_used: AtomicUsize,
+    memory_violations: AtomicUsize,
+    cpu_time_us: AtomicU64,
+    cpu_violations: AtomicUsize,
+}
+
+impl ResourceMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 记录当前内存占用并更新峰值
+    pub fn record_memory(&self, used: usize) {
+        self.memory_used.store(used, Ordering::Relaxed);
+        self.peak_memory_used.fetch_max(used, Ordering::Relaxed);
+    }
+
+    /// 记录一次内存超限及分配失败时测得的内存占用，返回累计的违规次数
+    pub fn record_memory_violation(&self, used: usize) -> usize {
+        self.record_memory(used);
+        self.memory_violations.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    /// 累加一次脚本执行占用的时间
+    pub fn record_cpu_time(&self, elapsed: Duration) {
+        self.cpu_time_us
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// 记录一次执行预算耗尽，返回累计的违规次数
+    pub fn record_cpu_violation(&self) -> usize {
+        self.cpu_violations.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    pub fn usage(&self) -> ResourceUsage {
+        ResourceUsage {
+            memory_used: self.memory_used.load(Ordering::Relaxed),
+            peak_memory_used: self.peak_memory_used.load(Ordering::Relaxed),
+            memory_violations: self.memory_violations.load(Ordering::Relaxed),
+            cpu_time: self.cpu_time_us.load(Ordering::Relaxed) / 1000,
+            cpu_violations: self.cpu_violations.load(Ordering::Relaxed),
+            ..ResourceUsage::default()
+        }
+    }
+}
+
+/// 按 CPU 时间估算指令预算时，假定每毫秒执行的指令数
+///
+/// 取值偏保守，解释执行的 WebAssembly 通常比这更快，实际可用时间会略短于 `max_cpu_time`。
+pub const INSTRUCTIONS_PER_MS: u64 = 100_000;
+
+/// 执行预算耗尽的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BudgetExceeded {
+    CpuTime,
+    Instructions,
+}
+
+/// 单次进入插件脚本（生命周期钩子、消息处理、函数调用）的执行预算
+///
+/// 运行时在脚本执行过程中周期性地调用 [`BudgetTracker::tick`]，预算耗尽后由运行时中断脚本。
+/// 指令预算与机器速度无关，可以得到确定的中断位置。
+///
+/// `max_cpu_time` 计量的是脚本自身的执行时间：从进入脚本开始的墙钟时间减去在宿主 API 中
+/// 花费的时间（见 [`BudgetTracker::in_host`]），等待网络、磁盘等宿主操作不计入插件的预算。
+#[derive(Debug)]
+pub struct BudgetTracker {
+    max_cpu_time: Option<Duration>,
+    max_instructions: Option<u64>,
+    started: Instant,
+    // 在宿主 API 中花费的时间（纳秒）
+    host_time: AtomicU64,
+    instructions: AtomicU64,
+    exceeded: AtomicU8,
+}
+
+impl BudgetTracker {
+    pub fn new(limits: &ResourceLimits) -> Self {
+        Self {
+            max_cpu_time: limits.max_cpu_time.map(Duration::from_millis),
+            max_instructions: limits.max_instructions,
+            started: Instant::now(),
+            host_time: AtomicU64::new(0),
+            instructions: AtomicU64::new(0),
+            exceeded: AtomicU8::new(0),
+        }
+    }
+
+    /// 是否设置了任何预算
+    pub fn is_limited(&self) -> bool {
+        self.max_cpu_time.is_some() || self.max_instructions.is_some()
+    }
+
+    /// 记录执行了 `instructions` 条指令，返回预算是否已经耗尽
+    pub fn tick(&self, instructions: u64) -> Option<BudgetExceeded> {
+        if let Some(exceeded) = self.exceeded() {
+            return Some(exceeded);
+        }
+
+        let executed = self.instructions.fetch_add(instructions, Ordering::Relaxed) + instructions;
+        let exceeded = if self.max_instructions.is_some_and(|max| executed > max) {
+            Some(BudgetExceeded::Instructions)
+        } else if self.max_cpu_time.is_some_and(|max| self.elapsed() > max) {
+            Some(BudgetExceeded::CpuTime)
+        } else {
+            None
+        };
+
+        if let Some(exceeded) = exceeded {
+            self.exhaust(exceeded);
+        }
+        exceeded
+    }
+
+    /// 把执行预算折算为一次性发放的燃料，供无法周期性检查时间的运行时使用
+    ///
+    /// 只设置了 CPU 时间时按 [`INSTRUCTIONS_PER_MS`] 估算指令数。返回燃料数量以及燃料耗尽时
+    /// 对应的原因；没有设置任何预算时返回 `None`。
+    pub fn fuel(&self) -> Option<(u64, BudgetExceeded)> {
+        let by_time = self
+            .max_cpu_time
+            .map(|max| (max.as_millis() as u64).saturating_mul(INSTRUCTIONS_PER_MS));
+        match (self.max_instructions, by_time) {
+            (Some(instructions), Some(by_time)) if by_time < instructions => Some((by_time, BudgetExceeded::CpuTime)),
+            (Some(instructions), _) => Some((instructions, BudgetExceeded::Instructions)),
+            (None, Some(by_time)) => Some((by_time, BudgetExceeded::CpuTime)),
+            (None, None) => None,
+        }
+    }
+
+    /// 运行时自行检测到预算耗尽（例如燃料用完）时标记原因
+    pub fn exhaust(&self, exceeded: BudgetExceeded) {
+        let code = match exceeded {
+            BudgetExceeded::CpuTime => 1,
+            BudgetExceeded::Instructions => 2,
+        };
+        let _ = self.exceeded.compare_exchange(0, code, Ordering::Relaxed, Ordering::Relaxed);
+    }
+
+    pub fn exceeded(&self) -> Option<BudgetExceeded> {
+        match self.exceeded.load(Ordering::Relaxed) {
+            1 => Some(BudgetExceeded::CpuTime),
+            2 => Some(BudgetExceeded::Instructions),
+            _ => None,
+        }
+    }
+
+    /// 执行 `f` 并把其耗时记为宿主时间，不计入 `max_cpu_time`
+    pub fn in_host<T>(&self, f: impl FnOnce() -> T) -> T {
+        let started = Instant::now();
+        let result = f();
+        let nanos = u64::try_from(started.elapsed().as_nanos()).unwrap_or(u64::MAX);
+        self.host_time.fetch_add(nanos, Ordering::Relaxed);
+        result
+    }
+
+    /// 脚本自身的执行时间，不含宿主 API 中花费的时间
+    pub fn elapsed(&self) -> Duration {
+        let host_time = Duration::from_nanos(self.host_time.load(Ordering::Relaxed));
+        self.started.elapsed().saturating_sub(host_time)
+    }
+
+    /// 预算耗尽时对应的错误
+    pub fn error(&self, plugin_id: &str) -> Option<PluginError> {
+        let plugin_id = plugin_id.to_string();
+        self.exceeded().map(|exceeded| match exceeded {
+            BudgetExceeded::CpuTime => PluginError::CpuTimeExceeded {
+                plugin_id,
+                limit_ms: self.max_cpu_time.map_or(0, |max| max.as_millis() as u64),
+            },
+            BudgetExceeded::Instructions => PluginError::InstructionLimitExceeded {
+                plugin_id,
+                limit: self.max_instructions.unwrap_or(0),
+            },
+        })
+    }
+}
+
+/// API 调用配额的统计窗口
+pub const API_RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// 插件调用宿主 API 的滑动窗口限流器
+///
+/// `max_api_calls` 限制插件在一个窗口内的调用总数，`api_quotas` 可以再为单个 API
+/// 设置更严格的配额。只有所有配额都满足时调用才会被计入。
+#[derive(Debug)]
+pub struct RateLimiter {
+    quotas: Mutex<Quotas>,
+    windows: Mutex<HashMap<Option<String>, VecDeque<Instant>>>,
+    calls: AtomicUsize,
+    rejected: AtomicUsize,
+}
+
+#[derive(Debug, Default)]
+struct Quotas {
+    total: Option<usize>,
+    per_api: HashMap<String, usize>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: &ResourceLimits) -> Self {
+        let limiter = Self {
+            quotas: Mutex::new(Quotas::default()),
+            windows: Mutex::new(HashMap::new()),
+            calls: AtomicUsize::new(0),
+            rejected: AtomicUsize::new(0),
+        };
+        limiter.set_limits(limits);
+        limiter
+    }
+
+    /// 更新配额，已经记录的调用仍然计入当前窗口
+    pub fn set_limits(&self, limits: &ResourceLimits) {
+        *self.quotas.lock().unwrap() = Quotas {
+            total: limits.max_api_calls,
+            per_api: limits.api_quotas.clone(),
+        };
+    }
+
+    /// 申请一次对 `api` 的调用，超出配额时返回需要等待的时间
+    pub fn acquire(&self, api: &str) -> Result<(), Duration> {
+        self.acquire_at(api, Instant::now())
+    }
+
+    fn acquire_at(&self, api: &str, now: Instant) -> Result<(), Duration> {
+        let quotas = self.quotas.lock().unwrap();
+        let mut windows = self.windows.lock().unwrap();
+
+        let keys = [
+            (None, quotas.total),
+            (Some(api.to_string()), quotas.per_api.get(api).copied()),
+        ];
+        let mut retry_after = Duration::ZERO;
+        for (key, quota) in &keys {
+            let Some(quota) = *quota else {
+                continue;
+            };
+            let window = windows.entry(key.clone()).or_default();
+            while window
+                .front()
+                .is_some_and(|t| now.duration_since(*t) >= API_RATE_WINDOW)
+            {
+                window.pop_front();
+            }
+            if window.len() >= quota {
+                // 最早的一次调用移出窗口之后才有空余配额
+                let wait = window
+                    .front()
+                    .map(|t| API_RATE_WINDOW - now.duration_since(*t))
+                    .unwrap_or(API_RATE_WINDOW);
+                retry_after = retry_after.max(wait);
+            }
+        }
+
+        if !retry_after.is_zero() {
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+            return Err(retry_after);
+        }
+        for (key, quota) in keys {
+            if quota.is_some() {
+                windows.entry(key).or_default().push_back(now);
+            }
+        }
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// 累计放行的调用次数
+    pub fn calls(&self) -> usize {
+        self.calls.load(Ordering::Relaxed)
+    }
+
+    /// 累计因超出配额被拒绝的调用次数
+    pub fn rejected(&self) -> usize {
+        self.rejected.load(Ordering::Relaxed)
+    }
+}
+
+/// 插件反复超出资源限制（内存、执行预算）时采取的措施
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ViolationAction {
+    /// 只记录，不做处理
+    Ignore,
+    /// 在冷却时间内拒绝对插件的调用
+    Throttle { cooldown_ms: u64 },
+    /// 停止运行时并进入错误状态，需要用户重新启用
+    Disable,
+}
+
+/// 资源违规处理策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ViolationPolicy {
+    /// 累计违规达到该次数后触发 `action`
+    pub max_violations: usize,
+    pub action: ViolationAction,
+}
+
+impl Default for ViolationPolicy {
+    fn default() -> Self {
+        Self {
+            max_violations: 3,
+            action: ViolationAction::Disable,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_tracking() {
+        let monitor = ResourceMonitor::new();
+        monitor.record_memory(100);
+        monitor.record_memory(300);
+        monitor.record_memory(200);
+
+        let usage = monitor.usage();
+        assert_eq!(usage.memory_used, 200);
+        assert_eq!(usage.peak_memory_used, 300);
+        assert_eq!(usage.memory_violations, 0);
+
+        assert_eq!(monitor.record_memory_violation(1000), 1);
+        assert_eq!(monitor.record_memory_violation(900), 2);
+        let usage = monitor.usage();
+        assert_eq!(usage.memory_used, 900);
+        assert_eq!(usage.peak_memory_used, 1000);
+        assert_eq!(usage.memory_violations, 2);
+    }
+
+    #[test]
+    fn test_instruction_budget() {
+        let tracker = BudgetTracker::new(&ResourceLimits {
+            max_instructions: Some(2500),
+            ..ResourceLimits::default()
+        });
+        assert!(tracker.is_limited());
+        assert_eq!(tracker.tick(1000), None);
+        assert_eq!(tracker.tick(1000), None);
+        assert_eq!(tracker.tick(1000), Some(BudgetExceeded::Instructions));
+        // 预算耗尽之后保持耗尽状态
+        assert_eq!(tracker.tick(0), Some(BudgetExceeded::Instructions));
+        assert!(!BudgetTracker::new(&ResourceLimits::default()).is_limited());
+
+        // 折算为燃料时取两种预算中更严格的一个
+        let fuel = |max_cpu_time, max_instructions| {
+            BudgetTracker::new(&ResourceLimits {
+                max_cpu_time,
+                max_instructions,
+                ..ResourceLimits::default()
+            })
+            .fuel()
+        };
+        assert_eq!(fuel(Some(10), Some(5000)), Some((5000, BudgetExceeded::Instructions)));
+        assert_eq!(fuel(Some(10), None), Some((10 * INSTRUCTIONS_PER_MS, BudgetExceeded::CpuTime)));
+        assert_eq!(fuel(Some(1), Some(u64::MAX)), Some((INSTRUCTIONS_PER_MS, BudgetExceeded::CpuTime)));
+        assert_eq!(fuel(None, None), None);
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = RateLimiter::new(&ResourceLimits {
+            max_api_calls: Some(3),
+            api_quotas: HashMap::from([("task".to_string(), 2)]),
+            ..ResourceLimits::default()
+        });
+        let start = Instant::now();
+
+        assert!(limiter.acquire_at("task", start).is_ok());
+        assert!(limiter.acquire_at("task", start + Duration::from_secs(10)).is_ok());
+        // task 的单独配额已用完
+        assert_eq!(
+            limiter.acquire_at("task", start + Duration::from_secs(20)),
+            Err(Duration::from_secs(40))
+        );
+        assert!(limiter.acquire_at("storage", start + Duration::from_secs(20)).is_ok());
+        // 插件总配额已用完
+        assert_eq!(
+            limiter.acquire_at("storage", start + Duration::from_secs(30)),
+            Err(Duration::from_secs(30))
+        );
+        // 最早的调用移出窗口后恢复
+        assert!(limiter.acquire_at("task", start + API_RATE_WINDOW).is_ok());
+
+        assert_eq!(limiter.calls(), 4);
+        assert_eq!(limiter.rejected(), 2);
+    }
+}
@@ -0,0 +1,130 @@
+use std::path::{Path, PathBuf};
+
+use crate::plugin::error::PluginError;
+
+/// 解析插件 `require` 的模块
+///
+/// 模块名由 `.` 分隔的标识符组成，`foo.bar` 依次查找 `foo/bar.lua` 和 `foo/bar/init.lua`。
+/// 先在插件自己的目录中查找，再在共享的只读 vendor 目录中查找。解析出的文件必须仍然位于
+/// 对应的根目录内，经由符号链接指向根目录之外的文件会被拒绝。
+#[derive(Debug)]
+pub struct ModuleResolver {
+    plugin_id: String,
+    roots: Vec<PathBuf>,
+}
+
+impl ModuleResolver {
+    pub fn new(plugin_id: &str, plugin_dir: &Path, vendor_dir: Option<&Path>) -> std::io::Result<Self> {
+        let mut roots = vec![plugin_dir.canonicalize()?];
+        // vendor 目录是可选的，不存在时只在插件目录中查找
+        if let Some(vendor) = vendor_dir.and_then(|dir| dir.canonicalize().ok()) {
+            roots.push(vendor);
+        }
+        Ok(Self {
+            plugin_id: plugin_id.to_string(),
+            roots,
+        })
+    }
+
+    pub fn resolve(&self, module: &str) -> Result<PathBuf, PluginError> {
+        let valid = !module.is_empty()
+            && module.split('.').all(|segment| {
+                !segment.is_empty()
+                    && segment.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+            });
+        if !valid {
+            return Err(self.access_denied(module));
+        }
+
+        let relative: PathBuf = module.split('.').collect();
+        for root in &self.roots {
+            for candidate in [root.join(&relative).with_extension("lua"), root.join(&relative).join("init.lua")] {
+                let Ok(real) = candidate.canonicalize() else {
+                    continue;
+                };
+                if !real.starts_with(root) {
+                    return Err(self.access_denied(module));
+                }
+                if real.is_file() {
+                    return Ok(real);
+                }
+            }
+        }
+        Err(PluginError::ModuleNotFound {
+            plugin_id: self.plugin_id.clone(),
+            module: module.to_string(),
+        })
+    }
+
+    /// 模块文件相对于所在根目录的路径，用作错误堆栈中的代码块名称
+    pub fn display_name(&self, path: &Path) -> String {
+        self.roots
+            .iter()
+            .find_map(|root| path.strip_prefix(root).ok())
+            .unwrap_or(path)
+            .to_string_lossy()
+            .replace('\\', "/")
+    }
+
+    fn access_denied(&self, module: &str) -> PluginError {
+        PluginError::ModuleAccessDenied {
+            plugin_id: self.plugin_id.clone(),
+            module: module.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_resolve_modules() {
+        let dir = tempdir().unwrap();
+        let plugin = dir.path().join("plugin");
+        let vendor = dir.path().join("vendor");
+        fs::create_dir_all(plugin.join("lib/util")).unwrap();
+        fs::create_dir_all(&vendor).unwrap();
+        fs::write(plugin.join("helpers.lua"), "").unwrap();
+        fs::write(plugin.join("lib/util/init.lua"), "").unwrap();
+        fs::write(plugin.join("lib/util/strings.lua"), "").unwrap();
+        fs::write(vendor.join("helpers.lua"), "").unwrap();
+        fs::write(vendor.join("json.lua"), "").unwrap();
+        fs::write(dir.path().join("secret.lua"), "").unwrap();
+
+        let resolver = ModuleResolver::new("demo", &plugin, Some(&vendor)).unwrap();
+        let resolve = |module: &str| resolver.resolve(module).map(|path| resolver.display_name(&path));
+
+        // 插件目录优先于 vendor 目录
+        assert_eq!(resolve("helpers").unwrap(), "helpers.lua");
+        assert_eq!(resolve("lib.util").unwrap(), "lib/util/init.lua");
+        assert_eq!(resolve("lib.util.strings").unwrap(), "lib/util/strings.lua");
+        assert_eq!(resolve("json").unwrap(), "json.lua");
+        assert!(matches!(resolve("missing"), Err(PluginError::ModuleNotFound { .. })));
+
+        for module in ["..secret", "../secret", "/etc/passwd", "lib..util", "", "lib/util", "a.b\\c"] {
+            assert!(
+                matches!(resolve(module), Err(PluginError::ModuleAccessDenied { .. })),
+                "{} should be rejected",
+                module
+            );
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlink_escape() {
+        let dir = tempdir().unwrap();
+        let plugin = dir.path().join("plugin");
+        fs::create_dir_all(&plugin).unwrap();
+        fs::write(dir.path().join("secret.lua"), "").unwrap();
+        std::os::unix::fs::symlink(dir.path().join("secret.lua"), plugin.join("escape.lua")).unwrap();
+        std::os::unix::fs::symlink(dir.path(), plugin.join("parent")).unwrap();
+
+        let resolver = ModuleResolver::new("demo", &plugin, None).unwrap();
+        assert!(matches!(resolver.resolve("escape"), Err(PluginError::ModuleAccessDenied { .. })));
+        assert!(matches!(resolver.resolve("parent.secret"), Err(PluginError::ModuleAccessDenied { .. })));
+    }
+}
@@ -1,61 +1,323 @@
-use std::path::Path;
-use std::sync::Arc;
 use std::collections::HashMap;
-use mlua::{Lua, Result as LuaResult, Function, Table, Value as LuaValue};
+use std::path::{Path, PathBuf};
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use async_trait::async_trait;
+use mlua::{ChunkMode, HookTriggers, Lua, MultiValue, Result as LuaResult, Function, Table, Value as LuaValue};
 use anyhow::{Result, Context};
+use crate::plugin::bridge::{self, ApiBridge};
+use crate::plugin::convert;
+use crate::plugin::error::PluginError;
+use crate::plugin::limits::{BudgetTracker, ResourceMonitor};
+use crate::plugin::loader::ModuleResolver;
 use crate::plugin::{PluginRuntime, PluginMetadata, PluginMessage, ResourceLimits, ResourceUsage, PluginApi};
 
+/// 在宿主全局环境中构造插件可见的 `api` 表
+///
+/// 底层调用返回 `ok, result`，失败时由包装函数把结构化错误表抛给插件，
+/// 插件可以用 `pcall` 捕获并读取 `err.code`、`err.message` 等字段。
+const API_WRAPPER: &str = r#"
+return function(raw_call, names)
+    local error_mt = { __tostring = function(e) return e.message end }
+    local api = {}
+    for _, name in ipairs(names) do
+        api[name] = function(method, params)
+            local ok, result = raw_call(name, method, params)
+            if not ok then
+                error(setmetatable(result, error_mt), 2)
+            end
+            return result
+        end
+    end
+    return api
+end
+"#;
+
+/// 基于 `api.bus` 构造插件可见的 `bus` 表
+const BUS_WRAPPER: &str = r#"
+return function(call)
+    return {
+        publish = function(message_type, payload, target)
+            return call("publish", { message_type = message_type, payload = payload, target = target })
+        end,
+        subscribe = function(pattern)
+            return call("subscribe", { pattern = pattern })
+        end,
+        unsubscribe = function(pattern)
+            return call("unsubscribe", { pattern = pattern })
+        end,
+    }
+end
+"#;
+
+/// 包装沙箱中的 `pcall` / `xpcall`：执行预算耗尽后继续向外抛出错误，插件无法吞掉超时
+const PCALL_WRAPPER: &str = r#"
+return function(error, pack, unpack, interrupted)
+    return function(protected_call)
+        return function(...)
+            local results = pack(protected_call(...))
+            if interrupted() then
+                error(results[2], 0)
+            end
+            return unpack(results, 1, results.n)
+        end
+    end
+end
+"#;
+
+/// 包装沙箱中的 `setmetatable`：拒绝带 `__gc` 的元表
+///
+/// 终结器在垃圾回收和关闭 Lua 状态时执行，此时没有执行预算，死循环的终结器会卡住宿主线程。
+/// Lua 只在设置元表时检查 `__gc`，之后再往元表中加入的 `__gc` 不会生效。
+const SETMETATABLE_WRAPPER: &str = r#"
+return function(setmetatable, rawget, type, error)
+    return function(t, mt)
+        if type(mt) == "table" and rawget(mt, "__gc") ~= nil then
+            error("__gc metamethods are not allowed", 2)
+        end
+        return setmetatable(t, mt)
+    end
+end
+"#;
+
+/// 沙箱中可用的基础函数
+///
+/// 不包含 `load`、`loadstring`、`dofile`、`loadfile`、`require`、`collectgarbage` 等
+/// 可以加载代码或影响宿主的函数。
+const SAFE_GLOBALS: &[&str] = &[
+    "assert", "error", "getmetatable", "ipairs", "next", "pairs", "rawequal", "rawget", "rawlen",
+    "rawset", "select", "setmetatable", "tonumber", "tostring", "type", "_VERSION",
+];
+
+/// 沙箱中可用的标准库函数，未列出的库（`io`、`debug`、`package`、`coroutine`）和函数
+/// （`string.dump`、`os.execute`、`os.getenv` 等）都不可用
+const SAFE_LIBRARIES: &[(&str, &[&str])] = &[
+    (
+        "string",
+        &[
+            "byte", "char", "find", "format", "gmatch", "gsub", "len", "lower", "match", "pack",
+            "packsize", "rep", "reverse", "sub", "unpack", "upper",
+        ],
+    ),
+    ("table", &["concat", "insert", "move", "pack", "remove", "sort", "unpack"]),
+    ("utf8", &["char", "charpattern", "codepoint", "codes", "len", "offset"]),
+    (
+        "math",
+        &[
+            "abs", "ceil", "cos", "exp", "floor", "fmod", "huge", "log", "max", "maxinteger", "min",
+            "mininteger", "modf", "pi", "random", "randomseed", "sin", "sqrt", "tan", "tointeger",
+            "type", "ult", "acos", "asin", "atan",
+        ],
+    ),
+    ("os", &["clock", "date", "difftime", "time"]),
+];
+
+/// `string.rep` 允许生成的最大字符串长度
+const MAX_STRING_SIZE: usize = 16 * 1024 * 1024;
+
+/// 每执行多少条指令检查一次执行预算
+const BUDGET_CHECK_INTERVAL: u32 = 1000;
+
 pub struct LuaRuntime {
-    lua: Lua,
+    lua: Mutex<Lua>,
     metadata: PluginMetadata,
     script_path: String,
-    apis: Arc<Mutex<HashMap<String, Box<dyn PluginApi>>>>,
+    bridge: Arc<ApiBridge>,
     resource_limits: Arc<Mutex<ResourceLimits>>,
-    message_handler: Option<Function<'static>>,
+    monitor: ResourceMonitor,
+    // 当前调用的执行预算是否已经耗尽
+    interrupted: Arc<AtomicBool>,
+    // 共享的只读第三方库目录，`require` 在插件目录之后查找
+    vendor_dir: Option<PathBuf>,
 }
 
 impl LuaRuntime {
     pub fn new(metadata: PluginMetadata, script_path: String) -> Result<Self> {
         let lua = Lua::new();
-        Self::setup_sandbox(&lua)?;
+        let interrupted = Arc::new(AtomicBool::new(false));
+        Self::setup_sandbox(&lua, Arc::clone(&interrupted))?;
+        
+        let limits = ResourceLimits {
+            max_memory: Some(50 * 1024 * 1024), // 默认 50MB
+            max_cpu_time: Some(1000),           // 默认 1 秒
+            max_instructions: None,             // 默认不限制指令数
+            max_tasks: Some(10),                // 默认最多 10 个并发任务
+            max_api_calls: Some(1000),          // 默认每分钟 1000 次 API 调用
+            api_quotas: HashMap::new(),
+            max_disk_usage: None,
+        };
+        lua.set_memory_limit(limits.max_memory.unwrap_or(0))?;
+        
+        let monitor = ResourceMonitor::new();
+        monitor.record_memory(lua.used_memory());
+        
+        let bridge = ApiBridge::new(&metadata);
+        bridge.set_limits(&limits);
         
         Ok(Self {
-            lua,
+            lua: Mutex::new(lua),
+            bridge: Arc::new(bridge),
             metadata,
             script_path,
-            apis: Arc::new(Mutex::new(HashMap::new())),
-            resource_limits: Arc::new(Mutex::new(ResourceLimits {
-                max_memory: Some(50 * 1024 * 1024), // 默认 50MB
-                max_cpu_time: Some(1000),           // 默认 1 秒
-                max_tasks: Some(10),                // 默认最多 10 个并发任务
-                max_api_calls: Some(1000),          // 默认每分钟 1000 次 API 调用
-            })),
-            message_handler: None,
+            resource_limits: Arc::new(Mutex::new(limits)),
+            monitor,
+            interrupted,
+            vendor_dir: None,
         })
     }
 
-    fn setup_sandbox(lua: &Lua) -> LuaResult<()> {
-        // 创建一个受限制的环境
-        let globals = lua.globals();
+    /// 设置共享的 vendor 目录，在 `init` 之前调用才会生效
+    pub fn with_vendor_dir(mut self, vendor_dir: Option<PathBuf>) -> Self {
+        self.vendor_dir = vendor_dir;
+        self
+    }
+
+    /// 进入 Lua 执行代码的唯一入口
+    ///
+    /// 每次进入都有独立的执行预算，超出 CPU 时间或指令数的调用会被中断并转换为
+    /// `PluginError::CpuTimeExceeded` / `PluginError::InstructionLimitExceeded`。
+    /// 执行结束后统计内存占用；分配失败会被转换为 `PluginError::MemoryLimitExceeded`，
+    /// 而不是让宿主进程耗尽内存。
+    fn enter<R>(&self, context: impl FnOnce() -> String, f: impl FnOnce(&Lua) -> LuaResult<R>) -> Result<R> {
+        let lua = self.lua.lock().unwrap();
+        let limits = self.resource_limits.lock().unwrap().clone();
+        let tracker = Arc::new(BudgetTracker::new(&limits));
+        self.bridge.set_budget(Some(Arc::clone(&tracker)));
+        
+        self.interrupted.store(false, Ordering::Relaxed);
+        if tracker.is_limited() {
+            let hook_tracker = Arc::clone(&tracker);
+            let interrupted = Arc::clone(&self.interrupted);
+            lua.set_hook(
+                HookTriggers::new().every_nth_instruction(BUDGET_CHECK_INTERVAL),
+                move |_, _| match hook_tracker.tick(BUDGET_CHECK_INTERVAL as u64) {
+                    Some(_) => {
+                        interrupted.store(true, Ordering::Relaxed);
+                        Err(mlua::Error::runtime("execution budget exceeded"))
+                    }
+                    None => Ok(()),
+                },
+            );
+        }
+        let result = f(&lua);
+        lua.remove_hook();
+        self.bridge.set_budget(None);
+        self.interrupted.store(false, Ordering::Relaxed);
+        self.monitor.record_cpu_time(tracker.elapsed());
+        
+        if let Some(err) = tracker.error(&self.metadata.name) {
+            self.monitor.record_cpu_violation();
+            self.monitor.record_memory(lua.used_memory());
+            return Err(anyhow::Error::new(err).context(context()));
+        }
         
-        // 创建一个新的环境表
+        if let Err(err) = &result {
+            if is_memory_error(err) {
+                let limit = limits.max_memory.unwrap_or(0);
+                self.monitor.record_memory_violation(lua.used_memory());
+                // 尽量回收本次调用留下的垃圾，让插件后续调用可以继续执行
+                let _ = lua.gc_collect();
+                self.monitor.record_memory(lua.used_memory());
+                return Err(anyhow::Error::new(PluginError::MemoryLimitExceeded {
+                    plugin_id: self.metadata.name.clone(),
+                    limit,
+                })
+                .context(context()));
+            }
+        }
+        
+        self.monitor.record_memory(lua.used_memory());
+        result.map_err(|e| anyhow::Error::new(e).context(context()))
+    }
+
+    fn setup_sandbox(lua: &Lua, interrupted: Arc<AtomicBool>) -> LuaResult<()> {
+        // 插件运行在独立的环境表中，只能看到下面白名单中的函数，宿主的全局表不会暴露给插件
+        let globals = lua.globals();
         let env = lua.create_table()?;
         
-        // 只允许访问安全的标准库函数
-        let string = lua.create_table()?;
-        string.set("len", globals.get::<_, Function>("string.len")?)?;
-        string.set("sub", globals.get::<_, Function>("string.sub")?)?;
-        string.set("upper", globals.get::<_, Function>("string.upper")?)?;
-        string.set("lower", globals.get::<_, Function>("string.lower")?)?;
-        env.set("string", string)?;
-        
-        let math = lua.create_table()?;
-        math.set("abs", globals.get::<_, Function>("math.abs")?)?;
-        math.set("ceil", globals.get::<_, Function>("math.ceil")?)?;
-        math.set("floor", globals.get::<_, Function>("math.floor")?)?;
-        math.set("max", globals.get::<_, Function>("math.max")?)?;
-        math.set("min", globals.get::<_, Function>("math.min")?)?;
-        env.set("math", math)?;
+        for name in SAFE_GLOBALS {
+            env.set(*name, globals.get::<_, LuaValue>(*name)?)?;
+        }
+        env.set("_G", env.clone())?;
+        
+        for (library, names) in SAFE_LIBRARIES {
+            let source: Table = globals.get(*library)?;
+            let table = lua.create_table()?;
+            for name in *names {
+                table.set(*name, source.get::<_, LuaValue>(*name)?)?;
+            }
+            env.set(*library, table)?;
+        }
+        
+        // string.rep 是最容易用极少代码耗尽内存的函数，提前按长度拒绝
+        let string: Table = env.get("string")?;
+        string.set(
+            "rep",
+            lua.create_function(|lua, (s, n, sep): (mlua::String, i64, Option<mlua::String>)| {
+                let count = n.max(0) as usize;
+                let sep = sep.as_ref().map_or(&[][..], |sep| sep.as_bytes());
+                let size = s
+                    .as_bytes()
+                    .len()
+                    .checked_mul(count)
+                    .and_then(|size| size.checked_add(sep.len().saturating_mul(count.saturating_sub(1))));
+                let size = match size {
+                    Some(size) if size <= MAX_STRING_SIZE => size,
+                    _ => {
+                        return Err(mlua::Error::runtime(format!(
+                            "string.rep result exceeds {} bytes",
+                            MAX_STRING_SIZE
+                        )))
+                    }
+                };
+                let mut result = Vec::with_capacity(size);
+                for i in 0..count {
+                    if i > 0 {
+                        result.extend_from_slice(sep);
+                    }
+                    result.extend_from_slice(s.as_bytes());
+                }
+                lua.create_string(&result)
+            })?,
+        )?;
+        
+        // 字符串共享同一个元表，其 __index 默认指向完整的 string 库（包括 string.dump），
+        // 改为沙箱中的 string 表并锁定元表，插件无法读取或替换
+        let string_mt: Table = lua.load("return getmetatable('')").eval()?;
+        string_mt.set("__index", string)?;
+        string_mt.set("__metatable", false)?;
+        
+        // 每个 Lua 状态的随机数种子取自 RandomState 的随机密钥（来自操作系统随机源），
+        // 不同插件、同一插件的每次加载都不相同，插件之间无法互相预测
+        let seed = std::collections::hash_map::RandomState::new().build_hasher().finish() as i64;
+        let lua_math: Table = globals.get("math")?;
+        lua_math.get::<_, Function>("randomseed")?.call::<_, ()>(seed)?;
+        
+        let make_setmetatable: Function = lua.load(SETMETATABLE_WRAPPER).set_name("=setmetatable").eval()?;
+        env.set(
+            "setmetatable",
+            make_setmetatable.call::<_, Function>((
+                globals.get::<_, Function>("setmetatable")?,
+                globals.get::<_, Function>("rawget")?,
+                globals.get::<_, Function>("type")?,
+                globals.get::<_, Function>("error")?,
+            ))?,
+        )?;
+        
+        // 执行预算耗尽后 pcall / xpcall 继续向外抛出错误，插件无法吞掉超时
+        let lua_table: Table = globals.get("table")?;
+        let is_interrupted = lua.create_function(move |_, ()| Ok(interrupted.load(Ordering::Relaxed)))?;
+        let make_guard: Function = lua.load(PCALL_WRAPPER).set_name("=pcall").eval()?;
+        let guard: Function = make_guard.call((
+            globals.get::<_, Function>("error")?,
+            lua_table.get::<_, Function>("pack")?,
+            lua_table.get::<_, Function>("unpack")?,
+            is_interrupted,
+        ))?;
+        for name in ["pcall", "xpcall"] {
+            env.set(name, guard.call::<_, Function>(globals.get::<_, Function>(name)?)?)?;
+        }
         
         // 添加 print 函数的安全版本
         let print = lua.create_function(|_, msg: String| {
@@ -64,6 +326,9 @@ impl LuaRuntime {
         })?;
         env.set("print", print)?;
         
+        // 与宿主交换数据时使用的 json.null / json.array
+        env.set("json", convert::create_json_table(lua)?)?;
+        
         // 设置为默认环境
         lua.set_named_registry_value("plugin_env", env)?;
         
@@ -71,110 +336,222 @@ impl LuaRuntime {
     }
 
     fn setup_api_bindings(&self) -> Result<()> {
-        let env: Table = self.lua.named_registry_value("plugin_env")?;
+        let lua = self.lua.lock().unwrap();
+        let env: Table = lua.named_registry_value("plugin_env")?;
+        
+        // 所有 API 调用都经过 ApiBridge 完成权限校验
+        let api_bridge = Arc::clone(&self.bridge);
+        let raw_call = lua.create_function(
+            move |lua, (api, method, params): (String, String, LuaValue)| {
+                let params_json = convert::lua_to_json(lua, &params)?;
+                match api_bridge.call(&api, &method, params_json) {
+                    Ok(result) => Ok((true, convert::json_to_lua(lua, &result)?)),
+                    Err(e) => Ok((false, convert::json_to_lua(lua, &bridge::error_value(&e))?)),
+                }
+            },
+        )?;
         
         // 创建 API 访问表
-        let api_table = self.lua.create_table()?;
-        
-        // 遍历所有注册的 API
-        for (name, api) in self.apis.lock().unwrap().iter() {
-            let api_clone = api.clone();
-            let func = self.lua.create_function(move |_, (method, params): (String, LuaValue)| {
-                let params_json = serde_json::to_value(params)?;
-                let result = api_clone.call(&method, params_json)?;
-                Ok(LuaValue::String(self.lua.create_string(&serde_json::to_string(&result)?)?))
-            })?;
-            api_table.set(name.clone(), func)?;
+        let make_api: Function = lua.load(API_WRAPPER).set_name("=api").eval()?;
+        let api_table: Table = make_api.call((raw_call, self.bridge.api_names()))?;
+        if let Some(bus_call) = api_table.get::<_, Option<Function>>("bus")? {
+            let make_bus: Function = lua.load(BUS_WRAPPER).set_name("=bus").eval()?;
+            env.set("bus", make_bus.call::<_, Table>(bus_call)?)?;
         }
-        
         env.set("api", api_table)?;
         
         Ok(())
     }
 
+    /// 安装只能加载插件目录和 vendor 目录中模块的 `require`
+    ///
+    /// 模块在第一次 `require` 时执行，返回值缓存在当前 Lua 状态中；热重载会创建新的运行时，
+    /// 缓存随之失效。
+    fn setup_require(&self, lua: &Lua) -> Result<()> {
+        let plugin_dir = Path::new(&self.script_path).parent().unwrap_or(Path::new("."));
+        let resolver = Arc::new(
+            ModuleResolver::new(&self.metadata.name, plugin_dir, self.vendor_dir.as_deref())
+                .context("Failed to resolve plugin directory")?,
+        );
+        lua.set_named_registry_value("plugin_modules", lua.create_table()?)?;
+        lua.set_named_registry_value("plugin_modules_loading", lua.create_table()?)?;
+
+        let require = lua.create_function(move |lua, module: String| {
+            let modules: Table = lua.named_registry_value("plugin_modules")?;
+            let cached: LuaValue = modules.get(module.as_str())?;
+            if !cached.is_nil() {
+                return Ok(cached);
+            }
+            let loading: Table = lua.named_registry_value("plugin_modules_loading")?;
+            if loading.get::<_, bool>(module.as_str())? {
+                return Err(mlua::Error::runtime(format!("circular require of module '{}'", module)));
+            }
+
+            let path = resolver.resolve(&module).map_err(mlua::Error::external)?;
+            let source = std::fs::read_to_string(&path).map_err(mlua::Error::external)?;
+            let env: Table = lua.named_registry_value("plugin_env")?;
+            let chunk = lua
+                .load(&source)
+                .set_name(resolver.display_name(&path))
+                .set_mode(ChunkMode::Text)
+                .set_environment(env);
+
+            loading.set(module.as_str(), true)?;
+            let result = chunk.call::<_, LuaValue>(module.as_str());
+            loading.set(module.as_str(), LuaValue::Nil)?;
+
+            // 与 Lua 的 require 一致，模块没有返回值时缓存为 true
+            let value = match result? {
+                LuaValue::Nil => LuaValue::Boolean(true),
+                value => value,
+            };
+            modules.set(module.as_str(), value.clone())?;
+            Ok(value)
+        })?;
+
+        let env: Table = lua.named_registry_value("plugin_env")?;
+        env.set("require", require)?;
+        Ok(())
+    }
+
     fn load_script(&self) -> Result<()> {
         let script = std::fs::read_to_string(&self.script_path)
             .context("Failed to read Lua script")?;
+        self.setup_require(&self.lua.lock().unwrap())?;
             
-        // 获取沙箱环境
-        let env: Table = self.lua.named_registry_value("plugin_env")?;
-        
-        // 在沙箱环境中加载并执行脚本
-        self.lua.load(&script)
-            .set_environment(env)?
-            .exec()
-            .context("Failed to execute Lua script")?;
-            
-        // 保存消息处理函数（如果存在）
-        if let Ok(handler) = env.get::<_, Function>("on_message") {
-            self.message_handler = Some(handler);
-        }
-            
-        Ok(())
+        self.enter(
+            || "Failed to execute Lua script".to_string(),
+            |lua| {
+                // 获取沙箱环境
+                let env: Table = lua.named_registry_value("plugin_env")?;
+                
+                // 在沙箱环境中加载并执行脚本
+                // 只接受源码，预编译的字节码可以绕过校验破坏解释器
+                lua.load(&script)
+                    .set_name(&self.metadata.main_file)
+                    .set_mode(ChunkMode::Text)
+                    .set_environment(env)
+                    .exec()
+            },
+        )
     }
 
-    fn call_function(&self, name: &str) -> Result<()> {
-        let env: Table = self.lua.named_registry_value("plugin_env")?;
-        
-        if let Ok(func) = env.get::<_, Function>(name) {
-            func.call(()).context(format!("Failed to call Lua function '{}'", name))?;
-        }
-        
-        Ok(())
+    /// 调用可选的生命周期钩子，插件未定义该函数时直接跳过
+    fn call_hook(&self, name: &str) -> Result<()> {
+        self.enter(
+            || format!("Failed to call Lua function '{}'", name),
+            |lua| {
+                let env: Table = lua.named_registry_value("plugin_env")?;
+                match env.get::<_, Option<Function>>(name)? {
+                    Some(func) => func.call::<_, ()>(()),
+                    None => Ok(()),
+                }
+            },
+        )
     }
 }
 
+/// 判断错误（包括经由回调或上下文包装的错误）是否由内存分配失败引起
+fn is_memory_error(err: &mlua::Error) -> bool {
+    match err {
+        mlua::Error::MemoryError(_) => true,
+        mlua::Error::CallbackError { cause, .. } => is_memory_error(cause),
+        mlua::Error::WithContext { cause, .. } => is_memory_error(cause),
+        _ => false,
+    }
+}
+
+#[async_trait]
 impl PluginRuntime for LuaRuntime {
-    fn init(&self) -> Result<()> {
+    fn metadata(&self) -> &PluginMetadata {
+        &self.metadata
+    }
+
+    async fn init(&self) -> Result<()> {
         self.setup_api_bindings()?;
         self.load_script()?;
-        self.call_function("init")
+        self.call_hook("init")
     }
 
-    fn start(&self) -> Result<()> {
-        self.call_function("start")
+    async fn start(&self) -> Result<()> {
+        self.call_hook("start")
     }
 
-    fn stop(&self) -> Result<()> {
-        self.call_function("stop")
+    async fn stop(&self) -> Result<()> {
+        self.call_hook("stop")
     }
 
-    fn unload(&self) -> Result<()> {
-        self.call_function("unload")
+    async fn unload(&self) -> Result<()> {
+        self.call_hook("unload")
+    }
+
+    async fn call_function(&self, name: &str, args: Vec<serde_json::Value>) -> Result<serde_json::Value> {
+        self.enter(
+            || format!("Failed to call Lua function '{}'", name),
+            |lua| {
+                let env: Table = lua.named_registry_value("plugin_env")?;
+                let func: Function = env
+                    .get::<_, Option<Function>>(name)?
+                    .ok_or_else(|| mlua::Error::runtime(format!("Lua function '{}' is not defined", name)))?;
+
+                let args = args
+                    .iter()
+                    .map(|arg| convert::json_to_lua(lua, arg))
+                    .collect::<LuaResult<MultiValue>>()?;
+                let result: LuaValue = func.call(args)?;
+                convert::lua_to_json(lua, &result)
+            },
+        )
+    }
+
+    fn has_function(&self, name: &str) -> bool {
+        let lua = self.lua.lock().unwrap();
+        lua.named_registry_value::<Table>("plugin_env")
+            .and_then(|env| env.get::<_, Option<Function>>(name))
+            .is_ok_and(|func| func.is_some())
     }
     
-    fn send_message(&self, message: PluginMessage) -> Result<()> {
-        if let Some(handler) = &self.message_handler {
-            let message_table = self.lua.create_table()?;
-            message_table.set("source", message.source)?;
-            message_table.set("target", message.target)?;
-            message_table.set("message_type", message.message_type)?;
-            message_table.set("payload", serde_json::to_string(&message.payload)?)?;
-            message_table.set("timestamp", message.timestamp.to_rfc3339())?;
-            
-            handler.call(message_table)
-                .context("Failed to call message handler")?;
-        }
-        Ok(())
+    async fn send_message(&self, message: PluginMessage) -> Result<()> {
+        self.enter(
+            || "Failed to call message handler".to_string(),
+            |lua| {
+                let env: Table = lua.named_registry_value("plugin_env")?;
+                if let Some(handler) = env.get::<_, Option<Function>>("on_message")? {
+                    let message_table = lua.create_table()?;
+                    message_table.set("source", message.source)?;
+                    message_table.set("target", message.target)?;
+                    message_table.set("message_type", message.message_type)?;
+                    message_table.set("payload", convert::json_to_lua(lua, &message.payload)?)?;
+                    message_table.set("timestamp", message.timestamp.to_rfc3339())?;
+                    
+                    handler.call::<_, ()>(message_table)?;
+                }
+                Ok(())
+            },
+        )
     }
     
     fn get_resource_usage(&self) -> Result<ResourceUsage> {
-        // TODO: 实现资源使用统计
         Ok(ResourceUsage {
-            memory_used: 0,
-            cpu_time: 0,
-            active_tasks: 0,
-            api_calls: 0,
+            api_calls: self.bridge.rate_limiter().calls(),
+            api_calls_rejected: self.bridge.rate_limiter().rejected(),
+            ..self.monitor.usage()
         })
     }
     
     fn set_resource_limits(&self, limits: ResourceLimits) -> Result<()> {
+        self.lua
+            .lock()
+            .unwrap()
+            .set_memory_limit(limits.max_memory.unwrap_or(0))?;
+        self.bridge.set_limits(&limits);
+        // 执行预算在下一次进入 Lua 时生效
         *self.resource_limits.lock().unwrap() = limits;
         Ok(())
     }
     
-    fn register_api(&self, name: &str, api: Box<dyn PluginApi>) -> Result<()> {
-        self.apis.lock().unwrap().insert(name.to_string(), api);
+    fn register_api(&self, name: &str, api: Arc<dyn PluginApi>) -> Result<()> {
+        self.bridge.register(name, api);
         self.setup_api_bindings()?;
         Ok(())
     }
@@ -228,6 +605,9 @@ mod tests {
             main_file: "test.lua".to_string(),
             permissions: vec!["task.read".to_string()],
             dependencies: None,
+            configuration: None,
+            commands: vec![],
+            contributes: Default::default(),
         };
         
         let runtime = LuaRuntime::new(
@@ -238,19 +618,37 @@ mod tests {
         (runtime, dir)
     }
 
-    #[test]
-    fn test_lua_runtime_lifecycle() {
+    #[tokio::test]
+    async fn test_lua_runtime_lifecycle() {
         let (runtime, _dir) = create_test_plugin();
         
-        assert!(runtime.init().is_ok());
-        assert!(runtime.start().is_ok());
-        assert!(runtime.stop().is_ok());
-        assert!(runtime.unload().is_ok());
+        assert!(runtime.init().await.is_ok());
+        assert!(runtime.start().await.is_ok());
+        assert!(runtime.stop().await.is_ok());
+        assert!(runtime.unload().await.is_ok());
     }
 
-    #[test]
-    fn test_sandbox_security() {
+    #[tokio::test]
+    async fn test_call_function() {
         let (runtime, dir) = create_test_plugin();
+        fs::write(
+            dir.path().join("test.lua"),
+            "function add(x, y) return x + y end",
+        )
+        .unwrap();
+        runtime.init().await.unwrap();
+
+        let result = runtime
+            .call_function("add", vec![serde_json::json!(5), serde_json::json!(3)])
+            .await
+            .unwrap();
+        assert_eq!(result, serde_json::json!(8));
+        assert!(runtime.call_function("missing", vec![]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sandbox_security() {
+        let (_runtime, dir) = create_test_plugin();
         let script_path = dir.path().join("malicious.lua");
         
         // 尝试访问文件系统（这应该被阻止）
@@ -279,6 +677,9 @@ mod tests {
             main_file: "malicious.lua".to_string(),
             permissions: vec![],
             dependencies: None,
+            configuration: None,
+            commands: vec![],
+            contributes: Default::default(),
         };
         
         let malicious_runtime = LuaRuntime::new(
@@ -286,15 +687,213 @@ mod tests {
             script_path.to_str().unwrap().to_string()
         ).unwrap();
         
-        // 初始化应该成功，但恶意操作应该被阻止
-        assert!(malicious_runtime.init().is_ok());
+        // 沙箱中不存在 io 库，恶意操作会以错误的形式被阻止
+        assert!(malicious_runtime.init().await.is_err());
         
         // 验证文件没有被创建
         assert!(!dir.path().join("sensitive.txt").exists());
     }
+
+    #[tokio::test]
+    async fn test_sandbox_blocks_escape_techniques() {
+        let (runtime, dir) = create_test_plugin();
+        fs::write(
+            dir.path().join("test.lua"),
+            r#"
+                -- 每个函数返回 true 表示逃逸成功
+                local attempts = {
+                    load = function() return load ~= nil or loadstring ~= nil end,
+                    dofile = function() return dofile ~= nil or loadfile ~= nil end,
+                    require_traversal = function() return pcall(require, "..test") or pcall(require, "/etc/passwd") end,
+                    debug = function() return debug ~= nil end,
+                    io = function() return io ~= nil or package ~= nil end,
+                    collectgarbage = function() return collectgarbage ~= nil end,
+                    os_execute = function() return os.execute ~= nil or os.getenv ~= nil or os.exit ~= nil or os.remove ~= nil end,
+                    string_dump = function() return string.dump ~= nil or ("").dump ~= nil end,
+                    string_metatable = function() return getmetatable("") ~= false end,
+                    string_setmetatable = function() return pcall(setmetatable, "", {}) end,
+                    gc_finalizer = function() return pcall(setmetatable, {}, { __gc = function() end }) end,
+                    global_table = function() return _G.io ~= nil or rawget(_G, "load") ~= nil end,
+                    env_upvalue = function() return _ENV ~= _G end,
+                    string_methods = function()
+                        local ok = pcall(function() return ("x"):dump() end)
+                        return ok
+                    end,
+                    rep_bomb = function() return pcall(string.rep, "x", 1 << 40) end,
+                    rep_separator_bomb = function() return pcall(string.rep, "", 1 << 40, "yy") end,
+                    format_width = function() return pcall(string.format, "%099999d", 1) end,
+                    pcall_hides_globals = function()
+                        local ok, value = pcall(function() return io.open end)
+                        return ok
+                    end,
+                }
+
+                function escapes()
+                    local succeeded = {}
+                    for name, attempt in pairs(attempts) do
+                        if attempt() then succeeded[#succeeded + 1] = name end
+                    end
+                    table.sort(succeeded)
+                    return table.concat(succeeded, ",")
+                end
+            "#,
+        )
+        .unwrap();
+        runtime.init().await.unwrap();
+
+        let result = runtime.call_function("escapes", vec![]).await.unwrap();
+        assert_eq!(result, serde_json::json!(""));
+    }
+
+    #[tokio::test]
+    async fn test_unload_with_looping_finalizer() {
+        let (runtime, dir) = create_test_plugin();
+        fs::write(
+            dir.path().join("test.lua"),
+            r#"
+                local mt = {}
+                setmetatable({}, mt)
+                -- 设置元表之后才加入的 __gc 不会被 Lua 调用
+                mt.__gc = function() while true do end end
+                local ok = pcall(setmetatable, {}, { __gc = function() while true do end end })
+                function finalizer_rejected() return not ok end
+            "#,
+        )
+        .unwrap();
+
+        // 卸载并关闭 Lua 状态时会执行终结器，放在单独的线程里以免回归时卡住测试
+        let (done, finished) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let rt = tokio::runtime::Runtime::new().unwrap();
+            rt.block_on(runtime.init()).unwrap();
+            let rejected = rt.block_on(runtime.call_function("finalizer_rejected", vec![])).unwrap();
+            let unloaded = rt.block_on(runtime.unload()).is_ok();
+            drop(runtime);
+            let _ = done.send((rejected, unloaded));
+        });
+        let result = finished.recv_timeout(std::time::Duration::from_secs(5));
+        assert_eq!(result, Ok((serde_json::json!(true), true)));
+    }
+
+    #[tokio::test]
+    async fn test_require_modules() {
+        let (runtime, dir) = create_test_plugin();
+        let vendor = tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("lib")).unwrap();
+        fs::write(
+            dir.path().join("lib/counter.lua"),
+            r#"
+                loads = (loads or 0) + 1
+                local M = { name = ... }
+                function M.loads() return loads end
+                return M
+            "#,
+        )
+        .unwrap();
+        fs::write(dir.path().join("lib/a.lua"), "return require('lib.b')").unwrap();
+        fs::write(dir.path().join("lib/b.lua"), "return require('lib.a')").unwrap();
+        fs::write(vendor.path().join("shared.lua"), "return { answer = 42 }").unwrap();
+        fs::write(
+            dir.path().join("test.lua"),
+            r#"
+                local counter = require("lib.counter")
+                local again = require("lib.counter")
+
+                function check()
+                    local ok, err = pcall(require, "lib.a")
+                    return {
+                        same = counter == again,
+                        loads = counter.loads(),
+                        name = counter.name,
+                        shared = require("shared").answer,
+                        circular = not ok and tostring(err):find("circular require") ~= nil,
+                        missing = not pcall(require, "missing"),
+                    }
+                end
+            "#,
+        )
+        .unwrap();
+        let runtime = runtime.with_vendor_dir(Some(vendor.path().to_path_buf()));
+        runtime.init().await.unwrap();
+
+        let result = runtime.call_function("check", vec![]).await.unwrap();
+        assert_eq!(
+            result,
+            serde_json::json!({
+                "same": true,
+                "loads": 1,
+                "name": "lib.counter",
+                "shared": 42,
+                "circular": true,
+                "missing": true,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sandbox_rejects_bytecode() {
+        let (runtime, dir) = create_test_plugin();
+        let bytecode = Lua::new()
+            .load("function init() end")
+            .into_function()
+            .unwrap()
+            .dump(true);
+        fs::write(dir.path().join("test.lua"), bytecode).unwrap();
+        assert!(runtime.init().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sandbox_standard_library() {
+        let (runtime, dir) = create_test_plugin();
+        fs::write(
+            dir.path().join("test.lua"),
+            r##"
+                function stdlib()
+                    local words = {}
+                    for word in string.gmatch("b a c", "%a") do table.insert(words, word) end
+                    table.sort(words)
+                    local now = os.time()
+                    local r = math.random(1, 6)
+                    return {
+                        format = string.format("%s=%05.1f", "x", 3.14159),
+                        gsub = ("hello world"):gsub("o", "0"),
+                        find = string.find("plugin", "gin", 1, true),
+                        rep = string.rep("ab", 3, "-"),
+                        words = table.concat(words),
+                        utf8 = utf8.len("插件"),
+                        date = os.date("!%Y-%m-%d", 0),
+                        time = math.type(now),
+                        clock = type(os.clock()),
+                        random = r >= 1 and r <= 6,
+                        unpack = select("#", table.unpack({1, 2, 3})),
+                    }
+                end
+            "##,
+        )
+        .unwrap();
+        runtime.init().await.unwrap();
+
+        let result = runtime.call_function("stdlib", vec![]).await.unwrap();
+        assert_eq!(
+            result,
+            serde_json::json!({
+                "format": "x=003.1",
+                "gsub": "hell0 w0rld",
+                "find": 4,
+                "rep": "ab-ab-ab",
+                "words": "abc",
+                "utf8": 2,
+                "date": "1970-01-01",
+                "time": "integer",
+                "clock": "number",
+                "random": true,
+                "unpack": 3,
+            })
+        );
+    }
     
-    #[test]
-    fn test_api_registration() {
+    #[tokio::test]
+    async fn test_api_registration() {
         let (runtime, _dir) = create_test_plugin();
         
         // 创建测试 API
@@ -310,25 +909,308 @@ mod tests {
             fn get_permissions(&self) -> Vec<String> {
                 vec!["test.api".to_string()]
             }
+
+            fn method_access(&self, _method: &str) -> Option<crate::plugin::MethodAccess> {
+                Some(crate::plugin::MethodAccess::Public)
+            }
         }
         
         // 注册 API
-        assert!(runtime.register_api("test", Box::new(TestApi)).is_ok());
+        assert!(runtime.register_api("test", Arc::new(TestApi)).is_ok());
         
         // 验证 API 是否可用
         let script = r#"
             function test_api()
                 local result = api.test("hello", {message = "world"})
-                print(result)
+                print(result.params.message)
                 return result
             end
         "#;
         
-        runtime.lua.load(script)
-            .set_environment(runtime.lua.named_registry_value::<Table>("plugin_env").unwrap())
-            .exec()
-            .unwrap();
+        {
+            let lua = runtime.lua.lock().unwrap();
+            lua.load(script)
+                .set_environment(lua.named_registry_value::<Table>("plugin_env").unwrap())
+                .exec()
+                .unwrap();
+        }
             
-        assert!(runtime.call_function("test_api").is_ok());
+        // API 结果以表的形式交给插件，原样返回后得到相同的 JSON
+        let result = runtime.call_function("test_api", vec![]).await.unwrap();
+        assert_eq!(result, serde_json::json!({ "method": "hello", "params": { "message": "world" } }));
+    }
+
+    #[tokio::test]
+    async fn test_api_permission_denied() {
+        let (runtime, dir) = create_test_plugin();
+        fs::write(
+            dir.path().join("test.lua"),
+            r#"
+                function list_tasks()
+                    return api.task("list", {})
+                end
+
+                function create_task()
+                    local ok, err = pcall(api.task, "create", {title = "x"})
+                    return {ok = ok, code = err.code, permission = err.permission, message = tostring(err)}
+                end
+            "#,
+        )
+        .unwrap();
+        runtime
+            .register_api("task", Arc::new(crate::plugin::api::TaskApi {}))
+            .unwrap();
+        runtime.init().await.unwrap();
+
+        // 清单授予了 task.read
+        assert!(runtime.call_function("list_tasks", vec![]).await.is_ok());
+
+        // 没有授予 task.write，错误以结构化的形式交给 Lua
+        let result = runtime.call_function("create_task", vec![]).await.unwrap();
+        assert_eq!(result["ok"], false);
+        assert_eq!(result["code"], "permission_denied");
+        assert_eq!(result["permission"], "task.write");
+        assert!(result["message"].as_str().unwrap().contains("Permission denied"));
+    }
+
+    #[tokio::test]
+    async fn test_memory_limit() {
+        let (runtime, dir) = create_test_plugin();
+        fs::write(
+            dir.path().join("test.lua"),
+            r#"
+                function grow()
+                    local t = {}
+                    for i = 1, 10000000 do
+                        t[i] = i
+                    end
+                    return #t
+                end
+
+                function small()
+                    return 42
+                end
+
+                function hold()
+                    kept = string.rep("x", 512 * 1024)
+                    return #kept
+                end
+            "#,
+        )
+        .unwrap();
+        runtime.init().await.unwrap();
+        runtime
+            .set_resource_limits(ResourceLimits {
+                max_memory: Some(2 * 1024 * 1024),
+                ..ResourceLimits::default()
+            })
+            .unwrap();
+
+        runtime.call_function("hold", vec![]).await.unwrap();
+        let before = runtime.get_resource_usage().unwrap().memory_used;
+
+        let err = runtime.call_function("grow", vec![]).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<PluginError>(),
+            Some(PluginError::MemoryLimitExceeded { limit, .. }) if *limit == 2 * 1024 * 1024
+        ));
+
+        // 分配失败之后插件仍然可以继续执行
+        assert_eq!(runtime.call_function("small", vec![]).await.unwrap(), serde_json::json!(42));
+
+        let usage = runtime.get_resource_usage().unwrap();
+        assert!(usage.memory_used > 0);
+        assert!(usage.memory_used < 2 * 1024 * 1024);
+        // 峰值是分配失败时的实际占用，而不是配置的上限
+        assert!(usage.peak_memory_used > before);
+        assert!(usage.peak_memory_used < 2 * 1024 * 1024);
+        assert_eq!(usage.memory_violations, 1);
+    }
+
+    #[tokio::test]
+    async fn test_cpu_time_budget() {
+        let (runtime, dir) = create_test_plugin();
+        fs::write(dir.path().join("test.lua"), "function init() while true do end end").unwrap();
+        runtime
+            .set_resource_limits(ResourceLimits {
+                max_cpu_time: Some(50),
+                ..ResourceLimits::default()
+            })
+            .unwrap();
+
+        let err = runtime.init().await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<PluginError>(),
+            Some(PluginError::CpuTimeExceeded { limit_ms: 50, .. })
+        ));
+
+        let usage = runtime.get_resource_usage().unwrap();
+        assert!(usage.cpu_time >= 50);
+        assert_eq!(usage.cpu_violations, 1);
+    }
+
+    /// 每次调用都等待一段时间的宿主 API
+    struct SlowApi;
+
+    impl PluginApi for SlowApi {
+        fn call(&self, _method: &str, _params: serde_json::Value) -> Result<serde_json::Value> {
+            std::thread::sleep(std::time::Duration::from_millis(60));
+            Ok(serde_json::Value::Null)
+        }
+
+        fn get_permissions(&self) -> Vec<String> {
+            vec![]
+        }
+
+        fn method_access(&self, _method: &str) -> Option<crate::plugin::MethodAccess> {
+            Some(crate::plugin::MethodAccess::Public)
+        }
     }
-} 
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_host_api_time_not_charged() {
+        let (runtime, dir) = create_test_plugin();
+        fs::write(
+            dir.path().join("test.lua"),
+            r#"
+                function wait()
+                    local sum = 0
+                    for i = 1, 3 do api.slow("wait", {}) end
+                    for i = 1, 100000 do sum = sum + i end
+                    return "done"
+                end
+            "#,
+        )
+        .unwrap();
+        runtime
+            .set_resource_limits(ResourceLimits {
+                max_cpu_time: Some(100),
+                ..ResourceLimits::default()
+            })
+            .unwrap();
+        runtime.register_api("slow", Arc::new(SlowApi)).unwrap();
+        runtime.init().await.unwrap();
+
+        // 在宿主 API 中等待了 180 毫秒，超过预算但不计入插件的执行时间
+        let result = runtime.call_function("wait", vec![]).await.unwrap();
+        assert_eq!(result, serde_json::json!("done"));
+        let usage = runtime.get_resource_usage().unwrap();
+        assert_eq!(usage.cpu_violations, 0);
+        assert!(usage.cpu_time < 100);
+    }
+
+    #[tokio::test]
+    async fn test_instruction_budget_cannot_be_caught() {
+        let (runtime, dir) = create_test_plugin();
+        fs::write(
+            dir.path().join("test.lua"),
+            r#"
+                function spin()
+                    local ok = pcall(function() while true do end end)
+                    return "swallowed"
+                end
+
+                function small()
+                    return 42
+                end
+            "#,
+        )
+        .unwrap();
+        runtime
+            .set_resource_limits(ResourceLimits {
+                max_instructions: Some(100_000),
+                ..ResourceLimits::default()
+            })
+            .unwrap();
+        runtime.init().await.unwrap();
+
+        // pcall 不能吞掉预算耗尽的错误
+        let err = runtime.call_function("spin", vec![]).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<PluginError>(),
+            Some(PluginError::InstructionLimitExceeded { limit: 100_000, .. })
+        ));
+
+        // 每次调用都有新的预算
+        assert_eq!(runtime.call_function("small", vec![]).await.unwrap(), serde_json::json!(42));
+        assert_eq!(runtime.get_resource_usage().unwrap().cpu_violations, 1);
+    }
+
+    #[tokio::test]
+    async fn test_api_rate_limit() {
+        let (runtime, dir) = create_test_plugin();
+        fs::write(
+            dir.path().join("test.lua"),
+            r#"
+                function hammer()
+                    for i = 1, 100 do
+                        local ok, err = pcall(api.task, "list", {})
+                        if not ok then
+                            return {calls = i - 1, code = err.code, retry_after_ms = err.retry_after_ms}
+                        end
+                    end
+                    return {calls = 100}
+                end
+            "#,
+        )
+        .unwrap();
+        runtime
+            .register_api("task", Arc::new(crate::plugin::api::TaskApi {}))
+            .unwrap();
+        runtime
+            .set_resource_limits(ResourceLimits {
+                max_api_calls: Some(10),
+                ..ResourceLimits::default()
+            })
+            .unwrap();
+        runtime.init().await.unwrap();
+
+        let result = runtime.call_function("hammer", vec![]).await.unwrap();
+        assert_eq!(result["calls"], 10);
+        assert_eq!(result["code"], "rate_limited");
+        assert!(result["retry_after_ms"].as_u64().unwrap() > 0);
+
+        let usage = runtime.get_resource_usage().unwrap();
+        assert_eq!(usage.api_calls, 10);
+        assert_eq!(usage.api_calls_rejected, 1);
+    }
+
+    #[tokio::test]
+    async fn test_message_payload_is_table() {
+        let (runtime, dir) = create_test_plugin();
+        fs::write(
+            dir.path().join("test.lua"),
+            r#"
+                local last
+                function on_message(message) last = message.payload end
+                function inspect()
+                    return {
+                        count = #last.items,
+                        second_is_null = last.items[2] == json.null,
+                        payload = last,
+                    }
+                end
+            "#,
+        )
+        .unwrap();
+        runtime.init().await.unwrap();
+
+        let payload = serde_json::json!({ "items": [1, null, 2.5], "empty": [], "meta": {} });
+        runtime
+            .send_message(PluginMessage {
+                source: "host".to_string(),
+                target: Some("test_plugin".to_string()),
+                message_type: "data".to_string(),
+                payload: payload.clone(),
+                timestamp: chrono::Utc::now(),
+            })
+            .await
+            .unwrap();
+
+        let result = runtime.call_function("inspect", vec![]).await.unwrap();
+        assert_eq!(result["count"], 3);
+        assert_eq!(result["second_is_null"], true);
+        // 整数与小数、空数组与空对象都保持不变
+        assert_eq!(result["payload"], payload);
+    }
+}
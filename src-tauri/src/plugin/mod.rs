@@ -1,14 +1,54 @@
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use serde::{Deserialize, Serialize};
-use anyhow::Result;
-use chrono;
-use tokio::sync::mpsc;
+use anyhow::{Context, Result};
+use tokio::sync::{broadcast, mpsc};
 
 pub mod api;
+pub mod bridge;
+pub mod bus;
+pub mod command;
+pub mod config;
+pub mod contribution;
+pub mod convert;
+pub mod deps;
+pub mod diagnostics;
+pub mod js;
+pub mod error;
+pub mod files;
+pub mod harness;
+pub mod http;
+pub mod lifecycle;
+pub mod limits;
+pub mod loader;
 pub mod runtime;
+pub mod timer;
 pub mod watcher;
 pub mod lua;
+pub mod package;
+pub mod permission;
+pub mod typescript;
+pub mod wasm;
+
+#[cfg(test)]
+mod conformance;
+
+pub use error::PluginError;
+pub use lifecycle::{LifecycleAction, PluginEvent, RuntimeState};
+pub use limits::{ViolationAction, ViolationPolicy};
+pub use package::{TrustLevel, TrustPolicy};
+pub use runtime::PluginRuntime;
+
+/// 插件清单文件名，每个插件目录下必须包含该文件
+pub const MANIFEST_FILE: &str = "plugin.json";
+
+/// 未单独设置数据目录时，插件数据保存在插件目录下的该子目录中，每个插件一个子目录
+pub const DATA_DIR: &str = ".data";
+
+const EVENT_CHANNEL_CAPACITY: usize = 64;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PluginMetadata {
@@ -21,18 +61,29 @@ pub struct PluginMetadata {
     pub license: Option<String>,
     pub language: PluginLanguage,
     pub main_file: String,
+    #[serde(default)]
     pub permissions: Vec<String>,
     pub dependencies: Option<HashMap<String, String>>,
+    /// 插件的用户配置项，见 [`config::ConfigSchema`]
+    #[serde(default)]
+    pub configuration: Option<config::ConfigSchema>,
+    /// 插件提供给命令面板的命令，见 [`command::CommandSpec`]
+    #[serde(default)]
+    pub commands: Vec<command::CommandSpec>,
+    /// 插件的界面扩展点，见 [`contribution::Contributions`]
+    #[serde(default)]
+    pub contributes: contribution::Contributions,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum PluginLanguage {
     Lua,
     TypeScript,
+    Wasm,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum PluginStatus {
     Installed,
@@ -41,17 +92,57 @@ pub enum PluginStatus {
     Disabled,
 }
 
+#[derive(Clone)]
 pub struct PluginManager {
-    plugins: Arc<Mutex<HashMap<String, Box<dyn PluginRuntime>>>>,
-    watcher: Option<watcher::PluginWatcher>,
-    watcher_rx: Option<mpsc::Receiver<watcher::PluginWatchEvent>>,
+    plugin_dir: PathBuf,
+    plugins: Arc<Mutex<HashMap<String, Plugin>>>,
+    apis: Arc<Mutex<HashMap<String, Arc<dyn PluginApi>>>>,
+    events: broadcast::Sender<PluginEvent>,
+    watcher: Arc<Mutex<Option<watcher::PluginWatcher>>>,
+    violation_policy: Arc<Mutex<ViolationPolicy>>,
+    bus: Arc<bus::MessageBus>,
+    vendor_dir: Arc<Mutex<Option<PathBuf>>>,
+    data_dir: Arc<Mutex<Option<PathBuf>>>,
+    trust_policy: Arc<Mutex<TrustPolicy>>,
+    user: Arc<Mutex<String>>,
+    errors: Arc<diagnostics::ErrorLog>,
+    commands: Arc<command::CommandRegistry>,
+    http: Arc<http::HttpClient>,
+    timers: Arc<timer::TimerScheduler>,
 }
 
 #[derive(Clone)]
 pub struct Plugin {
     metadata: PluginMetadata,
     status: PluginStatus,
+    state: RuntimeState,
+    path: PathBuf,
+    limits: Option<ResourceLimits>,
+    // 文件 API 的磁盘配额，与运行时实例共享，修改资源限制后立即生效
+    disk_quota: Arc<AtomicU64>,
+    // 定时器数量上限，同样与运行时实例共享
+    task_limit: Arc<AtomicUsize>,
     runtime: Option<Arc<dyn PluginRuntime>>,
+    // 当前运行时实例的编号，定时器按实例归属
+    instance: u64,
+    // 上次处理之后累计的资源违规次数
+    violations: usize,
+    // 被限流时拒绝调用直到该时刻
+    throttled_until: Option<Instant>,
+    // 串行化同一插件的生命周期操作
+    lifecycle: Arc<tokio::sync::Mutex<()>>,
+}
+
+/// 插件管理界面展示的插件概况
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PluginInfo {
+    pub metadata: PluginMetadata,
+    pub status: PluginStatus,
+    pub state: RuntimeState,
+    pub path: PathBuf,
+    /// 插件没有运行时（未加载或已停用）时为 `None`
+    pub usage: Option<ResourceUsage>,
+    pub last_error: Option<diagnostics::PluginErrorRecord>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -63,203 +154,2639 @@ pub struct PluginMessage {
     pub timestamp: chrono::DateTime<chrono::Utc>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ResourceLimits {
     pub max_memory: Option<usize>,      // 最大内存使用量（字节）
-    pub max_cpu_time: Option<u64>,      // 最大 CPU 时间（毫秒）
+    pub max_cpu_time: Option<u64>,      // 单次调用最大 CPU 时间（毫秒）
+    #[serde(default)]
+    pub max_instructions: Option<u64>,  // 单次调用最大 Lua 指令数
     pub max_tasks: Option<usize>,       // 最大并发任务数
-    pub max_api_calls: Option<usize>,   // API 调用频率限制
+    pub max_api_calls: Option<usize>,   // 每分钟 API 调用次数上限
+    #[serde(default)]
+    pub api_quotas: HashMap<String, usize>, // 单个 API 每分钟的调用次数上限
+    #[serde(default)]
+    pub max_disk_usage: Option<u64>,    // 插件私有文件的总大小上限（字节）
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ResourceUsage {
     pub memory_used: usize,
-    pub cpu_time: u64,
+    #[serde(default)]
+    pub peak_memory_used: usize,        // 内存占用峰值（字节）
+    #[serde(default)]
+    pub memory_violations: usize,       // 内存超限次数
+    pub cpu_time: u64,                  // 累计 CPU 时间（毫秒）
+    #[serde(default)]
+    pub cpu_violations: usize,          // 执行预算耗尽次数
     pub active_tasks: usize,
-    pub api_calls: usize,
+    pub api_calls: usize,               // 累计 API 调用次数
+    #[serde(default)]
+    pub api_calls_rejected: usize,      // 因超出配额被拒绝的 API 调用次数
 }
 
-pub trait PluginRuntime: Send + Sync {
-    // 基本生命周期管理
-    fn init(&self) -> Result<()>;
-    fn start(&self) -> Result<()>;
-    fn stop(&self) -> Result<()>;
-    fn unload(&self) -> Result<()>;
-    
-    // 插件间通信
-    fn send_message(&self, message: PluginMessage) -> Result<()> {
-        Ok(()) // 默认实现：不处理消息
-    }
-    
-    fn receive_message(&self, message: PluginMessage) -> Result<()> {
-        Ok(()) // 默认实现：不处理消息
-    }
-    
-    // 资源管理
-    fn get_resource_usage(&self) -> Result<ResourceUsage> {
-        Ok(ResourceUsage {
-            memory_used: 0,
-            cpu_time: 0,
-            active_tasks: 0,
-            api_calls: 0,
-        })
-    }
-    
-    fn set_resource_limits(&self, limits: ResourceLimits) -> Result<()> {
-        Ok(()) // 默认实现：不限制资源
-    }
-    
-    // 热重载支持
-    fn reload(&self) -> Result<()> {
-        self.stop()?;
-        self.init()?;
-        self.start()
-    }
-    
-    // API 注册
-    fn register_api(&self, name: &str, api: Box<dyn PluginApi>) -> Result<()> {
-        Ok(()) // 默认实现：不支持 API 注册
-    }
+/// 调用 API 方法的授权要求
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MethodAccess {
+    /// 任何插件都可以调用
+    Public,
+    /// 插件清单需要声明该权限
+    Requires(String),
 }
 
 pub trait PluginApi: Send + Sync {
     fn call(&self, method: &str, params: serde_json::Value) -> Result<serde_json::Value>;
     fn get_permissions(&self) -> Vec<String>;
+
+    /// 调用 `method` 的授权要求，返回 `None` 表示 API 没有公开该方法，调用会被拒绝
+    ///
+    /// 只有明确列出的方法可以调用，新增方法时必须同时在这里声明。
+    fn method_access(&self, method: &str) -> Option<MethodAccess>;
 }
 
 impl PluginManager {
     pub fn new() -> Self {
+        Self::with_plugin_dir("plugins")
+    }
+
+    pub fn with_plugin_dir(plugin_dir: impl AsRef<Path>) -> Self {
+        let (events, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+        let commands = Arc::new(command::CommandRegistry::new(events.clone()));
+        let errors = diagnostics::ErrorLog::with_events(diagnostics::DEFAULT_ERROR_CAPACITY, events.clone());
         Self {
+            plugin_dir: plugin_dir.as_ref().to_path_buf(),
             plugins: Arc::new(Mutex::new(HashMap::new())),
-            watcher: None,
-            watcher_rx: None,
+            apis: Arc::new(Mutex::new(HashMap::new())),
+            events,
+            watcher: Arc::new(Mutex::new(None)),
+            violation_policy: Arc::new(Mutex::new(ViolationPolicy::default())),
+            bus: Arc::new(bus::MessageBus::new()),
+            vendor_dir: Arc::new(Mutex::new(None)),
+            data_dir: Arc::new(Mutex::new(None)),
+            trust_policy: Arc::new(Mutex::new(TrustPolicy::default())),
+            user: Arc::new(Mutex::new(config::DEFAULT_USER.to_string())),
+            errors: Arc::new(errors),
+            commands,
+            http: Arc::new(http::HttpClient::default()),
+            timers: Arc::new(timer::TimerScheduler::new()),
+        }
+    }
+
+    pub fn plugin_dir(&self) -> &Path {
+        &self.plugin_dir
+    }
+
+    /// 设置所有 Lua 插件共享的只读库目录，之后创建的运行时可以 `require` 其中的模块
+    pub fn set_vendor_dir(&self, vendor_dir: Option<PathBuf>) {
+        *self.vendor_dir.lock().unwrap() = vendor_dir;
+    }
+
+    pub fn vendor_dir(&self) -> Option<PathBuf> {
+        self.vendor_dir.lock().unwrap().clone()
+    }
+
+    /// 设置保存插件数据的根目录，`None` 表示使用插件目录下的 `.data`
+    pub fn set_data_dir(&self, data_dir: Option<PathBuf>) {
+        *self.data_dir.lock().unwrap() = data_dir;
+    }
+
+    pub fn data_dir(&self) -> PathBuf {
+        self.data_dir
+            .lock()
+            .unwrap()
+            .clone()
+            .unwrap_or_else(|| self.plugin_dir.join(DATA_DIR))
+    }
+
+    /// 插件的私有数据目录，升级时保留，卸载时可选择清除
+    pub fn plugin_data_dir(&self, name: &str) -> PathBuf {
+        self.data_dir().join(name)
+    }
+
+    /// 设置安装插件包时的信任策略
+    pub fn set_trust_policy(&self, policy: TrustPolicy) {
+        *self.trust_policy.lock().unwrap() = policy;
+    }
+
+    pub fn trust_policy(&self) -> TrustPolicy {
+        self.trust_policy.lock().unwrap().clone()
+    }
+
+    /// 插件最近的错误记录，最新的在前
+    pub fn recent_errors(&self, name: &str, limit: usize) -> Vec<diagnostics::PluginErrorRecord> {
+        self.errors.recent(name, limit)
+    }
+
+    pub fn clear_errors(&self, name: &str) {
+        self.errors.clear(name);
+    }
+
+    /// 设置插件错误和 HTTP 请求记录的持久化目标，记录按 `logs` 表的格式写入
+    pub fn set_log_sink(&self, sink: Option<Arc<dyn diagnostics::LogSink>>) {
+        self.http.audit().set_sink(sink.clone());
+        self.errors.set_sink(sink);
+    }
+
+    /// 替换插件 HTTP 请求的传输层，对已加载的插件立即生效
+    pub fn set_http_transport(&self, transport: Arc<dyn http::HttpTransport>) {
+        self.http.set_transport(transport);
+    }
+
+    pub fn http_policy(&self) -> http::HttpPolicy {
+        self.http.policy()
+    }
+
+    pub fn set_http_policy(&self, policy: http::HttpPolicy) {
+        self.http.set_policy(policy);
+    }
+
+    /// 插件最近的 HTTP 请求记录，最新的在前
+    pub fn http_audit(&self, name: &str, limit: usize) -> Vec<http::HttpAuditRecord> {
+        self.http.audit().recent(name, limit)
+    }
+
+    /// 授权插件通过文件 API 的 `root` 参数只读访问目录 `dir`，授权保存在插件数据目录中
+    pub fn grant_read_root(&self, name: &str, root: &str, dir: impl AsRef<Path>) -> Result<()> {
+        files::FileGrants::new(&self.plugin_data_dir(name)).grant(root, dir.as_ref())
+    }
+
+    pub fn revoke_read_root(&self, name: &str, root: &str) -> Result<bool> {
+        files::FileGrants::new(&self.plugin_data_dir(name)).revoke(root)
+    }
+
+    pub fn read_roots(&self, name: &str) -> Result<std::collections::BTreeMap<String, PathBuf>> {
+        files::FileGrants::new(&self.plugin_data_dir(name)).load()
+    }
+
+    /// 当前用户，插件读取和保存的都是该用户的配置
+    pub fn user(&self) -> String {
+        self.user.lock().unwrap().clone()
+    }
+
+    /// 切换当前用户，声明了配置项的运行中插件会收到 `on_config_changed`
+    pub async fn set_user(&self, user: &str) -> Result<()> {
+        if !package::is_valid_name(user) {
+            return Err(anyhow::anyhow!("Invalid user name '{}'", user));
+        }
+        *self.user.lock().unwrap() = user.to_string();
+        let configurable: Vec<_> = self
+            .list_plugins()
+            .into_iter()
+            .filter(|metadata| metadata.configuration.is_some())
+            .map(|metadata| metadata.name)
+            .collect();
+        for name in configurable {
+            self.notify_config_changed(&name).await;
         }
+        Ok(())
+    }
+
+    /// 插件清单中声明的配置项，供前端渲染设置表单
+    pub fn config_schema(&self, name: &str) -> Result<Option<config::ConfigSchema>> {
+        self.plugins
+            .lock()
+            .unwrap()
+            .get(name)
+            .map(|plugin| plugin.metadata.configuration.clone())
+            .ok_or_else(|| PluginError::NotLoaded { plugin_id: name.to_string() }.into())
+    }
+
+    /// 当前用户的插件配置，未设置的项使用清单中的默认值
+    pub fn plugin_config(&self, name: &str) -> Result<serde_json::Map<String, serde_json::Value>> {
+        let schema = self.config_schema(name)?.unwrap_or_default();
+        let values = self.config_store(name).load(&self.user())?;
+        Ok(schema.resolve(&values))
+    }
+
+    /// 校验并保存当前用户的插件配置，`values` 是完整的配置，`null` 表示清除该项
+    ///
+    /// 保存成功后运行中的插件会收到 `on_config_changed(config)`，回调失败不影响保存结果。
+    pub async fn set_plugin_config(
+        &self,
+        name: &str,
+        values: serde_json::Map<String, serde_json::Value>,
+    ) -> Result<serde_json::Map<String, serde_json::Value>> {
+        let schema = self.config_schema(name)?.unwrap_or_default();
+        schema.validate(name, &values)?;
+        self.config_store(name).save(&self.user(), &values)?;
+        self.notify_config_changed(name).await;
+        self.plugin_config(name)
+    }
+
+    /// 注册对所有插件开放的宿主 API，插件能否调用由其清单中的权限决定
+    pub fn register_api(&self, name: &str, api: Arc<dyn PluginApi>) {
+        self.apis.lock().unwrap().insert(name.to_string(), api);
+    }
+
+    /// 订阅插件生命周期事件
+    pub fn subscribe(&self) -> broadcast::Receiver<PluginEvent> {
+        self.events.subscribe()
     }
 
     pub async fn init_with_watch(&mut self, plugin_dir: impl AsRef<std::path::Path>) -> Result<()> {
-        let (tx, rx) = mpsc::channel(32);
-        let watcher = watcher::PluginWatcher::new(plugin_dir, tx)?;
-        
-        self.watcher = Some(watcher);
-        self.watcher_rx = Some(rx);
-        
-        // 启动文件监控处理循环
-        let plugins = Arc::clone(&self.plugins);
+        self.init_with_watch_options(plugin_dir, watcher::WatchOptions::default()).await
+    }
+
+    /// 监控插件目录，插件的任何文件（包括 `plugin.json`）变化后重新加载该插件
+    pub async fn init_with_watch_options(
+        &mut self,
+        plugin_dir: impl AsRef<std::path::Path>,
+        options: watcher::WatchOptions,
+    ) -> Result<()> {
+        self.plugin_dir = plugin_dir.as_ref().to_path_buf();
+
+        let (tx, mut rx) = mpsc::channel(32);
+        let watcher = watcher::PluginWatcher::with_options(&self.plugin_dir, tx, options)?;
+        *self.watcher.lock().unwrap() = Some(watcher);
+
+        // 启动文件监控处理循环，监控器被丢弃后通道关闭，循环随之退出
+        let manager = self.clone();
         tokio::spawn(async move {
-            if let Some(mut rx) = self.watcher_rx.take() {
-                while let Some(event) = rx.recv().await {
-                    let mut plugins = plugins.lock().await;
-                    match event.event_type {
-                        watcher::PluginWatchEventType::Modified => {
-                            if let Some(plugin_id) = event.path.file_stem() {
-                                if let Some(plugin) = plugins.get_mut(plugin_id.to_str().unwrap()) {
-                                    let _ = plugin.reload().await;
-                                }
-                            }
-                        }
-                        watcher::PluginWatchEventType::Created => {
-                            // 处理新插件创建
-                            if let Some(plugin_id) = event.path.file_stem() {
-                                if !plugins.contains_key(plugin_id.to_str().unwrap()) {
-                                    // TODO: 加载新插件
-                                }
-                            }
-                        }
-                        watcher::PluginWatchEventType::Deleted => {
-                            // 处理插件删除
-                            if let Some(plugin_id) = event.path.file_stem() {
-                                plugins.remove(plugin_id.to_str().unwrap());
-                            }
-                        }
-                    }
-                }
+            while let Some(event) = rx.recv().await {
+                manager.handle_watch_event(event).await;
             }
         });
 
         Ok(())
     }
 
-    pub fn load_plugin(&self, name: &str) -> Result<()> {
-        // TODO: 实现插件加载逻辑
+    /// 停止文件监控
+    pub fn stop_watching(&self) {
+        self.watcher.lock().unwrap().take();
+    }
+
+    /// 扫描插件目录，返回所有包含有效 `plugin.json` 的插件目录及其元数据
+    pub fn discover_plugins(&self) -> Result<Vec<(PathBuf, PluginMetadata)>> {
+        let mut found = Vec::new();
+        if !self.plugin_dir.is_dir() {
+            return Ok(found);
+        }
+
+        for entry in std::fs::read_dir(&self.plugin_dir)
+            .with_context(|| format!("Failed to read plugin directory {}", self.plugin_dir.display()))?
+        {
+            let dir = entry?.path();
+            // 以点开头的是安装和升级使用的暂存、备份目录
+            let hidden = dir.file_name().and_then(|n| n.to_str()).is_some_and(|n| n.starts_with('.'));
+            if hidden || !dir.join(MANIFEST_FILE).is_file() {
+                continue;
+            }
+            match read_manifest(&dir) {
+                Ok(metadata) => found.push((dir, metadata)),
+                Err(e) => eprintln!("[PluginManager] 跳过无效插件 {}: {:#}", dir.display(), e),
+            }
+        }
+
+        found.sort_by(|a, b| a.1.name.cmp(&b.1.name));
+        Ok(found)
+    }
+
+    /// 按依赖顺序加载插件目录中的所有插件，返回成功激活的插件名称
+    pub async fn load_all(&self) -> Result<Vec<String>> {
+        let manifests: Vec<_> = self
+            .discover_plugins()?
+            .into_iter()
+            .map(|(_, metadata)| metadata)
+            .collect();
+        let order = deps::load_order(&manifests);
+        for (name, e) in &order.errors {
+            eprintln!("[PluginManager] 插件 {} 加载失败: {}", name, e);
+        }
+
+        let mut loaded = Vec::new();
+        for name in order.order {
+            match self.load_plugin(&name).await {
+                Ok(()) => loaded.push(name),
+                Err(e) => eprintln!("[PluginManager] 插件 {} 加载失败: {:#}", name, e),
+            }
+        }
+        Ok(loaded)
+    }
+
+    pub async fn load_plugin(&self, name: &str) -> Result<()> {
+        if self.plugins.lock().unwrap().contains_key(name) {
+            return Err(PluginError::AlreadyLoaded { plugin_id: name.to_string() }.into());
+        }
+
+        let (path, metadata) = self
+            .discover_plugins()?
+            .into_iter()
+            .find(|(_, metadata)| metadata.name == name)
+            .ok_or_else(|| PluginError::NotFound { plugin_id: name.to_string() })?;
+        self.add_plugin(path, metadata).await
+    }
+
+    /// 直接加载指定目录中的插件，不扫描插件目录，同名的其他插件目录不会被选中
+    pub async fn load_plugin_from(&self, dir: &Path) -> Result<()> {
+        let metadata = read_manifest(dir)?;
+        if self.plugins.lock().unwrap().contains_key(&metadata.name) {
+            return Err(PluginError::AlreadyLoaded { plugin_id: metadata.name }.into());
+        }
+        self.add_plugin(dir.to_path_buf(), metadata).await
+    }
+
+    async fn add_plugin(&self, path: PathBuf, metadata: PluginMetadata) -> Result<()> {
+        let name = metadata.name.clone();
+        self.plugins
+            .lock()
+            .unwrap()
+            .insert(name.clone(), Plugin::with_path(metadata, path));
+        self.emit(PluginEvent::StatusChanged {
+            plugin_id: name.clone(),
+            status: PluginStatus::Installed,
+        });
+
+        self.enable_plugin(&name).await
+    }
+
+    /// 卸载插件，依赖它的插件会先被停用
+    pub async fn unload_plugin(&self, name: &str) -> Result<()> {
+        self.disable_dependents(name).await;
+        let lifecycle = self.lifecycle_lock(name)?;
+        let _guard = lifecycle.lock().await;
+
+        let result = self.shutdown_runtime(name).await;
+        self.plugins.lock().unwrap().remove(name);
+        result
+    }
+
+    /// 校验并安装插件包，插件被放入插件目录下与插件同名的子目录，需要再通过 `load_plugin` 加载
+    pub async fn install_package(&self, archive: impl AsRef<Path>) -> Result<PluginMetadata> {
+        let package = package::PluginPackage::open(archive.as_ref(), &self.trust_policy())?;
+        let metadata = package.metadata().clone();
+        let target = self.plugin_dir.join(&metadata.name);
+        if target.exists() || self.find_installed(&metadata.name)?.is_some() {
+            return Err(PluginError::AlreadyInstalled { plugin_id: metadata.name }.into());
+        }
+
+        let staging = self.stage_package(&package)?;
+        std::fs::rename(staging.path(), &target)
+            .with_context(|| format!("Failed to install plugin to {}", target.display()))?;
+        self.emit(PluginEvent::Installed {
+            plugin_id: metadata.name.clone(),
+            version: metadata.version.clone(),
+        });
+        Ok(metadata)
+    }
+
+    /// 用更高版本的插件包替换已安装的插件，插件数据目录保持不变
+    ///
+    /// 已加载的插件会先卸载，替换完成后重新加载，并重新启用因此被停用的依赖方。
+    /// 新版本无法就位或加载失败时恢复旧版本，并按原状态重新加载旧版本及其依赖方。
+    pub async fn upgrade_package(&self, archive: impl AsRef<Path>) -> Result<PluginMetadata> {
+        let package = package::PluginPackage::open(archive.as_ref(), &self.trust_policy())?;
+        let metadata = package.metadata().clone();
+        let name = metadata.name.clone();
+        let (target, installed) = self
+            .find_installed(&name)?
+            .ok_or_else(|| PluginError::NotFound { plugin_id: name.clone() })?;
+        if deps::parse_version(&name, &metadata.version)? <= deps::parse_version(&name, &installed.version)? {
+            return Err(PluginError::NotAnUpgrade {
+                plugin_id: name,
+                installed: installed.version,
+                version: metadata.version,
+            }
+            .into());
+        }
+
+        let staging = self.stage_package(&package)?;
+        let loaded = self.plugins.lock().unwrap().contains_key(&name);
+        let dependents: Vec<_> = self
+            .dependents(&name)
+            .into_iter()
+            .filter(|dependent| self.plugin_status(dependent) == Some(PluginStatus::Active))
+            .collect();
+        if loaded {
+            self.unload_plugin(&name).await?;
+        }
+
+        // 旧版本先移入备份目录，新版本加载成功后才删除，任何一步失败都恢复旧版本并重新加载
+        let backup = staging.path().with_extension("old");
+        if let Err(e) = self.swap_version(&name, staging.path(), &target, &backup, loaded).await {
+            self.restore_version(&name, &target, &backup).await;
+            if loaded {
+                match self.load_plugin(&name).await {
+                    Ok(()) => self.enable_dependents(dependents).await,
+                    Err(e) => eprintln!("[PluginManager] 插件 {} 旧版本重新加载失败: {:#}", name, e),
+                }
+            }
+            return Err(e);
+        }
+        if let Err(e) = std::fs::remove_dir_all(&backup) {
+            eprintln!("[PluginManager] 清理旧版本 {} 失败: {}", backup.display(), e);
+        }
+        self.emit(PluginEvent::Installed {
+            plugin_id: name.clone(),
+            version: metadata.version.clone(),
+        });
+        self.enable_dependents(dependents).await;
+        Ok(metadata)
+    }
+
+    /// 把旧版本移到 `backup`，新版本移入 `target`，插件原本已加载时再加载新版本
+    async fn swap_version(&self, name: &str, staging: &Path, target: &Path, backup: &Path, loaded: bool) -> Result<()> {
+        std::fs::rename(target, backup).with_context(|| format!("Failed to replace {}", target.display()))?;
+        std::fs::rename(staging, target).with_context(|| format!("Failed to replace {}", target.display()))?;
+        if loaded {
+            self.load_plugin(name).await?;
+        }
         Ok(())
     }
 
-    pub fn unload_plugin(&self, name: &str) -> Result<()> {
-        // TODO: 实现插件卸载逻辑
+    /// 升级失败时卸载新版本并把备份移回 `target`
+    async fn restore_version(&self, name: &str, target: &Path, backup: &Path) {
+        if self.plugins.lock().unwrap().contains_key(name) {
+            let _ = self.unload_plugin(name).await;
+        }
+        if !backup.exists() {
+            return;
+        }
+        if target.exists() {
+            if let Err(e) = std::fs::remove_dir_all(target) {
+                eprintln!("[PluginManager] 删除新版本 {} 失败: {}", target.display(), e);
+            }
+        }
+        if let Err(e) = std::fs::rename(backup, target) {
+            eprintln!("[PluginManager] 恢复旧版本 {} 失败: {}", target.display(), e);
+        }
+    }
+
+    /// 重新启用升级前处于启用状态的依赖方
+    async fn enable_dependents(&self, dependents: Vec<String>) {
+        for dependent in dependents {
+            if let Err(e) = self.enable_plugin(&dependent).await {
+                eprintln!("[PluginManager] 插件 {} 重新启用失败: {:#}", dependent, e);
+            }
+        }
+    }
+
+    /// 卸载并删除已安装的插件，`purge` 为 `true` 时同时删除插件数据目录
+    pub async fn uninstall_plugin(&self, name: &str, purge: bool) -> Result<()> {
+        let (path, _) = self
+            .find_installed(name)?
+            .ok_or_else(|| PluginError::NotFound { plugin_id: name.to_string() })?;
+        if self.plugins.lock().unwrap().contains_key(name) {
+            self.unload_plugin(name).await?;
+        }
+        std::fs::remove_dir_all(&path).with_context(|| format!("Failed to remove {}", path.display()))?;
+        self.emit(PluginEvent::Uninstalled { plugin_id: name.to_string() });
+
+        let data_dir = self.plugin_data_dir(name);
+        if purge && data_dir.exists() {
+            std::fs::remove_dir_all(&data_dir)
+                .with_context(|| format!("Failed to remove {}", data_dir.display()))?;
+        }
         Ok(())
     }
 
-    pub fn enable_plugin(&self, name: &str) -> Result<()> {
-        // TODO: 实现插件启用逻辑
+    pub async fn enable_plugin(&self, name: &str) -> Result<()> {
+        let lifecycle = self.lifecycle_lock(name)?;
+        let _guard = lifecycle.lock().await;
+
+        if self.plugin_status(name) == Some(PluginStatus::Active) {
+            return Ok(());
+        }
+
+        let result = match self.plugin_metadata(name).and_then(|metadata| self.check_dependencies(&metadata)) {
+            Ok(()) => self.start_runtime(name).await,
+            Err(e) => {
+                self.record_error(name, diagnostics::ErrorSource::Load, &e);
+                Err(e)
+            }
+        };
+        match &result {
+            Ok(()) => self.set_status(name, PluginStatus::Active),
+            Err(_) => {
+                let _ = self.shutdown_runtime(name).await;
+                self.set_status(name, PluginStatus::Error);
+            }
+        }
+        result.with_context(|| format!("Failed to enable plugin '{}'", name))
+    }
+
+    /// 停用插件，依赖它的插件会先被停用
+    pub async fn disable_plugin(&self, name: &str) -> Result<()> {
+        self.disable_dependents(name).await;
+        let lifecycle = self.lifecycle_lock(name)?;
+        let _guard = lifecycle.lock().await;
+
+        let result = self.shutdown_runtime(name).await;
+        self.set_status(name, PluginStatus::Disabled);
+        result
+    }
+
+    /// 基于磁盘上的文件重新加载插件
+    ///
+    /// 运行中的插件先在新的运行时中完成加载和初始化，成功后再替换旧实例，失败时旧实例继续运行；
+    /// 其他状态的插件直接重新启用。失败时广播 `ReloadFailed`，错误中带有出错的文件和行号。
+    pub async fn reload_plugin(&self, name: &str) -> Result<()> {
+        match self.reload(name).await {
+            Ok(()) => {
+                self.emit(PluginEvent::Reloaded {
+                    plugin_id: name.to_string(),
+                    version: self.plugin_metadata(name).map(|m| m.version).unwrap_or_default(),
+                });
+                Ok(())
+            }
+            Err(e) => {
+                self.record_error(name, diagnostics::ErrorSource::Reload, &e);
+                let error = PluginError::reload_failed(name, &e);
+                self.emit(PluginEvent::ReloadFailed { error: error.clone() });
+                Err(e.context(error))
+            }
+        }
+    }
+
+    async fn reload(&self, name: &str) -> Result<()> {
+        let lifecycle = self.lifecycle_lock(name)?;
+        let guard = lifecycle.lock().await;
+        let path = self.plugins.lock().unwrap().get(name).map(|p| p.path.clone()).unwrap_or_default();
+        let metadata = read_manifest(&path)?;
+        if metadata.name != name {
+            return Err(anyhow::anyhow!(
+                "Plugin '{}' was renamed to '{}', load it as a new plugin",
+                name,
+                metadata.name
+            ));
+        }
+
+        if self.plugin_state(name) == Some(RuntimeState::Running) {
+            return self.hot_swap(name, metadata).await;
+        }
+
+        self.shutdown_runtime(name).await?;
+        self.update(name, |plugin| plugin.metadata = metadata);
+        self.set_status(name, PluginStatus::Installed);
+        drop(guard);
+        self.enable_plugin(name).await
+    }
+
+    /// 启动已初始化或已停止的插件
+    pub async fn start_plugin(&self, name: &str) -> Result<()> {
+        let lifecycle = self.lifecycle_lock(name)?;
+        let _guard = lifecycle.lock().await;
+
+        self.transition(name, LifecycleAction::Start).await?;
+        self.set_status(name, PluginStatus::Active);
         Ok(())
     }
 
-    pub fn disable_plugin(&self, name: &str) -> Result<()> {
-        // TODO: 实现插件禁用逻辑
+    /// 停止正在运行的插件，运行时保留以便再次启动
+    pub async fn stop_plugin(&self, name: &str) -> Result<()> {
+        let lifecycle = self.lifecycle_lock(name)?;
+        let _guard = lifecycle.lock().await;
+
+        self.transition(name, LifecycleAction::Stop).await?;
+        self.set_status(name, PluginStatus::Installed);
         Ok(())
     }
 
-    pub fn get_plugin(&self, name: &str) -> Option<Box<dyn PluginRuntime>> {
-        self.plugins.lock().unwrap().get(name).cloned()
+    /// 调用插件中的函数，插件必须处于已初始化或运行状态
+    pub async fn call_function(
+        &self,
+        name: &str,
+        function: &str,
+        args: Vec<serde_json::Value>,
+    ) -> Result<serde_json::Value> {
+        let source = diagnostics::ErrorSource::Function { name: function.to_string() };
+        self.call(name, function, args, source).await.map_err(|(e, _)| e)
     }
 
-    pub fn list_plugins(&self) -> Vec<PluginMetadata> {
-        self.plugins
+    /// 命令面板中的全部命令：先是清单中声明的命令，再是运行时注册的命令
+    pub fn list_commands(&self) -> Vec<command::CommandInfo> {
+        let mut plugins: Vec<(String, Vec<command::CommandSpec>, bool)> = self
+            .plugins
             .lock()
             .unwrap()
-            .values()
-            .map(|p| p.metadata.clone())
-            .collect()
+            .iter()
+            .map(|(name, plugin)| {
+                (name.clone(), plugin.metadata.commands.clone(), plugin.state() == RuntimeState::Running)
+            })
+            .collect();
+        plugins.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut commands = Vec::new();
+        for (name, declared, enabled) in plugins {
+            let registered = self.commands.registered(&name);
+            let declared = declared.into_iter().map(|spec| (spec, command::CommandSource::Manifest));
+            let registered = registered.into_iter().map(|spec| (spec, command::CommandSource::Runtime));
+            for (spec, source) in declared.chain(registered) {
+                commands.push(command::CommandInfo {
+                    plugin_id: name.clone(),
+                    spec,
+                    source,
+                    enabled,
+                });
+            }
+        }
+        commands
     }
-}
 
-impl Plugin {
-    pub fn new(metadata: PluginMetadata) -> Self {
-        Self {
-            metadata,
-            status: PluginStatus::Installed,
-            runtime: None,
+    /// 执行插件命令，返回命令函数的返回值
+    pub async fn invoke_command(
+        &self,
+        name: &str,
+        command_id: &str,
+        args: serde_json::Value,
+    ) -> Result<serde_json::Value> {
+        self.invoke_command_with_progress(name, command_id, args, None).await
+    }
+
+    /// 执行插件命令，插件上报的进度同时交给 `on_progress` 并作为 [`PluginEvent::CommandProgress`] 广播
+    ///
+    /// 命令函数的参数为 `(args, context)`，`context.invocation_id` 用于通过 `api.commands("progress", ...)` 上报进度。
+    pub async fn invoke_command_with_progress(
+        &self,
+        name: &str,
+        command_id: &str,
+        args: serde_json::Value,
+        on_progress: Option<command::ProgressCallback>,
+    ) -> Result<serde_json::Value> {
+        let spec = self
+            .find_command(name, command_id)?
+            .ok_or_else(|| PluginError::CommandNotFound {
+                plugin_id: name.to_string(),
+                command_id: command_id.to_string(),
+            })?;
+        let args = spec.resolve_arguments(name, args)?;
+
+        let invocation_id = self.commands.begin(name, command_id, on_progress);
+        let context = serde_json::json!({ "invocation_id": invocation_id, "command_id": command_id });
+        let source = diagnostics::ErrorSource::Command { id: command_id.to_string() };
+        let result = self.call(name, spec.handler(), vec![args, context], source).await;
+        self.commands.finish(invocation_id);
+
+        let (plugin_id, command_id) = (name.to_string(), command_id.to_string());
+        match result {
+            Ok(result) => {
+                self.emit(PluginEvent::CommandCompleted {
+                    invocation_id,
+                    plugin_id,
+                    command_id,
+                    result: result.clone(),
+                });
+                Ok(result)
+            }
+            Err((e, error)) => {
+                let error = error.unwrap_or_else(|| {
+                    let source = diagnostics::ErrorSource::Command { id: command_id.clone() };
+                    diagnostics::PluginErrorRecord::new(name, source, &e)
+                });
+                self.emit(PluginEvent::CommandFailed {
+                    invocation_id,
+                    plugin_id,
+                    command_id,
+                    error,
+                });
+                Err(e)
+            }
         }
     }
 
-    pub fn status(&self) -> PluginStatus {
-        self.status.clone()
+    /// 通过消息总线发布消息，返回进入队列的接收者数量
+    ///
+    /// `target` 为空时广播给其他插件，消息由各插件的队列异步投递给 `on_message`。
+    pub fn send_message(&self, message: PluginMessage) -> Result<usize> {
+        self.bus.publish(message)
     }
 
-    pub fn metadata(&self) -> PluginMetadata {
-        self.metadata.clone()
+    pub fn bus(&self) -> Arc<bus::MessageBus> {
+        Arc::clone(&self.bus)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// 最近无法投递的消息
+    pub fn dead_letters(&self) -> Vec<bus::DeadLetter> {
+        self.bus.dead_letters()
+    }
 
-    #[test]
-    fn test_plugin_manager_creation() {
-        let plugin_dir = PathBuf::from("plugins");
-        let manager = PluginManager::new();
-        assert!(manager.plugins.lock().unwrap().is_empty());
+    /// 直接或间接依赖 `name` 的已加载插件，停用或卸载 `name` 前可以用来提示用户
+    pub fn dependents(&self, name: &str) -> Vec<String> {
+        deps::dependents(&self.list_plugins(), name)
     }
 
-    #[test]
-    fn test_plugin_metadata_serialization() {
-        let metadata = PluginMetadata {
-            name: "test-plugin".to_string(),
-            version: "1.0.0".to_string(),
-            description: Some("Test plugin".to_string()),
-            author: Some("Test Author".to_string()),
-            homepage_url: None,
-            repository_url: None,
-            license: Some("MIT".to_string()),
-            language: PluginLanguage::Lua,
-            main_file: "main.lua".to_string(),
-            permissions: vec!["fs.read".to_string()],
-            dependencies: None,
+    /// 设置插件反复超出资源限制时的处理策略，对所有插件生效
+    pub fn set_violation_policy(&self, policy: ViolationPolicy) {
+        *self.violation_policy.lock().unwrap() = policy;
+    }
+
+    pub fn violation_policy(&self) -> ViolationPolicy {
+        *self.violation_policy.lock().unwrap()
+    }
+
+    /// 设置插件的资源限制，重新启用插件时会沿用该设置
+    pub fn set_resource_limits(&self, name: &str, limits: ResourceLimits) -> Result<()> {
+        let runtime = {
+            let mut plugins = self.plugins.lock().unwrap();
+            let plugin = plugins
+                .get_mut(name)
+                .ok_or_else(|| PluginError::NotLoaded { plugin_id: name.to_string() })?;
+            let quota = limits.max_disk_usage.unwrap_or(files::DEFAULT_DISK_QUOTA);
+            plugin.disk_quota.store(quota, Ordering::Relaxed);
+            let task_limit = limits.max_tasks.unwrap_or(timer::DEFAULT_MAX_TIMERS);
+            plugin.task_limit.store(task_limit, Ordering::Relaxed);
+            plugin.limits = Some(limits.clone());
+            plugin.runtime.clone()
         };
+        match runtime {
+            Some(runtime) => runtime.set_resource_limits(limits),
+            None => Ok(()),
+        }
+    }
 
-        let serialized = serde_json::to_string(&metadata).unwrap();
-        let deserialized: PluginMetadata = serde_json::from_str(&serialized).unwrap();
-        assert_eq!(metadata.name, deserialized.name);
+    /// 获取插件的资源使用情况
+    pub fn resource_usage(&self, name: &str) -> Result<ResourceUsage> {
+        let runtime = self
+            .get_plugin(name)
+            .ok_or_else(|| PluginError::NotLoaded { plugin_id: name.to_string() })?;
+        let mut usage = runtime.get_resource_usage()?;
+        usage.active_tasks = self.timers.count(name);
+        Ok(usage)
+    }
+
+    /// 插件当前的定时器
+    pub fn timers(&self, name: &str) -> Vec<timer::TimerSpec> {
+        self.timers.list(name)
+    }
+
+    /// 定时器使用的时钟，测试时可以切换为手动时钟
+    pub fn clock(&self) -> timer::Clock {
+        self.timers.clock()
+    }
+
+    /// 把手动时钟向前拨 `by`，按时间顺序依次执行其间到期的定时器，返回执行的次数
+    pub async fn advance_clock(&self, by: chrono::Duration) -> Result<usize> {
+        let clock = self.clock();
+        if !clock.is_manual() {
+            anyhow::bail!("The timer clock follows the system time and cannot be advanced");
+        }
+        let until = clock.now() + by;
+        let mut fired = 0;
+        while let Some((name, timer)) = self.timers.next_due(until) {
+            self.fire_timer(&name, timer).await;
+            fired += 1;
+        }
+        Ok(fired)
+    }
+
+    pub fn get_plugin(&self, name: &str) -> Option<Arc<dyn PluginRuntime>> {
+        self.plugins.lock().unwrap().get(name).and_then(|p| p.runtime.clone())
+    }
+
+    pub fn plugin_status(&self, name: &str) -> Option<PluginStatus> {
+        self.plugins.lock().unwrap().get(name).map(|p| p.status())
+    }
+
+    /// 找到目录对应的已加载插件，目录可能已被删除
+    fn plugin_for_dir(&self, dir: &Path) -> Option<String> {
+        let dir = normalize_path(dir);
+        let plugins = self.plugins.lock().unwrap();
+        plugins
+            .iter()
+            .find(|(_, plugin)| normalize_path(&plugin.path) == dir)
+            .map(|(name, _)| name.clone())
+    }
+
+    /// 处理插件目录的变更：清单或入口文件已不存在时卸载插件，否则重新加载
+    async fn handle_watch_event(&self, event: watcher::PluginWatchEvent) {
+        // 新插件需要通过 load_plugin 显式加载
+        let Some(plugin_id) = self.plugin_for_dir(&event.plugin_dir) else {
+            return;
+        };
+        // 清单无法解析时按修改处理，重新加载失败会保留旧实例
+        let removed = !event.plugin_dir.join(MANIFEST_FILE).is_file()
+            || read_manifest(&event.plugin_dir)
+                .is_ok_and(|metadata| !event.plugin_dir.join(&metadata.main_file).is_file());
+        let result = if removed {
+            self.unload_plugin(&plugin_id).await
+        } else {
+            self.reload_plugin(&plugin_id).await
+        };
+        if let Err(e) = result {
+            eprintln!("[PluginManager] {:#}", e);
+        }
     }
-} 
\ No newline at end of file
+
+    pub fn plugin_state(&self, name: &str) -> Option<RuntimeState> {
+        self.plugins.lock().unwrap().get(name).map(|p| p.state())
+    }
+
+    /// 已加载插件的概况
+    pub fn plugin_info(&self, name: &str) -> Result<PluginInfo> {
+        let (metadata, status, state, path, runtime) = {
+            let plugins = self.plugins.lock().unwrap();
+            let plugin = plugins
+                .get(name)
+                .ok_or_else(|| PluginError::NotLoaded { plugin_id: name.to_string() })?;
+            (
+                plugin.metadata.clone(),
+                plugin.status(),
+                plugin.state(),
+                plugin.path.clone(),
+                plugin.runtime.clone(),
+            )
+        };
+        Ok(PluginInfo {
+            metadata,
+            status,
+            state,
+            path,
+            usage: runtime.and_then(|runtime| runtime.get_resource_usage().ok()).map(|mut usage| {
+                usage.active_tasks = self.timers.count(name);
+                usage
+            }),
+            last_error: self.errors.recent(name, 1).pop(),
+        })
+    }
+
+    /// 插件目录中全部插件的概况，按名称排序；未加载的插件状态为 `installed` / `unloaded`
+    pub fn plugin_infos(&self) -> Result<Vec<PluginInfo>> {
+        let mut infos = Vec::new();
+        for (path, metadata) in self.discover_plugins()? {
+            let info = self.plugin_info(&metadata.name).unwrap_or_else(|_| PluginInfo {
+                last_error: self.errors.recent(&metadata.name, 1).pop(),
+                metadata,
+                status: PluginStatus::Installed,
+                state: RuntimeState::Unloaded,
+                path,
+                usage: None,
+            });
+            infos.push(info);
+        }
+        // 目录已被删除但仍在运行的插件
+        for name in self.plugins.lock().unwrap().keys().cloned().collect::<Vec<_>>() {
+            if !infos.iter().any(|info| info.metadata.name == name) {
+                infos.extend(self.plugin_info(&name).ok());
+            }
+        }
+        infos.sort_by(|a, b| a.metadata.name.cmp(&b.metadata.name));
+        Ok(infos)
+    }
+
+    pub fn list_plugins(&self) -> Vec<PluginMetadata> {
+        self.plugins
+            .lock()
+            .unwrap()
+            .values()
+            .map(|p| p.metadata.clone())
+            .collect()
+    }
+
+    /// 所有运行中插件的界面扩展点
+    pub fn contributions(&self) -> contribution::ContributionSet {
+        let mut plugins: Vec<(String, contribution::Contributions)> = self
+            .plugins
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, plugin)| plugin.state() == RuntimeState::Running && !plugin.metadata.contributes.is_empty())
+            .map(|(name, plugin)| (name.clone(), plugin.metadata.contributes.clone()))
+            .collect();
+        plugins.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut set = contribution::ContributionSet::default();
+        for (name, contributions) in &plugins {
+            set.add(name, contributions);
+        }
+        set
+    }
+
+    /// 用户触发界面扩展点时调用插件的处理函数，参数见 [`contribution::Contributions::arguments`]
+    ///
+    /// 没有处理函数的任务字段直接返回 `null`。
+    pub async fn invoke_contribution(
+        &self,
+        name: &str,
+        point: contribution::ContributionPoint,
+        id: &str,
+        payload: serde_json::Value,
+    ) -> Result<serde_json::Value> {
+        let handler = self
+            .plugin_metadata(name)?
+            .contributes
+            .handler(point, id)
+            .ok_or_else(|| PluginError::ContributionNotFound {
+                plugin_id: name.to_string(),
+                point,
+                id: id.to_string(),
+            })?;
+        let Some(function) = handler else {
+            return Ok(serde_json::Value::Null);
+        };
+        let args = contribution::Contributions::arguments(point, payload);
+        let source = diagnostics::ErrorSource::Contribution { point, id: id.to_string() };
+        self.call(name, &function, args, source).await.map_err(|(e, _)| e)
+    }
+
+    fn find_command(&self, name: &str, command_id: &str) -> Result<Option<command::CommandSpec>> {
+        let declared = self.plugin_metadata(name)?.commands;
+        Ok(declared
+            .into_iter()
+            .chain(self.commands.registered(name))
+            .find(|spec| spec.id == command_id))
+    }
+
+    /// 调用插件函数，失败时记录错误，并返回错误记录（插件无法调用时没有记录）
+    async fn call(
+        &self,
+        name: &str,
+        function: &str,
+        args: Vec<serde_json::Value>,
+        source: diagnostics::ErrorSource,
+    ) -> Result<serde_json::Value, (anyhow::Error, Option<diagnostics::PluginErrorRecord>)> {
+        let runtime = self.checked_runtime(name, LifecycleAction::Call).map_err(|e| (e, None))?;
+        match runtime.call_function(function, args).await {
+            Ok(value) => Ok(value),
+            Err(e) => {
+                let record = self.record_error(name, source, &e);
+                self.enforce_limits(name, &e).await;
+                Err((e, Some(record)))
+            }
+        }
+    }
+
+    fn config_store(&self, name: &str) -> config::ConfigStore {
+        config::ConfigStore::new(&self.plugin_data_dir(name))
+    }
+
+    /// 把最新配置交给运行中插件的 `on_config_changed`，插件未定义该函数时跳过
+    async fn notify_config_changed(&self, name: &str) {
+        let Ok(runtime) = self.checked_runtime(name, LifecycleAction::Call) else {
+            return;
+        };
+        if !runtime.has_function("on_config_changed") {
+            return;
+        }
+        let config = match self.plugin_config(name) {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!("[PluginManager] 插件 {} 配置读取失败: {:#}", name, e);
+                return;
+            }
+        };
+        let result = runtime
+            .call_function("on_config_changed", vec![serde_json::Value::Object(config)])
+            .await;
+        if let Err(e) = result {
+            eprintln!("[PluginManager] 插件 {} 处理配置变更失败: {:#}", name, e);
+            let source = diagnostics::ErrorSource::Hook { name: "on_config_changed".to_string() };
+            self.record_error(name, source, &e);
+            self.enforce_limits(name, &e).await;
+        }
+    }
+
+    fn find_installed(&self, name: &str) -> Result<Option<(PathBuf, PluginMetadata)>> {
+        Ok(self
+            .discover_plugins()?
+            .into_iter()
+            .find(|(_, metadata)| metadata.name == name))
+    }
+
+    /// 把插件包解压到插件目录中的临时目录，保证之后可以原子地重命名到位
+    fn stage_package(&self, package: &package::PluginPackage) -> Result<tempfile::TempDir> {
+        std::fs::create_dir_all(&self.plugin_dir)
+            .with_context(|| format!("Failed to create {}", self.plugin_dir.display()))?;
+        let staging = tempfile::Builder::new().prefix(".install-").tempdir_in(&self.plugin_dir)?;
+        package.extract_to(staging.path())?;
+        Ok(staging)
+    }
+
+    fn lifecycle_lock(&self, name: &str) -> Result<Arc<tokio::sync::Mutex<()>>> {
+        self.plugins
+            .lock()
+            .unwrap()
+            .get(name)
+            .map(|p| Arc::clone(&p.lifecycle))
+            .ok_or_else(|| PluginError::NotLoaded { plugin_id: name.to_string() }.into())
+    }
+
+    fn checked_runtime(&self, name: &str, action: LifecycleAction) -> Result<Arc<dyn PluginRuntime>> {
+        let plugins = self.plugins.lock().unwrap();
+        let plugin = plugins
+            .get(name)
+            .ok_or_else(|| PluginError::NotLoaded { plugin_id: name.to_string() })?;
+        plugin.state.next(name, action)?;
+        if let Some(until) = plugin.throttled_until {
+            let now = Instant::now();
+            if until > now {
+                return Err(PluginError::Throttled {
+                    plugin_id: name.to_string(),
+                    retry_after_ms: (until - now).as_millis() as u64,
+                }
+                .into());
+            }
+        }
+        plugin
+            .runtime
+            .clone()
+            .ok_or_else(|| PluginError::NotLoaded { plugin_id: name.to_string() }.into())
+    }
+
+    /// 检查插件的依赖是否已加载、版本兼容且处于激活状态
+    fn check_dependencies(&self, metadata: &PluginMetadata) -> Result<()> {
+        let plugins = self.plugins.lock().unwrap();
+        deps::check_dependencies(metadata, |dependency| {
+            plugins.get(dependency).map(|p| deps::InstalledDependency {
+                version: p.metadata.version.clone(),
+                active: p.status == PluginStatus::Active,
+            })
+        })?;
+        Ok(())
+    }
+
+    fn plugin_metadata(&self, name: &str) -> Result<PluginMetadata> {
+        self.plugins
+            .lock()
+            .unwrap()
+            .get(name)
+            .map(|plugin| plugin.metadata.clone())
+            .ok_or_else(|| PluginError::NotLoaded { plugin_id: name.to_string() }.into())
+    }
+
+    /// 停用所有仍在运行、直接或间接依赖 `name` 的插件
+    async fn disable_dependents(&self, name: &str) {
+        for dependent in self.dependents(name) {
+            if self.plugin_status(&dependent) != Some(PluginStatus::Active) {
+                continue;
+            }
+            eprintln!("[PluginManager] 插件 {} 依赖 {}，一并停用", dependent, name);
+            let Ok(lifecycle) = self.lifecycle_lock(&dependent) else {
+                continue;
+            };
+            let _guard = lifecycle.lock().await;
+            if let Err(e) = self.shutdown_runtime(&dependent).await {
+                eprintln!("[PluginManager] 插件 {} 停用失败: {:#}", dependent, e);
+            }
+            self.set_status(&dependent, PluginStatus::Disabled);
+        }
+    }
+
+    /// 基于清单创建新的运行时并执行 init、start
+    async fn start_runtime(&self, name: &str) -> Result<()> {
+        let (metadata, path) = {
+            let plugins = self.plugins.lock().unwrap();
+            let plugin = plugins
+                .get(name)
+                .ok_or_else(|| PluginError::NotLoaded { plugin_id: name.to_string() })?;
+            (plugin.metadata.clone(), plugin.path.clone())
+        };
+
+        if self.plugin_state(name) == Some(RuntimeState::Unloaded) {
+            let instance = self.timers.next_instance();
+            let runtime = self.build_runtime(&metadata, &path, instance).inspect_err(|e| {
+                self.record_error(name, diagnostics::ErrorSource::Load, e);
+            })?;
+            self.update(name, |plugin| {
+                plugin.runtime = Some(runtime);
+                plugin.instance = instance;
+            });
+            self.set_state(name, RuntimeState::Loaded);
+            self.spawn_delivery(name);
+            self.spawn_timers(name);
+        }
+        if self.plugin_state(name) == Some(RuntimeState::Loaded) {
+            self.transition(name, LifecycleAction::Init).await?;
+        }
+        self.transition(name, LifecycleAction::Start).await
+    }
+
+    /// 创建运行时并注册宿主 API、应用资源限制，不执行任何生命周期钩子
+    ///
+    /// `instance` 是新实例的编号，实例注册的定时器归属于它
+    fn build_runtime(
+        &self,
+        metadata: &PluginMetadata,
+        path: &Path,
+        instance: u64,
+    ) -> Result<Arc<dyn PluginRuntime>> {
+        let name = metadata.name.as_str();
+        let runtime = create_runtime(metadata, path, self.vendor_dir().as_deref())?;
+        for (api_name, api) in self.apis.lock().unwrap().iter() {
+            let api = diagnostics::RecordingApi::new(name, api_name, Arc::clone(api), Arc::clone(&self.errors));
+            runtime.register_api(api_name, Arc::new(api))?;
+        }
+        runtime.register_api("bus", Arc::new(bus::BusApi::new(name, Arc::clone(&self.bus))))?;
+        let declared = metadata.commands.iter().map(|spec| spec.id.clone()).collect();
+        runtime.register_api(
+            "commands",
+            Arc::new(command::CommandsApi::new(name, declared, Arc::clone(&self.commands))),
+        )?;
+        runtime.register_api(
+            "config",
+            Arc::new(config::ConfigApi::new(
+                metadata.configuration.clone().unwrap_or_default(),
+                self.config_store(name),
+                Arc::clone(&self.user),
+            )),
+        )?;
+        let (limits, disk_quota, task_limit) = {
+            let plugins = self.plugins.lock().unwrap();
+            let plugin = plugins.get(name);
+            let disk_quota = plugin.map(|p| Arc::clone(&p.disk_quota));
+            let task_limit = plugin.map(|p| Arc::clone(&p.task_limit));
+            (plugin.and_then(|p| p.limits.clone()), disk_quota, task_limit)
+        };
+        let disk_quota = disk_quota.unwrap_or_else(|| Arc::new(AtomicU64::new(files::DEFAULT_DISK_QUOTA)));
+        let file_api = files::FileApi::new(name, &self.plugin_data_dir(name), disk_quota);
+        runtime.register_api("fs", Arc::new(file_api))?;
+        let task_limit = task_limit.unwrap_or_else(|| Arc::new(AtomicUsize::new(timer::DEFAULT_MAX_TIMERS)));
+        let timer_api =
+            timer::TimerApi::new(name, instance, &self.plugin_data_dir(name), Arc::clone(&self.timers), task_limit);
+        runtime.register_api("timer", Arc::new(timer_api))?;
+        let http_api = http::HttpApi::new(name, &metadata.permissions, Arc::clone(&self.http));
+        runtime.register_api("http", Arc::new(http_api))?;
+        if let Some(limits) = limits {
+            runtime.set_resource_limits(limits)?;
+        }
+        Ok(runtime)
+    }
+
+    /// 在新的运行时中加载并初始化新版本，迁移状态后替换正在运行的旧实例
+    ///
+    /// 新版本初始化、恢复状态或启动失败时，旧实例继续运行。
+    async fn hot_swap(&self, name: &str, metadata: PluginMetadata) -> Result<()> {
+        let (old, old_instance, path) = {
+            let plugins = self.plugins.lock().unwrap();
+            let plugin = plugins
+                .get(name)
+                .ok_or_else(|| PluginError::NotLoaded { plugin_id: name.to_string() })?;
+            let old = plugin
+                .runtime
+                .clone()
+                .ok_or_else(|| PluginError::NotLoaded { plugin_id: name.to_string() })?;
+            (old, plugin.instance, plugin.path.clone())
+        };
+        self.check_dependencies(&metadata)?;
+
+        // 新实例在初始化时重新订阅、重新注册命令，失败时恢复旧实例的订阅和命令，并取消新实例的定时器
+        let instance = self.timers.next_instance();
+        let subscriptions = self.bus.subscriptions(name);
+        let registered = self.commands.registered(name);
+        self.bus.set_subscriptions(name, Vec::new());
+        self.commands.clear(name);
+        let rollback = |e: anyhow::Error| {
+            self.bus.set_subscriptions(name, subscriptions.clone());
+            self.commands.set_registered(name, registered.clone());
+            self.timers.cancel_instance(name, instance);
+            Err(e)
+        };
+
+        let new = match self.build_runtime(&metadata, &path, instance) {
+            Ok(new) => new,
+            Err(e) => return rollback(e),
+        };
+        if let Err(e) = self.prepare_runtime(&old, &new).await {
+            let _ = new.unload().await;
+            return rollback(e);
+        }
+
+        if let Err(e) = old.stop().await {
+            eprintln!("[PluginManager] 插件 {} 旧实例停止失败: {:#}", name, e);
+        }
+        self.timers.cancel_instance(name, old_instance);
+        if let Err(e) = new.start().await {
+            let _ = new.unload().await;
+            match old.start().await {
+                Ok(()) => self.restore_timers(name, old_instance),
+                Err(restart) => eprintln!("[PluginManager] 插件 {} 旧实例恢复失败: {:#}", name, restart),
+            }
+            return rollback(e.context(format!("Plugin '{}' failed to start", name)));
+        }
+
+        self.update(name, |plugin| {
+            plugin.runtime = Some(new);
+            plugin.instance = instance;
+            plugin.metadata = metadata;
+            plugin.violations = 0;
+        });
+        self.restore_timers(name, instance);
+        if let Err(e) = old.unload().await {
+            eprintln!("[PluginManager] 插件 {} 旧实例卸载失败: {:#}", name, e);
+        }
+        Ok(())
+    }
+
+    /// 初始化新实例，并通过 `on_save_state` / `on_restore_state` 把旧实例的状态交给新实例
+    async fn prepare_runtime(&self, old: &Arc<dyn PluginRuntime>, new: &Arc<dyn PluginRuntime>) -> Result<()> {
+        new.init().await?;
+        if !new.has_function("on_restore_state") || !old.has_function("on_save_state") {
+            return Ok(());
+        }
+        let state = old
+            .call_function("on_save_state", vec![])
+            .await
+            .context("Failed to save plugin state")?;
+        new.call_function("on_restore_state", vec![state])
+            .await
+            .context("Failed to restore plugin state")?;
+        Ok(())
+    }
+
+    /// 停止并卸载插件运行时，插件条目保留在管理器中
+    async fn shutdown_runtime(&self, name: &str) -> Result<()> {
+        let state = self.plugin_state(name);
+        let mut result = Ok(());
+        if state == Some(RuntimeState::Running) {
+            result = self.transition(name, LifecycleAction::Stop).await;
+        }
+        if matches!(
+            self.plugin_state(name),
+            Some(RuntimeState::Loaded | RuntimeState::Initialized | RuntimeState::Stopped)
+        ) {
+            let unloaded = self.transition(name, LifecycleAction::Unload).await;
+            result = result.and(unloaded);
+        }
+        if self.plugin_state(name).is_some_and(|s| s != RuntimeState::Unloaded) {
+            // 生命周期钩子失败时也要释放运行时
+            self.set_state(name, RuntimeState::Unloaded);
+        }
+        self.update(name, |plugin| plugin.runtime = None);
+        self.bus.detach(name);
+        self.commands.clear(name);
+        self.timers.detach(name);
+        result
+    }
+
+    /// 为插件创建消息队列，并按顺序把消息投递给插件
+    fn spawn_delivery(&self, name: &str) {
+        let mut rx = self.bus.attach(name);
+        let manager = self.clone();
+        let name = name.to_string();
+        tokio::spawn(async move {
+            // 插件卸载或重新创建队列后发送端被丢弃，任务随之结束
+            while let Some(message) = rx.recv().await {
+                manager.deliver(&name, message).await;
+            }
+        });
+    }
+
+    async fn deliver(&self, name: &str, message: PluginMessage) {
+        let runtime = match self.checked_runtime(name, LifecycleAction::Message) {
+            Ok(runtime) => runtime,
+            Err(e) => {
+                let reason = bus::DeadLetterReason::DeliveryFailed { error: format!("{:#}", e) };
+                self.bus.dead_letter(message, Some(name.to_string()), reason);
+                return;
+            }
+        };
+        if let Err(e) = runtime.send_message(message.clone()).await {
+            self.record_error(name, diagnostics::ErrorSource::Message, &e);
+            self.enforce_limits(name, &e).await;
+            let reason = bus::DeadLetterReason::DeliveryFailed { error: format!("{:#}", e) };
+            self.bus.dead_letter(message, Some(name.to_string()), reason);
+        }
+    }
+
+    /// 为插件创建定时器队列，并按顺序调用到期定时器的处理函数
+    fn spawn_timers(&self, name: &str) {
+        let mut rx = self.timers.attach(name);
+        let manager = self.clone();
+        let name = name.to_string();
+        tokio::spawn(async move {
+            while let Some(fired) = rx.recv().await {
+                manager.fire_timer(&name, fired).await;
+            }
+        });
+    }
+
+    async fn fire_timer(&self, name: &str, fired: timer::TimerFired) {
+        // 实例编号递增，比当前实例新说明热重载还没有完成替换（新实例在 init / start 中注册的定时器），
+        // 等生命周期锁释放后再判断：替换成功时它已是当前实例，失败时定时器已随新实例取消
+        let current = self.plugins.lock().unwrap().get(name).map(|p| p.instance);
+        if current.is_some_and(|current| fired.instance > current) {
+            if let Ok(lifecycle) = self.lifecycle_lock(name) {
+                drop(lifecycle.lock().await);
+            }
+        }
+        // 已取消、已替换或属于旧实例的定时器不再执行
+        let current = self.plugins.lock().unwrap().get(name).map(|p| p.instance);
+        if current != Some(fired.instance) || !self.timers.is_active(name, fired.seq) {
+            return;
+        }
+        let spec = fired.spec;
+        if spec.schedule.is_once() {
+            self.timers.finish(name, fired.seq);
+            if spec.persistent {
+                if let Err(e) = timer::TimerStore::new(&self.plugin_data_dir(name)).remove(&spec.id) {
+                    eprintln!("[PluginManager] 插件 {} 的定时器 {} 移除失败: {:#}", name, spec.id, e);
+                }
+            }
+        }
+        let source = diagnostics::ErrorSource::Timer { id: spec.id.clone() };
+        // 错误已经记录在插件的错误日志中
+        let _ = self.call(name, spec.handler(), vec![spec.data.clone()], source).await;
+    }
+
+    /// 恢复插件数据目录中持久化的定时器，插件启动时已经重新注册的定时器保持不变
+    fn restore_timers(&self, name: &str, instance: u64) {
+        let store = timer::TimerStore::new(&self.plugin_data_dir(name));
+        let specs = match store.load() {
+            Ok(specs) => specs,
+            Err(e) => {
+                self.record_error(name, diagnostics::ErrorSource::Load, &e);
+                return;
+            }
+        };
+        let active: Vec<String> = self.timers.list(name).into_iter().map(|spec| spec.id).collect();
+        let limit = self
+            .plugins
+            .lock()
+            .unwrap()
+            .get(name)
+            .map_or(timer::DEFAULT_MAX_TIMERS, |p| p.task_limit.load(Ordering::Relaxed));
+        for spec in specs.into_values().filter(|spec| !active.contains(&spec.id)) {
+            let id = spec.id.clone();
+            if let Err(e) = self.timers.schedule(name, instance, spec, limit) {
+                self.record_error(name, diagnostics::ErrorSource::Timer { id }, &e);
+            }
+        }
+    }
+
+    /// 校验并执行一次生命周期状态转换，成功后广播状态变化
+    async fn transition(&self, name: &str, action: LifecycleAction) -> Result<()> {
+        let (runtime, to, instance) = {
+            let plugins = self.plugins.lock().unwrap();
+            let plugin = plugins
+                .get(name)
+                .ok_or_else(|| PluginError::NotLoaded { plugin_id: name.to_string() })?;
+            let to = plugin.state.next(name, action)?;
+            let runtime = plugin
+                .runtime
+                .clone()
+                .ok_or_else(|| PluginError::NotLoaded { plugin_id: name.to_string() })?;
+            (runtime, to, plugin.instance)
+        };
+
+        let result = match action {
+            LifecycleAction::Init => runtime.init().await,
+            LifecycleAction::Start => runtime.start().await,
+            LifecycleAction::Stop => runtime.stop().await,
+            LifecycleAction::Unload => runtime.unload().await,
+            LifecycleAction::Call | LifecycleAction::Message => Ok(()),
+        };
+        // 停止或卸载后实例的定时器失效，钩子失败时也一样
+        if matches!(action, LifecycleAction::Stop | LifecycleAction::Unload) {
+            self.timers.cancel_instance(name, instance);
+        }
+        if let Err(e) = result {
+            let source = diagnostics::ErrorSource::Hook { name: action.to_string() };
+            self.record_error(name, source, &e);
+            self.set_status(name, PluginStatus::Error);
+            return Err(e.context(format!("Plugin '{}' failed to {}", name, action)));
+        }
+
+        self.set_state(name, to);
+        if action == LifecycleAction::Start {
+            self.restore_timers(name, instance);
+        }
+        Ok(())
+    }
+
+    /// 统计插件的资源违规（内存超限、执行超时），达到次数后按违规策略处理
+    async fn enforce_limits(&self, name: &str, err: &anyhow::Error) {
+        if !err
+            .downcast_ref::<PluginError>()
+            .is_some_and(PluginError::is_resource_violation)
+        {
+            return;
+        }
+        let policy = self.violation_policy();
+        let mut violations = 0;
+        self.update(name, |plugin| {
+            plugin.violations += 1;
+            violations = plugin.violations;
+        });
+        if violations < policy.max_violations {
+            return;
+        }
+
+        match policy.action {
+            ViolationAction::Ignore => {}
+            ViolationAction::Throttle { cooldown_ms } => {
+                eprintln!(
+                    "[PluginManager] 插件 {} 资源超限 {} 次，限流 {} ms",
+                    name, violations, cooldown_ms
+                );
+                self.update(name, |plugin| {
+                    plugin.violations = 0;
+                    plugin.throttled_until = Some(Instant::now() + Duration::from_millis(cooldown_ms));
+                });
+            }
+            ViolationAction::Disable => {
+                let Ok(lifecycle) = self.lifecycle_lock(name) else {
+                    return;
+                };
+                let _guard = lifecycle.lock().await;
+                eprintln!(
+                    "[PluginManager] 插件 {} 资源超限 {} 次，已停止运行",
+                    name, violations
+                );
+                let _ = self.shutdown_runtime(name).await;
+                self.update(name, |plugin| plugin.violations = 0);
+                self.set_status(name, PluginStatus::Error);
+            }
+        }
+    }
+
+    fn record_error(
+        &self,
+        name: &str,
+        source: diagnostics::ErrorSource,
+        err: &anyhow::Error,
+    ) -> diagnostics::PluginErrorRecord {
+        let record = diagnostics::PluginErrorRecord::new(name, source, err);
+        self.errors.record(record.clone());
+        record
+    }
+
+    fn update(&self, name: &str, f: impl FnOnce(&mut Plugin)) {
+        if let Some(plugin) = self.plugins.lock().unwrap().get_mut(name) {
+            f(plugin);
+        }
+    }
+
+    fn set_state(&self, name: &str, to: RuntimeState) {
+        let mut from = None;
+        self.update(name, |plugin| {
+            from = Some(plugin.state);
+            plugin.state = to;
+        });
+        if let Some(from) = from.filter(|from| *from != to) {
+            self.emit(PluginEvent::StateChanged {
+                plugin_id: name.to_string(),
+                from,
+                to,
+            });
+        }
+    }
+
+    fn set_status(&self, name: &str, status: PluginStatus) {
+        let mut changed = false;
+        self.update(name, |plugin| {
+            changed = plugin.status != status;
+            plugin.status = status.clone();
+        });
+        if changed {
+            self.emit(PluginEvent::StatusChanged {
+                plugin_id: name.to_string(),
+                status,
+            });
+        }
+    }
+
+    fn emit(&self, event: PluginEvent) {
+        // 没有订阅者时发送失败是正常情况
+        let _ = self.events.send(event);
+    }
+}
+
+impl Default for PluginManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Plugin {
+    pub fn new(metadata: PluginMetadata) -> Self {
+        Self::with_path(metadata, PathBuf::new())
+    }
+
+    pub fn with_path(metadata: PluginMetadata, path: PathBuf) -> Self {
+        Self {
+            metadata,
+            status: PluginStatus::Installed,
+            state: RuntimeState::Unloaded,
+            path,
+            limits: None,
+            disk_quota: Arc::new(AtomicU64::new(files::DEFAULT_DISK_QUOTA)),
+            task_limit: Arc::new(AtomicUsize::new(timer::DEFAULT_MAX_TIMERS)),
+            runtime: None,
+            instance: 0,
+            violations: 0,
+            throttled_until: None,
+            lifecycle: Arc::new(tokio::sync::Mutex::new(())),
+        }
+    }
+
+    pub fn status(&self) -> PluginStatus {
+        self.status.clone()
+    }
+
+    pub fn state(&self) -> RuntimeState {
+        self.state
+    }
+
+    pub fn metadata(&self) -> PluginMetadata {
+        self.metadata.clone()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// 读取并解析插件目录下的 `plugin.json`
+pub fn read_manifest(plugin_dir: &Path) -> Result<PluginMetadata> {
+    let manifest_path = plugin_dir.join(MANIFEST_FILE);
+    let content = std::fs::read_to_string(&manifest_path)
+        .with_context(|| format!("Failed to read {}", manifest_path.display()))?;
+    let metadata: PluginMetadata = serde_json::from_str(&content)
+        .with_context(|| format!("Invalid plugin manifest {}", manifest_path.display()))?;
+    Ok(metadata)
+}
+
+/// 规范化可能已被删除的路径：路径存在时取真实路径，否则规范化其所在目录
+fn normalize_path(path: &Path) -> PathBuf {
+    path.canonicalize()
+        .ok()
+        .or_else(|| Some(path.parent()?.canonicalize().ok()?.join(path.file_name()?)))
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+/// 根据插件语言创建对应的运行时
+fn create_runtime(
+    metadata: &PluginMetadata,
+    plugin_dir: &Path,
+    vendor_dir: Option<&Path>,
+) -> Result<Arc<dyn PluginRuntime>> {
+    let main_file = plugin_dir.join(&metadata.main_file);
+    if !main_file.is_file() {
+        return Err(anyhow::anyhow!(
+            "Main file '{}' of plugin '{}' does not exist",
+            metadata.main_file,
+            metadata.name
+        ));
+    }
+
+    match metadata.language {
+        PluginLanguage::Lua => Ok(Arc::new(
+            lua::LuaRuntime::new(metadata.clone(), main_file.to_string_lossy().into_owned())?
+                .with_vendor_dir(vendor_dir.map(Path::to_path_buf)),
+        )),
+        // `.ts` 入口在加载时擦除类型，`.js` 入口直接执行
+        PluginLanguage::TypeScript => Ok(Arc::new(js::JsRuntime::new(
+            metadata.clone(),
+            main_file.to_string_lossy().into_owned(),
+        )?)),
+        PluginLanguage::Wasm => Ok(Arc::new(wasm::WasmRuntime::new(
+            metadata.clone(),
+            main_file.to_string_lossy().into_owned(),
+        )?)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn write_plugin(root: &Path, name: &str, script: &str) -> PathBuf {
+        write_plugin_with(root, name, script, serde_json::json!({}))
+    }
+
+    /// 与 `write_plugin` 相同，`extra` 中的字段覆盖清单中的同名字段
+    fn write_plugin_with(root: &Path, name: &str, script: &str, extra: serde_json::Value) -> PathBuf {
+        let dir = root.join(name);
+        fs::create_dir_all(&dir).unwrap();
+        let mut manifest = serde_json::json!({
+            "name": name,
+            "version": "1.0.0",
+            "description": null,
+            "author": null,
+            "homepage_url": null,
+            "repository_url": null,
+            "license": null,
+            "language": "lua",
+            "main_file": "main.lua",
+            "permissions": [],
+            "dependencies": null,
+        });
+        if let serde_json::Value::Object(extra) = extra {
+            manifest.as_object_mut().unwrap().extend(extra);
+        }
+        fs::write(dir.join(MANIFEST_FILE), manifest.to_string()).unwrap();
+        fs::write(dir.join("main.lua"), script).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_plugin_manager_creation() {
+        let manager = PluginManager::new();
+        assert!(manager.plugins.lock().unwrap().is_empty());
+        assert_eq!(manager.plugin_dir(), Path::new("plugins"));
+    }
+
+    #[test]
+    fn test_plugin_metadata_serialization() {
+        let metadata = PluginMetadata {
+            name: "test-plugin".to_string(),
+            version: "1.0.0".to_string(),
+            description: Some("Test plugin".to_string()),
+            author: Some("Test Author".to_string()),
+            homepage_url: None,
+            repository_url: None,
+            license: Some("MIT".to_string()),
+            language: PluginLanguage::Lua,
+            main_file: "main.lua".to_string(),
+            permissions: vec!["fs.read".to_string()],
+            dependencies: None,
+            configuration: None,
+            commands: vec![],
+            contributes: Default::default(),
+        };
+
+        let serialized = serde_json::to_string(&metadata).unwrap();
+        let deserialized: PluginMetadata = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(metadata.name, deserialized.name);
+    }
+
+    #[tokio::test]
+    async fn test_discover_and_load_plugins() {
+        let dir = tempdir().unwrap();
+        write_plugin(dir.path(), "hello", "function init() end");
+        write_plugin(dir.path(), "broken", "function init() error('boom') end");
+        fs::create_dir_all(dir.path().join("not_a_plugin")).unwrap();
+
+        let manager = PluginManager::with_plugin_dir(dir.path());
+        let names: Vec<_> = manager
+            .discover_plugins()
+            .unwrap()
+            .into_iter()
+            .map(|(_, m)| m.name)
+            .collect();
+        assert_eq!(names, vec!["broken", "hello"]);
+
+        assert_eq!(manager.load_all().await.unwrap(), vec!["hello"]);
+        assert_eq!(manager.plugin_status("hello"), Some(PluginStatus::Active));
+        assert_eq!(manager.plugin_status("broken"), Some(PluginStatus::Error));
+        assert!(manager.get_plugin("hello").is_some());
+        assert!(manager.get_plugin("broken").is_none());
+
+        let err = manager.load_plugin("missing").await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<PluginError>(),
+            Some(&PluginError::NotFound { plugin_id: "missing".to_string() })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_enable_disable_unload() {
+        let dir = tempdir().unwrap();
+        write_plugin(dir.path(), "hello", "function start() end");
+
+        let manager = PluginManager::with_plugin_dir(dir.path());
+        manager.load_plugin("hello").await.unwrap();
+        assert!(manager.load_plugin("hello").await.is_err());
+
+        manager.disable_plugin("hello").await.unwrap();
+        assert_eq!(manager.plugin_status("hello"), Some(PluginStatus::Disabled));
+        assert_eq!(manager.plugin_state("hello"), Some(RuntimeState::Unloaded));
+        assert!(manager.get_plugin("hello").is_none());
+
+        manager.enable_plugin("hello").await.unwrap();
+        assert_eq!(manager.plugin_status("hello"), Some(PluginStatus::Active));
+        assert_eq!(manager.plugin_state("hello"), Some(RuntimeState::Running));
+
+        manager.unload_plugin("hello").await.unwrap();
+        assert!(manager.plugin_status("hello").is_none());
+        assert!(manager.list_plugins().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_reload_invalidates_module_cache() {
+        let dir = tempdir().unwrap();
+        let plugin_dir = write_plugin(dir.path(), "multi", "local config = require('config')\nfunction value() return config.value end");
+        fs::write(plugin_dir.join("config.lua"), "return { value = 1 }").unwrap();
+
+        let manager = PluginManager::with_plugin_dir(dir.path());
+        manager.load_plugin("multi").await.unwrap();
+        assert_eq!(manager.call_function("multi", "value", vec![]).await.unwrap(), serde_json::json!(1));
+
+        fs::write(plugin_dir.join("config.lua"), "return { value = 2 }").unwrap();
+        manager.reload_plugin("multi").await.unwrap();
+        assert_eq!(manager.call_function("multi", "value", vec![]).await.unwrap(), serde_json::json!(2));
+    }
+
+    /// 在 `root` 下打包并签名名为 `packed` 的插件
+    fn write_package(root: &Path, key: &ed25519_dalek::SigningKey, version: &str, script: &str) -> PathBuf {
+        let src = write_plugin(&root.join("src").join(version), "packed", script);
+        let mut metadata = read_manifest(&src).unwrap();
+        metadata.version = version.to_string();
+        fs::write(src.join(MANIFEST_FILE), serde_json::to_string(&metadata).unwrap()).unwrap();
+        let archive = root.join(format!("packed-{}.tar.gz", version));
+        package::build(&src, &archive).unwrap();
+        package::sign(&archive, "release", key).unwrap();
+        archive
+    }
+
+    #[tokio::test]
+    async fn test_install_upgrade_uninstall_package() {
+        let dir = tempdir().unwrap();
+        let key = ed25519_dalek::SigningKey::from_bytes(&[3; 32]);
+        let pack = |version: &str, script: &str| write_package(dir.path(), &key, version, script);
+        let v1 = pack("1.0.0", "function version() return 1 end");
+        let v2 = pack("2.0.0", "function version() return 2 end");
+
+        let manager = PluginManager::with_plugin_dir(dir.path().join("plugins"));
+        let err = manager.install_package(&v1).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<PluginError>(), Some(PluginError::UntrustedPackage { .. })));
+        manager.set_trust_policy(TrustPolicy {
+            level: TrustLevel::Strict,
+            trusted_keys: vec![package::TrustedKey::new("release", &key.verifying_key())],
+        });
+
+        manager.install_package(&v1).await.unwrap();
+        let err = manager.install_package(&v1).await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<PluginError>(),
+            Some(&PluginError::AlreadyInstalled { plugin_id: "packed".to_string() })
+        );
+        manager.load_plugin("packed").await.unwrap();
+        let data = manager.plugin_data_dir("packed");
+        fs::create_dir_all(&data).unwrap();
+        fs::write(data.join("state.json"), "{}").unwrap();
+
+        manager.upgrade_package(&v2).await.unwrap();
+        assert_eq!(manager.plugin_status("packed"), Some(PluginStatus::Active));
+        assert_eq!(manager.call_function("packed", "version", vec![]).await.unwrap(), serde_json::json!(2));
+        assert!(data.join("state.json").is_file());
+        let err = manager.upgrade_package(&v1).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<PluginError>(), Some(PluginError::NotAnUpgrade { .. })));
+
+        manager.uninstall_plugin("packed", false).await.unwrap();
+        assert!(manager.plugin_status("packed").is_none());
+        assert!(manager.discover_plugins().unwrap().is_empty());
+        assert!(data.join("state.json").is_file());
+
+        manager.install_package(&v1).await.unwrap();
+        manager.uninstall_plugin("packed", true).await.unwrap();
+        assert!(!data.exists());
+    }
+
+    #[tokio::test]
+    async fn test_upgrade_rolls_back_on_failed_init() {
+        let dir = tempdir().unwrap();
+        let key = ed25519_dalek::SigningKey::from_bytes(&[3; 32]);
+        let v1 = write_package(dir.path(), &key, "1.0.0", "function version() return 1 end");
+        let v2 = write_package(dir.path(), &key, "2.0.0", "function init() error('boom') end");
+
+        let plugins = dir.path().join("plugins");
+        let manager = PluginManager::with_plugin_dir(&plugins);
+        manager.set_trust_policy(TrustPolicy {
+            level: TrustLevel::Strict,
+            trusted_keys: vec![package::TrustedKey::new("release", &key.verifying_key())],
+        });
+        manager.install_package(&v1).await.unwrap();
+        write_dependent_plugin(&plugins, "consumer", "1.0.0", &[("packed", "^1")]);
+        manager.load_plugin("packed").await.unwrap();
+        manager.load_plugin("consumer").await.unwrap();
+
+        let err = manager.upgrade_package(&v2).await.unwrap_err();
+        assert!(format!("{:#}", err).contains("boom"));
+        assert_eq!(manager.plugin_status("packed"), Some(PluginStatus::Active));
+        assert_eq!(manager.plugin_status("consumer"), Some(PluginStatus::Active));
+        assert_eq!(manager.call_function("packed", "version", vec![]).await.unwrap(), serde_json::json!(1));
+        assert_eq!(read_manifest(&plugins.join("packed")).unwrap().version, "1.0.0");
+        // 暂存目录和备份目录都已清理
+        let mut entries: Vec<_> = fs::read_dir(&plugins)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        entries.sort();
+        assert_eq!(entries, vec!["consumer", "packed"]);
+    }
+
+    #[tokio::test]
+    async fn test_plugin_config() {
+        let dir = tempdir().unwrap();
+        let plugin_dir = write_plugin(
+            dir.path(),
+            "configurable",
+            r#"
+            local seen = nil
+            function on_config_changed(config) seen = config.list_id end
+            function seen_list_id() return seen end
+            function api_key() return api.config("get", { key = "api_key" }) end
+            "#,
+        );
+        let mut metadata = read_manifest(&plugin_dir).unwrap();
+        metadata.configuration = Some(
+            serde_json::from_value(serde_json::json!({
+                "properties": {
+                    "api_key": { "type": "string", "secret": true },
+                    "list_id": { "type": "integer", "default": 1 }
+                },
+                "required": ["api_key"]
+            }))
+            .unwrap(),
+        );
+        fs::write(plugin_dir.join(MANIFEST_FILE), serde_json::to_string(&metadata).unwrap()).unwrap();
+
+        let manager = PluginManager::with_plugin_dir(dir.path());
+        manager.load_plugin("configurable").await.unwrap();
+        assert!(manager.config_schema("configurable").unwrap().unwrap().properties["api_key"].secret);
+        assert_eq!(manager.plugin_config("configurable").unwrap().get("list_id"), Some(&serde_json::json!(1)));
+
+        let values = |value: serde_json::Value| value.as_object().unwrap().clone();
+        let err = manager
+            .set_plugin_config("configurable", values(serde_json::json!({ "list_id": 2 })))
+            .await
+            .unwrap_err();
+        assert!(matches!(err.downcast_ref::<PluginError>(), Some(PluginError::InvalidConfig { .. })));
+
+        manager
+            .set_plugin_config("configurable", values(serde_json::json!({ "api_key": "secret", "list_id": 5 })))
+            .await
+            .unwrap();
+        let call = |function: &'static str| manager.call_function("configurable", function, vec![]);
+        assert_eq!(call("seen_list_id").await.unwrap(), serde_json::json!(5));
+        assert_eq!(call("api_key").await.unwrap(), serde_json::json!("secret"));
+
+        // 其他用户有独立的配置，切换用户时插件同样收到通知
+        manager.set_user("bob").await.unwrap();
+        assert_eq!(call("seen_list_id").await.unwrap(), serde_json::json!(1));
+        assert_eq!(call("api_key").await.unwrap(), serde_json::Value::Null);
+        assert!(manager.set_user("../bob").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_hot_reload_keeps_state_and_rolls_back() {
+        let dir = tempdir().unwrap();
+        let counter = |version: i32| {
+            format!(
+                r#"
+                count = 0
+                function increment() count = count + 1 return count end
+                function version() return {} end
+                function on_save_state() return {{ count = count }} end
+                function on_restore_state(state) count = state.count end
+                "#,
+                version
+            )
+        };
+        let plugin_dir = write_plugin(dir.path(), "counter", &counter(1));
+
+        let manager = PluginManager::with_plugin_dir(dir.path());
+        let mut events = manager.subscribe();
+        manager.load_plugin("counter").await.unwrap();
+        let call = |function: &'static str| manager.call_function("counter", function, vec![]);
+        call("increment").await.unwrap();
+        call("increment").await.unwrap();
+
+        fs::write(plugin_dir.join("main.lua"), counter(2)).unwrap();
+        manager.reload_plugin("counter").await.unwrap();
+        assert_eq!(call("version").await.unwrap(), serde_json::json!(2));
+        assert_eq!(call("increment").await.unwrap(), serde_json::json!(3));
+
+        // 语法错误和初始化失败都不影响正在运行的旧实例
+        fs::write(plugin_dir.join("main.lua"), "function version() return 3 end\nlocal = 1").unwrap();
+        let err = manager.reload_plugin("counter").await.unwrap_err();
+        match err.downcast_ref::<PluginError>() {
+            Some(PluginError::ReloadFailed { file, line, .. }) => {
+                assert_eq!(file.as_deref(), Some("main.lua"));
+                assert_eq!(*line, Some(2));
+            }
+            other => panic!("unexpected error {:?}", other),
+        }
+        fs::write(plugin_dir.join("main.lua"), "function init() error('boom') end").unwrap();
+        assert!(manager.reload_plugin("counter").await.is_err());
+
+        assert_eq!(manager.plugin_state("counter"), Some(RuntimeState::Running));
+        assert_eq!(call("version").await.unwrap(), serde_json::json!(2));
+        assert_eq!(call("increment").await.unwrap(), serde_json::json!(4));
+
+        let mut reloads = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            match event {
+                PluginEvent::Reloaded { version, .. } => reloads.push(format!("ok {}", version)),
+                PluginEvent::ReloadFailed { .. } => reloads.push("failed".to_string()),
+                _ => {}
+            }
+        }
+        assert_eq!(reloads, vec!["ok 1.0.0", "failed", "failed"]);
+    }
+
+    struct FailingApi;
+
+    impl PluginApi for FailingApi {
+        fn call(&self, method: &str, _params: serde_json::Value) -> Result<serde_json::Value> {
+            Err(anyhow::anyhow!("{} is unavailable", method))
+        }
+
+        fn get_permissions(&self) -> Vec<String> {
+            vec![]
+        }
+
+        fn method_access(&self, _method: &str) -> Option<MethodAccess> {
+            Some(MethodAccess::Public)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_error_records() {
+        let dir = tempdir().unwrap();
+        write_plugin(dir.path(), "broken", "function init()\n    error('boom')\nend");
+        write_plugin(
+            dir.path(),
+            "faulty",
+            r#"
+                function fail() local value = nil; return value.field end
+                function fetch() return pcall(api.remote, "fetch", {}) end
+            "#,
+        );
+
+        let manager = PluginManager::with_plugin_dir(dir.path());
+        manager.register_api("remote", Arc::new(FailingApi));
+        assert!(manager.load_plugin("broken").await.is_err());
+        let errors = manager.recent_errors("broken", 10);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].source, diagnostics::ErrorSource::Hook { name: "init".to_string() });
+        assert_eq!((errors[0].file.as_deref(), errors[0].line), (Some("main.lua"), Some(2)));
+        assert!(errors[0].message.contains("boom"));
+
+        manager.load_plugin("faulty").await.unwrap();
+        assert!(manager.call_function("faulty", "fail", vec![]).await.is_err());
+        manager.call_function("faulty", "fetch", vec![]).await.unwrap();
+        let errors = manager.recent_errors("faulty", 10);
+        assert_eq!(errors.len(), 2);
+        let api = diagnostics::ErrorSource::Api { api: "remote".to_string(), method: "fetch".to_string() };
+        assert_eq!(errors[0].source, api);
+        assert_eq!(errors[0].message, "fetch is unavailable");
+        assert_eq!(errors[1].source, diagnostics::ErrorSource::Function { name: "fail".to_string() });
+        assert!(errors[1].traceback.as_deref().unwrap().contains("in function"));
+
+        manager.clear_errors("faulty");
+        assert!(manager.recent_errors("faulty", 10).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_plugin_commands() {
+        let dir = tempdir().unwrap();
+        write_plugin_with(
+            dir.path(),
+            "exporter",
+            r#"
+                function init()
+                    api.commands("register", { id = "ping", title = "Ping" })
+                end
+
+                function export(args, context)
+                    api.commands("progress", { invocation_id = context.invocation_id, message = "half", percent = 50 })
+                    if args.format == "bad" then error("unsupported format") end
+                    return args.format
+                end
+
+                function ping() return "pong" end
+            "#,
+            serde_json::json!({
+                "commands": [{
+                    "id": "export",
+                    "title": "Export",
+                    "keybinding": "Ctrl+E",
+                    "arguments": { "properties": { "format": { "type": "string", "default": "csv" } } }
+                }]
+            }),
+        );
+
+        let manager = PluginManager::with_plugin_dir(dir.path());
+        let mut events = manager.subscribe();
+        manager.load_plugin("exporter").await.unwrap();
+        let commands = manager.list_commands();
+        let ids: Vec<_> = commands.iter().map(|c| (c.spec.id.as_str(), c.source, c.enabled)).collect();
+        assert_eq!(
+            ids,
+            vec![
+                ("export", command::CommandSource::Manifest, true),
+                ("ping", command::CommandSource::Runtime, true),
+            ]
+        );
+
+        let progress = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&progress);
+        let on_progress: command::ProgressCallback =
+            Arc::new(move |p: &command::CommandProgress| sink.lock().unwrap().push(p.message.clone()));
+        let result = manager
+            .invoke_command_with_progress("exporter", "export", serde_json::Value::Null, Some(on_progress))
+            .await
+            .unwrap();
+        assert_eq!(result, "csv");
+        assert_eq!(*progress.lock().unwrap(), vec![Some("half".to_string())]);
+        assert_eq!(manager.invoke_command("exporter", "ping", serde_json::Value::Null).await.unwrap(), "pong");
+
+        let err = manager
+            .invoke_command("exporter", "export", serde_json::json!({ "format": 1 }))
+            .await
+            .unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(PluginError::InvalidArguments { .. })));
+        let err = manager.invoke_command("exporter", "missing", serde_json::Value::Null).await.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(PluginError::CommandNotFound { .. })));
+        assert!(manager
+            .invoke_command("exporter", "export", serde_json::json!({ "format": "bad" }))
+            .await
+            .is_err());
+
+        let mut outcomes = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            match event {
+                PluginEvent::CommandProgress(p) => outcomes.push(format!("progress {}", p.command_id)),
+                PluginEvent::CommandCompleted { command_id, .. } => outcomes.push(format!("ok {}", command_id)),
+                PluginEvent::CommandFailed { command_id, error, .. } => {
+                    assert_eq!(error.source, diagnostics::ErrorSource::Command { id: command_id.clone() });
+                    outcomes.push(format!("failed {}", command_id));
+                }
+                _ => {}
+            }
+        }
+        assert_eq!(
+            outcomes,
+            vec!["progress export", "ok export", "ok ping", "progress export", "failed export"]
+        );
+
+        // 卸载后运行时注册的命令一并移除
+        manager.unload_plugin("exporter").await.unwrap();
+        assert!(manager.list_commands().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_plugin_infos() {
+        let dir = tempdir().unwrap();
+        write_plugin(dir.path(), "alpha", "function init() end");
+        write_plugin(dir.path(), "beta", "function init() error('boom') end");
+        write_plugin(dir.path(), "gamma", "");
+
+        let manager = PluginManager::with_plugin_dir(dir.path());
+        let mut events = manager.subscribe();
+        manager.load_plugin("alpha").await.unwrap();
+        assert!(manager.load_plugin("beta").await.is_err());
+
+        let infos = manager.plugin_infos().unwrap();
+        let summary: Vec<_> = infos
+            .iter()
+            .map(|info| (info.metadata.name.as_str(), info.status.clone(), info.state, info.usage.is_some()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("alpha", PluginStatus::Active, RuntimeState::Running, true),
+                ("beta", PluginStatus::Error, RuntimeState::Unloaded, false),
+                ("gamma", PluginStatus::Installed, RuntimeState::Unloaded, false),
+            ]
+        );
+        assert!(infos[1].last_error.as_ref().unwrap().message.contains("boom"));
+        assert!(matches!(manager.plugin_info("gamma").unwrap_err().downcast_ref(), Some(PluginError::NotLoaded { .. })));
+
+        let mut recorded = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            if let PluginEvent::ErrorRecorded { error } = event {
+                recorded.push(error.plugin_id);
+            }
+        }
+        assert_eq!(recorded, vec!["beta"]);
+    }
+
+    #[tokio::test]
+    async fn test_contributions() {
+        let dir = tempdir().unwrap();
+        write_plugin_with(
+            dir.path(),
+            "estimates",
+            r#"
+                function share_task(task) return "shared " .. task.title end
+                function burndown() return { remaining = 3 } end
+            "#,
+            serde_json::json!({
+                "contributes": {
+                    "taskActions": [{ "id": "share", "title": "Share", "function": "share_task" }],
+                    "taskFields": [{ "id": "estimate", "title": "Estimate", "type": "number" }],
+                    "dashboardWidgets": [{ "id": "burndown", "title": "Burndown" }]
+                }
+            }),
+        );
+        write_plugin(dir.path(), "idle", "");
+
+        let manager = PluginManager::with_plugin_dir(dir.path());
+        manager.load_all().await.unwrap();
+        let set = manager.contributions();
+        assert_eq!(set.task_actions.len(), 1);
+        assert_eq!(set.task_actions[0].plugin_id, "estimates");
+        assert_eq!(set.dashboard_widgets[0].item.id, "burndown");
+
+        use contribution::ContributionPoint;
+        let task = serde_json::json!({ "id": 1, "title": "Write report" });
+        let shared = manager
+            .invoke_contribution("estimates", ContributionPoint::TaskAction, "share", task.clone())
+            .await
+            .unwrap();
+        assert_eq!(shared, "shared Write report");
+        let widget = manager
+            .invoke_contribution("estimates", ContributionPoint::DashboardWidget, "burndown", serde_json::Value::Null)
+            .await
+            .unwrap();
+        assert_eq!(widget, serde_json::json!({ "remaining": 3 }));
+        let field = serde_json::json!({ "task": task, "value": 2 });
+        let result = manager
+            .invoke_contribution("estimates", ContributionPoint::TaskField, "estimate", field)
+            .await
+            .unwrap();
+        assert!(result.is_null());
+        let err = manager
+            .invoke_contribution("estimates", ContributionPoint::TaskBadge, "share", serde_json::Value::Null)
+            .await
+            .unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(PluginError::ContributionNotFound { .. })));
+
+        // 停用的插件不再提供扩展点
+        manager.disable_plugin("estimates").await.unwrap();
+        assert!(manager.contributions().task_actions.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_plugin_files() {
+        let dir = tempdir().unwrap();
+        let script = r#"
+            function save(text) return api.fs("write", { path = "notes/today.txt", content = text }) end
+            function load() return api.fs("read", { path = "notes/today.txt" }) end
+        "#;
+        write_plugin_with(dir.path(), "notes", script, serde_json::json!({ "permissions": ["fs.read", "fs.write"] }));
+        write_plugin(dir.path(), "nosy", script);
+
+        let manager = PluginManager::with_plugin_dir(dir.path());
+        manager.load_all().await.unwrap();
+        manager.call_function("notes", "save", vec!["hello".into()]).await.unwrap();
+        manager.reload_plugin("notes").await.unwrap();
+        assert_eq!(manager.call_function("notes", "load", vec![]).await.unwrap(), "hello");
+        let files_dir = manager.plugin_data_dir("notes").join(files::FILES_DIR);
+        assert!(files_dir.join("notes/today.txt").is_file());
+
+        let err = manager.call_function("nosy", "load", vec![]).await.unwrap_err();
+        assert!(format!("{:#}", err).contains("fs.read"));
+
+        manager
+            .set_resource_limits("notes", ResourceLimits { max_disk_usage: Some(3), ..ResourceLimits::default() })
+            .unwrap();
+        assert!(manager.call_function("notes", "save", vec!["too long".into()]).await.is_err());
+
+        manager.uninstall_plugin("notes", true).await.unwrap();
+        assert!(!files_dir.exists());
+    }
+
+    #[tokio::test]
+    async fn test_plugin_http() {
+        struct MockTransport(Mutex<Vec<http::HttpRequest>>);
+        impl http::HttpTransport for MockTransport {
+            fn send(&self, request: &http::HttpRequest) -> Result<http::HttpResponse, http::TransportError> {
+                self.0.lock().unwrap().push(request.clone());
+                Ok(http::HttpResponse {
+                    status: 200,
+                    headers: [("content-type".to_string(), "application/json".to_string())].into(),
+                    body: br#"{"id":42}"#.to_vec(),
+                })
+            }
+        }
+
+        let dir = tempdir().unwrap();
+        write_plugin_with(
+            dir.path(),
+            "tracker",
+            r#"
+            function create(title)
+                local response = api.http("post", {
+                    url = "https://api.example.com/issues?key=secret",
+                    headers = { Authorization = "Bearer t" },
+                    json = { title = title },
+                })
+                return response.json.id
+            end
+            function leak()
+                local ok, err = pcall(api.http, "get", { url = "https://evil.test/" })
+                return err.code
+            end
+            "#,
+            serde_json::json!({ "permissions": ["http:api.example.com"] }),
+        );
+
+        let manager = PluginManager::with_plugin_dir(dir.path());
+        let transport = Arc::new(MockTransport(Mutex::new(Vec::new())));
+        manager.set_http_transport(transport.clone());
+        manager.load_plugin("tracker").await.unwrap();
+
+        let id = manager.call_function("tracker", "create", vec!["Bug".into()]).await.unwrap();
+        assert_eq!(id, serde_json::json!(42));
+        let code = manager.call_function("tracker", "leak", vec![]).await.unwrap();
+        assert_eq!(code, "host_not_allowed");
+
+        let requests = transport.0.lock().unwrap().clone();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method, "POST");
+        assert_eq!(requests[0].headers["authorization"], "Bearer t");
+        assert_eq!(requests[0].body.as_deref(), Some(br#"{"title":"Bug"}"#.as_slice()));
+
+        let audit = manager.http_audit("tracker", 10);
+        assert_eq!(audit.len(), 2);
+        assert_eq!(audit[0].url, "https://evil.test/");
+        assert!(audit[0].error.as_deref().unwrap().contains("evil.test"));
+        assert_eq!(audit[1].url, "https://api.example.com/issues");
+        assert_eq!(audit[1].status, Some(200));
+    }
+
+    #[tokio::test]
+    async fn test_plugin_timers() {
+        let dir = tempdir().unwrap();
+        write_plugin(
+            dir.path(),
+            "clock",
+            r#"
+            ticks = 0
+            function start() api.timer("every", { id = "tick", interval = 20 }) end
+            function tick() ticks = ticks + 1 end
+            function count() return ticks end
+            function remind_later()
+                return api.timer("after", { id = "remind", delay = 50, persistent = true, data = { note = "hi" } })
+            end
+            function remind(data) note = data.note end
+            function reminded() return note end
+            function add(id) api.timer("every", { id = id, interval = 1000, ["function"] = "tick" }) end
+            "#,
+        );
+        let manager = PluginManager::with_plugin_dir(dir.path());
+        manager.load_plugin("clock").await.unwrap();
+        let count = || async { manager.call_function("clock", "count", vec![]).await.unwrap().as_u64().unwrap() };
+
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert!(count().await >= 2);
+        assert_eq!(manager.resource_usage("clock").unwrap().active_tasks, 1);
+
+        // 停止后定时器被取消，重新启动时由 start 重新注册
+        manager.stop_plugin("clock").await.unwrap();
+        assert!(manager.timers("clock").is_empty());
+        manager.start_plugin("clock").await.unwrap();
+        let ticks = count().await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(count().await > ticks);
+
+        // 持久化的定时器在插件重新加载后恢复
+        manager.call_function("clock", "remind_later", vec![]).await.unwrap();
+        manager.unload_plugin("clock").await.unwrap();
+        manager.load_plugin("clock").await.unwrap();
+        let ids: Vec<String> = manager.timers("clock").into_iter().map(|spec| spec.id).collect();
+        assert_eq!(ids, vec!["remind", "tick"]);
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert_eq!(manager.call_function("clock", "reminded", vec![]).await.unwrap(), "hi");
+        assert_eq!(manager.timers("clock").len(), 1);
+        let store = timer::TimerStore::new(&manager.plugin_data_dir("clock"));
+        assert!(store.load().unwrap().is_empty());
+
+        manager
+            .set_resource_limits("clock", ResourceLimits { max_tasks: Some(1), ..ResourceLimits::default() })
+            .unwrap();
+        let err = manager.call_function("clock", "add", vec!["extra".into()]).await.unwrap_err();
+        assert!(format!("{:#}", err).contains("more than 1 active timers"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_reload_fires_timers_scheduled_during_swap() {
+        let dir = tempdir().unwrap();
+        write_plugin(
+            dir.path(),
+            "boot",
+            r#"
+            booted = 0
+            function start()
+                api.timer("after", { id = "boot", delay = 0 })
+                -- 定时器在新实例替换旧实例之前就已触发
+                local begin = os.clock()
+                while os.clock() - begin < 0.05 do end
+            end
+            function boot() booted = booted + 1 end
+            function count() return booted end
+            "#,
+        );
+        let manager = PluginManager::with_plugin_dir(dir.path());
+        manager.load_plugin("boot").await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(manager.call_function("boot", "count", vec![]).await.unwrap(), serde_json::json!(1));
+
+        manager.reload_plugin("boot").await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(manager.call_function("boot", "count", vec![]).await.unwrap(), serde_json::json!(1));
+        assert!(manager.timers("boot").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_watch_reloads_and_unloads() {
+        let dir = tempdir().unwrap();
+        let plugin_dir = write_plugin(dir.path(), "watched", "function value() return 1 end");
+        fs::write(plugin_dir.join("config.lua"), "return {}").unwrap();
+
+        let mut manager = PluginManager::with_plugin_dir(dir.path());
+        let options = watcher::WatchOptions {
+            debounce: Duration::from_millis(100),
+            ..Default::default()
+        };
+        manager.init_with_watch_options(dir.path(), options).await.unwrap();
+        manager.load_plugin("watched").await.unwrap();
+        assert_eq!(manager.plugin_for_dir(&plugin_dir), Some("watched".to_string()));
+
+        // 先订阅再修改文件，避免错过事件
+        let wait_for = |expected: fn(&PluginEvent) -> bool| {
+            let mut events = manager.subscribe();
+            async move {
+                tokio::time::timeout(Duration::from_secs(5), async {
+                    while !expected(&events.recv().await.unwrap()) {}
+                })
+                .await
+                .expect("timed out waiting for plugin event")
+            }
+        };
+
+        let reloaded = wait_for(|e| matches!(e, PluginEvent::Reloaded { .. }));
+        fs::write(plugin_dir.join("main.lua"), "function value() return 2 end").unwrap();
+        reloaded.await;
+        assert_eq!(manager.call_function("watched", "value", vec![]).await.unwrap(), serde_json::json!(2));
+
+        // 删除模块文件只触发重新加载，删除入口文件则卸载插件
+        let reloaded = wait_for(|e| matches!(e, PluginEvent::Reloaded { .. }));
+        fs::remove_file(plugin_dir.join("config.lua")).unwrap();
+        reloaded.await;
+        assert_eq!(manager.plugin_status("watched"), Some(PluginStatus::Active));
+
+        fs::remove_file(plugin_dir.join("main.lua")).unwrap();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while manager.plugin_status("watched").is_some() {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .expect("plugin was not unloaded");
+        manager.stop_watching();
+    }
+
+    #[tokio::test]
+    async fn test_lifecycle_state_machine() {
+        let dir = tempdir().unwrap();
+        write_plugin(dir.path(), "hello", "function greet(name) return 'hi ' .. name end");
+
+        let manager = PluginManager::with_plugin_dir(dir.path());
+        let mut events = manager.subscribe();
+        manager.load_plugin("hello").await.unwrap();
+
+        let result = manager
+            .call_function("hello", "greet", vec![serde_json::json!("bob")])
+            .await
+            .unwrap();
+        assert_eq!(result, serde_json::json!("hi bob"));
+
+        // 运行中的插件不能直接卸载运行时，需要先停止
+        manager.stop_plugin("hello").await.unwrap();
+        let err = manager.stop_plugin("hello").await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<PluginError>(),
+            Some(PluginError::InvalidTransition {
+                from: RuntimeState::Stopped,
+                action: LifecycleAction::Stop,
+                ..
+            })
+        ));
+        manager.start_plugin("hello").await.unwrap();
+
+        manager.disable_plugin("hello").await.unwrap();
+        let err = manager
+            .call_function("hello", "greet", vec![serde_json::json!("bob")])
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<PluginError>(),
+            Some(PluginError::InvalidTransition {
+                from: RuntimeState::Unloaded,
+                action: LifecycleAction::Call,
+                ..
+            })
+        ));
+
+        let mut transitions = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            if let PluginEvent::StateChanged { to, .. } = event {
+                transitions.push(to);
+            }
+        }
+        assert_eq!(
+            transitions,
+            vec![
+                RuntimeState::Loaded,
+                RuntimeState::Initialized,
+                RuntimeState::Running,
+                RuntimeState::Stopped,
+                RuntimeState::Running,
+                RuntimeState::Stopped,
+                RuntimeState::Unloaded,
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_repeated_memory_violations_disable_plugin() {
+        let dir = tempdir().unwrap();
+        write_plugin(
+            dir.path(),
+            "hungry",
+            r#"
+                function grow()
+                    local t = {}
+                    for i = 1, 10000000 do
+                        t[i] = i
+                    end
+                end
+            "#,
+        );
+
+        let manager = PluginManager::with_plugin_dir(dir.path());
+        manager.load_plugin("hungry").await.unwrap();
+        manager
+            .set_resource_limits(
+                "hungry",
+                ResourceLimits {
+                    max_memory: Some(1024 * 1024),
+                    ..ResourceLimits::default()
+                },
+            )
+            .unwrap();
+
+        for _ in 0..ViolationPolicy::default().max_violations - 1 {
+            assert!(manager.call_function("hungry", "grow", vec![]).await.is_err());
+            assert_eq!(manager.plugin_status("hungry"), Some(PluginStatus::Active));
+        }
+        assert!(manager.call_function("hungry", "grow", vec![]).await.is_err());
+        assert_eq!(manager.plugin_status("hungry"), Some(PluginStatus::Error));
+        assert_eq!(manager.plugin_state("hungry"), Some(RuntimeState::Unloaded));
+    }
+
+    #[tokio::test]
+    async fn test_runaway_init_times_out() {
+        let dir = tempdir().unwrap();
+        write_plugin(dir.path(), "runaway", "function init() while true do end end");
+
+        let manager = PluginManager::with_plugin_dir(dir.path());
+        let err = manager.load_plugin("runaway").await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<PluginError>(),
+            Some(PluginError::CpuTimeExceeded { .. })
+        ));
+        assert_eq!(manager.plugin_status("runaway"), Some(PluginStatus::Error));
+    }
+
+    #[tokio::test]
+    async fn test_violation_policy_throttle() {
+        let dir = tempdir().unwrap();
+        write_plugin(
+            dir.path(),
+            "spinner",
+            r#"
+                function spin() while true do end end
+                function ping() return "pong" end
+            "#,
+        );
+
+        let manager = PluginManager::with_plugin_dir(dir.path());
+        manager.set_violation_policy(ViolationPolicy {
+            max_violations: 1,
+            action: ViolationAction::Throttle { cooldown_ms: 60_000 },
+        });
+        manager.load_plugin("spinner").await.unwrap();
+        manager
+            .set_resource_limits(
+                "spinner",
+                ResourceLimits {
+                    max_instructions: Some(10_000),
+                    ..ResourceLimits::default()
+                },
+            )
+            .unwrap();
+
+        assert!(manager.call_function("spinner", "spin", vec![]).await.is_err());
+        let err = manager.call_function("spinner", "ping", vec![]).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<PluginError>(),
+            Some(PluginError::Throttled { retry_after_ms, .. }) if *retry_after_ms > 0
+        ));
+        assert_eq!(manager.plugin_status("spinner"), Some(PluginStatus::Active));
+    }
+
+    #[tokio::test]
+    async fn test_message_bus_between_plugins() {
+        let dir = tempdir().unwrap();
+        write_plugin(
+            dir.path(),
+            "listener",
+            r#"
+                local received = {}
+
+                function init()
+                    bus.subscribe("task.*")
+                end
+
+                function on_message(message)
+                    received[#received + 1] = message.source .. ":" .. message.message_type
+                end
+
+                function received_count()
+                    return #received
+                end
+
+                function last_received()
+                    return received[#received]
+                end
+            "#,
+        );
+        write_plugin(
+            dir.path(),
+            "sender",
+            r#"
+                function announce()
+                    bus.publish("task.created", { id = 1 })
+                    bus.publish("note.created", { id = 2 })
+                    bus.publish("ping", nil, "listener")
+                    local ok, err = pcall(bus.publish, "ping", nil, "missing")
+                    return err.code
+                end
+            "#,
+        );
+
+        let manager = PluginManager::with_plugin_dir(dir.path());
+        manager.load_all().await.unwrap();
+        let code = manager.call_function("sender", "announce", vec![]).await.unwrap();
+        assert_eq!(code, "not_loaded");
+
+        // 消息异步投递，等待 listener 处理完
+        let mut count = serde_json::json!(0);
+        for _ in 0..100 {
+            count = manager.call_function("listener", "received_count", vec![]).await.unwrap();
+            if count == 2 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(count, 2);
+        assert_eq!(
+            manager.call_function("listener", "last_received", vec![]).await.unwrap(),
+            "sender:ping"
+        );
+
+        // note.created 没有订阅者，missing 插件不存在
+        let dead_letters = manager.dead_letters();
+        assert_eq!(dead_letters.len(), 2);
+        assert_eq!(dead_letters[0].message.message_type, "note.created");
+        assert_eq!(dead_letters[0].reason, bus::DeadLetterReason::NoSubscribers);
+        assert_eq!(dead_letters[1].recipient.as_deref(), Some("missing"));
+        assert_eq!(dead_letters[1].reason, bus::DeadLetterReason::UnknownTarget);
+    }
+
+    /// 写入插件并在清单中声明版本和依赖
+    fn write_dependent_plugin(root: &Path, name: &str, version: &str, dependencies: &[(&str, &str)]) {
+        let dependencies: HashMap<_, _> = dependencies.iter().map(|(n, r)| (n.to_string(), r.to_string())).collect();
+        write_plugin_with(
+            root,
+            name,
+            "",
+            serde_json::json!({ "version": version, "dependencies": dependencies }),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dependency_resolution() {
+        let dir = tempdir().unwrap();
+        write_dependent_plugin(dir.path(), "zcore", "1.4.0", &[]);
+        write_dependent_plugin(dir.path(), "app", "1.0.0", &[("zcore", "^1.2")]);
+        write_dependent_plugin(dir.path(), "legacy", "1.0.0", &[("zcore", "^2")]);
+        write_dependent_plugin(dir.path(), "orphan", "1.0.0", &[("missing", "*")]);
+        write_dependent_plugin(dir.path(), "ping", "1.0.0", &[("pong", "*")]);
+        write_dependent_plugin(dir.path(), "pong", "1.0.0", &[("ping", "*")]);
+
+        let manager = PluginManager::with_plugin_dir(dir.path());
+        // 依赖先于依赖者加载，与名称顺序无关
+        let loaded = manager.load_all().await.unwrap();
+        assert_eq!(loaded, vec!["zcore", "app"]);
+        assert_eq!(manager.plugin_status("legacy"), Some(PluginStatus::Error));
+        assert_eq!(manager.plugin_status("orphan"), Some(PluginStatus::Error));
+        // 循环依赖的插件不会被加载
+        assert_eq!(manager.plugin_status("ping"), None);
+
+        let err = manager.enable_plugin("legacy").await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<PluginError>(),
+            Some(PluginError::IncompatibleDependency { found, .. }) if found == "1.4.0"
+        ));
+
+        // 停用依赖会一并停用依赖者，依赖者在依赖恢复前无法启用
+        assert_eq!(manager.dependents("zcore"), vec!["legacy", "app"]);
+        manager.disable_plugin("zcore").await.unwrap();
+        assert_eq!(manager.plugin_status("app"), Some(PluginStatus::Disabled));
+        let err = manager.enable_plugin("app").await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<PluginError>(),
+            Some(PluginError::InactiveDependency { .. })
+        ));
+
+        manager.enable_plugin("zcore").await.unwrap();
+        manager.enable_plugin("app").await.unwrap();
+        assert_eq!(manager.plugin_status("app"), Some(PluginStatus::Active));
+    }
+}
@@ -0,0 +1,519 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::plugin::error::PluginError;
+use crate::plugin::{read_manifest, PluginMetadata, MANIFEST_FILE};
+
+/// 插件包中的校验清单，记录除自身以外每个文件的 SHA-256
+pub const CHECKSUMS_FILE: &str = "checksums.json";
+
+/// 分离签名文件的扩展名，签名文件与插件包放在同一目录，例如 `todo-sync.tar.gz.sig`
+pub const SIGNATURE_EXTENSION: &str = "sig";
+
+/// 信任策略文件名，应用启动时从配置目录读取
+pub const TRUST_POLICY_FILE: &str = "plugin-trust.json";
+
+/// 插件包解压后的总大小上限（字节）
+const MAX_UNPACKED_SIZE: u64 = 64 * 1024 * 1024;
+
+/// 安装插件包时对签名的要求
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrustLevel {
+    /// 只接受由受信任公钥签名的插件包
+    #[default]
+    Strict,
+    /// 开发者模式：未签名或签名无法验证的插件包也可以安装，只打印警告
+    Developer,
+}
+
+/// 受信任的签名公钥
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrustedKey {
+    /// 公钥标识，签名文件通过它指明使用的公钥
+    pub id: String,
+    /// Base64 编码的 32 字节 ed25519 公钥
+    pub public_key: String,
+}
+
+/// 安装插件包时使用的信任策略
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrustPolicy {
+    pub level: TrustLevel,
+    #[serde(default)]
+    pub trusted_keys: Vec<TrustedKey>,
+}
+
+/// 分离签名文件的内容，签名覆盖插件包中 `checksums.json` 的原始字节
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackageSignature {
+    pub key_id: String,
+    /// Base64 编码的 64 字节 ed25519 签名
+    pub signature: String,
+}
+
+/// 插件包的签名校验结果
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Verification {
+    /// 由受信任的公钥签名
+    Trusted { key_id: String },
+    /// 签名缺失或无法验证，只有开发者模式下会出现
+    Unverified { reason: String },
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ChecksumManifest {
+    sha256: BTreeMap<String, String>,
+}
+
+/// 已通过完整性和签名校验的插件包
+#[derive(Debug)]
+pub struct PluginPackage {
+    metadata: PluginMetadata,
+    files: BTreeMap<String, Vec<u8>>,
+    verification: Verification,
+}
+
+impl TrustPolicy {
+    /// 读取 JSON 格式的信任策略，文件不存在时返回默认的严格策略
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.is_file() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let policy: Self =
+            serde_json::from_str(&content).with_context(|| format!("Invalid trust policy {}", path.display()))?;
+        for key in &policy.trusted_keys {
+            key.verifying_key()
+                .map_err(anyhow::Error::msg)
+                .with_context(|| format!("Invalid trust policy {}", path.display()))?;
+        }
+        Ok(policy)
+    }
+}
+
+impl TrustedKey {
+    pub fn new(id: impl Into<String>, key: &VerifyingKey) -> Self {
+        Self {
+            id: id.into(),
+            public_key: BASE64.encode(key.as_bytes()),
+        }
+    }
+
+    fn verifying_key(&self) -> Result<VerifyingKey, String> {
+        let bytes: [u8; 32] = BASE64
+            .decode(&self.public_key)
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| format!("trusted key '{}' is not a valid ed25519 public key", self.id))?;
+        VerifyingKey::from_bytes(&bytes)
+            .map_err(|_| format!("trusted key '{}' is not a valid ed25519 public key", self.id))
+    }
+}
+
+impl PluginPackage {
+    /// 读取插件包及其分离签名，依次校验文件完整性、签名和插件清单
+    pub fn open(archive: &Path, policy: &TrustPolicy) -> Result<Self> {
+        let data = fs::read(archive)
+            .with_context(|| format!("Failed to read plugin package {}", archive.display()))?;
+        let files = unpack(&data)?;
+        let manifest = verify_checksums(&files)?;
+
+        let signature_file = signature_path(archive);
+        let signature = if signature_file.is_file() {
+            let content = fs::read_to_string(&signature_file)
+                .with_context(|| format!("Failed to read {}", signature_file.display()))?;
+            Some(serde_json::from_str(&content).map_err(|e| PluginError::UntrustedPackage {
+                reason: format!("invalid signature file: {}", e),
+            })?)
+        } else {
+            None
+        };
+        let verification = verify_signature(manifest, signature.as_ref(), policy)?;
+
+        let metadata: PluginMetadata = serde_json::from_slice(&files[MANIFEST_FILE])
+            .map_err(|e| invalid(format!("invalid {}: {}", MANIFEST_FILE, e)))?;
+        if !is_valid_name(&metadata.name) {
+            return Err(invalid(format!("invalid plugin name '{}'", metadata.name)).into());
+        }
+        if !files.contains_key(&metadata.main_file) {
+            return Err(invalid(format!("main file '{}' is missing", metadata.main_file)).into());
+        }
+
+        if let Verification::Unverified { reason } = &verification {
+            eprintln!(
+                "[PluginManager] 开发者模式：安装未验证的插件包 {} ({})",
+                metadata.name, reason
+            );
+        }
+        Ok(Self { metadata, files, verification })
+    }
+
+    pub fn metadata(&self) -> &PluginMetadata {
+        &self.metadata
+    }
+
+    pub fn verification(&self) -> &Verification {
+        &self.verification
+    }
+
+    /// 把插件文件写入 `dir`，校验清单不会写出
+    pub fn extract_to(&self, dir: &Path) -> Result<()> {
+        for (name, content) in &self.files {
+            if name == CHECKSUMS_FILE {
+                continue;
+            }
+            let path = dir.join(name);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&path, content).with_context(|| format!("Failed to write {}", path.display()))?;
+        }
+        Ok(())
+    }
+}
+
+/// 插件包对应的分离签名文件路径
+pub fn signature_path(archive: &Path) -> PathBuf {
+    let mut path = archive.as_os_str().to_os_string();
+    path.push(".");
+    path.push(SIGNATURE_EXTENSION);
+    PathBuf::from(path)
+}
+
+/// 把插件目录打包为 `.tar.gz` 插件包，并生成校验清单
+///
+/// 隐藏文件和目录（以 `.` 开头）不会被打包。
+pub fn build(plugin_dir: &Path, archive: &Path) -> Result<PluginMetadata> {
+    let metadata = read_manifest(plugin_dir)?;
+    let mut files = BTreeMap::new();
+    collect_files(plugin_dir, plugin_dir, &mut files)?;
+    files.remove(CHECKSUMS_FILE);
+
+    let manifest = ChecksumManifest {
+        sha256: files.iter().map(|(name, content)| (name.clone(), sha256(content))).collect(),
+    };
+    files.insert(CHECKSUMS_FILE.to_string(), serde_json::to_vec_pretty(&manifest)?);
+
+    let file = fs::File::create(archive)
+        .with_context(|| format!("Failed to create {}", archive.display()))?;
+    let encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+    let mut builder = tar::Builder::new(encoder);
+    for (name, content) in &files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, name, content.as_slice())?;
+    }
+    builder.into_inner()?.finish()?;
+    Ok(metadata)
+}
+
+/// 用私钥为插件包生成分离签名文件，返回签名文件路径
+pub fn sign(archive: &Path, key_id: &str, key: &SigningKey) -> Result<PathBuf> {
+    let data = fs::read(archive)
+        .with_context(|| format!("Failed to read plugin package {}", archive.display()))?;
+    let files = unpack(&data)?;
+    let manifest = verify_checksums(&files)?;
+    let signature = PackageSignature {
+        key_id: key_id.to_string(),
+        signature: BASE64.encode(key.sign(manifest).to_bytes()),
+    };
+    let path = signature_path(archive);
+    fs::write(&path, serde_json::to_string_pretty(&signature)?)?;
+    Ok(path)
+}
+
+fn invalid(reason: impl Into<String>) -> PluginError {
+    PluginError::InvalidPackage { reason: reason.into() }
+}
+
+fn sha256(content: &[u8]) -> String {
+    hex::encode(Sha256::digest(content))
+}
+
+/// 插件名称会用作安装目录名，只允许字母、数字、`-`、`_` 和 `.`，且不能以 `.` 开头
+pub(crate) fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('.')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// 解压插件包，只接受位于包内的普通文件和目录
+fn unpack(data: &[u8]) -> Result<BTreeMap<String, Vec<u8>>> {
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(data));
+    let mut files = BTreeMap::new();
+    let mut total = 0u64;
+
+    for entry in archive.entries().map_err(|e| invalid(e.to_string()))? {
+        let mut entry = entry.map_err(|e| invalid(e.to_string()))?;
+        let path = entry.path().map_err(|e| invalid(e.to_string()))?.into_owned();
+        match entry.header().entry_type() {
+            tar::EntryType::Directory => continue,
+            tar::EntryType::Regular => {}
+            other => {
+                return Err(invalid(format!(
+                    "entry '{}' has unsupported type {:?}",
+                    path.display(),
+                    other
+                ))
+                .into())
+            }
+        }
+
+        let name = normalize_entry(&path)
+            .ok_or_else(|| invalid(format!("entry '{}' escapes the package", path.display())))?;
+        total += entry.header().size().unwrap_or(0);
+        if total > MAX_UNPACKED_SIZE {
+            return Err(invalid(format!("package exceeds {} bytes when unpacked", MAX_UNPACKED_SIZE)).into());
+        }
+        let mut content = Vec::new();
+        entry.read_to_end(&mut content).map_err(|e| invalid(e.to_string()))?;
+        if files.insert(name.clone(), content).is_some() {
+            return Err(invalid(format!("duplicate entry '{}'", name)).into());
+        }
+    }
+    Ok(files)
+}
+
+/// 把包内路径转换为以 `/` 分隔的相对路径，绝对路径或包含 `..` 的路径返回 `None`
+fn normalize_entry(path: &Path) -> Option<String> {
+    let mut parts = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_str()?),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    (!parts.is_empty()).then(|| parts.join("/"))
+}
+
+/// 校验清单必须与包内文件一一对应且摘要一致，返回清单的原始字节
+fn verify_checksums(files: &BTreeMap<String, Vec<u8>>) -> Result<&[u8]> {
+    let raw = files
+        .get(CHECKSUMS_FILE)
+        .ok_or_else(|| invalid(format!("{} is missing", CHECKSUMS_FILE)))?;
+    if !files.contains_key(MANIFEST_FILE) {
+        return Err(invalid(format!("{} is missing", MANIFEST_FILE)).into());
+    }
+    let manifest: ChecksumManifest = serde_json::from_slice(raw)
+        .map_err(|e| invalid(format!("invalid {}: {}", CHECKSUMS_FILE, e)))?;
+
+    for (name, content) in files {
+        if name == CHECKSUMS_FILE {
+            continue;
+        }
+        match manifest.sha256.get(name) {
+            Some(expected) if expected.eq_ignore_ascii_case(&sha256(content)) => {}
+            _ => return Err(PluginError::PackageTampered { file: name.clone() }.into()),
+        }
+    }
+    if let Some(name) = manifest.sha256.keys().find(|name| !files.contains_key(*name)) {
+        return Err(PluginError::PackageTampered { file: name.clone() }.into());
+    }
+    Ok(raw)
+}
+
+/// 按信任策略校验签名；严格模式下校验失败返回 `UntrustedPackage`
+fn verify_signature(
+    manifest: &[u8],
+    signature: Option<&PackageSignature>,
+    policy: &TrustPolicy,
+) -> Result<Verification, PluginError> {
+    let result = match signature {
+        None => Err("package is not signed".to_string()),
+        Some(signature) => check_signature(manifest, signature, &policy.trusted_keys),
+    };
+    match (result, policy.level) {
+        (Ok(key_id), _) => Ok(Verification::Trusted { key_id }),
+        (Err(reason), TrustLevel::Developer) => Ok(Verification::Unverified { reason }),
+        (Err(reason), TrustLevel::Strict) => Err(PluginError::UntrustedPackage { reason }),
+    }
+}
+
+fn check_signature(
+    manifest: &[u8],
+    signature: &PackageSignature,
+    trusted_keys: &[TrustedKey],
+) -> Result<String, String> {
+    let key = trusted_keys
+        .iter()
+        .find(|key| key.id == signature.key_id)
+        .ok_or_else(|| format!("signing key '{}' is not trusted", signature.key_id))?
+        .verifying_key()?;
+    let bytes: [u8; 64] = BASE64
+        .decode(&signature.signature)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| "signature is malformed".to_string())?;
+    key.verify(manifest, &Signature::from_bytes(&bytes))
+        .map_err(|_| format!("signature does not match key '{}'", signature.key_id))?;
+    Ok(signature.key_id.clone())
+}
+
+fn collect_files(root: &Path, dir: &Path, files: &mut BTreeMap<String, Vec<u8>>) -> Result<()> {
+    for entry in fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+        let entry = entry?;
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            collect_files(root, &path, files)?;
+        } else if file_type.is_file() {
+            let name = path
+                .strip_prefix(root)
+                .ok()
+                .and_then(normalize_entry)
+                .with_context(|| format!("Cannot package {}", path.display()))?;
+            files.insert(name, fs::read(&path)?);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn write_plugin(dir: &Path) {
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        fs::write(
+            dir.join(MANIFEST_FILE),
+            serde_json::json!({
+                "name": "packed",
+                "version": "1.0.0",
+                "description": null,
+                "author": null,
+                "homepage_url": null,
+                "repository_url": null,
+                "license": null,
+                "language": "lua",
+                "main_file": "main.lua",
+            })
+            .to_string(),
+        )
+        .unwrap();
+        fs::write(dir.join("main.lua"), "local util = require('lib.util')").unwrap();
+        fs::write(dir.join("lib/util.lua"), "return {}").unwrap();
+        fs::write(dir.join(".editorconfig"), "root = true").unwrap();
+    }
+
+    fn policy(level: TrustLevel, key: &SigningKey) -> TrustPolicy {
+        TrustPolicy {
+            level,
+            trusted_keys: vec![TrustedKey::new("release", &key.verifying_key())],
+        }
+    }
+
+    fn error_of(result: Result<PluginPackage>) -> PluginError {
+        result.unwrap_err().downcast::<PluginError>().unwrap()
+    }
+
+    #[test]
+    fn test_build_sign_and_open() {
+        let dir = tempdir().unwrap();
+        write_plugin(&dir.path().join("src"));
+        let archive = dir.path().join("packed.tar.gz");
+        build(&dir.path().join("src"), &archive).unwrap();
+
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let strict = policy(TrustLevel::Strict, &key);
+        assert!(matches!(
+            error_of(PluginPackage::open(&archive, &strict)),
+            PluginError::UntrustedPackage { .. }
+        ));
+
+        sign(&archive, "release", &key).unwrap();
+        let package = PluginPackage::open(&archive, &strict).unwrap();
+        assert_eq!(package.metadata().name, "packed");
+        assert_eq!(package.verification(), &Verification::Trusted { key_id: "release".to_string() });
+
+        let out = dir.path().join("out");
+        package.extract_to(&out).unwrap();
+        assert!(out.join("lib/util.lua").is_file());
+        assert!(!out.join(CHECKSUMS_FILE).exists());
+        assert!(!out.join(".editorconfig").exists());
+
+        // 由不受信任的公钥签名
+        let other = SigningKey::from_bytes(&[8; 32]);
+        sign(&archive, "release", &other).unwrap();
+        assert!(matches!(
+            error_of(PluginPackage::open(&archive, &strict)),
+            PluginError::UntrustedPackage { .. }
+        ));
+        let package = PluginPackage::open(&archive, &policy(TrustLevel::Developer, &key)).unwrap();
+        assert!(matches!(package.verification(), Verification::Unverified { .. }));
+    }
+
+    #[test]
+    fn test_load_trust_policy() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join(TRUST_POLICY_FILE);
+        assert_eq!(TrustPolicy::load(&path).unwrap(), TrustPolicy::default());
+
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let expected = policy(TrustLevel::Developer, &key);
+        fs::write(&path, serde_json::to_string(&expected).unwrap()).unwrap();
+        assert_eq!(TrustPolicy::load(&path).unwrap(), expected);
+
+        fs::write(&path, r#"{ "level": "strict", "trusted_keys": [{ "id": "bad", "public_key": "AAAA" }] }"#).unwrap();
+        let err = TrustPolicy::load(&path).unwrap_err();
+        assert!(format!("{:#}", err).contains("trusted key 'bad'"));
+    }
+
+    #[test]
+    fn test_rejects_tampered_package() {
+        let dir = tempdir().unwrap();
+        let src = dir.path().join("src");
+        write_plugin(&src);
+        let archive = dir.path().join("packed.tar.gz");
+        build(&src, &archive).unwrap();
+        let key = SigningKey::from_bytes(&[7; 32]);
+        sign(&archive, "release", &key).unwrap();
+
+        // 替换一个文件后重新打包，但沿用原来的校验清单和签名
+        let files = unpack(&fs::read(&archive).unwrap()).unwrap();
+        let file = fs::File::create(&archive).unwrap();
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(file, flate2::Compression::default()));
+        for (name, content) in &files {
+            let content: &[u8] = if name == "main.lua" { b"os.exit()" } else { content };
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_cksum();
+            builder.append_data(&mut header, name, content).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+
+        for level in [TrustLevel::Strict, TrustLevel::Developer] {
+            assert_eq!(
+                error_of(PluginPackage::open(&archive, &policy(level, &key))),
+                PluginError::PackageTampered { file: "main.lua".to_string() }
+            );
+        }
+    }
+
+    #[test]
+    fn test_normalize_entry() {
+        assert_eq!(normalize_entry(Path::new("./lib/util.lua")), Some("lib/util.lua".to_string()));
+        assert_eq!(normalize_entry(Path::new("../evil.lua")), None);
+        assert_eq!(normalize_entry(Path::new("/etc/passwd")), None);
+        assert!(is_valid_name("todo-sync"));
+        assert!(!is_valid_name("../todo"));
+        assert!(!is_valid_name(".hidden"));
+    }
+}
@@ -0,0 +1,94 @@
+use std::collections::HashSet;
+
+use crate::plugin::error::PluginError;
+
+/// 插件清单中声明并被授予的权限集合
+///
+/// 权限字符串按 `.` 分段，支持以 `.*` 结尾的通配，例如 `task.*` 同时授予
+/// `task.read` 和 `task.write`。
+#[derive(Debug, Clone, Default)]
+pub struct PermissionSet {
+    granted: HashSet<String>,
+}
+
+impl PermissionSet {
+    pub fn new<I, S>(permissions: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            granted: permissions.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// 判断是否授予了 `permission`
+    pub fn grants(&self, permission: &str) -> bool {
+        if self.granted.contains(permission) {
+            return true;
+        }
+        // 逐级检查通配，例如 task.read -> task.*
+        let mut prefix = permission;
+        while let Some(pos) = prefix.rfind('.') {
+            prefix = &prefix[..pos];
+            if self.granted.contains(&format!("{}.*", prefix)) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// 校验插件是否可以调用 `api.method`，`required` 为该方法所需的权限
+    pub fn authorize(
+        &self,
+        plugin_id: &str,
+        api: &str,
+        method: &str,
+        required: Option<&str>,
+    ) -> Result<(), PluginError> {
+        match required {
+            Some(permission) if !self.grants(permission) => Err(PluginError::PermissionDenied {
+                plugin_id: plugin_id.to_string(),
+                api: api.to_string(),
+                method: method.to_string(),
+                permission: permission.to_string(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.granted.iter().map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grants_exact_and_wildcard() {
+        let permissions = PermissionSet::new(["task.read", "storage.*"]);
+        assert!(permissions.grants("task.read"));
+        assert!(!permissions.grants("task.write"));
+        assert!(permissions.grants("storage.read"));
+        assert!(permissions.grants("storage.write"));
+        assert!(!permissions.grants("storagex.read"));
+    }
+
+    #[test]
+    fn test_authorize() {
+        let permissions = PermissionSet::new(["task.read"]);
+        assert!(permissions.authorize("p", "task", "list", Some("task.read")).is_ok());
+        assert!(permissions.authorize("p", "system", "ping", None).is_ok());
+        assert_eq!(
+            permissions.authorize("p", "task", "create", Some("task.write")),
+            Err(PluginError::PermissionDenied {
+                plugin_id: "p".to_string(),
+                api: "task".to_string(),
+                method: "create".to_string(),
+                permission: "task.write".to_string(),
+            })
+        );
+    }
+}
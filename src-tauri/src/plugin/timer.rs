@@ -0,0 +1,717 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDateTime, TimeZone, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+
+use crate::plugin::error::PluginError;
+use crate::plugin::{MethodAccess, PluginApi};
+
+/// 没有设置 `max_tasks` 时每个插件同时存在的定时器上限
+pub const DEFAULT_MAX_TIMERS: usize = 10;
+
+/// 每个插件待执行定时器队列的容量，队列满时本次触发被跳过
+pub const TIMER_QUEUE_CAPACITY: usize = 16;
+
+/// `every` 的最小间隔
+const MIN_INTERVAL_MS: u64 = 10;
+
+/// `after` 的延迟和 `every` 的间隔允许的最大值（10 年）
+const MAX_DELAY_MS: u64 = 10 * 366 * 24 * 60 * 60 * 1000;
+
+/// 插件数据目录下保存持久化定时器的文件
+const TIMERS_FILE: &str = "timers.json";
+
+/// 定时器的触发规则
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Schedule {
+    /// 在指定时刻执行一次，`after` 注册时换算为绝对时间，重启后错过的时刻会立即补执行
+    Once { at: DateTime<Utc> },
+    /// 每隔固定毫秒数执行
+    Every { interval_ms: u64 },
+    /// 按本地时间的 cron 表达式执行
+    Cron { expr: String },
+}
+
+impl Schedule {
+    /// `now` 之后的下一次执行时间，`Once` 总是返回其设定的时刻
+    pub fn next_run(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Schedule::Once { at } => Some(*at),
+            Schedule::Every { interval_ms } => now.checked_add_signed(millis_to_duration(*interval_ms)?),
+            Schedule::Cron { expr } => CronSchedule::parse(expr)
+                .ok()?
+                .next_after(&now.with_timezone(&Local))
+                .map(|next| next.with_timezone(&Utc)),
+        }
+    }
+
+    pub fn is_once(&self) -> bool {
+        matches!(self, Schedule::Once { .. })
+    }
+}
+
+/// 毫秒数转换为时间间隔，超出范围时返回 `None`
+fn millis_to_duration(ms: u64) -> Option<Duration> {
+    Duration::try_milliseconds(i64::try_from(ms).ok()?)
+}
+
+/// 插件注册的定时器
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimerSpec {
+    pub id: String,
+    #[serde(flatten)]
+    pub schedule: Schedule,
+    /// 处理函数，缺省时与 `id` 同名
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub function: Option<String>,
+    /// 调用处理函数时传入的参数
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub data: Value,
+    /// 是否保存在插件数据目录中，重启应用或重新启用插件后恢复
+    #[serde(default)]
+    pub persistent: bool,
+}
+
+impl TimerSpec {
+    pub fn handler(&self) -> &str {
+        self.function.as_deref().unwrap_or(&self.id)
+    }
+}
+
+/// 定时器到期，由管理器调用插件的处理函数
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimerFired {
+    /// 注册时分配的序号，定时器被取消或替换后不再执行
+    pub seq: u64,
+    /// 注册该定时器的运行时实例
+    pub instance: u64,
+    pub spec: TimerSpec,
+}
+
+/// 五段式 cron 表达式：分 时 日 月 周
+///
+/// 每段支持 `*`、`5`、`1-5`、`*/15`、`1-30/5` 及其逗号列表，月份和星期可以写成 `jan`、`mon` 等英文缩写，
+/// 星期的 0 和 7 都表示周日。另外支持 `@hourly`、`@daily`、`@weekly`、`@monthly`、`@yearly`。
+/// 与 cron 相同，日和周都有限制时满足其一即可。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    days_restricted: bool,
+    weekdays_restricted: bool,
+}
+
+const MONTH_NAMES: &[&str] = &["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+const WEEKDAY_NAMES: &[&str] = &["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// 向后查找的最长范围，超出时认为表达式不会触发（例如 2 月 30 日）
+const CRON_SEARCH_YEARS: i64 = 5;
+
+impl CronSchedule {
+    pub fn parse(expr: &str) -> Result<Self> {
+        let expr = match expr.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            expr => expr,
+        };
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(anyhow::anyhow!("Invalid cron expression '{}': expected 5 fields", expr));
+        };
+        let weekdays = parse_field(weekday, 0, 7, WEEKDAY_NAMES, 0)
+            .with_context(|| format!("Invalid day of week in cron expression '{}'", expr))?;
+        Ok(Self {
+            minutes: parse_field(minute, 0, 59, &[], 0)
+                .with_context(|| format!("Invalid minute in cron expression '{}'", expr))?,
+            hours: parse_field(hour, 0, 23, &[], 0)
+                .with_context(|| format!("Invalid hour in cron expression '{}'", expr))?,
+            days: parse_field(day, 1, 31, &[], 1)
+                .with_context(|| format!("Invalid day of month in cron expression '{}'", expr))?,
+            months: parse_field(month, 1, 12, MONTH_NAMES, 1)
+                .with_context(|| format!("Invalid month in cron expression '{}'", expr))?,
+            // 7 与 0 同为周日
+            weekdays: (weekdays | (weekdays >> 7)) & 0x7f,
+            days_restricted: !day.starts_with('*'),
+            weekdays_restricted: !weekday.starts_with('*'),
+        })
+    }
+
+    /// `after` 之后（不含）第一个匹配的整分钟，夏令时跳过的时刻不会触发
+    pub fn next_after<Tz: TimeZone>(&self, after: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+        let timezone = after.timezone();
+        let start = after.naive_local();
+        let mut time = start.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        // 按天数计算上限，2 月 29 日加上整年后不存在
+        let end = start + Duration::days(366 * CRON_SEARCH_YEARS);
+        while time <= end {
+            if !has(self.months, time.month()) {
+                let (year, month) = match time.month() {
+                    12 => (time.year() + 1, 1),
+                    month => (time.year(), month + 1),
+                };
+                time = start_of_day(time.with_day(1)?.with_month(month)?.with_year(year)?);
+            } else if !self.matches_day(&time) {
+                time = start_of_day(time) + Duration::days(1);
+            } else if !has(self.hours, time.hour()) {
+                time = time.with_minute(0)? + Duration::hours(1);
+            } else if !has(self.minutes, time.minute()) {
+                time += Duration::minutes(1);
+            } else {
+                match timezone.from_local_datetime(&time).earliest() {
+                    Some(next) => return Some(next),
+                    None => time += Duration::minutes(1),
+                }
+            }
+        }
+        None
+    }
+
+    fn matches_day(&self, time: &NaiveDateTime) -> bool {
+        let day = has(self.days, time.day());
+        let weekday = has(self.weekdays, time.weekday().num_days_from_sunday());
+        match (self.days_restricted, self.weekdays_restricted) {
+            (true, true) => day || weekday,
+            (true, false) => day,
+            (false, true) => weekday,
+            (false, false) => true,
+        }
+    }
+}
+
+fn has(bits: u64, value: u32) -> bool {
+    bits & (1 << value) != 0
+}
+
+fn start_of_day(time: NaiveDateTime) -> NaiveDateTime {
+    time.date().and_hms_opt(0, 0, 0).unwrap_or(time)
+}
+
+/// 解析 cron 的一段，返回位图；`names` 为从 `name_base` 开始的英文缩写
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str], name_base: u32) -> Result<u64> {
+    let value = |s: &str| -> Result<u32> {
+        let lower = s.to_ascii_lowercase();
+        let value = match names.iter().position(|name| *name == lower) {
+            Some(index) => index as u32 + name_base,
+            None => s.parse().map_err(|_| anyhow::anyhow!("'{}' is not a number", s))?,
+        };
+        if value < min || value > max {
+            return Err(anyhow::anyhow!("{} is out of range {}-{}", value, min, max));
+        }
+        Ok(value)
+    };
+
+    let mut bits = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().ok().filter(|s| *s > 0)),
+            None => (part, Some(1)),
+        };
+        let step = step.ok_or_else(|| anyhow::anyhow!("Invalid step in '{}'", part))?;
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((start, end)) => (value(start)?, value(end)?),
+                // `5/15` 表示从 5 开始到最大值
+                None if part.contains('/') => (value(range)?, max),
+                None => (value(range)?, value(range)?),
+            },
+        };
+        if start > end {
+            return Err(anyhow::anyhow!("Invalid range '{}'", range));
+        }
+        for v in (start..=end).step_by(step as usize) {
+            bits |= 1 << v;
+        }
+    }
+    Ok(bits)
+}
+
+/// 插件数据目录中持久化的定时器
+#[derive(Debug, Clone)]
+pub struct TimerStore {
+    path: PathBuf,
+}
+
+impl TimerStore {
+    pub fn new(plugin_data_dir: &Path) -> Self {
+        Self { path: plugin_data_dir.join(TIMERS_FILE) }
+    }
+
+    pub fn load(&self) -> Result<BTreeMap<String, TimerSpec>> {
+        if !self.path.is_file() {
+            return Ok(BTreeMap::new());
+        }
+        let content =
+            fs::read_to_string(&self.path).with_context(|| format!("Failed to read {}", self.path.display()))?;
+        serde_json::from_str(&content).with_context(|| format!("Invalid timers file {}", self.path.display()))
+    }
+
+    pub fn save(&self, spec: &TimerSpec) -> Result<()> {
+        let mut timers = self.load()?;
+        timers.insert(spec.id.clone(), spec.clone());
+        self.write(&timers)
+    }
+
+    pub fn remove(&self, id: &str) -> Result<bool> {
+        let mut timers = self.load()?;
+        let removed = timers.remove(id).is_some();
+        if removed {
+            self.write(&timers)?;
+        }
+        Ok(removed)
+    }
+
+    fn write(&self, timers: &BTreeMap<String, TimerSpec>) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&self.path, serde_json::to_string_pretty(timers)?)
+            .with_context(|| format!("Failed to write {}", self.path.display()))
+    }
+}
+
+/// 定时器使用的时钟，默认跟随系统时间
+///
+/// 切换为手动时钟后时间只在 [`TimerScheduler::next_due`] 中前进，定时器不再由异步任务驱动，
+/// 供测试按需拨动时间。
+#[derive(Debug, Clone, Default)]
+pub struct Clock(Arc<Mutex<Option<DateTime<Utc>>>>);
+
+impl Clock {
+    pub fn now(&self) -> DateTime<Utc> {
+        self.0.lock().unwrap().unwrap_or_else(Utc::now)
+    }
+
+    pub fn is_manual(&self) -> bool {
+        self.0.lock().unwrap().is_some()
+    }
+
+    /// 把时钟设为手动并拨到 `now`，`None` 恢复为系统时间
+    pub fn set(&self, now: Option<DateTime<Utc>>) {
+        *self.0.lock().unwrap() = now;
+    }
+}
+
+struct ActiveTimer {
+    seq: u64,
+    instance: u64,
+    spec: TimerSpec,
+    // 手动时钟下的下一次执行时间
+    next: Option<DateTime<Utc>>,
+    // 系统时钟下驱动定时器的异步任务
+    task: Option<tokio::task::AbortHandle>,
+}
+
+impl ActiveTimer {
+    fn abort(&self) {
+        if let Some(task) = &self.task {
+            task.abort();
+        }
+    }
+}
+
+/// 所有插件的定时器
+///
+/// 每个定时器是宿主异步运行时中的一个任务，到期时把 [`TimerFired`] 放入插件的队列，
+/// 由管理器按顺序调用插件的处理函数。定时器归属于注册它的运行时实例，实例停止或卸载时一并取消。
+/// 手动时钟下不创建任务，由调用方通过 [`Self::next_due`] 逐个取出到期的触发。
+#[derive(Default)]
+pub struct TimerScheduler {
+    next_seq: AtomicU64,
+    next_instance: AtomicU64,
+    queues: Mutex<HashMap<String, mpsc::Sender<TimerFired>>>,
+    timers: Mutex<HashMap<String, Vec<ActiveTimer>>>,
+    clock: Clock,
+}
+
+impl TimerScheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clock(&self) -> Clock {
+        self.clock.clone()
+    }
+
+    /// 为新的运行时实例分配编号
+    pub fn next_instance(&self) -> u64 {
+        self.next_instance.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    /// 为插件创建定时器队列，返回的接收端由调用方负责消费
+    pub fn attach(&self, plugin_id: &str) -> mpsc::Receiver<TimerFired> {
+        let (tx, rx) = mpsc::channel(TIMER_QUEUE_CAPACITY);
+        self.queues.lock().unwrap().insert(plugin_id.to_string(), tx);
+        rx
+    }
+
+    /// 取消插件的全部定时器并移除队列
+    pub fn detach(&self, plugin_id: &str) {
+        self.queues.lock().unwrap().remove(plugin_id);
+        for timer in self.timers.lock().unwrap().remove(plugin_id).unwrap_or_default() {
+            timer.abort();
+        }
+    }
+
+    /// 注册定时器，同名定时器会被替换；返回下一次执行时间
+    pub fn schedule(&self, plugin_id: &str, instance: u64, spec: TimerSpec, limit: usize) -> Result<DateTime<Utc>> {
+        let now = self.clock.now();
+        let next_run = spec
+            .schedule
+            .next_run(now)
+            .ok_or_else(|| anyhow::anyhow!("Timer '{}' would never run", spec.id))?;
+        let sender = self
+            .queues
+            .lock()
+            .unwrap()
+            .get(plugin_id)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("Plugin '{}' cannot schedule timers while it is not running", plugin_id))?;
+        let runtime = if self.clock.is_manual() {
+            None
+        } else {
+            Some(tokio::runtime::Handle::try_current().context("Timers require an async runtime")?)
+        };
+
+        let mut timers = self.timers.lock().unwrap();
+        let active = timers.entry(plugin_id.to_string()).or_default();
+        if let Some(pos) = active.iter().position(|timer| timer.spec.id == spec.id) {
+            active.remove(pos).abort();
+        }
+        if active.len() >= limit {
+            return Err(PluginError::TaskLimitExceeded {
+                plugin_id: plugin_id.to_string(),
+                limit,
+            }
+            .into());
+        }
+
+        let seq = self.next_seq.fetch_add(1, Ordering::Relaxed);
+        let task = runtime.map(|runtime| {
+            let fired = TimerFired { seq, instance, spec: spec.clone() };
+            runtime
+                .spawn(async move {
+                    let mut next = Some(next_run);
+                    while let Some(at) = next {
+                        if let Ok(wait) = (at - Utc::now()).to_std() {
+                            tokio::time::sleep(wait).await;
+                        }
+                        // 队列已满说明插件处理不过来，跳过本次触发
+                        if let Err(mpsc::error::TrySendError::Closed(_)) = sender.try_send(fired.clone()) {
+                            break;
+                        }
+                        if fired.spec.schedule.is_once() {
+                            break;
+                        }
+                        next = fired.spec.schedule.next_run(Utc::now());
+                    }
+                })
+                .abort_handle()
+        });
+        active.push(ActiveTimer {
+            seq,
+            instance,
+            spec,
+            next: task.is_none().then_some(next_run),
+            task,
+        });
+        Ok(next_run)
+    }
+
+    /// 手动时钟下取出不晚于 `until` 的最早一次触发，并把时钟拨到触发时刻
+    ///
+    /// 没有到期的定时器时把时钟拨到 `until` 并返回 `None`；系统时钟下总是返回 `None`。
+    pub fn next_due(&self, until: DateTime<Utc>) -> Option<(String, TimerFired)> {
+        if !self.clock.is_manual() {
+            return None;
+        }
+        let now = self.clock.now();
+        let mut timers = self.timers.lock().unwrap();
+        let due = timers
+            .iter_mut()
+            .flat_map(|(plugin_id, active)| active.iter_mut().map(move |timer| (plugin_id, timer)))
+            .filter(|(_, timer)| timer.next.is_some_and(|at| at <= until))
+            .min_by_key(|(_, timer)| (timer.next, timer.seq));
+        let Some((plugin_id, timer)) = due else {
+            self.clock.set(Some(until.max(now)));
+            return None;
+        };
+
+        // 错过的一次性定时器在当前时刻执行，时钟不会倒退
+        let at = timer.next.unwrap_or(now).max(now);
+        self.clock.set(Some(at));
+        timer.next = if timer.spec.schedule.is_once() {
+            None
+        } else {
+            timer.spec.schedule.next_run(at)
+        };
+        let fired = TimerFired {
+            seq: timer.seq,
+            instance: timer.instance,
+            spec: timer.spec.clone(),
+        };
+        Some((plugin_id.clone(), fired))
+    }
+
+    pub fn cancel(&self, plugin_id: &str, id: &str) -> bool {
+        let mut timers = self.timers.lock().unwrap();
+        let Some(active) = timers.get_mut(plugin_id) else {
+            return false;
+        };
+        let Some(pos) = active.iter().position(|timer| timer.spec.id == id) else {
+            return false;
+        };
+        active.remove(pos).abort();
+        true
+    }
+
+    /// 取消运行时实例注册的全部定时器，持久化的定时器仍保留在插件数据目录中
+    pub fn cancel_instance(&self, plugin_id: &str, instance: u64) {
+        if let Some(active) = self.timers.lock().unwrap().get_mut(plugin_id) {
+            active.retain(|timer| {
+                if timer.instance == instance {
+                    timer.abort();
+                }
+                timer.instance != instance
+            });
+        }
+    }
+
+    /// 定时器是否仍然有效，一次性定时器执行后调用 [`Self::finish`] 移除
+    pub fn is_active(&self, plugin_id: &str, seq: u64) -> bool {
+        self.timers
+            .lock()
+            .unwrap()
+            .get(plugin_id)
+            .is_some_and(|active| active.iter().any(|timer| timer.seq == seq))
+    }
+
+    pub fn finish(&self, plugin_id: &str, seq: u64) {
+        if let Some(active) = self.timers.lock().unwrap().get_mut(plugin_id) {
+            active.retain(|timer| timer.seq != seq);
+        }
+    }
+
+    /// 插件当前的定时器，按 ID 排序
+    pub fn list(&self, plugin_id: &str) -> Vec<TimerSpec> {
+        let mut specs: Vec<TimerSpec> = self
+            .timers
+            .lock()
+            .unwrap()
+            .get(plugin_id)
+            .map(|active| active.iter().map(|timer| timer.spec.clone()).collect())
+            .unwrap_or_default();
+        specs.sort_by(|a, b| a.id.cmp(&b.id));
+        specs
+    }
+
+    pub fn count(&self, plugin_id: &str) -> usize {
+        self.timers.lock().unwrap().get(plugin_id).map_or(0, Vec::len)
+    }
+}
+
+/// 插件的定时器 API
+///
+/// | 方法 | 参数 |
+/// |------|------|
+/// | `after` | `id`, `delay`（毫秒）, `function?`, `data?`, `persistent?` |
+/// | `every` | `id`, `interval`（毫秒）, `function?`, `data?`, `persistent?` |
+/// | `cron` | `id`, `expr`, `function?`, `data?`, `persistent?` |
+/// | `cancel` | `id` |
+/// | `list` | - |
+///
+/// 到期时调用处理函数（缺省与 `id` 同名），参数为 `data`。注册方法返回下一次执行时间。
+pub struct TimerApi {
+    plugin_id: String,
+    instance: u64,
+    store: TimerStore,
+    scheduler: Arc<TimerScheduler>,
+    limit: Arc<AtomicUsize>,
+}
+
+impl TimerApi {
+    /// `limit` 由管理器持有，修改资源限制后立即生效
+    pub fn new(
+        plugin_id: &str,
+        instance: u64,
+        plugin_data_dir: &Path,
+        scheduler: Arc<TimerScheduler>,
+        limit: Arc<AtomicUsize>,
+    ) -> Self {
+        Self {
+            plugin_id: plugin_id.to_string(),
+            instance,
+            store: TimerStore::new(plugin_data_dir),
+            scheduler,
+            limit,
+        }
+    }
+
+    fn schedule(&self, method: &str, params: Value) -> Result<Value> {
+        let id = params["id"]
+            .as_str()
+            .filter(|id| !id.is_empty())
+            .ok_or_else(|| anyhow::anyhow!("timer.{} requires an id", method))?;
+        let millis = |field: &str| {
+            let ms = params[field]
+                .as_u64()
+                .ok_or_else(|| anyhow::anyhow!("timer.{} requires '{}' in milliseconds", method, field))?;
+            if ms > MAX_DELAY_MS {
+                anyhow::bail!("timer.{} '{}' must not exceed {} milliseconds", method, field, MAX_DELAY_MS);
+            }
+            Ok(ms)
+        };
+        let schedule = match method {
+            "after" => {
+                let delay = millis("delay")?;
+                let at = millis_to_duration(delay)
+                    .and_then(|delay| self.scheduler.clock().now().checked_add_signed(delay))
+                    .ok_or_else(|| anyhow::anyhow!("timer.after delay {} is out of range", delay))?;
+                Schedule::Once { at }
+            }
+            "every" => Schedule::Every { interval_ms: millis("interval")?.max(MIN_INTERVAL_MS) },
+            _ => {
+                let expr = params["expr"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("timer.cron requires an expr"))?;
+                CronSchedule::parse(expr)?;
+                Schedule::Cron { expr: expr.to_string() }
+            }
+        };
+        let spec = TimerSpec {
+            id: id.to_string(),
+            schedule,
+            function: params["function"].as_str().map(str::to_string),
+            data: params["data"].clone(),
+            persistent: params["persistent"].as_bool().unwrap_or(false),
+        };
+
+        let limit = self.limit.load(Ordering::Relaxed);
+        let next_run = self.scheduler.schedule(&self.plugin_id, self.instance, spec.clone(), limit)?;
+        if spec.persistent {
+            self.store.save(&spec)?;
+        } else {
+            // 同名的持久化定时器被替换为非持久化的
+            self.store.remove(id)?;
+        }
+        Ok(Value::String(next_run.to_rfc3339()))
+    }
+}
+
+impl PluginApi for TimerApi {
+    fn call(&self, method: &str, params: Value) -> Result<Value> {
+        match method {
+            "after" | "every" | "cron" => self.schedule(method, params),
+            "cancel" => {
+                let id = params["id"]
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("timer.cancel requires an id"))?;
+                let removed = self.store.remove(id)?;
+                Ok(Value::Bool(self.scheduler.cancel(&self.plugin_id, id) || removed))
+            }
+            "list" => Ok(json!(self.scheduler.list(&self.plugin_id))),
+            _ => Err(anyhow::anyhow!("Unknown timer method: {}", method)),
+        }
+    }
+
+    fn get_permissions(&self) -> Vec<String> {
+        vec![]
+    }
+
+    fn method_access(&self, method: &str) -> Option<MethodAccess> {
+        match method {
+            "after" | "every" | "cron" | "cancel" | "list" => Some(MethodAccess::Public),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_cron_next_after() {
+        let next = |expr: &str, after: &str| {
+            CronSchedule::parse(expr)
+                .unwrap()
+                .next_after(&utc(after))
+                .map(|t| t.to_rfc3339())
+        };
+        assert_eq!(next("*/15 * * * *", "2024-03-20T10:07:30Z").unwrap(), "2024-03-20T10:15:00+00:00");
+        assert_eq!(next("0 2 * * *", "2024-03-20T02:00:00Z").unwrap(), "2024-03-21T02:00:00+00:00");
+        assert_eq!(next("30 9 * * mon-fri", "2024-03-22T10:00:00Z").unwrap(), "2024-03-25T09:30:00+00:00");
+        assert_eq!(next("0 0 1 jan *", "2024-03-20T00:00:00Z").unwrap(), "2025-01-01T00:00:00+00:00");
+        assert_eq!(next("@weekly", "2024-03-20T00:00:00Z").unwrap(), "2024-03-24T00:00:00+00:00");
+        // 日和周都有限制时满足其一即可
+        assert_eq!(next("0 0 13 * 5", "2024-03-10T00:00:00Z").unwrap(), "2024-03-13T00:00:00+00:00");
+        assert_eq!(next("0 0 29 2 *", "2024-03-01T00:00:00Z").unwrap(), "2028-02-29T00:00:00+00:00");
+        assert_eq!(next("0 0 30 2 *", "2024-03-01T00:00:00Z"), None);
+        // 从 2 月 29 日开始查找
+        assert_eq!(next("0 * * * *", "2028-02-29T10:30:00Z").unwrap(), "2028-02-29T11:00:00+00:00");
+        assert_eq!(next("0 0 29 2 *", "2028-02-29T10:30:00Z").unwrap(), "2032-02-29T00:00:00+00:00");
+
+        for invalid in ["* * * *", "60 * * * *", "*/0 * * * *", "5-1 * * * *", "* * * foo *"] {
+            assert!(CronSchedule::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_scheduler() {
+        let dir = tempfile::tempdir().unwrap();
+        let scheduler = Arc::new(TimerScheduler::new());
+        let limit = Arc::new(AtomicUsize::new(2));
+        let api = TimerApi::new("p", 1, dir.path(), Arc::clone(&scheduler), limit);
+
+        assert!(api.call("every", json!({ "id": "tick", "interval": 20 })).is_err());
+        // 超出范围的延迟和间隔被拒绝，不会让宿主溢出
+        let huge = json!({ "id": "huge", "delay": 1_000_000_000_000_000_000u64, "interval": u64::MAX });
+        assert!(api.call("after", huge.clone()).unwrap_err().to_string().contains("must not exceed"));
+        assert!(api.call("every", huge).unwrap_err().to_string().contains("must not exceed"));
+        let every = Schedule::Every { interval_ms: u64::MAX };
+        assert_eq!(every.next_run(Utc::now()), None);
+        let mut rx = scheduler.attach("p");
+        api.call("every", json!({ "id": "tick", "interval": 20, "data": 1 })).unwrap();
+        api.call("after", json!({ "id": "once", "delay": 0, "function": "remind", "persistent": true }))
+            .unwrap();
+        let err = api.call("cron", json!({ "id": "nightly", "expr": "0 2 * * *" })).unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(PluginError::TaskLimitExceeded { limit: 2, .. })));
+        assert_eq!(TimerStore::new(dir.path()).load().unwrap().len(), 1);
+
+        let mut fired = Vec::new();
+        while fired.len() < 3 {
+            fired.push(rx.recv().await.unwrap());
+        }
+        let once = fired.iter().find(|f| f.spec.id == "once").unwrap();
+        assert_eq!(once.spec.handler(), "remind");
+        assert_eq!(fired.iter().filter(|f| f.spec.id == "tick").count(), 2);
+        scheduler.finish("p", once.seq);
+        assert_eq!(scheduler.count("p"), 1);
+
+        // 取消后不再触发，已经排队的触发也会被识别为无效
+        let tick = fired.iter().find(|f| f.spec.id == "tick").unwrap().seq;
+        assert_eq!(api.call("cancel", json!({ "id": "tick" })).unwrap(), true);
+        assert!(!scheduler.is_active("p", tick));
+        api.call("every", json!({ "id": "other", "interval": 20 })).unwrap();
+        scheduler.cancel_instance("p", 1);
+        assert!(api.call("list", Value::Null).unwrap().as_array().unwrap().is_empty());
+    }
+}
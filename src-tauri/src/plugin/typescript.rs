@@ -0,0 +1,330 @@
+use anyhow::{anyhow, Result};
+use oxc_allocator::Allocator;
+use oxc_ast::ast::*;
+use oxc_ast_visit::{walk, Visit};
+use oxc_parser::Parser;
+use oxc_span::{GetSpan, SourceType, Span};
+use oxc_syntax::scope::ScopeFlags;
+
+/// 把 TypeScript 源码转换为可以直接执行的 JavaScript
+///
+/// 只擦除类型相关的语法（类型注解、interface、type、`as` 断言、访问修饰符等），
+/// 被擦除的部分替换为等长的空白，行列号与源文件保持一致，错误堆栈可以直接对应到 `.ts` 文件。
+/// 需要生成代码的语法（非 `declare` 的 enum、namespace、构造函数参数属性）以及
+/// `import` / `export default` 不被支持，会返回带位置的错误。
+///
+/// 顶层的 `export` 关键字会被去掉，导出的函数成为插件的全局函数。
+pub fn strip_types(source: &str, file_name: &str) -> Result<String> {
+    let allocator = Allocator::default();
+    let parsed = Parser::new(&allocator, source, SourceType::ts()).parse();
+    if parsed.panicked || !parsed.errors.is_empty() {
+        let messages: Vec<_> = parsed.errors.iter().map(|e| e.to_string()).collect();
+        return Err(anyhow!("Failed to parse {}: {}", file_name, messages.join("; ")));
+    }
+
+    let mut eraser = TypeEraser {
+        source,
+        blanks: Vec::new(),
+        unsupported: None,
+    };
+    eraser.visit_program(&parsed.program);
+
+    if let Some((offset, what)) = eraser.unsupported {
+        let (line, column) = line_column(source, offset as usize);
+        return Err(anyhow!(
+            "{}:{}:{}: {} is not supported in plugins, only erasable TypeScript syntax can be used",
+            file_name, line, column, what
+        ));
+    }
+
+    let mut output = source.as_bytes().to_vec();
+    for (start, end) in eraser.blanks {
+        for byte in &mut output[start as usize..end as usize] {
+            if *byte != b'\n' && *byte != b'\r' {
+                *byte = b' ';
+            }
+        }
+    }
+    // 只会把完整的字符替换为 ASCII 空格，结果仍然是合法的 UTF-8
+    Ok(String::from_utf8(output)?)
+}
+
+fn line_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map_or(0, |pos| pos + 1) + 1;
+    (line, column)
+}
+
+/// 在语法树中收集需要擦除的区间
+struct TypeEraser<'s> {
+    source: &'s str,
+    blanks: Vec<(u32, u32)>,
+    unsupported: Option<(u32, &'static str)>,
+}
+
+/// 类成员前只存在于 TypeScript 中的修饰符
+const TS_MODIFIERS: &[&str] = &["public", "private", "protected", "readonly", "override", "abstract", "declare"];
+
+impl TypeEraser<'_> {
+    fn blank(&mut self, span: Span) {
+        self.blank_range(span.start, span.end);
+    }
+
+    fn blank_range(&mut self, start: u32, end: u32) {
+        if start < end {
+            self.blanks.push((start, end));
+        }
+    }
+
+    fn unsupported(&mut self, span: Span, what: &'static str) {
+        if self.unsupported.is_none() {
+            self.unsupported = Some((span.start, what));
+        }
+    }
+
+    /// 擦除 `offset` 之后紧跟的 `?` 或 `!`，例如 `x?: number`、`y!: string`
+    fn blank_marker_after(&mut self, offset: u32) {
+        let rest = &self.source[offset as usize..];
+        let trimmed = rest.trim_start();
+        if trimmed.starts_with('?') || trimmed.starts_with('!') {
+            let pos = offset + (rest.len() - trimmed.len()) as u32;
+            self.blank_range(pos, pos + 1);
+        }
+    }
+
+    /// 擦除 `start..end` 之间的 TypeScript 修饰符关键字
+    fn blank_modifiers(&mut self, start: u32, end: u32) {
+        let text = &self.source[start as usize..end as usize];
+        let mut offset = 0;
+        for word in text.split(|c: char| c.is_whitespace()) {
+            if TS_MODIFIERS.contains(&word) {
+                self.blank_range(start + offset, start + offset + word.len() as u32);
+            }
+            offset += word.len() as u32 + 1;
+        }
+    }
+
+    /// 擦除 `span` 开头的关键字，例如 `abstract class`
+    fn blank_leading_keyword(&mut self, span: Span, keyword: &str) {
+        if self.source[span.start as usize..].starts_with(keyword) {
+            self.blank_range(span.start, span.start + keyword.len() as u32);
+        }
+    }
+}
+
+impl<'a> Visit<'a> for TypeEraser<'_> {
+    fn visit_declaration(&mut self, it: &Declaration<'a>) {
+        match it {
+            Declaration::TSTypeAliasDeclaration(_)
+            | Declaration::TSInterfaceDeclaration(_)
+            | Declaration::TSGlobalDeclaration(_) => self.blank(it.span()),
+            Declaration::TSEnumDeclaration(decl) if decl.declare => self.blank(decl.span),
+            Declaration::TSEnumDeclaration(decl) => self.unsupported(decl.span, "enum"),
+            Declaration::TSModuleDeclaration(decl) if decl.declare => self.blank(decl.span),
+            Declaration::TSModuleDeclaration(decl) => self.unsupported(decl.span, "namespace"),
+            Declaration::TSImportEqualsDeclaration(decl) => self.unsupported(decl.span, "import ="),
+            Declaration::VariableDeclaration(decl) if decl.declare => self.blank(decl.span),
+            // declare function 和函数重载签名没有函数体
+            Declaration::FunctionDeclaration(func) if func.declare || func.body.is_none() => {
+                self.blank(func.span)
+            }
+            Declaration::ClassDeclaration(class) if class.declare => self.blank(class.span),
+            _ => walk::walk_declaration(self, it),
+        }
+    }
+
+    fn visit_module_declaration(&mut self, it: &ModuleDeclaration<'a>) {
+        match it {
+            ModuleDeclaration::ImportDeclaration(decl) if decl.import_kind.is_type() => self.blank(decl.span),
+            ModuleDeclaration::ImportDeclaration(decl) => self.unsupported(decl.span, "import"),
+            ModuleDeclaration::ExportNamedDeclaration(decl) => match &decl.declaration {
+                _ if decl.export_kind.is_type() => self.blank(decl.span),
+                Some(declaration) => {
+                    self.blank_range(decl.span.start, declaration.span().start);
+                    self.visit_declaration(declaration);
+                }
+                None if decl.source.is_some() => self.unsupported(decl.span, "re-export"),
+                // 插件以脚本方式执行，`export { a }` 没有意义
+                None => self.blank(decl.span),
+            },
+            ModuleDeclaration::ExportDefaultDeclaration(decl) => {
+                self.unsupported(decl.span, "export default")
+            }
+            ModuleDeclaration::ExportAllDeclaration(decl) if decl.export_kind.is_type() => self.blank(decl.span),
+            ModuleDeclaration::ExportAllDeclaration(decl) => self.unsupported(decl.span, "re-export"),
+            ModuleDeclaration::TSExportAssignment(decl) => self.unsupported(decl.span, "export ="),
+            ModuleDeclaration::TSNamespaceExportDeclaration(decl) => self.blank(decl.span),
+        }
+    }
+
+    fn visit_ts_type_annotation(&mut self, it: &TSTypeAnnotation<'a>) {
+        self.blank(it.span);
+    }
+
+    fn visit_ts_type_parameter_declaration(&mut self, it: &TSTypeParameterDeclaration<'a>) {
+        self.blank(it.span);
+    }
+
+    fn visit_ts_type_parameter_instantiation(&mut self, it: &TSTypeParameterInstantiation<'a>) {
+        self.blank(it.span);
+    }
+
+    fn visit_function(&mut self, it: &Function<'a>, flags: ScopeFlags) {
+        if let Some(this_param) = &it.this_param {
+            // `this: Foo` 以及后面的逗号
+            let rest = &self.source[this_param.span.end as usize..];
+            let comma = rest
+                .find(|c: char| !c.is_whitespace())
+                .filter(|pos| rest[*pos..].starts_with(','))
+                .map_or(0, |pos| pos as u32 + 1);
+            self.blank_range(this_param.span.start, this_param.span.end + comma);
+        }
+        walk::walk_function(self, it, flags);
+    }
+
+    fn visit_formal_parameter(&mut self, it: &FormalParameter<'a>) {
+        if it.accessibility.is_some() || it.readonly || it.r#override {
+            self.unsupported(it.span, "constructor parameter property");
+            return;
+        }
+        if it.optional {
+            self.blank_marker_after(it.pattern.span().end);
+        }
+        walk::walk_formal_parameter(self, it);
+    }
+
+    fn visit_variable_declarator(&mut self, it: &VariableDeclarator<'a>) {
+        if it.definite {
+            self.blank_marker_after(it.id.span().end);
+        }
+        walk::walk_variable_declarator(self, it);
+    }
+
+    fn visit_class(&mut self, it: &Class<'a>) {
+        if it.r#abstract {
+            self.blank_leading_keyword(it.span, "abstract");
+        }
+        if let (Some(first), Some(last)) = (it.implements.first(), it.implements.last()) {
+            let before = &self.source[..first.span.start as usize];
+            if let Some(keyword) = before.rfind("implements") {
+                self.blank_range(keyword as u32, last.span.end);
+            }
+        }
+        walk::walk_class(self, it);
+    }
+
+    fn visit_class_element(&mut self, it: &ClassElement<'a>) {
+        match it {
+            ClassElement::TSIndexSignature(signature) => self.blank(signature.span),
+            ClassElement::MethodDefinition(method)
+                if method.r#type.is_abstract() || method.value.body.is_none() =>
+            {
+                self.blank(method.span)
+            }
+            ClassElement::MethodDefinition(method) => {
+                self.blank_modifiers(method.span.start, method.key.span().start);
+                if method.optional {
+                    self.blank_marker_after(method.key.span().end);
+                }
+                walk::walk_method_definition(self, method);
+            }
+            ClassElement::PropertyDefinition(property)
+                if property.declare || property.r#type.is_abstract() =>
+            {
+                self.blank(property.span)
+            }
+            ClassElement::PropertyDefinition(property) => {
+                self.blank_modifiers(property.span.start, property.key.span().start);
+                if property.optional || property.definite {
+                    self.blank_marker_after(property.key.span().end);
+                }
+                walk::walk_property_definition(self, property);
+            }
+            _ => walk::walk_class_element(self, it),
+        }
+    }
+
+    fn visit_ts_as_expression(&mut self, it: &TSAsExpression<'a>) {
+        self.blank_range(it.expression.span().end, it.span.end);
+        self.visit_expression(&it.expression);
+    }
+
+    fn visit_ts_satisfies_expression(&mut self, it: &TSSatisfiesExpression<'a>) {
+        self.blank_range(it.expression.span().end, it.span.end);
+        self.visit_expression(&it.expression);
+    }
+
+    fn visit_ts_non_null_expression(&mut self, it: &TSNonNullExpression<'a>) {
+        self.blank_range(it.span.end - 1, it.span.end);
+        self.visit_expression(&it.expression);
+    }
+
+    fn visit_ts_type_assertion(&mut self, it: &TSTypeAssertion<'a>) {
+        self.blank_range(it.span.start, it.expression.span().start);
+        self.visit_expression(&it.expression);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_types() {
+        let source = r#"import type { Task } from "./types";
+interface Options { limit?: number }
+type Id = string;
+declare const host: unknown;
+
+export function init(options?: Options): void {
+    let count!: number;
+    const id = "a" as Id;
+    const tasks = <Task[]>[];
+    return helper<number>(options!.limit ?? 1, tasks.length);
+}
+
+function helper<T>(this: void, a: T, b: number): T { return a; }
+
+abstract class Base implements Options {
+    private readonly name: string = "base";
+    limit?: number;
+    abstract run(): void;
+    public describe(): string { return this.name; }
+}
+"#;
+        let output = strip_types(source, "main.ts").unwrap();
+        // 行号保持不变
+        assert_eq!(output.lines().count(), source.lines().count());
+        for fragment in [
+            "interface", "type Id", "declare", ": void", "as Id", "<Task[]>", "<number>", "<T>",
+            "this: void", "abstract", "implements", "private", "readonly", "?:", "!:", "!.",
+            "public", "import",
+        ] {
+            assert!(!output.contains(fragment), "{} was not erased:\n{}", fragment, output);
+        }
+        // 擦除留下的空白不影响语义，比较时忽略空白
+        let compact: String = output.split_whitespace().collect();
+        for expected in [
+            "functioninit(options){",
+            "constid=\"a\";",
+            "consttasks=[];",
+            "returnhelper(options.limit??1,tasks.length);",
+            "functionhelper(a,b){returna;}",
+            "classBase{name=\"base\";limit;describe(){returnthis.name;}}",
+        ] {
+            assert!(compact.contains(expected), "missing {}:\n{}", expected, output);
+        }
+    }
+
+    #[test]
+    fn test_unsupported_syntax() {
+        let err = strip_types("const a = 1;\nenum Color { Red }\n", "main.ts").unwrap_err();
+        assert!(err.to_string().starts_with("main.ts:2:1: enum is not supported"));
+
+        let err = strip_types("class A { constructor(private x: number) {} }", "main.ts").unwrap_err();
+        assert!(err.to_string().contains("constructor parameter property"));
+
+        assert!(strip_types("function (", "main.ts").is_err());
+    }
+}
@@ -0,0 +1,635 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use async_trait::async_trait;
+use anyhow::{anyhow, Context, Result};
+use wasmi::core::TrapCode;
+use wasmi::{AsContext, AsContextMut, Caller, Config, Engine, Instance, Linker, Memory, Module, Store, StoreLimits, StoreLimitsBuilder, TypedFunc};
+
+use crate::plugin::bridge::{self, ApiBridge};
+use crate::plugin::error::PluginError;
+use crate::plugin::limits::{BudgetTracker, ResourceMonitor};
+use crate::plugin::{PluginApi, PluginMessage, PluginMetadata, PluginRuntime, ResourceLimits, ResourceUsage};
+
+/// 宿主函数所在的导入模块名
+const HOST_MODULE: &str = "ptla";
+
+/// 插件导出的内存和分配函数
+#[derive(Clone)]
+struct GuestExports {
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    dealloc: Option<TypedFunc<(i32, i32), ()>>,
+}
+
+/// 保存在 `Store` 中、宿主函数可以访问的状态
+struct HostState {
+    plugin_id: String,
+    limits: StoreLimits,
+    bridge: Arc<ApiBridge>,
+    instance: Option<Instance>,
+    exports: Option<GuestExports>,
+    budget: Option<Arc<BudgetTracker>>,
+}
+
+/// 基于 wasmi 解释器的 WebAssembly 插件运行时
+///
+/// 插件与宿主之间用 JSON 交换数据，字符串和 JSON 都以 UTF-8 字节写入插件的线性内存，
+/// 用 `(ptr, len)` 传递；返回值把两者打包为 `i64`：高 32 位是 `ptr`，低 32 位是 `len`，
+/// `0` 表示 `null`。
+///
+/// 插件需要导出：
+/// - `memory`
+/// - `alloc(len: i32) -> i32`：宿主向插件传递数据前申请内存
+/// - `dealloc(ptr: i32, len: i32)`（可选）：宿主读取完数据后释放内存
+/// - `init`、`start`、`stop`、`unload`（可选）：`() -> ()`
+/// - `on_message(ptr: i32, len: i32)`（可选）：参数为 `PluginMessage` 的 JSON
+/// - 可调用函数 `(ptr: i32, len: i32) -> i64`：参数为 JSON 数组，返回任意 JSON
+///
+/// 宿主在 `ptla` 模块中提供：
+/// - `api_call(name_ptr, name_len, method_ptr, method_len, params_ptr, params_len) -> i64`：
+///   调用宿主 API，返回 `{ "ok": true, "result": ... }` 或 `{ "ok": false, "error": {...} }`
+/// - `log(ptr, len)`：输出日志
+/// - `fail(ptr, len)`：以给定信息终止当前调用
+///
+/// `max_memory` 限制线性内存大小，执行预算折算为燃料，见 [`BudgetTracker::fuel`]。
+pub struct WasmRuntime {
+    engine: Engine,
+    store: Mutex<Store<HostState>>,
+    metadata: PluginMetadata,
+    script_path: String,
+    bridge: Arc<ApiBridge>,
+    resource_limits: Arc<Mutex<ResourceLimits>>,
+    monitor: ResourceMonitor,
+}
+
+impl WasmRuntime {
+    pub fn new(metadata: PluginMetadata, script_path: String) -> Result<Self> {
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+
+        let limits = ResourceLimits {
+            max_memory: Some(50 * 1024 * 1024), // 默认 50MB
+            max_cpu_time: Some(1000),           // 默认 1 秒
+            max_instructions: None,             // 默认不限制指令数
+            max_tasks: Some(10),                // 默认最多 10 个并发任务
+            max_api_calls: Some(1000),          // 默认每分钟 1000 次 API 调用
+            api_quotas: HashMap::new(),
+            max_disk_usage: None,
+        };
+
+        let bridge = Arc::new(ApiBridge::new(&metadata));
+        bridge.set_limits(&limits);
+
+        let mut store = Store::new(
+            &engine,
+            HostState {
+                plugin_id: metadata.name.clone(),
+                limits: store_limits(&limits),
+                bridge: Arc::clone(&bridge),
+                instance: None,
+                exports: None,
+                budget: None,
+            },
+        );
+        store.limiter(|state| &mut state.limits);
+
+        Ok(Self {
+            engine,
+            store: Mutex::new(store),
+            metadata,
+            script_path,
+            bridge,
+            resource_limits: Arc::new(Mutex::new(limits)),
+            monitor: ResourceMonitor::new(),
+        })
+    }
+
+    /// 进入 WebAssembly 执行代码的唯一入口
+    ///
+    /// 与 `LuaRuntime::enter` 相同：每次进入都有独立的执行预算，燃料耗尽和内存增长失败被转换为
+    /// 对应的 [`PluginError`]。
+    fn enter<R>(
+        &self,
+        context: impl FnOnce() -> String,
+        f: impl FnOnce(&mut Store<HostState>) -> Result<R>,
+    ) -> Result<R> {
+        let mut store = self.store.lock().unwrap();
+        let limits = self.resource_limits.lock().unwrap().clone();
+        let tracker = Arc::new(BudgetTracker::new(&limits));
+        let fuel = tracker.fuel();
+
+        store
+            .set_fuel(fuel.map_or(u64::MAX, |(fuel, _)| fuel))
+            .map_err(|e| anyhow!("Failed to set fuel: {}", e))?;
+        store.data_mut().budget = Some(Arc::clone(&tracker));
+        store.data().bridge.set_budget(Some(Arc::clone(&tracker)));
+        let result = f(&mut store);
+        store.data().bridge.set_budget(None);
+        store.data_mut().budget = None;
+        self.monitor.record_cpu_time(tracker.elapsed());
+
+        let trap = result.as_ref().err().and_then(trap_code);
+        if let (Some(TrapCode::OutOfFuel), Some((_, exceeded))) = (trap, fuel) {
+            tracker.exhaust(exceeded);
+        }
+
+        if let Some(err) = tracker.error(&self.metadata.name) {
+            self.monitor.record_cpu_violation();
+            self.monitor.record_memory(memory_used(&store));
+            return Err(anyhow::Error::new(err).context(context()));
+        }
+
+        if trap == Some(TrapCode::GrowthOperationLimited) {
+            let limit = limits.max_memory.unwrap_or(0);
+            self.monitor.record_memory_violation(memory_used(&store));
+            return Err(anyhow::Error::new(PluginError::MemoryLimitExceeded {
+                plugin_id: self.metadata.name.clone(),
+                limit,
+            })
+            .context(context()));
+        }
+
+        self.monitor.record_memory(memory_used(&store));
+        result.with_context(context)
+    }
+
+    fn linker(&self) -> Result<Linker<HostState>> {
+        let mut linker = Linker::new(&self.engine);
+
+        linker.func_wrap(
+            HOST_MODULE,
+            "api_call",
+            |mut caller: Caller<'_, HostState>,
+             name_ptr: i32,
+             name_len: i32,
+             method_ptr: i32,
+             method_len: i32,
+             params_ptr: i32,
+             params_len: i32|
+             -> Result<i64, wasmi::Error> {
+                // 宿主 API 的耗时不计入预算，这里检查的是进入宿主之前脚本已经执行的时间
+                if let Some(tracker) = &caller.data().budget {
+                    if tracker.tick(0).is_some() {
+                        return Err(wasmi::Error::new("execution budget exceeded"));
+                    }
+                }
+
+                let name = read_string(&caller, name_ptr, name_len)?;
+                let method = read_string(&caller, method_ptr, method_len)?;
+                let params = read_string(&caller, params_ptr, params_len)?;
+
+                // 所有 API 调用都经过 ApiBridge 完成权限校验和限流
+                let bridge = Arc::clone(&caller.data().bridge);
+                let response = match params.as_str() {
+                    "" => Ok(serde_json::Value::Null),
+                    params => serde_json::from_str(params).map_err(anyhow::Error::from),
+                }
+                .and_then(|params| bridge.call(&name, &method, params));
+                let response = match response {
+                    Ok(result) => serde_json::json!({ "ok": true, "result": result }),
+                    Err(e) => serde_json::json!({ "ok": false, "error": bridge::error_value(&e) }),
+                };
+                write_guest(&mut caller, response.to_string().as_bytes())
+            },
+        )?;
+
+        linker.func_wrap(
+            HOST_MODULE,
+            "log",
+            |caller: Caller<'_, HostState>, ptr: i32, len: i32| -> Result<(), wasmi::Error> {
+                println!("[Wasm Plugin {}]: {}", caller.data().plugin_id, read_string(&caller, ptr, len)?);
+                Ok(())
+            },
+        )?;
+
+        linker.func_wrap(
+            HOST_MODULE,
+            "fail",
+            |caller: Caller<'_, HostState>, ptr: i32, len: i32| -> Result<(), wasmi::Error> {
+                Err(wasmi::Error::new(read_string(&caller, ptr, len)?))
+            },
+        )?;
+
+        Ok(linker)
+    }
+
+    fn instantiate(&self) -> Result<()> {
+        let bytes = std::fs::read(&self.script_path).context("Failed to read WebAssembly module")?;
+        let module = Module::new(&self.engine, &bytes[..]).context("Failed to compile WebAssembly module")?;
+        let linker = self.linker()?;
+
+        self.enter(
+            || format!("Failed to instantiate WebAssembly module {}", self.metadata.main_file),
+            |store| {
+                let instance = linker.instantiate(&mut *store, &module)?.start(&mut *store)?;
+                let memory = instance
+                    .get_memory(&*store, "memory")
+                    .ok_or_else(|| anyhow!("WebAssembly plugin must export `memory`"))?;
+                let alloc = instance
+                    .get_typed_func::<i32, i32>(&*store, "alloc")
+                    .context("WebAssembly plugin must export `alloc(len: i32) -> i32`")?;
+                let dealloc = instance.get_typed_func::<(i32, i32), ()>(&*store, "dealloc").ok();
+
+                let state = store.data_mut();
+                state.instance = Some(instance);
+                state.exports = Some(GuestExports { memory, alloc, dealloc });
+                Ok(())
+            },
+        )
+    }
+
+    /// 调用可选的生命周期钩子，插件未导出该函数时直接跳过
+    fn call_hook(&self, name: &str) -> Result<()> {
+        self.enter(
+            || format!("Failed to call WebAssembly function '{}'", name),
+            |store| {
+                let instance = instance(store)?;
+                if instance.get_func(&*store, name).is_some() {
+                    let hook = instance.get_typed_func::<(), ()>(&*store, name)?;
+                    hook.call(&mut *store, ())?;
+                }
+                Ok(())
+            },
+        )
+    }
+}
+
+fn store_limits(limits: &ResourceLimits) -> StoreLimits {
+    let builder = StoreLimitsBuilder::new().trap_on_grow_failure(true);
+    match limits.max_memory {
+        Some(max) => builder.memory_size(max).build(),
+        None => builder.build(),
+    }
+}
+
+fn instance(store: &Store<HostState>) -> Result<Instance> {
+    store
+        .data()
+        .instance
+        .ok_or_else(|| anyhow!("WebAssembly module is not instantiated"))
+}
+
+fn memory_used(store: &Store<HostState>) -> usize {
+    store
+        .data()
+        .exports
+        .as_ref()
+        .map_or(0, |exports| exports.memory.data(store).len())
+}
+
+fn trap_code(err: &anyhow::Error) -> Option<TrapCode> {
+    err.downcast_ref::<wasmi::Error>().and_then(wasmi::Error::as_trap_code)
+}
+
+fn pack(ptr: i32, len: usize) -> i64 {
+    ((ptr as u32 as i64) << 32) | len as u32 as i64
+}
+
+fn unpack(value: i64) -> (i32, i32) {
+    ((value >> 32) as i32, value as u32 as i32)
+}
+
+fn exports(ctx: &impl AsContext<Data = HostState>) -> Result<GuestExports, wasmi::Error> {
+    ctx.as_context()
+        .data()
+        .exports
+        .clone()
+        .ok_or_else(|| wasmi::Error::new("WebAssembly module is not instantiated"))
+}
+
+fn read_guest(ctx: &impl AsContext<Data = HostState>, ptr: i32, len: i32) -> Result<Vec<u8>, wasmi::Error> {
+    let exports = exports(ctx)?;
+    // 先按实际内存大小检查范围再复制，插件传入的长度不能让宿主分配超出其内存的缓冲区
+    let start = ptr as u32 as usize;
+    start
+        .checked_add(len as u32 as usize)
+        .and_then(|end| exports.memory.data(ctx).get(start..end))
+        .map(<[u8]>::to_vec)
+        .ok_or_else(|| wasmi::Error::new(format!("out of bounds memory access at {}..+{}", ptr as u32, len as u32)))
+}
+
+fn read_string(ctx: &impl AsContext<Data = HostState>, ptr: i32, len: i32) -> Result<String, wasmi::Error> {
+    String::from_utf8(read_guest(ctx, ptr, len)?).map_err(|_| wasmi::Error::new("string is not valid UTF-8"))
+}
+
+/// 调用插件的 `alloc` 申请内存并写入 `bytes`，返回打包后的指针和长度
+fn write_guest(mut ctx: impl AsContextMut<Data = HostState>, bytes: &[u8]) -> Result<i64, wasmi::Error> {
+    let exports = exports(&ctx)?;
+    let ptr = exports.alloc.call(&mut ctx, bytes.len() as i32)?;
+    exports
+        .memory
+        .write(&mut ctx, ptr as u32 as usize, bytes)
+        .map_err(|_| wasmi::Error::new("alloc returned an out of bounds pointer"))?;
+    Ok(pack(ptr, bytes.len()))
+}
+
+/// `inner` 指向的数据是否完全位于 `outer` 之内
+fn contains(outer: i64, inner: i64) -> bool {
+    let (outer_ptr, outer_len) = unpack(outer);
+    let (inner_ptr, inner_len) = unpack(inner);
+    let (outer_start, inner_start) = (outer_ptr as u32 as u64, inner_ptr as u32 as u64);
+    inner_start >= outer_start && inner_start + inner_len as u32 as u64 <= outer_start + outer_len as u32 as u64
+}
+
+/// 释放宿主写入或插件返回的数据，插件没有导出 `dealloc` 时由插件自行管理
+fn free_guest(mut ctx: impl AsContextMut<Data = HostState>, packed: i64) -> Result<(), wasmi::Error> {
+    if let Some(dealloc) = exports(&ctx)?.dealloc {
+        let (ptr, len) = unpack(packed);
+        dealloc.call(&mut ctx, (ptr, len))?;
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl PluginRuntime for WasmRuntime {
+    fn metadata(&self) -> &PluginMetadata {
+        &self.metadata
+    }
+
+    async fn init(&self) -> Result<()> {
+        self.instantiate()?;
+        self.call_hook("init")
+    }
+
+    async fn start(&self) -> Result<()> {
+        self.call_hook("start")
+    }
+
+    async fn stop(&self) -> Result<()> {
+        self.call_hook("stop")
+    }
+
+    async fn unload(&self) -> Result<()> {
+        self.call_hook("unload")
+    }
+
+    async fn call_function(&self, name: &str, args: Vec<serde_json::Value>) -> Result<serde_json::Value> {
+        let input = serde_json::to_vec(&args)?;
+        self.enter(
+            || format!("Failed to call WebAssembly function '{}'", name),
+            |store| {
+                let instance = instance(store)?;
+                if instance.get_func(&*store, name).is_none() {
+                    return Err(anyhow!("WebAssembly function '{}' is not exported", name));
+                }
+                let func = instance.get_typed_func::<(i32, i32), i64>(&*store, name)?;
+
+                let args = write_guest(&mut *store, &input)?;
+                let (ptr, len) = unpack(args);
+                let result = func.call(&mut *store, (ptr, len))?;
+                if result == 0 {
+                    free_guest(&mut *store, args)?;
+                    return Ok(serde_json::Value::Null);
+                }
+
+                // 先读取结果再释放：插件可能直接返回参数缓冲区或其中的一段，此时只释放一次
+                let (ptr, len) = unpack(result);
+                let output = read_guest(&*store, ptr, len)?;
+                free_guest(&mut *store, args)?;
+                if !contains(args, result) {
+                    free_guest(&mut *store, result)?;
+                }
+                serde_json::from_slice(&output).context("WebAssembly function returned invalid JSON")
+            },
+        )
+    }
+
+    fn has_function(&self, name: &str) -> bool {
+        let store = self.store.lock().unwrap();
+        instance(&store).is_ok_and(|instance| instance.get_func(&*store, name).is_some())
+    }
+
+    async fn send_message(&self, message: PluginMessage) -> Result<()> {
+        let message = serde_json::to_vec(&message)?;
+        self.enter(
+            || "Failed to call message handler".to_string(),
+            |store| {
+                let instance = instance(store)?;
+                if instance.get_func(&*store, "on_message").is_some() {
+                    let handler = instance.get_typed_func::<(i32, i32), ()>(&*store, "on_message")?;
+                    let input = write_guest(&mut *store, &message)?;
+                    handler.call(&mut *store, unpack(input))?;
+                    free_guest(&mut *store, input)?;
+                }
+                Ok(())
+            },
+        )
+    }
+
+    fn get_resource_usage(&self) -> Result<ResourceUsage> {
+        Ok(ResourceUsage {
+            api_calls: self.bridge.rate_limiter().calls(),
+            api_calls_rejected: self.bridge.rate_limiter().rejected(),
+            ..self.monitor.usage()
+        })
+    }
+
+    fn set_resource_limits(&self, limits: ResourceLimits) -> Result<()> {
+        // 已经分配的线性内存不会收缩，新的上限只影响之后的增长
+        self.store.lock().unwrap().data_mut().limits = store_limits(&limits);
+        self.bridge.set_limits(&limits);
+        *self.resource_limits.lock().unwrap() = limits;
+        Ok(())
+    }
+
+    fn register_api(&self, name: &str, api: Arc<dyn PluginApi>) -> Result<()> {
+        // 宿主函数按名称动态分发，注册后立即可用
+        self.bridge.register(name, api);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+    use crate::plugin::api::TaskApi;
+
+    /// 带有简单递增分配器的测试插件
+    ///
+    /// `echo` 原样返回参数，`list_tasks` / `create_task` 调用宿主 API 并返回响应，
+    /// `spin` 死循环，`grow` 申请大量内存，`init` 在全局变量 `broken` 非零时失败。
+    const TEST_MODULE: &str = r#"
+        (module
+            (import "ptla" "api_call" (func $api_call (param i32 i32 i32 i32 i32 i32) (result i64)))
+            (import "ptla" "fail" (func $fail (param i32 i32)))
+            (memory (export "memory") 1)
+            (global $next (mut i32) (i32.const 1024))
+            (global $messages (mut i32) (i32.const 0))
+            (global $broken (mut i32) (i32.const BROKEN))
+            (global $last_freed (mut i32) (i32.const -1))
+
+            (data (i32.const 0) "task")
+            (data (i32.const 16) "list")
+            (data (i32.const 32) "create")
+            (data (i32.const 48) "{\"title\":\"x\"}")
+            (data (i32.const 64) "broken")
+
+            (func $alloc (export "alloc") (param $len i32) (result i32)
+                (local $ptr i32)
+                (local.set $ptr (global.get $next))
+                (global.set $next (i32.add (global.get $next) (local.get $len)))
+                (local.get $ptr))
+
+            ;; 释放时清零，连续两次释放同一块内存时陷入，用来发现释放后使用和重复释放
+            (func (export "dealloc") (param $ptr i32) (param $len i32)
+                (if (i32.eq (local.get $ptr) (global.get $last_freed)) (then unreachable))
+                (global.set $last_freed (local.get $ptr))
+                (memory.fill (local.get $ptr) (i32.const 0) (local.get $len)))
+
+            (func (export "init")
+                (if (global.get $broken) (then (call $fail (i32.const 64) (i32.const 6)))))
+
+            (func (export "on_message") (param i32 i32)
+                (global.set $messages (i32.add (global.get $messages) (i32.const 1))))
+
+            (func (export "echo") (param $ptr i32) (param $len i32) (result i64)
+                (i64.or
+                    (i64.shl (i64.extend_i32_u (local.get $ptr)) (i64.const 32))
+                    (i64.extend_i32_u (local.get $len))))
+
+            ;; 返回参数中去掉首尾 `[` `]` 的一段
+            (func (export "first") (param $ptr i32) (param $len i32) (result i64)
+                (i64.or
+                    (i64.shl (i64.extend_i32_u (i32.add (local.get $ptr) (i32.const 1))) (i64.const 32))
+                    (i64.extend_i32_u (i32.sub (local.get $len) (i32.const 2)))))
+
+            ;; 返回长度为 -1（0xFFFFFFFF）的结果
+            (func (export "huge") (param i32 i32) (result i64)
+                (i64.const 0xFFFFFFFF))
+
+            (func (export "list_tasks") (param i32 i32) (result i64)
+                (call $api_call (i32.const 0) (i32.const 4) (i32.const 16) (i32.const 4) (i32.const 0) (i32.const 0)))
+
+            (func (export "create_task") (param i32 i32) (result i64)
+                (call $api_call (i32.const 0) (i32.const 4) (i32.const 32) (i32.const 6) (i32.const 48) (i32.const 13)))
+
+            (func (export "spin") (param i32 i32) (result i64)
+                (loop $forever (br $forever))
+                (i64.const 0))
+
+            (func (export "grow") (param i32 i32) (result i64)
+                (drop (memory.grow (i32.const 1000)))
+                (i64.const 0))
+        )
+    "#;
+
+    fn create_test_plugin(broken: bool, permissions: &[&str]) -> (WasmRuntime, tempfile::TempDir) {
+        let dir = tempdir().unwrap();
+        let script_path = dir.path().join("main.wasm");
+        let source = TEST_MODULE.replace("BROKEN", if broken { "1" } else { "0" });
+        fs::write(&script_path, wat::parse_str(source).unwrap()).unwrap();
+
+        let metadata = PluginMetadata {
+            name: "wasm_plugin".to_string(),
+            version: "1.0.0".to_string(),
+            author: None,
+            description: None,
+            homepage_url: None,
+            repository_url: None,
+            license: None,
+            language: crate::plugin::PluginLanguage::Wasm,
+            main_file: "main.wasm".to_string(),
+            permissions: permissions.iter().map(|p| p.to_string()).collect(),
+            dependencies: None,
+            configuration: None,
+            commands: vec![],
+            contributes: Default::default(),
+        };
+        let runtime = WasmRuntime::new(metadata, script_path.to_str().unwrap().to_string()).unwrap();
+        (runtime, dir)
+    }
+
+    #[tokio::test]
+    async fn test_json_abi_and_api_calls() {
+        let (runtime, _dir) = create_test_plugin(false, &["task.read"]);
+        runtime.register_api("task", Arc::new(TaskApi {})).unwrap();
+        runtime.init().await.unwrap();
+
+        let args = vec![serde_json::json!({ "id": 1, "score": 0.5 }), serde_json::json!("中文")];
+        let result = runtime.call_function("echo", args.clone()).await.unwrap();
+        assert_eq!(result, serde_json::Value::Array(args));
+        // 返回参数缓冲区中的一段，读取后只释放一次
+        let result = runtime.call_function("first", vec![serde_json::json!({ "n": 1 })]).await.unwrap();
+        assert_eq!(result, serde_json::json!({ "n": 1 }));
+
+        let result = runtime.call_function("list_tasks", vec![]).await.unwrap();
+        assert_eq!(result["ok"], serde_json::json!(true));
+
+        let result = runtime.call_function("create_task", vec![]).await.unwrap();
+        assert_eq!(result["ok"], serde_json::json!(false));
+        assert_eq!(result["error"]["code"], serde_json::json!("permission_denied"));
+        assert_eq!(result["error"]["permission"], serde_json::json!("task.write"));
+
+        assert!(runtime.call_function("missing", vec![]).await.is_err());
+        // 长度先按插件内存大小检查，不会在宿主上分配 4GB 缓冲区
+        let err = runtime.call_function("huge", vec![]).await.unwrap_err();
+        assert!(format!("{:#}", err).contains("out of bounds memory access at 0..+4294967295"));
+
+        let message = PluginMessage {
+            source: "host".to_string(),
+            target: Some("wasm_plugin".to_string()),
+            message_type: "ping".to_string(),
+            payload: serde_json::json!({ "n": 1 }),
+            timestamp: chrono::Utc::now(),
+        };
+        runtime.send_message(message).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_fuel_limits() {
+        let (runtime, _dir) = create_test_plugin(false, &[]);
+        runtime.init().await.unwrap();
+
+        runtime
+            .set_resource_limits(ResourceLimits {
+                max_instructions: Some(10_000),
+                ..ResourceLimits::default()
+            })
+            .unwrap();
+        let err = runtime.call_function("spin", vec![]).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<PluginError>(),
+            Some(PluginError::InstructionLimitExceeded { limit: 10_000, .. })
+        ));
+
+        runtime
+            .set_resource_limits(ResourceLimits {
+                max_cpu_time: Some(20),
+                ..ResourceLimits::default()
+            })
+            .unwrap();
+        let err = runtime.call_function("spin", vec![]).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<PluginError>(), Some(PluginError::CpuTimeExceeded { .. })));
+
+        // 中断之后插件仍然可以继续调用
+        let result = runtime.call_function("echo", vec![serde_json::json!(1)]).await.unwrap();
+        assert_eq!(result, serde_json::json!([1]));
+        assert_eq!(runtime.get_resource_usage().unwrap().cpu_violations, 2);
+    }
+
+    #[tokio::test]
+    async fn test_memory_limit() {
+        let (runtime, _dir) = create_test_plugin(false, &[]);
+        runtime.init().await.unwrap();
+        runtime
+            .set_resource_limits(ResourceLimits {
+                max_memory: Some(1024 * 1024),
+                ..ResourceLimits::default()
+            })
+            .unwrap();
+
+        let err = runtime.call_function("grow", vec![]).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<PluginError>(), Some(PluginError::MemoryLimitExceeded { .. })));
+        assert_eq!(runtime.get_resource_usage().unwrap().memory_violations, 1);
+    }
+
+    #[tokio::test]
+    async fn test_fail_reports_message() {
+        let (runtime, _dir) = create_test_plugin(true, &[]);
+        let err = runtime.init().await.unwrap_err();
+        assert!(format!("{:#}", err).contains("broken"));
+    }
+}
@@ -1,73 +1,231 @@
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::sync::mpsc as std_mpsc;
+use std::time::Duration;
 use tokio::sync::mpsc;
-use notify::{Watcher, RecursiveMode, Event};
+use notify::{Watcher, RecursiveMode, Event, EventKind};
 use anyhow::Result;
 
+use crate::plugin::MANIFEST_FILE;
+
+/// 监控插件目录，把零散的文件系统事件合并为按插件分组的变更
+///
+/// 原始事件先经过去抖：在 `debounce` 时间内没有新事件后，按文件的最终状态生成变更，
+/// 因此编辑器“写临时文件再重命名”的保存方式只会产生一次修改。隐藏文件和目录（例如 `.data`）
+/// 以及常见的编辑器临时文件会被忽略。
 pub struct PluginWatcher {
-    watcher: notify::RecommendedWatcher,
+    _watcher: Box<dyn Watcher + Send>,
     watch_path: PathBuf,
-    _tx: mpsc::Sender<PluginWatchEvent>,
+    polling: bool,
+}
+
+/// 文件监控选项
+#[derive(Debug, Clone, Copy)]
+pub struct WatchOptions {
+    /// 最后一个事件之后等待多久再发出变更
+    pub debounce: Duration,
+    /// 轮询模式下的扫描间隔
+    pub poll_interval: Duration,
+    /// 始终使用轮询，适用于 inotify 等机制不可用的文件系统（网络盘、部分容器挂载）
+    pub force_poll: bool,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        Self {
+            debounce: Duration::from_millis(300),
+            poll_interval: Duration::from_secs(2),
+            force_poll: false,
+        }
+    }
 }
 
+/// 一个插件目录在一次去抖周期内的全部变更
 #[derive(Debug, Clone)]
 pub struct PluginWatchEvent {
+    /// 发生变化的插件目录，即监控目录下的直接子目录
+    pub plugin_dir: PathBuf,
+    pub changes: Vec<FileChange>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FileChange {
     pub path: PathBuf,
     pub event_type: PluginWatchEventType,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum PluginWatchEventType {
     Created,
     Modified,
     Deleted,
 }
 
+impl PluginWatchEvent {
+    /// 本次变更是否涉及插件清单
+    pub fn manifest_changed(&self) -> bool {
+        self.changes.iter().any(|change| change.path == self.plugin_dir.join(MANIFEST_FILE))
+    }
+}
+
 impl PluginWatcher {
     pub fn new(
         watch_path: impl AsRef<Path>,
         tx: mpsc::Sender<PluginWatchEvent>
     ) -> Result<Self> {
-        let watch_path = watch_path.as_ref().to_path_buf();
-        let tx_clone = tx.clone();
+        Self::with_options(watch_path, tx, WatchOptions::default())
+    }
+
+    pub fn with_options(
+        watch_path: impl AsRef<Path>,
+        tx: mpsc::Sender<PluginWatchEvent>,
+        options: WatchOptions,
+    ) -> Result<Self> {
+        let watch_path = watch_path.as_ref().canonicalize()?;
+
+        // 原始事件交给去抖线程处理，监控器被丢弃后发送端随之关闭，线程退出
+        let (raw_tx, raw_rx) = std_mpsc::channel::<Event>();
+        let root = watch_path.clone();
+        let debounce = options.debounce;
+        std::thread::spawn(move || debounce_events(&root, raw_rx, tx, debounce));
 
-        // 创建文件系统监控器
-        let watcher = notify::recommended_watcher(move |res: Result<Event, notify::Error>| {
+        let handler = move |res: Result<Event, notify::Error>| {
             if let Ok(event) = res {
-                let event_type = match event.kind {
-                    notify::EventKind::Create(_) => Some(PluginWatchEventType::Created),
-                    notify::EventKind::Modify(_) => Some(PluginWatchEventType::Modified),
-                    notify::EventKind::Remove(_) => Some(PluginWatchEventType::Deleted),
-                    _ => None,
-                };
-
-                if let Some(event_type) = event_type {
-                    for path in event.paths {
-                        if path.extension().map_or(false, |ext| ext == "lua") {
-                            let watch_event = PluginWatchEvent {
-                                path,
-                                event_type: event_type.clone(),
-                            };
-                            let _ = tx_clone.blocking_send(watch_event);
-                        }
-                    }
-                }
+                let _ = raw_tx.send(event);
             }
-        })?;
+        };
 
-        // 开始监控插件目录
-        watcher.watch(&watch_path, RecursiveMode::Recursive)?;
+        // 优先使用系统的文件通知机制，不可用时退回轮询
+        let (watcher, polling): (Box<dyn Watcher + Send>, bool) = if options.force_poll {
+            (Box::new(poll_watcher(&watch_path, handler, options.poll_interval)?), true)
+        } else {
+            let native = notify::recommended_watcher(handler.clone())
+                .and_then(|mut watcher| watcher.watch(&watch_path, RecursiveMode::Recursive).map(|_| watcher));
+            match native {
+                Ok(watcher) => (Box::new(watcher), false),
+                Err(e) => {
+                    eprintln!("[PluginWatcher] 文件通知不可用，改为轮询: {}", e);
+                    (Box::new(poll_watcher(&watch_path, handler, options.poll_interval)?), true)
+                }
+            }
+        };
 
         Ok(Self {
-            watcher,
+            _watcher: watcher,
             watch_path,
-            _tx: tx,
+            polling,
         })
     }
 
     pub fn watch_path(&self) -> &Path {
         &self.watch_path
     }
+
+    /// 是否在使用轮询模式
+    pub fn is_polling(&self) -> bool {
+        self.polling
+    }
+}
+
+fn poll_watcher(
+    watch_path: &Path,
+    handler: impl notify::EventHandler,
+    interval: Duration,
+) -> Result<notify::PollWatcher> {
+    let mut watcher = notify::PollWatcher::new(handler, notify::Config::default().with_poll_interval(interval))?;
+    watcher.watch(watch_path, RecursiveMode::Recursive)?;
+    Ok(watcher)
+}
+
+/// 收集原始事件，安静 `debounce` 时间后按插件目录发出合并后的变更
+fn debounce_events(
+    root: &Path,
+    raw_rx: std_mpsc::Receiver<Event>,
+    tx: mpsc::Sender<PluginWatchEvent>,
+    debounce: Duration,
+) {
+    // 路径 -> 本批次中第一次出现时是否为创建事件
+    let mut pending: HashMap<PathBuf, bool> = HashMap::new();
+    loop {
+        let event = if pending.is_empty() {
+            match raw_rx.recv() {
+                Ok(event) => event,
+                Err(_) => return,
+            }
+        } else {
+            match raw_rx.recv_timeout(debounce) {
+                Ok(event) => event,
+                Err(std_mpsc::RecvTimeoutError::Timeout) => {
+                    for event in flush(root, &mut pending) {
+                        if tx.blocking_send(event).is_err() {
+                            return;
+                        }
+                    }
+                    continue;
+                }
+                Err(std_mpsc::RecvTimeoutError::Disconnected) => return,
+            }
+        };
+
+        if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)) {
+            continue;
+        }
+        let created = matches!(event.kind, EventKind::Create(_));
+        for path in event.paths {
+            if !is_ignored(root, &path) {
+                pending.entry(path).or_insert(created);
+            }
+        }
+    }
+}
+
+/// 按文件的当前状态生成变更：在本批次中创建又删除的临时文件不会出现在结果中
+fn flush(root: &Path, pending: &mut HashMap<PathBuf, bool>) -> Vec<PluginWatchEvent> {
+    let mut events: HashMap<PathBuf, Vec<FileChange>> = HashMap::new();
+    for (path, created) in pending.drain() {
+        let event_type = match (path.exists(), created) {
+            (true, true) => PluginWatchEventType::Created,
+            (true, false) => PluginWatchEventType::Modified,
+            (false, true) => continue,
+            (false, false) => PluginWatchEventType::Deleted,
+        };
+        if let Some(plugin_dir) = plugin_dir_of(root, &path) {
+            events.entry(plugin_dir).or_default().push(FileChange { path, event_type });
+        }
+    }
+
+    let mut events: Vec<_> = events
+        .into_iter()
+        .map(|(plugin_dir, mut changes)| {
+            changes.sort_by(|a, b| a.path.cmp(&b.path));
+            PluginWatchEvent { plugin_dir, changes }
+        })
+        .collect();
+    events.sort_by(|a, b| a.plugin_dir.cmp(&b.plugin_dir));
+    events
+}
+
+/// 文件所属的插件目录：与插件发现规则一致，插件位于监控目录的直接子目录中
+fn plugin_dir_of(root: &Path, path: &Path) -> Option<PathBuf> {
+    let first = path.strip_prefix(root).ok()?.components().next()?;
+    Some(root.join(first))
+}
+
+/// 忽略隐藏文件和目录，以及编辑器保存时产生的临时文件
+fn is_ignored(root: &Path, path: &Path) -> bool {
+    let Ok(relative) = path.strip_prefix(root) else {
+        return true;
+    };
+    if relative
+        .components()
+        .any(|c| c.as_os_str().to_string_lossy().starts_with('.'))
+    {
+        return true;
+    }
+    let name = relative.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+    name.ends_with('~')
+        || name == "4913" // vim 检测目录是否可写时创建的文件
+        || [".swp", ".swx", ".tmp", ".bak"].iter().any(|ext| name.ends_with(ext))
 }
 
 #[cfg(test)]
@@ -76,44 +234,84 @@ mod tests {
     use std::fs;
     use tempfile::tempdir;
     use tokio::sync::mpsc;
-    use std::time::Duration;
-    use tokio::time::sleep;
+
+    fn options(force_poll: bool) -> WatchOptions {
+        WatchOptions {
+            debounce: Duration::from_millis(100),
+            poll_interval: Duration::from_millis(50),
+            force_poll,
+        }
+    }
+
+    async fn next_event(rx: &mut mpsc::Receiver<PluginWatchEvent>) -> PluginWatchEvent {
+        tokio::time::timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .expect("timed out waiting for watch event")
+            .expect("watcher channel closed")
+    }
+
+    fn change(event: &PluginWatchEvent, path: &Path) -> Option<PluginWatchEventType> {
+        event
+            .changes
+            .iter()
+            .find(|change| change.path == path)
+            .map(|change| change.event_type.clone())
+    }
 
     #[tokio::test]
     async fn test_plugin_watcher() {
         let dir = tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let plugin_dir = root.join("hello");
+        fs::create_dir_all(plugin_dir.join("lib")).unwrap();
+        fs::write(plugin_dir.join(MANIFEST_FILE), "{}").unwrap();
+
         let (tx, mut rx) = mpsc::channel(32);
-        
-        let _watcher = PluginWatcher::new(dir.path(), tx).unwrap();
-        
-        // 创建测试文件
-        let test_file = dir.path().join("test.lua");
-        fs::write(&test_file, "print('Hello')").unwrap();
-        
-        // 等待文件创建事件
-        if let Some(event) = rx.recv().await {
-            assert_eq!(event.path, test_file);
-            assert!(matches!(event.event_type, PluginWatchEventType::Created));
-        }
-        
-        // 修改文件
-        sleep(Duration::from_millis(100)).await;
-        fs::write(&test_file, "print('Updated')").unwrap();
-        
-        // 等待文件修改事件
-        if let Some(event) = rx.recv().await {
-            assert_eq!(event.path, test_file);
-            assert!(matches!(event.event_type, PluginWatchEventType::Modified));
-        }
-        
-        // 删除文件
-        sleep(Duration::from_millis(100)).await;
-        fs::remove_file(&test_file).unwrap();
-        
-        // 等待文件删除事件
-        if let Some(event) = rx.recv().await {
-            assert_eq!(event.path, test_file);
-            assert!(matches!(event.event_type, PluginWatchEventType::Deleted));
-        }
+        let watcher = PluginWatcher::with_options(&root, tx, options(false)).unwrap();
+        assert!(!watcher.is_polling());
+
+        // 嵌套目录中的多次写入合并为一次变更
+        let util = plugin_dir.join("lib/util.lua");
+        fs::write(&util, "return 1").unwrap();
+        fs::write(&util, "return 2").unwrap();
+        fs::write(plugin_dir.join(".main.lua.swp"), "").unwrap();
+        let event = next_event(&mut rx).await;
+        assert_eq!(event.plugin_dir, plugin_dir);
+        assert_eq!(change(&event, &util), Some(PluginWatchEventType::Created));
+        assert_eq!(event.changes.len(), 1);
+
+        // 写临时文件再重命名的保存方式表现为一次修改
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let temp = plugin_dir.join("util.lua.tmp");
+        fs::write(&temp, "return 3").unwrap();
+        fs::rename(&temp, &util).unwrap();
+        let event = next_event(&mut rx).await;
+        assert_eq!(change(&event, &util), Some(PluginWatchEventType::Modified));
+        assert_eq!(event.changes.len(), 1);
+
+        fs::write(plugin_dir.join(MANIFEST_FILE), "{ }").unwrap();
+        assert!(next_event(&mut rx).await.manifest_changed());
+
+        fs::remove_file(&util).unwrap();
+        let event = next_event(&mut rx).await;
+        assert_eq!(change(&event, &util), Some(PluginWatchEventType::Deleted));
     }
-} 
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_polling_fallback() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join("hello")).unwrap();
+
+        let (tx, mut rx) = mpsc::channel(32);
+        let watcher = PluginWatcher::with_options(&root, tx, options(true)).unwrap();
+        assert!(watcher.is_polling());
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let main = root.join("hello/main.ts");
+        fs::write(&main, "export {}").unwrap();
+        let event = next_event(&mut rx).await;
+        assert_eq!(event.plugin_dir, root.join("hello"));
+        assert!(change(&event, &main).is_some());
+    }
+}